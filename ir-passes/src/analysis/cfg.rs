@@ -0,0 +1,174 @@
+//! The shape of the control flow graph of a function.
+
+use llvmlite::{Block, ControlFlowGraph, Label, Terminator};
+
+/// The block numbered `block` in the [`Graph`] of `cfg`.
+pub fn block(cfg: &ControlFlowGraph, block: usize) -> &Block {
+    match block {
+        0 => &cfg.entry,
+        _ => &cfg.blocks[block - 1],
+    }
+}
+
+/// The block numbered `block` in the [`Graph`] of `cfg`, to be changed.
+pub fn block_mut(cfg: &mut ControlFlowGraph, block: usize) -> &mut Block {
+    match block {
+        0 => &mut cfg.entry,
+        _ => &mut cfg.blocks[block - 1],
+    }
+}
+
+/// The blocks of a function with the edges between them.
+///
+/// Blocks are numbered in the order of the function: block 0 is the entry
+/// block, and block `i + 1` is `cfg.blocks[i]`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Graph {
+    labels: Vec<Option<Label>>,
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+    reverse_post_order: Vec<usize>,
+    /// The position of each block in the reverse post-order, if it can be
+    /// reached
+    order: Vec<Option<usize>>,
+}
+
+impl Graph {
+    /// The graph of `cfg`, which must only branch to its own blocks.
+    pub fn new(cfg: &ControlFlowGraph) -> Self {
+        let labels: Vec<_> = std::iter::once(None)
+            .chain(cfg.blocks.keys().cloned().map(Some))
+            .collect();
+        let index = |label: &Label| {
+            cfg.blocks
+                .get_index_of(label)
+                .expect("branches go to blocks of the function")
+                + 1
+        };
+        let terminators = std::iter::once(&cfg.entry)
+            .chain(cfg.blocks.values())
+            .map(|block| &block.terminator.1);
+        let successors: Vec<Vec<usize>> = terminators
+            .map(|terminator| match terminator {
+                Terminator::Ret(..) => vec![],
+                Terminator::Break(label) => vec![index(label)],
+                Terminator::CondBreak(_, then, else_) if then == else_ => vec![index(then)],
+                Terminator::CondBreak(_, then, else_) => vec![index(then), index(else_)],
+            })
+            .collect();
+        let mut predecessors = vec![vec![]; labels.len()];
+        for (block, successors) in successors.iter().enumerate() {
+            for &successor in successors {
+                predecessors[successor].push(block);
+            }
+        }
+
+        // Depth first search from the entry, with an explicit stack of the
+        // blocks being visited and their next successor
+        let mut post_order = vec![];
+        let mut visited = vec![false; labels.len()];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            match successors[block].get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => post_order.push(block),
+            }
+        }
+        let reverse_post_order: Vec<_> = post_order.into_iter().rev().collect();
+        let mut order = vec![None; labels.len()];
+        for (position, &block) in reverse_post_order.iter().enumerate() {
+            order[block] = Some(position);
+        }
+
+        Graph {
+            labels,
+            successors,
+            predecessors,
+            reverse_post_order,
+            order,
+        }
+    }
+
+    /// The number of blocks, including those that cannot be reached.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Whether the graph is empty, which it never is: there is always an
+    /// entry block.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The label of `block`, which is `None` for the entry block.
+    pub fn label(&self, block: usize) -> Option<&Label> {
+        self.labels[block].as_ref()
+    }
+
+    /// The number of the block labelled `label`.
+    pub fn block(&self, label: &str) -> Option<usize> {
+        self.labels.iter().position(|l| l.as_deref() == Some(label))
+    }
+
+    pub fn successors(&self, block: usize) -> &[usize] {
+        &self.successors[block]
+    }
+
+    pub fn predecessors(&self, block: usize) -> &[usize] {
+        &self.predecessors[block]
+    }
+
+    /// The blocks that can be reached from the entry, each before its
+    /// successors except along back edges.
+    pub fn reverse_post_order(&self) -> &[usize] {
+        &self.reverse_post_order
+    }
+
+    /// The position of `block` in the reverse post-order, if it can be
+    /// reached.
+    pub fn order(&self, block: usize) -> Option<usize> {
+        self.order[block]
+    }
+
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.order[block].is_some()
+    }
+}
+
+#[cfg(test)]
+mod cfg_tests {
+    use super::*;
+    use crate::analysis::analysis_tests::function;
+
+    #[test]
+    fn edges_and_order() {
+        let f = function(concat!(
+            "  br label %loop\n",
+            "loop:\n",
+            "  br i1 %b, label %body, label %exit\n",
+            "body:\n",
+            "  br label %loop\n",
+            "exit:\n",
+            "  ret void\n",
+            "dead:\n",
+            "  br i1 %b, label %exit, label %exit\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        assert_eq!(graph.len(), 5);
+        assert_eq!(graph.label(0), None);
+        assert_eq!(graph.block("body"), Some(2));
+        assert_eq!(graph.successors(1), &[2, 3]);
+        assert_eq!(graph.successors(4), &[3]);
+        assert_eq!(graph.predecessors(1), &[0, 2]);
+        assert_eq!(graph.predecessors(3), &[1, 4]);
+        assert_eq!(graph.reverse_post_order(), &[0, 1, 3, 2]);
+        assert!(!graph.is_reachable(4));
+    }
+}
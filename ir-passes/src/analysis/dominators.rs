@@ -0,0 +1,212 @@
+//! Dominance: a block `a` dominates a block `b` when every path from the
+//! entry to `b` goes through `a`.
+//!
+//! The dominator tree is computed with the iterative algorithm of Cooper,
+//! Harvey and Kennedy, "A Simple, Fast Dominance Algorithm", which also gives
+//! the dominance frontiers. Blocks that cannot be reached have no dominators
+//! and are not in the tree.
+
+use std::collections::BTreeSet;
+
+use super::Graph;
+
+/// The tree of immediate dominators of a function.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DominatorTree {
+    /// The immediate dominator of each block. The entry is its own
+    /// immediate dominator, and blocks that cannot be reached have none.
+    idoms: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+}
+
+impl DominatorTree {
+    pub fn new(graph: &Graph) -> Self {
+        let mut idoms = vec![None; graph.len()];
+        idoms[0] = Some(0);
+
+        // The common dominator of `a` and `b`, found by walking up the tree
+        // from the later one in reverse post-order
+        let intersect = |idoms: &[Option<usize>], mut a: usize, mut b: usize| {
+            let order = |block| graph.order(block).expect("processed blocks are reachable");
+            while a != b {
+                while order(a) > order(b) {
+                    a = idoms[a].expect("processed blocks have dominators");
+                }
+                while order(b) > order(a) {
+                    b = idoms[b].expect("processed blocks have dominators");
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &graph.reverse_post_order()[1..] {
+                let mut processed = graph
+                    .predecessors(block)
+                    .iter()
+                    .copied()
+                    .filter(|&p| idoms[p].is_some());
+                let first = processed
+                    .next()
+                    .expect("a reachable block has a predecessor before it");
+                let idom = processed.fold(first, |idom, p| intersect(&idoms, p, idom));
+                if idoms[block] != Some(idom) {
+                    idoms[block] = Some(idom);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![vec![]; graph.len()];
+        for &block in &graph.reverse_post_order()[1..] {
+            children[idoms[block].unwrap()].push(block);
+        }
+        DominatorTree { idoms, children }
+    }
+
+    /// The immediate dominator of `block`, which is `None` for the entry and
+    /// the blocks that cannot be reached.
+    pub fn idom(&self, block: usize) -> Option<usize> {
+        match self.idoms[block] {
+            Some(idom) if idom != block => Some(idom),
+            _ => None,
+        }
+    }
+
+    /// The blocks immediately dominated by `block`, in reverse post-order.
+    pub fn children(&self, block: usize) -> &[usize] {
+        &self.children[block]
+    }
+
+    /// Whether `a` dominates `b`. Every block dominates itself.
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        if self.idoms[b].is_none() {
+            return false;
+        }
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom(b) {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+
+    /// The blocks of the tree, each before the blocks it dominates.
+    pub fn pre_order(&self) -> Vec<usize> {
+        let mut order = vec![];
+        let mut stack = vec![0];
+        while let Some(block) = stack.pop() {
+            order.push(block);
+            stack.extend(self.children[block].iter().rev());
+        }
+        order
+    }
+}
+
+/// The dominance frontier of each block: the blocks where its dominance
+/// ends, which are the blocks that it does not strictly dominate but which
+/// have a predecessor that it dominates.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DominanceFrontiers(Vec<BTreeSet<usize>>);
+
+impl DominanceFrontiers {
+    pub fn new(graph: &Graph, tree: &DominatorTree) -> Self {
+        let mut frontiers = vec![BTreeSet::new(); graph.len()];
+        for &block in graph.reverse_post_order() {
+            let predecessors = graph.predecessors(block);
+            if predecessors.len() < 2 {
+                continue;
+            }
+            let idom = tree.idoms[block].unwrap();
+            for &p in predecessors.iter().filter(|&&p| graph.is_reachable(p)) {
+                let mut runner = p;
+                while runner != idom {
+                    frontiers[runner].insert(block);
+                    runner = tree.idoms[runner].unwrap();
+                }
+            }
+        }
+        DominanceFrontiers(frontiers)
+    }
+
+    pub fn frontier(&self, block: usize) -> &BTreeSet<usize> {
+        &self.0[block]
+    }
+}
+
+#[cfg(test)]
+mod dominators_tests {
+    use super::*;
+    use crate::analysis::analysis_tests::function;
+
+    /// entry -> a -> (b | c) -> d -> (a | exit)
+    const LOOP: &str = concat!(
+        "  br label %a\n",
+        "a:\n",
+        "  br i1 %x, label %b, label %c\n",
+        "b:\n",
+        "  br label %d\n",
+        "c:\n",
+        "  br label %d\n",
+        "d:\n",
+        "  br i1 %x, label %a, label %exit\n",
+        "exit:\n",
+        "  ret void\n",
+        "dead:\n",
+        "  br label %d\n",
+    );
+
+    #[test]
+    fn dominator_tree() {
+        let graph = Graph::new(&function(LOOP).cfg);
+        let tree = DominatorTree::new(&graph);
+        let idoms: Vec<_> = (0..graph.len()).map(|b| tree.idom(b)).collect();
+        assert_eq!(
+            idoms,
+            vec![None, Some(0), Some(1), Some(1), Some(1), Some(4), None]
+        );
+        assert_eq!(tree.children(1), &[3, 2, 4]);
+        assert!(tree.dominates(1, 5));
+        assert!(tree.dominates(4, 4));
+        assert!(!tree.dominates(2, 4));
+        assert!(!tree.dominates(0, 6));
+        assert_eq!(tree.pre_order(), vec![0, 1, 3, 2, 4, 5]);
+    }
+
+    #[test]
+    fn dominance_frontiers() {
+        let graph = Graph::new(&function(LOOP).cfg);
+        let frontiers = DominanceFrontiers::new(&graph, &DominatorTree::new(&graph));
+        let frontiers: Vec<Vec<usize>> = (0..graph.len())
+            .map(|b| frontiers.frontier(b).iter().copied().collect())
+            .collect();
+        assert_eq!(
+            frontiers,
+            vec![vec![], vec![1], vec![4], vec![4], vec![1], vec![], vec![]]
+        );
+    }
+
+    #[test]
+    fn irreducible() {
+        // Both b and c can be entered first, so neither dominates the other
+        let f = function(concat!(
+            "  br i1 %x, label %b, label %c\n",
+            "b:\n",
+            "  br label %c\n",
+            "c:\n",
+            "  br i1 %x, label %b, label %exit\n",
+            "exit:\n",
+            "  ret void\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        let tree = DominatorTree::new(&graph);
+        assert_eq!(tree.idom(1), Some(0));
+        assert_eq!(tree.idom(2), Some(0));
+        assert_eq!(tree.idom(3), Some(2));
+    }
+}
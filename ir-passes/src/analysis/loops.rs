@@ -0,0 +1,175 @@
+//! Natural loops and how they nest.
+//!
+//! A back edge is an edge from a block to one of its dominators, the header
+//! of the loop. The natural loop of a header is the header with every block
+//! that can reach one of its back edges without going through the header.
+//! Two natural loops are either disjoint or one is nested in the other, so
+//! the loops of a function form a forest. Cycles without a dominating header,
+//! which only come from irreducible control flow, are not loops.
+
+use std::collections::BTreeSet;
+
+use super::{DominatorTree, Graph};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Loop {
+    pub header: usize,
+    /// The blocks of the loop, including the header and those of the loops
+    /// nested in it
+    pub blocks: BTreeSet<usize>,
+    /// The blocks with a back edge to the header
+    pub latches: Vec<usize>,
+    /// The index of the innermost loop containing this one
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// The number of loops containing this one, including itself
+    pub depth: usize,
+}
+
+/// The natural loops of a function, indexed in reverse post-order of their
+/// headers, so that a loop comes before the loops nested in it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LoopForest {
+    loops: Vec<Loop>,
+    /// The index of the innermost loop containing each block
+    innermost: Vec<Option<usize>>,
+}
+
+impl LoopForest {
+    pub fn new(graph: &Graph, tree: &DominatorTree) -> Self {
+        let mut loops: Vec<Loop> = vec![];
+        for &header in graph.reverse_post_order() {
+            let latches: Vec<usize> = graph
+                .predecessors(header)
+                .iter()
+                .copied()
+                .filter(|&p| tree.dominates(header, p))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            let mut blocks = BTreeSet::from([header]);
+            let mut work = latches.clone();
+            while let Some(block) = work.pop() {
+                if graph.is_reachable(block) && blocks.insert(block) {
+                    work.extend(graph.predecessors(block));
+                }
+            }
+            loops.push(Loop {
+                header,
+                blocks,
+                latches,
+                parent: None,
+                children: vec![],
+                depth: 1,
+            });
+        }
+
+        // A loop is nested in the loops containing its header, which come
+        // before it, and its parent is the last of them
+        let mut innermost = vec![None; graph.len()];
+        for i in 0..loops.len() {
+            if let Some(parent) = innermost[loops[i].header] {
+                loops[i].parent = Some(parent);
+                loops[i].depth = loops[parent].depth + 1;
+                loops[parent].children.push(i);
+            }
+            for &block in &loops[i].blocks {
+                innermost[block] = Some(i);
+            }
+        }
+        LoopForest { loops, innermost }
+    }
+
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    /// The loops that are not nested in any other.
+    pub fn roots(&self) -> impl Iterator<Item = &Loop> {
+        self.loops.iter().filter(|l| l.parent.is_none())
+    }
+
+    /// The innermost loop containing `block`.
+    pub fn innermost(&self, block: usize) -> Option<&Loop> {
+        self.innermost[block].map(|i| &self.loops[i])
+    }
+
+    /// The number of loops containing `block`.
+    pub fn depth(&self, block: usize) -> usize {
+        self.innermost(block).map_or(0, |l| l.depth)
+    }
+}
+
+#[cfg(test)]
+mod loops_tests {
+    use super::*;
+    use crate::analysis::analysis_tests::function;
+
+    #[test]
+    fn nested_loops() {
+        // outer: entry -> o -> i -> (i | l) -> (o | exit)
+        let f = function(concat!(
+            "  br label %o\n",
+            "o:\n",
+            "  br label %i\n",
+            "i:\n",
+            "  br i1 %x, label %i, label %l\n",
+            "l:\n",
+            "  br i1 %x, label %o, label %exit\n",
+            "exit:\n",
+            "  ret void\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        let forest = LoopForest::new(&graph, &DominatorTree::new(&graph));
+        let loops = forest.loops();
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].header, 1);
+        assert_eq!(loops[0].blocks, BTreeSet::from([1, 2, 3]));
+        assert_eq!(loops[0].latches, vec![3]);
+        assert_eq!(loops[0].children, vec![1]);
+        assert_eq!(loops[1].header, 2);
+        assert_eq!(loops[1].blocks, BTreeSet::from([2]));
+        assert_eq!(loops[1].parent, Some(0));
+        assert_eq!(forest.roots().count(), 1);
+        let depths: Vec<_> = (0..graph.len()).map(|b| forest.depth(b)).collect();
+        assert_eq!(depths, vec![0, 1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn shared_header() {
+        // Both back edges of h make a single loop
+        let f = function(concat!(
+            "  br label %h\n",
+            "h:\n",
+            "  br i1 %x, label %a, label %b\n",
+            "a:\n",
+            "  br label %h\n",
+            "b:\n",
+            "  br i1 %x, label %h, label %exit\n",
+            "exit:\n",
+            "  ret void\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        let forest = LoopForest::new(&graph, &DominatorTree::new(&graph));
+        assert_eq!(forest.loops().len(), 1);
+        assert_eq!(forest.loops()[0].blocks, BTreeSet::from([1, 2, 3]));
+        assert_eq!(forest.loops()[0].latches, vec![2, 3]);
+    }
+
+    #[test]
+    fn irreducible_cycles_are_not_loops() {
+        let f = function(concat!(
+            "  br i1 %x, label %b, label %c\n",
+            "b:\n",
+            "  br label %c\n",
+            "c:\n",
+            "  br i1 %x, label %b, label %exit\n",
+            "exit:\n",
+            "  ret void\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        let forest = LoopForest::new(&graph, &DominatorTree::new(&graph));
+        assert!(forest.loops().is_empty());
+    }
+}
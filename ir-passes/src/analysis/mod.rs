@@ -0,0 +1,122 @@
+//! Analyses of the control flow graphs of functions, shared by the passes.
+//!
+//! Blocks are referred to by their number in the [`Graph`] of the function.
+//! The analyses of a function are computed when they are first needed and
+//! kept in its [`FunctionAnalyses`] until a pass reports that it changed
+//! what they depend on.
+
+use std::rc::Rc;
+
+use llvmlite::FunctionDecl;
+
+mod cfg;
+pub use cfg::{block, block_mut, Graph};
+
+mod dominators;
+pub use dominators::{DominanceFrontiers, DominatorTree};
+
+mod loops;
+pub use loops::{Loop, LoopForest};
+
+/// What a pass changed in a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Changes {
+    Nothing,
+    /// Instructions were changed, but every block kept its terminator
+    Instructions,
+    /// Blocks or terminators were changed
+    ControlFlow,
+}
+
+/// The cached analyses of a function.
+#[derive(Debug, Default)]
+pub struct FunctionAnalyses {
+    graph: Option<Rc<Graph>>,
+    dominators: Option<Rc<DominatorTree>>,
+    frontiers: Option<Rc<DominanceFrontiers>>,
+    loops: Option<Rc<LoopForest>>,
+}
+
+impl FunctionAnalyses {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn graph(&mut self, function: &FunctionDecl) -> Rc<Graph> {
+        self.graph
+            .get_or_insert_with(|| Rc::new(Graph::new(&function.cfg)))
+            .clone()
+    }
+
+    pub fn dominators(&mut self, function: &FunctionDecl) -> Rc<DominatorTree> {
+        if let Some(dominators) = &self.dominators {
+            return dominators.clone();
+        }
+        let dominators = Rc::new(DominatorTree::new(&self.graph(function)));
+        self.dominators.insert(dominators).clone()
+    }
+
+    pub fn frontiers(&mut self, function: &FunctionDecl) -> Rc<DominanceFrontiers> {
+        if let Some(frontiers) = &self.frontiers {
+            return frontiers.clone();
+        }
+        let graph = self.graph(function);
+        let frontiers = DominanceFrontiers::new(&graph, &self.dominators(function));
+        self.frontiers.insert(Rc::new(frontiers)).clone()
+    }
+
+    pub fn loops(&mut self, function: &FunctionDecl) -> Rc<LoopForest> {
+        if let Some(loops) = &self.loops {
+            return loops.clone();
+        }
+        let graph = self.graph(function);
+        let loops = LoopForest::new(&graph, &self.dominators(function));
+        self.loops.insert(Rc::new(loops)).clone()
+    }
+
+    /// Forget the analyses made out of date by `changes`. The analyses of
+    /// the control flow graph only depend on the terminators.
+    pub fn invalidate(&mut self, changes: Changes) {
+        if changes == Changes::ControlFlow {
+            *self = Self::new();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod analysis_tests {
+    use super::*;
+    use llvmlite::parse_program;
+    use oat_symbol::create_session_if_not_set_then;
+
+    /// The function `@f` of the program `source`.
+    pub(crate) fn parse_function(source: &str) -> FunctionDecl {
+        create_session_if_not_set_then(|_| {
+            let mut program = parse_program(source).expect("test program should parse");
+            program.functions.swap_remove("f").unwrap()
+        })
+    }
+
+    /// The function `@f(i1 %x, i1 %b)` with the blocks `body`.
+    pub(crate) fn function(body: &str) -> FunctionDecl {
+        parse_function(&format!("define void @f(i1 %x, i1 %b) {{\n{}}}\n", body))
+    }
+
+    #[test]
+    fn cached_until_invalidated() {
+        let mut f = function("  br label %exit\nexit:\n  ret void\n");
+        let mut analyses = FunctionAnalyses::new();
+        let dominators = analyses.dominators(&f);
+        assert!(Rc::ptr_eq(&dominators, &analyses.dominators(&f)));
+        assert!(Rc::ptr_eq(&analyses.graph(&f), &analyses.graph(&f)));
+
+        analyses.invalidate(Changes::Instructions);
+        assert!(Rc::ptr_eq(&dominators, &analyses.dominators(&f)));
+
+        f.cfg.blocks.clear();
+        f.cfg.entry.terminator.1 = llvmlite::Terminator::Ret(llvmlite::Type::Void, None);
+        analyses.invalidate(Changes::ControlFlow);
+        assert_eq!(analyses.graph(&f).len(), 1);
+        assert!(!Rc::ptr_eq(&dominators, &analyses.dominators(&f)));
+    }
+}
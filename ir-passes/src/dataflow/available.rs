@@ -0,0 +1,195 @@
+//! Available expressions: an expression is available at a point when it has
+//! been computed on every path to the point, and would still give the same
+//! value there.
+//!
+//! Arithmetic and comparisons only depend on locals, which are never
+//! redefined, so they stay available once computed. Loads are killed by the
+//! stores and calls that may change the memory they read: a store to one of
+//! the [`local_slots`] only kills the loads of that slot, while other stores
+//! and calls kill the loads of every other pointer.
+
+use std::collections::HashSet;
+
+use llvmlite::{BinaryOperator, Condition, FunctionDecl, Instruction, Operand, Type, Uid};
+
+use super::{local_slots, Dataflow, Direction, Lattice};
+
+/// The value computed by an instruction without side effects.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Expression {
+    Binop(BinaryOperator, Type, Operand, Operand),
+    Icmp(Condition, Type, Operand, Operand),
+    Load(Type, Operand),
+}
+
+impl Expression {
+    /// The expression computed by `instruction`, if it computes one.
+    pub fn of(instruction: &Instruction) -> Option<Self> {
+        Some(match instruction {
+            Instruction::Binop(op, t, left, right) => {
+                Expression::Binop(*op, t.clone(), left.clone(), right.clone())
+            }
+            Instruction::Icmp(condition, t, left, right) => {
+                Expression::Icmp(*condition, t.clone(), left.clone(), right.clone())
+            }
+            Instruction::Load(t, ptr) => Expression::Load(t.clone(), ptr.clone()),
+            _ => return None,
+        })
+    }
+}
+
+/// The expressions available at a point. Joining facts intersects them, and
+/// `None`, the bottom, stands for every expression, since no path has
+/// reached the point yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Available(pub Option<HashSet<Expression>>);
+
+impl Available {
+    pub fn contains(&self, expression: &Expression) -> bool {
+        match &self.0 {
+            Some(expressions) => expressions.contains(expression),
+            None => true,
+        }
+    }
+}
+
+impl Lattice for Available {
+    fn bottom() -> Self {
+        Available(None)
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        match (&mut self.0, &other.0) {
+            (_, None) => false,
+            (None, Some(other)) => {
+                self.0 = Some(other.clone());
+                true
+            }
+            (Some(expressions), Some(other)) => {
+                let before = expressions.len();
+                expressions.retain(|expression| other.contains(expression));
+                expressions.len() != before
+            }
+        }
+    }
+}
+
+pub struct AvailableExpressions {
+    slots: HashSet<Uid>,
+}
+
+impl AvailableExpressions {
+    pub fn new(function: &FunctionDecl) -> Self {
+        AvailableExpressions {
+            slots: local_slots(function),
+        }
+    }
+
+    fn is_slot(&self, ptr: &Operand) -> bool {
+        matches!(ptr, Operand::Id(uid) if self.slots.contains(uid))
+    }
+}
+
+impl Dataflow for AvailableExpressions {
+    type Fact = Available;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> Self::Fact {
+        Available(Some(HashSet::new()))
+    }
+
+    fn transfer(&self, _uid: &Uid, instruction: &Instruction, fact: &mut Self::Fact) {
+        let expressions = match &mut fact.0 {
+            Some(expressions) => expressions,
+            None => return,
+        };
+        match instruction {
+            Instruction::Store(_, _, ptr) if self.is_slot(ptr) => {
+                expressions.retain(|e| !matches!(e, Expression::Load(_, p) if p == ptr));
+            }
+            Instruction::Store(..) | Instruction::Call(..) => {
+                expressions.retain(|e| !matches!(e, Expression::Load(_, p) if !self.is_slot(p)));
+            }
+            instruction => expressions.extend(Expression::of(instruction)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod available_tests {
+    use super::*;
+    use crate::analysis::analysis_tests::parse_function;
+    use crate::analysis::Graph;
+    use crate::dataflow::solve;
+
+    fn id(uid: &str) -> Operand {
+        Operand::Id(uid.to_string())
+    }
+
+    #[test]
+    fn available_on_every_path() {
+        let f = parse_function(concat!(
+            "define void @f(i1 %c, i64 %a, i64* %p) {\n",
+            "  %s = alloca i64\n",
+            "  %sum = add i64 %a, 1\n",
+            "  %x = load i64, i64* %s\n",
+            "  %y = load i64, i64* %p\n",
+            "  br i1 %c, label %then, label %merge\n",
+            "then:\n",
+            "  %product = mul i64 %a, %a\n",
+            "  store i64 0, i64* %p\n",
+            "  br label %merge\n",
+            "merge:\n",
+            "  store i64 %sum, i64* %s\n",
+            "  ret void\n",
+            "}\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        let analysis = AvailableExpressions::new(&f);
+        let solution = solve(&analysis, &f, &graph);
+
+        let sum = Expression::Binop(BinaryOperator::Add, Type::I64, id("a"), Operand::Const(1));
+        let product = Expression::Binop(BinaryOperator::Mul, Type::I64, id("a"), id("a"));
+        let ptr = Type::Ptr(Box::new(Type::I64));
+        let x = Expression::Load(ptr.clone(), id("s"));
+        let y = Expression::Load(ptr, id("p"));
+
+        let then = solution.after(1);
+        assert!(then.contains(&sum) && then.contains(&product) && then.contains(&x));
+        assert!(!then.contains(&y));
+
+        // The product is only computed on one path, and the store in `then`
+        // may have changed what %p points to
+        let merge = solution.before(2);
+        assert_eq!(
+            merge,
+            &Available(Some(HashSet::from([sum.clone(), x.clone()])))
+        );
+
+        // Storing to the slot only kills its own loads
+        assert_eq!(solution.after(2), &Available(Some(HashSet::from([sum]))));
+    }
+
+    #[test]
+    fn loops() {
+        // The expression computed in the loop is not available at its header,
+        // which is also reached from the entry
+        let f = parse_function(concat!(
+            "define void @f(i1 %c, i64 %a) {\n",
+            "  br label %loop\n",
+            "loop:\n",
+            "  br i1 %c, label %body, label %exit\n",
+            "body:\n",
+            "  %b = add i64 %a, %a\n",
+            "  br label %loop\n",
+            "exit:\n",
+            "  ret void\n",
+            "}\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        let solution = solve(&AvailableExpressions::new(&f), &f, &graph);
+        assert_eq!(solution.before(1), &Available(Some(HashSet::new())));
+        assert_eq!(solution.before(3), &Available(Some(HashSet::new())));
+    }
+}
@@ -0,0 +1,84 @@
+//! Live locals: a local is live at a point when its value may be used after
+//! it, before the local is defined again.
+
+use std::collections::BTreeSet;
+
+use llvmlite::{Instruction, Operand, Terminator, Uid};
+
+use super::{Dataflow, Direction};
+
+fn uses(operands: Vec<&Operand>, fact: &mut BTreeSet<Uid>) {
+    for op in operands {
+        if let Operand::Id(uid) = op {
+            fact.insert(uid.clone());
+        }
+    }
+}
+
+pub struct Liveness;
+
+impl Dataflow for Liveness {
+    type Fact = BTreeSet<Uid>;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn transfer(&self, uid: &Uid, instruction: &Instruction, fact: &mut Self::Fact) {
+        fact.remove(uid);
+        uses(instruction.operands(), fact);
+    }
+
+    fn transfer_terminator(&self, terminator: &Terminator, fact: &mut Self::Fact) {
+        uses(terminator.operands(), fact);
+    }
+}
+
+#[cfg(test)]
+mod liveness_tests {
+    use super::*;
+    use crate::analysis::analysis_tests::parse_function;
+    use crate::analysis::Graph;
+    use crate::dataflow::solve;
+
+    fn set(uids: &[&str]) -> BTreeSet<Uid> {
+        uids.iter().map(|uid| uid.to_string()).collect()
+    }
+
+    #[test]
+    fn live_across_loop() {
+        let f = parse_function(concat!(
+            "define i64 @f(i64 %n, i64 %k) {\n",
+            "  %one = add i64 0, 1\n",
+            "  br label %loop\n",
+            "loop:\n",
+            "  %c = icmp slt i64 %n, %k\n",
+            "  br i1 %c, label %body, label %exit\n",
+            "body:\n",
+            "  %m = add i64 %n, %one\n",
+            "  br label %loop\n",
+            "exit:\n",
+            "  ret i64 %n\n",
+            "}\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        let solution = solve(&Liveness, &f, &graph);
+        assert_eq!(solution.before(0), &set(&["k", "n"]));
+        assert_eq!(solution.after(0), &set(&["k", "n", "one"]));
+        // %one stays live around the loop, since the body uses it
+        assert_eq!(solution.before(1), &set(&["k", "n", "one"]));
+        assert_eq!(solution.before(2), &set(&["k", "n", "one"]));
+        assert_eq!(solution.before(3), &set(&["n"]));
+        assert_eq!(solution.after(3), &set(&[]));
+        assert_eq!(
+            solution.points(&Liveness, &f, 1),
+            vec![
+                set(&["k", "n", "one"]),
+                set(&["c", "k", "n", "one"]),
+                set(&["k", "n", "one"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Dataflow analyses of functions, solved with a worklist.
+//!
+//! An analysis describes the facts it computes as a [`Lattice`], the
+//! direction in which they flow, and how each instruction transforms them.
+//! [`solve`] then finds the facts at the start and end of every block,
+//! joining the facts of the edges into a block for forward analyses, or out
+//! of a block for backward ones. Blocks that cannot be reached keep the
+//! bottom of the lattice.
+
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::hash::Hash;
+
+use llvmlite::{FunctionDecl, Instruction, Operand, Terminator, Uid};
+
+use crate::analysis::{block, Graph};
+
+mod available;
+pub use available::{Available, AvailableExpressions, Expression};
+
+mod liveness;
+pub use liveness::Liveness;
+
+mod reaching;
+pub use reaching::{Definition, ReachingDefinitions};
+
+/// The facts of an analysis, ordered from the least informed.
+pub trait Lattice: Clone + PartialEq {
+    /// The fact of a point that no path reaches, which is left unchanged by
+    /// joining it with any other fact.
+    fn bottom() -> Self;
+
+    /// Join `other` into `self`, returning whether `self` changed.
+    fn join(&mut self, other: &Self) -> bool;
+}
+
+/// Sets joined by union, as for analyses of what may hold.
+impl<T: Ord + Clone> Lattice for BTreeSet<T> {
+    fn bottom() -> Self {
+        BTreeSet::new()
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        let before = self.len();
+        self.extend(other.iter().cloned());
+        self.len() != before
+    }
+}
+
+impl<T: Hash + Eq + Clone> Lattice for HashSet<T> {
+    fn bottom() -> Self {
+        HashSet::new()
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        let before = self.len();
+        self.extend(other.iter().cloned());
+        self.len() != before
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Facts flow from the entry along the edges
+    Forward,
+    /// Facts flow from the returns against the edges
+    Backward,
+}
+
+pub trait Dataflow {
+    type Fact: Lattice;
+
+    const DIRECTION: Direction;
+
+    /// The fact at the start of the entry block of a forward analysis, or at
+    /// the end of the returning blocks of a backward analysis.
+    fn boundary(&self) -> Self::Fact;
+
+    /// Transform the fact before `instruction`, defining `uid`, into the fact
+    /// after it, or the other way around for a backward analysis.
+    fn transfer(&self, uid: &Uid, instruction: &Instruction, fact: &mut Self::Fact);
+
+    fn transfer_terminator(&self, _terminator: &Terminator, _fact: &mut Self::Fact) {}
+}
+
+/// The facts at the start and end of every block, numbered as in the
+/// [`Graph`] of the function.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Solution<F> {
+    before: Vec<F>,
+    after: Vec<F>,
+}
+
+impl<F: Lattice> Solution<F> {
+    /// The fact at the start of `block`.
+    pub fn before(&self, block: usize) -> &F {
+        &self.before[block]
+    }
+
+    /// The fact at the end of `block`.
+    pub fn after(&self, block: usize) -> &F {
+        &self.after[block]
+    }
+
+    /// The facts at every point of `block`: the fact before each of its
+    /// instructions, then before its terminator, then after it.
+    pub fn points<A>(&self, analysis: &A, function: &FunctionDecl, block: usize) -> Vec<F>
+    where
+        A: Dataflow<Fact = F>,
+    {
+        let code = self::block(&function.cfg, block);
+        match A::DIRECTION {
+            Direction::Forward => {
+                let mut fact = self.before[block].clone();
+                let mut points = vec![fact.clone()];
+                for (uid, instruction) in &code.instructions {
+                    analysis.transfer(uid, instruction, &mut fact);
+                    points.push(fact.clone());
+                }
+                analysis.transfer_terminator(&code.terminator.1, &mut fact);
+                points.push(fact);
+                points
+            }
+            Direction::Backward => {
+                let mut fact = self.after[block].clone();
+                let mut points = vec![fact.clone()];
+                analysis.transfer_terminator(&code.terminator.1, &mut fact);
+                points.push(fact.clone());
+                for (uid, instruction) in code.instructions.iter().rev() {
+                    analysis.transfer(uid, instruction, &mut fact);
+                    points.push(fact.clone());
+                }
+                points.reverse();
+                points
+            }
+        }
+    }
+}
+
+/// The fact at the other end of `block` from `fact`, in the direction of the
+/// analysis.
+fn transfer_block<A: Dataflow>(
+    analysis: &A,
+    function: &FunctionDecl,
+    block: usize,
+    mut fact: A::Fact,
+) -> A::Fact {
+    let code = self::block(&function.cfg, block);
+    match A::DIRECTION {
+        Direction::Forward => {
+            for (uid, instruction) in &code.instructions {
+                analysis.transfer(uid, instruction, &mut fact);
+            }
+            analysis.transfer_terminator(&code.terminator.1, &mut fact);
+        }
+        Direction::Backward => {
+            analysis.transfer_terminator(&code.terminator.1, &mut fact);
+            for (uid, instruction) in code.instructions.iter().rev() {
+                analysis.transfer(uid, instruction, &mut fact);
+            }
+        }
+    }
+    fact
+}
+
+/// Find the least facts satisfying `analysis` in `function`, whose graph is
+/// `graph`.
+pub fn solve<A: Dataflow>(
+    analysis: &A,
+    function: &FunctionDecl,
+    graph: &Graph,
+) -> Solution<A::Fact> {
+    let forward = A::DIRECTION == Direction::Forward;
+    // Facts flow from the inputs of a block to its outputs
+    let mut inputs = vec![A::Fact::bottom(); graph.len()];
+    let mut outputs = vec![A::Fact::bottom(); graph.len()];
+
+    // Visiting blocks in the direction of the flow makes most facts final
+    // the first time they are computed
+    let mut order = graph.reverse_post_order().to_vec();
+    if !forward {
+        order.reverse();
+    }
+    let mut queued = vec![false; graph.len()];
+    for &block in &order {
+        queued[block] = true;
+    }
+    let mut worklist: VecDeque<usize> = order.into();
+
+    while let Some(block) = worklist.pop_front() {
+        queued[block] = false;
+        let (sources, targets) = if forward {
+            (graph.predecessors(block), graph.successors(block))
+        } else {
+            (graph.successors(block), graph.predecessors(block))
+        };
+        let mut input = if (forward && block == 0) || (!forward && sources.is_empty()) {
+            analysis.boundary()
+        } else {
+            A::Fact::bottom()
+        };
+        for &source in sources.iter().filter(|&&b| graph.is_reachable(b)) {
+            input.join(&outputs[source]);
+        }
+        let output = transfer_block(analysis, function, block, input.clone());
+        inputs[block] = input;
+        if output != outputs[block] {
+            outputs[block] = output;
+            for &target in targets {
+                if graph.is_reachable(target) && !queued[target] {
+                    queued[target] = true;
+                    worklist.push_back(target);
+                }
+            }
+        }
+    }
+
+    let (before, after) = if forward {
+        (inputs, outputs)
+    } else {
+        (outputs, inputs)
+    };
+    Solution { before, after }
+}
+
+/// The `alloca`s of `function` whose address is only used to load from and
+/// store to it, so that nothing else can read or write the slot.
+pub fn local_slots(function: &FunctionDecl) -> HashSet<Uid> {
+    let blocks = std::iter::once(&function.cfg.entry).chain(function.cfg.blocks.values());
+    let mut slots = HashSet::new();
+    let mut escaped = HashSet::new();
+    for code in blocks {
+        for (uid, instruction) in &code.instructions {
+            let uses = match instruction {
+                Instruction::Alloca(_) => {
+                    slots.insert(uid.clone());
+                    vec![]
+                }
+                Instruction::Load(..) => vec![],
+                Instruction::Store(_, value, _) => vec![value],
+                instruction => instruction.operands(),
+            };
+            for op in uses {
+                if let Operand::Id(id) = op {
+                    escaped.insert(id.clone());
+                }
+            }
+        }
+        for op in code.terminator.1.operands() {
+            if let Operand::Id(id) = op {
+                escaped.insert(id.clone());
+            }
+        }
+    }
+    &slots - &escaped
+}
+
+#[cfg(test)]
+mod dataflow_tests {
+    use super::*;
+    use crate::analysis::analysis_tests::parse_function;
+
+    #[test]
+    fn escaping_slots() {
+        let f = parse_function(concat!(
+            "define void @f() {\n",
+            "  %a = alloca i64\n",
+            "  %b = alloca i64\n",
+            "  %c = alloca i64*\n",
+            "  %d = alloca i64\n",
+            "  store i64 1, i64* %a\n",
+            "  %x = load i64, i64* %a\n",
+            "  store i64* %b, i64** %c\n",
+            "  call void @g(i64* %d)\n",
+            "  ret void\n",
+            "}\n",
+            "declare void @g(i64*)\n",
+        ));
+        assert_eq!(
+            local_slots(&f),
+            HashSet::from(["a".to_string(), "c".to_string()])
+        );
+    }
+}
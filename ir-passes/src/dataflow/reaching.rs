@@ -0,0 +1,132 @@
+//! Reaching definitions of the local slots of a function.
+//!
+//! LLVMLite locals are only defined once, so the definitions that can vary
+//! along a path are the stores to the `alloca`s holding the variables of the
+//! source program. Only the [`local_slots`] are tracked, since a slot whose
+//! address escapes can be written through other pointers. The `alloca` of a
+//! slot counts as its first definition, so that a load reached by it may read
+//! the slot before anything is stored in it.
+
+use std::collections::{BTreeSet, HashSet};
+
+use llvmlite::{FunctionDecl, Instruction, Operand, Uid};
+
+use super::{local_slots, Dataflow, Direction};
+
+/// A definition of `slot` by the instruction `at`, which is either the
+/// `alloca` of the slot or a store to it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Definition {
+    pub slot: Uid,
+    pub at: Uid,
+}
+
+pub struct ReachingDefinitions {
+    slots: HashSet<Uid>,
+}
+
+impl ReachingDefinitions {
+    pub fn new(function: &FunctionDecl) -> Self {
+        ReachingDefinitions {
+            slots: local_slots(function),
+        }
+    }
+
+    /// The definitions in `fact` of `slot`.
+    pub fn of<'a>(
+        fact: &'a BTreeSet<Definition>,
+        slot: &'a str,
+    ) -> impl Iterator<Item = &'a Definition> {
+        fact.iter()
+            .filter(move |definition| definition.slot == slot)
+    }
+}
+
+impl Dataflow for ReachingDefinitions {
+    type Fact = BTreeSet<Definition>;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn transfer(&self, uid: &Uid, instruction: &Instruction, fact: &mut Self::Fact) {
+        let slot = match instruction {
+            Instruction::Alloca(_) => uid,
+            Instruction::Store(_, _, Operand::Id(slot)) => slot,
+            _ => return,
+        };
+        if !self.slots.contains(slot) {
+            return;
+        }
+        fact.retain(|definition| &definition.slot != slot);
+        fact.insert(Definition {
+            slot: slot.clone(),
+            at: uid.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod reaching_tests {
+    use super::*;
+    use crate::analysis::analysis_tests::parse_function;
+    use crate::analysis::Graph;
+    use crate::dataflow::solve;
+
+    fn definitions(pairs: &[(&str, &str)]) -> BTreeSet<Definition> {
+        pairs
+            .iter()
+            .map(|(slot, at)| Definition {
+                slot: slot.to_string(),
+                at: at.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stores_reach_merges() {
+        let f = parse_function(concat!(
+            "define i64 @f(i1 %c) {\n",
+            "  %x = alloca i64\n",
+            "  %y = alloca i64\n",
+            "  %z = alloca i64\n",
+            "  call void @g(i64* %z)\n",
+            "  store i64 0, i64* %x\n",
+            "  br i1 %c, label %then, label %merge\n",
+            "then:\n",
+            "  store i64 1, i64* %x\n",
+            "  store i64 2, i64* %y\n",
+            "  store i64 3, i64* %z\n",
+            "  br label %merge\n",
+            "merge:\n",
+            "  %v = load i64, i64* %x\n",
+            "  ret i64 %v\n",
+            "}\n",
+            "declare void @g(i64*)\n",
+        ));
+        let graph = Graph::new(&f.cfg);
+        let analysis = ReachingDefinitions::new(&f);
+        let solution = solve(&analysis, &f, &graph);
+        assert_eq!(
+            solution.after(0),
+            &definitions(&[("x", "_store1"), ("y", "y")])
+        );
+        assert_eq!(
+            solution.after(1),
+            &definitions(&[("x", "_store3"), ("y", "_store4")])
+        );
+        let merge = solution.before(2);
+        assert_eq!(
+            merge,
+            &definitions(&[
+                ("x", "_store1"),
+                ("x", "_store3"),
+                ("y", "_store4"),
+                ("y", "y")
+            ])
+        );
+        assert_eq!(ReachingDefinitions::of(merge, "x").count(), 2);
+    }
+}
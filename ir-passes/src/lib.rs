@@ -0,0 +1,147 @@
+//! Optimizations of LLVMLite programs, run between lowering and the backends.
+//!
+//! Passes transform one function at a time, and report what they changed so
+//! that the [`analysis`] results they did not affect can be reused by the
+//! passes that follow. In debug builds, the program is verified after every
+//! pass, so that an invalid program is blamed on the pass that produced it.
+
+use std::collections::HashMap;
+
+use llvmlite::{verify, FunctionDecl, Gid, Program};
+
+pub mod analysis;
+pub mod dataflow;
+pub mod mem2reg;
+pub mod sccp;
+use analysis::{Changes, FunctionAnalyses};
+
+/// A transformation of functions.
+pub trait FunctionPass {
+    fn name(&self) -> &'static str;
+
+    /// Transform `function`, returning what was changed.
+    fn run(&mut self, function: &mut FunctionDecl, analyses: &mut FunctionAnalyses) -> Changes;
+}
+
+/// Runs passes over every function of a program, keeping the analyses of
+/// each function between passes.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn FunctionPass>>,
+    analyses: HashMap<Gid, FunctionAnalyses>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, pass: impl FunctionPass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Run every pass in order, returning the most that any pass changed.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if a pass leaves `program` invalid. This must run in
+    /// the session of the program, as verification errors name types.
+    pub fn run(&mut self, program: &mut Program) -> Changes {
+        let mut changed = Changes::Nothing;
+        for pass in &mut self.passes {
+            for (gid, function) in &mut program.functions {
+                let analyses = self.analyses.entry(gid.clone()).or_default();
+                let changes = pass.run(function, analyses);
+                analyses.invalidate(changes);
+                changed = changed.max(changes);
+            }
+            if cfg!(debug_assertions) {
+                if let Err(e) = verify(program) {
+                    panic!("{} produced invalid LLVMLite: {}", pass.name(), e);
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod pass_manager_tests {
+    use super::*;
+    use crate::analysis::analysis_tests::function;
+    use llvmlite::Terminator;
+    use oat_symbol::create_session_if_not_set_then;
+
+    /// Removes the blocks that cannot be reached.
+    struct RemoveUnreachable;
+
+    impl FunctionPass for RemoveUnreachable {
+        fn name(&self) -> &'static str {
+            "remove-unreachable"
+        }
+
+        fn run(&mut self, function: &mut FunctionDecl, analyses: &mut FunctionAnalyses) -> Changes {
+            let graph = analyses.graph(function);
+            let before = function.cfg.blocks.len();
+            let mut index = 0;
+            function.cfg.blocks.retain(|_, _| {
+                index += 1;
+                graph.is_reachable(index)
+            });
+            if function.cfg.blocks.len() == before {
+                Changes::Nothing
+            } else {
+                Changes::ControlFlow
+            }
+        }
+    }
+
+    #[test]
+    fn invalidates_changed_functions() {
+        let mut program = Program::default();
+        program.functions.insert(
+            "f".into(),
+            function("  ret void\ndead:\n  br label %dead\n"),
+        );
+        let mut manager = PassManager::new();
+        manager.add(RemoveUnreachable);
+        assert_eq!(manager.run(&mut program), Changes::ControlFlow);
+        assert!(program.functions["f"].cfg.blocks.is_empty());
+        assert_eq!(manager.run(&mut program), Changes::Nothing);
+    }
+
+    /// Branches out of the entry block to a label that does not exist.
+    struct BreakToNowhere;
+
+    impl FunctionPass for BreakToNowhere {
+        fn name(&self) -> &'static str {
+            "break-to-nowhere"
+        }
+
+        fn run(&mut self, function: &mut FunctionDecl, _: &mut FunctionAnalyses) -> Changes {
+            function.cfg.entry.terminator.1 = Terminator::Break("nowhere".to_string());
+            Changes::ControlFlow
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        debug_assertions,
+        should_panic(expected = "break-to-nowhere produced invalid LLVMLite")
+    )]
+    fn verifies_after_each_pass() {
+        create_session_if_not_set_then(|_| {
+            let mut program = Program::default();
+            program.functions.insert(
+                "f".into(),
+                function(
+                    "  ret void
+",
+                ),
+            );
+            let mut manager = PassManager::new();
+            manager.add(BreakToNowhere);
+            manager.run(&mut program);
+        })
+    }
+}
@@ -0,0 +1,394 @@
+//! Promotion of local slots to SSA values.
+//!
+//! Oat locals are lowered to an `alloca` that is stored to when the local is
+//! assigned and loaded from when it is read. The [`local_slots`] of integer
+//! or pointer type are promoted: every load is replaced by the value last
+//! stored to the slot, and phi nodes pick between the values stored along
+//! different paths. Phi nodes are placed at the iterated dominance frontiers
+//! of the blocks storing to a slot, as in Cytron et al., "Efficiently
+//! Computing Static Single Assignment Form and the Control Dependence
+//! Graph", and only kept when their value is used.
+//!
+//! A load that no store reaches reads 0, or null for pointers.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use llvmlite::{FunctionDecl, Instruction, Label, Operand, Type, Uid, ENTRY_LABEL};
+
+use crate::analysis::{block, block_mut, Changes, FunctionAnalyses, Graph};
+use crate::dataflow::local_slots;
+use crate::FunctionPass;
+
+/// A phi node placed for `slot`, defining `uid`.
+struct Phi {
+    slot: Uid,
+    uid: Uid,
+    incoming: Vec<(Operand, Label)>,
+}
+
+pub struct Mem2Reg;
+
+/// The slots that can be promoted, with the type of their values. Loads
+/// and stores of a slot use the type it was allocated with, since its
+/// address is never cast.
+fn promotable(function: &FunctionDecl) -> BTreeMap<Uid, Type> {
+    let slots = local_slots(function);
+    function
+        .cfg
+        .entry
+        .instructions
+        .iter()
+        .chain(function.cfg.blocks.values().flat_map(|b| &b.instructions))
+        .filter_map(|(uid, instruction)| match instruction {
+            Instruction::Alloca(t @ (Type::I1 | Type::I8 | Type::I64 | Type::Ptr(_)))
+                if slots.contains(uid) =>
+            {
+                Some((uid.clone(), t.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The value of a slot of type `t` before anything is stored to it.
+fn default(t: &Type) -> Operand {
+    match t {
+        Type::Ptr(_) => Operand::Null,
+        _ => Operand::Const(0),
+    }
+}
+
+/// Place the phi nodes for `slots` in the blocks that need them, naming
+/// them after the slot.
+fn place_phis(
+    function: &FunctionDecl,
+    analyses: &mut FunctionAnalyses,
+    slots: &BTreeMap<Uid, Type>,
+) -> Vec<Vec<Phi>> {
+    let graph = analyses.graph(function);
+    let frontiers = analyses.frontiers(function);
+    let mut names: HashSet<Uid> = function.parameters.iter().cloned().collect();
+    for b in 0..graph.len() {
+        names.extend(
+            block(&function.cfg, b)
+                .instructions
+                .iter()
+                .map(|(uid, _)| uid.clone()),
+        );
+    }
+
+    let mut phis: Vec<Vec<Phi>> = (0..graph.len()).map(|_| vec![]).collect();
+    for slot in slots.keys() {
+        let mut stores: Vec<usize> = (0..graph.len())
+            .filter(|&b| {
+                block(&function.cfg, b).instructions.iter().any(|(_, instruction)| {
+                    matches!(instruction, Instruction::Store(_, _, Operand::Id(s)) if s == slot)
+                })
+            })
+            .collect();
+        let mut placed = vec![false; graph.len()];
+        // A phi node is itself a store to the slot, so the frontiers of the
+        // blocks given one also need one
+        while let Some(b) = stores.pop() {
+            for &frontier in frontiers.frontier(b) {
+                if placed[frontier] {
+                    continue;
+                }
+                placed[frontier] = true;
+                stores.push(frontier);
+                let uid = (1..)
+                    .map(|n| format!("{}.{}", slot, n))
+                    .find(|uid| !names.contains(uid))
+                    .unwrap();
+                names.insert(uid.clone());
+                phis[frontier].push(Phi {
+                    slot: slot.clone(),
+                    uid,
+                    incoming: vec![],
+                });
+            }
+        }
+    }
+    phis
+}
+
+/// Remove the loads and stores of `slots` from `b`, recording the value each
+/// load read in `replacements` and leaving the values stored last in
+/// `values`. Then give the phi nodes of the successors of `b` their operands
+/// from it.
+fn rename(
+    function: &mut FunctionDecl,
+    graph: &Graph,
+    b: usize,
+    phis: &mut [Vec<Phi>],
+    values: &mut HashMap<Uid, Operand>,
+    replacements: &mut HashMap<Uid, Operand>,
+) {
+    for phi in &phis[b] {
+        values.insert(phi.slot.clone(), Operand::Id(phi.uid.clone()));
+    }
+    let code = block_mut(&mut function.cfg, b);
+    code.instructions
+        .retain(|(uid, instruction)| match instruction {
+            Instruction::Alloca(_) => !values.contains_key(uid),
+            Instruction::Load(_, Operand::Id(slot)) => match values.get(slot) {
+                Some(value) => {
+                    replacements.insert(uid.clone(), value.clone());
+                    false
+                }
+                None => true,
+            },
+            Instruction::Store(_, value, Operand::Id(slot)) if values.contains_key(slot) => {
+                values.insert(slot.clone(), value.clone());
+                false
+            }
+            _ => true,
+        });
+    let label = graph.label(b).map_or(ENTRY_LABEL, Label::as_str);
+    for &successor in graph.successors(b) {
+        for phi in &mut phis[successor] {
+            let value = values[&phi.slot].clone();
+            phi.incoming.push((value, label.to_string()));
+        }
+    }
+}
+
+/// The operand `op` stands for once the loads are removed.
+fn resolve(op: &mut Operand, replacements: &HashMap<Uid, Operand>) {
+    while let Operand::Id(uid) = op {
+        match replacements.get(uid) {
+            Some(value) => *op = value.clone(),
+            None => break,
+        }
+    }
+}
+
+/// Keep the phi nodes whose value is used, by instructions other than phi
+/// nodes or by the phi nodes that are kept.
+fn remove_dead_phis(function: &FunctionDecl, phis: &mut [Vec<Phi>]) {
+    let operands: HashMap<&str, Vec<&Operand>> = phis
+        .iter()
+        .flatten()
+        .map(|phi| {
+            (
+                phi.uid.as_str(),
+                phi.incoming.iter().map(|(op, _)| op).collect(),
+            )
+        })
+        .collect();
+    let blocks = std::iter::once(&function.cfg.entry).chain(function.cfg.blocks.values());
+    let mut used: Vec<&Operand> = vec![];
+    for code in blocks {
+        for (_, instruction) in &code.instructions {
+            used.extend(instruction.operands());
+        }
+        used.extend(code.terminator.1.operands());
+    }
+    let mut live = HashSet::new();
+    while let Some(op) = used.pop() {
+        if let Operand::Id(uid) = op {
+            if let Some(incoming) = operands.get(uid.as_str()) {
+                if live.insert(uid.clone()) {
+                    used.extend(incoming);
+                }
+            }
+        }
+    }
+    for phis in phis {
+        phis.retain(|phi| live.contains(&phi.uid));
+    }
+}
+
+impl FunctionPass for Mem2Reg {
+    fn name(&self) -> &'static str {
+        "mem2reg"
+    }
+
+    fn run(&mut self, function: &mut FunctionDecl, analyses: &mut FunctionAnalyses) -> Changes {
+        let slots = promotable(function);
+        if slots.is_empty() {
+            return Changes::Nothing;
+        }
+        let mut phis = place_phis(function, analyses, &slots);
+        let graph = analyses.graph(function);
+        let dominators = analyses.dominators(function);
+        let defaults: HashMap<Uid, Operand> = slots
+            .iter()
+            .map(|(slot, t)| (slot.clone(), default(t)))
+            .collect();
+
+        // The values stored last in a block are those at the start of the
+        // blocks it immediately dominates
+        let mut replacements = HashMap::new();
+        let mut stack = vec![(0, defaults.clone())];
+        while let Some((b, mut values)) = stack.pop() {
+            rename(
+                function,
+                &graph,
+                b,
+                &mut phis,
+                &mut values,
+                &mut replacements,
+            );
+            for &child in dominators.children(b) {
+                stack.push((child, values.clone()));
+            }
+        }
+        // Blocks that cannot be reached have no phi nodes, and start with
+        // nothing stored
+        for b in (0..graph.len()).filter(|&b| !graph.is_reachable(b)) {
+            let mut values = defaults.clone();
+            rename(
+                function,
+                &graph,
+                b,
+                &mut phis,
+                &mut values,
+                &mut replacements,
+            );
+        }
+
+        for (b, phis) in phis.iter_mut().enumerate() {
+            let code = block_mut(&mut function.cfg, b);
+            for (_, instruction) in &mut code.instructions {
+                for op in instruction.operands_mut() {
+                    resolve(op, &replacements);
+                }
+            }
+            for op in code.terminator.1.operands_mut() {
+                resolve(op, &replacements);
+            }
+            for phi in phis {
+                for (op, _) in &mut phi.incoming {
+                    resolve(op, &replacements);
+                }
+            }
+        }
+        remove_dead_phis(function, &mut phis);
+        for (b, phis) in phis.into_iter().enumerate() {
+            let code = block_mut(&mut function.cfg, b);
+            let nodes = phis.into_iter().map(|phi| {
+                (
+                    phi.uid,
+                    Instruction::Phi(slots[&phi.slot].clone(), phi.incoming),
+                )
+            });
+            code.instructions.splice(0..0, nodes);
+        }
+        Changes::Instructions
+    }
+}
+
+#[cfg(test)]
+mod mem2reg_tests {
+    use super::*;
+    use llvmlite::{parse_program, verify};
+    use oat_symbol::create_session_if_not_set_then;
+
+    /// Promote the slots of `@f` in `source`, returning the program printed.
+    fn promote(source: &str) -> String {
+        create_session_if_not_set_then(|_| {
+            let mut program = parse_program(source).expect("test program should parse");
+            let f = program.functions.get_mut("f").unwrap();
+            Mem2Reg.run(f, &mut FunctionAnalyses::new());
+            verify(&program).expect("promoted program should verify");
+            program.to_string()
+        })
+    }
+
+    #[test]
+    fn straight_line() {
+        let source = concat!(
+            "define i64 @f(i64 %a) {\n",
+            "  %x = alloca i64\n",
+            "  store i64 %a, i64* %x\n",
+            "  %b = load i64, i64* %x\n",
+            "  %c = add i64 %b, 1\n",
+            "  store i64 %c, i64* %x\n",
+            "  %d = load i64, i64* %x\n",
+            "  ret i64 %d\n",
+            "}\n",
+        );
+        assert_eq!(
+            promote(source),
+            concat!(
+                "define i64 @f(i64 %a) {\n",
+                "  %c = add i64 %a, 1\n",
+                "  ret i64 %c\n",
+                "}\n\n",
+            )
+        );
+    }
+
+    #[test]
+    fn loops() {
+        // The loop counter needs a phi node at the header, while the slot
+        // only assigned before the loop does not
+        let source = concat!(
+            "define i64 @f(i64 %n) {\n",
+            "  %i = alloca i64\n",
+            "  %k = alloca i64\n",
+            "  store i64 0, i64* %i\n",
+            "  store i64 2, i64* %k\n",
+            "  br label %loop\n",
+            "loop:\n",
+            "  %i1 = load i64, i64* %i\n",
+            "  %c = icmp slt i64 %i1, %n\n",
+            "  br i1 %c, label %body, label %exit\n",
+            "body:\n",
+            "  %k1 = load i64, i64* %k\n",
+            "  %i2 = add i64 %i1, %k1\n",
+            "  store i64 %i2, i64* %i\n",
+            "  br label %loop\n",
+            "exit:\n",
+            "  %i3 = load i64, i64* %i\n",
+            "  ret i64 %i3\n",
+            "}\n",
+        );
+        assert_eq!(
+            promote(source),
+            concat!(
+                "define i64 @f(i64 %n) {\n",
+                "entry:\n",
+                "  br label %loop\n",
+                "loop:\n",
+                "  %i.1 = phi i64 [ 0, %entry ], [ %i2, %body ]\n",
+                "  %c = icmp slt i64 %i.1, %n\n",
+                "  br i1 %c, label %body, label %exit\n",
+                "body:\n",
+                "  %i2 = add i64 %i.1, 2\n",
+                "  br label %loop\n",
+                "exit:\n",
+                "  ret i64 %i.1\n",
+                "}\n\n",
+            )
+        );
+    }
+
+    #[test]
+    fn uninitialized_and_escaping() {
+        // %p escapes to @g and %s is a struct, so both stay in memory, while
+        // %q is read before it is assigned on one path
+        let source = concat!(
+            "define i64* @f(i1 %c) {\n",
+            "  %p = alloca i64\n",
+            "  %q = alloca i64*\n",
+            "  %s = alloca { i64 }\n",
+            "  call void @g(i64* %p)\n",
+            "  br i1 %c, label %then, label %merge\n",
+            "then:\n",
+            "  store i64* %p, i64** %q\n",
+            "  br label %merge\n",
+            "merge:\n",
+            "  %r = load i64*, i64** %q\n",
+            "  ret i64* %r\n",
+            "}\n",
+            "declare void @g(i64*)\n",
+        );
+        let promoted = promote(source);
+        assert!(promoted.contains("  %p = alloca i64\n"));
+        assert!(promoted.contains("  %s = alloca { i64 }\n"));
+        assert!(promoted.contains("  %q.1 = phi i64* [ null, %entry ], [ %p, %then ]\n"));
+        assert!(!promoted.contains("%q = alloca"));
+    }
+}
@@ -0,0 +1,473 @@
+//! Sparse conditional constant propagation.
+//!
+//! The values of the locals and the blocks that can be executed are found
+//! together, as in Wegman and Zadeck, "Constant Propagation with Conditional
+//! Branches": a block is only executed once a branch to it is, and a phi
+//! node only takes the operands of the edges that are executed. A branch on
+//! a constant condition then only executes one of its edges, so that loops
+//! can keep locals constant.
+//!
+//! Arithmetic and comparisons are folded as the interpreter evaluates them.
+//! Values of pointer type are never known, so that only integers are
+//! replaced by constants.
+
+use std::collections::{HashMap, HashSet};
+
+use llvmlite::{
+    BinaryOperator, Condition, FunctionDecl, Instruction, Label, Operand, Terminator, Type, Uid,
+};
+
+use crate::analysis::{block, block_mut, Changes, FunctionAnalyses};
+use crate::FunctionPass;
+
+/// What is known of the value of a local.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    /// Nothing defining the local has been executed yet
+    Undefined,
+    /// The local is always the constant
+    Constant(i64),
+    /// The local may take several values
+    Overdefined,
+}
+
+impl Value {
+    /// The value known of a local that may be either `self` or `other`.
+    fn meet(self, other: Value) -> Value {
+        match (self, other) {
+            (Value::Undefined, value) | (value, Value::Undefined) => value,
+            (Value::Constant(a), Value::Constant(b)) if a == b => self,
+            _ => Value::Overdefined,
+        }
+    }
+}
+
+/// The representation of `value` as a value of type `t`.
+fn normalize(t: &Type, value: i64) -> i64 {
+    match t {
+        Type::I1 => value & 1,
+        Type::I8 => value as i8 as i64,
+        _ => value,
+    }
+}
+
+/// The signed value of the representation `value` of type `t`.
+fn signed(t: &Type, value: i64) -> i64 {
+    match t {
+        Type::I1 => -(value & 1),
+        _ => value,
+    }
+}
+
+fn fold_binop(op: BinaryOperator, t: &Type, left: i64, right: i64) -> i64 {
+    // Shift amounts are taken modulo 64, as on x86
+    let shift = (right & 63) as u32;
+    let value = match op {
+        BinaryOperator::Add => left.wrapping_add(right),
+        BinaryOperator::Sub => left.wrapping_sub(right),
+        BinaryOperator::Mul => left.wrapping_mul(right),
+        BinaryOperator::Shl => left << shift,
+        BinaryOperator::Lshr => ((left as u64) >> shift) as i64,
+        BinaryOperator::Ashr => left >> shift,
+        BinaryOperator::And => left & right,
+        BinaryOperator::Or => left | right,
+        BinaryOperator::Xor => left ^ right,
+    };
+    normalize(t, value)
+}
+
+fn fold_icmp(condition: Condition, t: &Type, left: i64, right: i64) -> i64 {
+    let (left, right) = (signed(t, left), signed(t, right));
+    let holds = match condition {
+        Condition::Eq => left == right,
+        Condition::Ne => left != right,
+        Condition::Slt => left < right,
+        Condition::Sle => left <= right,
+        Condition::Sgt => left > right,
+        Condition::Sge => left >= right,
+    };
+    holds as i64
+}
+
+fn is_integer(t: &Type) -> bool {
+    matches!(t, Type::I1 | Type::I8 | Type::I64)
+}
+
+/// The number of the block labelled `label`, where the entry block is
+/// labelled [`ENTRY_LABEL`](llvmlite::ENTRY_LABEL) in phi nodes.
+fn index(function: &FunctionDecl, label: &Label) -> usize {
+    function.cfg.blocks.get_index_of(label).map_or(0, |i| i + 1)
+}
+
+/// An instruction of a block, or its terminator.
+#[derive(Debug, Clone, Copy)]
+enum Position {
+    Instruction(usize, usize),
+    Terminator(usize),
+}
+
+/// The values of the locals of a function and the blocks that can be
+/// executed, where blocks are numbered as in its
+/// [`Graph`](crate::analysis::Graph).
+#[derive(Debug)]
+pub struct Constants {
+    values: HashMap<Uid, Value>,
+    executable: Vec<bool>,
+    edges: HashSet<(usize, usize)>,
+}
+
+impl Constants {
+    pub fn new(function: &FunctionDecl, blocks: usize) -> Self {
+        let mut users: HashMap<&str, Vec<Position>> = HashMap::new();
+        for b in 0..blocks {
+            let code = block(&function.cfg, b);
+            for (i, (_, instruction)) in code.instructions.iter().enumerate() {
+                for op in instruction.operands() {
+                    if let Operand::Id(uid) = op {
+                        users
+                            .entry(uid)
+                            .or_default()
+                            .push(Position::Instruction(b, i));
+                    }
+                }
+            }
+            for op in code.terminator.1.operands() {
+                if let Operand::Id(uid) = op {
+                    users.entry(uid).or_default().push(Position::Terminator(b));
+                }
+            }
+        }
+
+        let mut constants = Constants {
+            values: function
+                .parameters
+                .iter()
+                .map(|uid| (uid.clone(), Value::Overdefined))
+                .collect(),
+            executable: vec![false; blocks],
+            edges: HashSet::new(),
+        };
+        // The entry block is executed as if control came from before it
+        let mut edges = vec![(blocks, 0)];
+        let mut changed: Vec<Uid> = vec![];
+        while !edges.is_empty() || !changed.is_empty() {
+            while let Some((from, to)) = edges.pop() {
+                if !constants.edges.insert((from, to)) {
+                    continue;
+                }
+                let code = block(&function.cfg, to);
+                // A block executed before only needs its phi nodes updated
+                let visited = match constants.executable[to] {
+                    true => code
+                        .instructions
+                        .iter()
+                        .take_while(|(_, i)| matches!(i, Instruction::Phi(..)))
+                        .count(),
+                    false => code.instructions.len(),
+                };
+                constants.executable[to] = true;
+                for i in 0..visited {
+                    constants.visit(
+                        function,
+                        Position::Instruction(to, i),
+                        &mut edges,
+                        &mut changed,
+                    );
+                }
+                if visited == code.instructions.len() {
+                    constants.visit(function, Position::Terminator(to), &mut edges, &mut changed);
+                }
+            }
+            if let Some(uid) = changed.pop() {
+                for &position in users.get(uid.as_str()).into_iter().flatten() {
+                    let (Position::Instruction(b, _) | Position::Terminator(b)) = position;
+                    if constants.executable[b] {
+                        constants.visit(function, position, &mut edges, &mut changed);
+                    }
+                }
+            }
+        }
+        constants
+    }
+
+    /// What is known of the value of `op`.
+    pub fn value(&self, op: &Operand) -> Value {
+        match op {
+            Operand::Const(n) => Value::Constant(*n),
+            Operand::Id(uid) => self.values.get(uid).copied().unwrap_or(Value::Undefined),
+            Operand::Null | Operand::Gid(_) => Value::Overdefined,
+        }
+    }
+
+    pub fn is_executable(&self, block: usize) -> bool {
+        self.executable[block]
+    }
+
+    /// Whether control can go from `from` to `to`.
+    pub fn is_executable_edge(&self, from: usize, to: usize) -> bool {
+        self.edges.contains(&(from, to))
+    }
+
+    /// Evaluate what is at `position` again, adding the edges it executes to
+    /// `edges` and the local it defines to `changed` if its value changed.
+    fn visit(
+        &mut self,
+        function: &FunctionDecl,
+        position: Position,
+        edges: &mut Vec<(usize, usize)>,
+        changed: &mut Vec<Uid>,
+    ) {
+        let (b, i) = match position {
+            Position::Instruction(b, i) => (b, i),
+            Position::Terminator(b) => {
+                let targets = match &block(&function.cfg, b).terminator.1 {
+                    Terminator::Ret(..) => vec![],
+                    Terminator::Break(label) => vec![label],
+                    Terminator::CondBreak(op, then, else_) => match self.value(op) {
+                        Value::Undefined => vec![],
+                        Value::Constant(0) => vec![else_],
+                        Value::Constant(_) => vec![then],
+                        Value::Overdefined => vec![then, else_],
+                    },
+                };
+                edges.extend(targets.into_iter().map(|label| (b, index(function, label))));
+                return;
+            }
+        };
+        let (uid, instruction) = &block(&function.cfg, b).instructions[i];
+        let both = |left, right, fold: &dyn Fn(i64, i64) -> i64| match (left, right) {
+            (Value::Constant(left), Value::Constant(right)) => Value::Constant(fold(left, right)),
+            (Value::Overdefined, _) | (_, Value::Overdefined) => Value::Overdefined,
+            _ => Value::Undefined,
+        };
+        let value = match instruction {
+            Instruction::Binop(op, t, left, right) => {
+                both(self.value(left), self.value(right), &|l, r| {
+                    fold_binop(*op, t, l, r)
+                })
+            }
+            Instruction::Icmp(condition, t, left, right) if is_integer(t) => {
+                both(self.value(left), self.value(right), &|l, r| {
+                    fold_icmp(*condition, t, l, r)
+                })
+            }
+            Instruction::Phi(t, incoming) if is_integer(t) => incoming
+                .iter()
+                .filter(|(_, label)| self.is_executable_edge(index(function, label), b))
+                .fold(Value::Undefined, |value, (op, _)| {
+                    value.meet(self.value(op))
+                }),
+            _ => Value::Overdefined,
+        };
+        let old = self.values.get(uid).copied().unwrap_or(Value::Undefined);
+        let new = old.meet(value);
+        if new != old {
+            self.values.insert(uid.clone(), new);
+            changed.push(uid.clone());
+        }
+    }
+}
+
+pub struct Sccp;
+
+impl FunctionPass for Sccp {
+    fn name(&self) -> &'static str {
+        "sccp"
+    }
+
+    fn run(&mut self, function: &mut FunctionDecl, analyses: &mut FunctionAnalyses) -> Changes {
+        let blocks = analyses.graph(function).len();
+        let constants = Constants::new(function, blocks);
+        let mut changes = Changes::Nothing;
+
+        for b in (0..blocks).filter(|&b| constants.is_executable(b)) {
+            let code = block_mut(&mut function.cfg, b);
+            let before = code.instructions.len();
+            code.instructions.retain(|(uid, instruction)| {
+                let pure = matches!(
+                    instruction,
+                    Instruction::Binop(..) | Instruction::Icmp(..) | Instruction::Phi(..)
+                );
+                !(pure && matches!(constants.values.get(uid), Some(Value::Constant(_))))
+            });
+            let operands = code
+                .instructions
+                .iter_mut()
+                .flat_map(|(_, instruction)| instruction.operands_mut())
+                .chain(code.terminator.1.operands_mut());
+            for op in operands {
+                if let (Operand::Id(_), Value::Constant(n)) = (&op, constants.value(op)) {
+                    *op = Operand::Const(n);
+                    changes = changes.max(Changes::Instructions);
+                }
+            }
+            if code.instructions.len() != before {
+                changes = changes.max(Changes::Instructions);
+            }
+        }
+
+        // Branches on constants become jumps, and phi nodes forget the edges
+        // that are never taken
+        for b in (0..blocks).filter(|&b| constants.is_executable(b)) {
+            let code = block_mut(&mut function.cfg, b);
+            if let Terminator::CondBreak(Operand::Const(n), then, else_) = &code.terminator.1 {
+                let target = if *n == 0 { else_ } else { then };
+                code.terminator.1 = Terminator::Break(target.clone());
+                changes = Changes::ControlFlow;
+            }
+        }
+        let labels: HashMap<Label, usize> = function
+            .cfg
+            .blocks
+            .keys()
+            .enumerate()
+            .map(|(i, label)| (label.clone(), i + 1))
+            .collect();
+        for b in (0..blocks).filter(|&b| constants.is_executable(b)) {
+            for (_, instruction) in &mut block_mut(&mut function.cfg, b).instructions {
+                if let Instruction::Phi(_, incoming) = instruction {
+                    incoming.retain(|(_, label)| {
+                        let from = labels.get(label).copied().unwrap_or(0);
+                        constants.is_executable_edge(from, b)
+                    });
+                }
+            }
+        }
+        let mut index = 0;
+        function.cfg.blocks.retain(|_, _| {
+            index += 1;
+            constants.is_executable(index)
+        });
+        if function.cfg.blocks.len() + 1 != blocks {
+            changes = Changes::ControlFlow;
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod sccp_tests {
+    use super::*;
+    use llvmlite::{parse_program, verify};
+    use oat_symbol::create_session_if_not_set_then;
+
+    /// Propagate the constants of `@f` in `source`, returning what changed
+    /// and the program printed.
+    fn propagate(source: &str) -> (Changes, String) {
+        create_session_if_not_set_then(|_| {
+            let mut program = parse_program(source).expect("test program should parse");
+            let f = program.functions.get_mut("f").unwrap();
+            let changes = Sccp.run(f, &mut FunctionAnalyses::new());
+            verify(&program).expect("propagated program should verify");
+            (changes, program.to_string())
+        })
+    }
+
+    #[test]
+    fn straight_line() {
+        let source = concat!(
+            "define i64 @f(i64 %a) {\n",
+            "  %x = add i64 0, 3\n",
+            "  %y = mul i64 %x, 4\n",
+            "  %z = add i64 %y, %a\n",
+            "  %w = shl i8 3, 6\n",
+            "  ret i64 %z\n",
+            "}\n",
+        );
+        assert_eq!(
+            propagate(source),
+            (
+                Changes::Instructions,
+                concat!(
+                    "define i64 @f(i64 %a) {\n",
+                    "  %z = add i64 12, %a\n",
+                    "  ret i64 %z\n",
+                    "}\n\n",
+                )
+                .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn constant_branches() {
+        let source = concat!(
+            "define i64 @f(i64 %a) {\n",
+            "  %c = icmp slt i64 1, 2\n",
+            "  br i1 %c, label %then, label %else\n",
+            "then:\n",
+            "  %t = add i64 %a, 1\n",
+            "  br label %merge\n",
+            "else:\n",
+            "  br label %merge\n",
+            "merge:\n",
+            "  %m = phi i64 [ %t, %then ], [ 7, %else ]\n",
+            "  ret i64 %m\n",
+            "}\n",
+        );
+        assert_eq!(
+            propagate(source),
+            (
+                Changes::ControlFlow,
+                concat!(
+                    "define i64 @f(i64 %a) {\n",
+                    "  br label %then\n",
+                    "then:\n",
+                    "  %t = add i64 %a, 1\n",
+                    "  br label %merge\n",
+                    "merge:\n",
+                    "  %m = phi i64 [ %t, %then ]\n",
+                    "  ret i64 %m\n",
+                    "}\n\n",
+                )
+                .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn constant_in_loop() {
+        // %k is only changed on a path that is never taken while it is 1, so
+        // it stays 1, which propagating without the branches cannot find
+        let source = concat!(
+            "define i64 @f(i64 %n) {\n",
+            "  br label %loop\n",
+            "loop:\n",
+            "  %i = phi i64 [ 0, %entry ], [ %j, %next ]\n",
+            "  %k = phi i64 [ 1, %entry ], [ %l, %next ]\n",
+            "  %c = icmp slt i64 %i, %n\n",
+            "  br i1 %c, label %body, label %exit\n",
+            "body:\n",
+            "  %d = icmp ne i64 %k, 1\n",
+            "  br i1 %d, label %change, label %same\n",
+            "change:\n",
+            "  br label %next\n",
+            "same:\n",
+            "  br label %next\n",
+            "next:\n",
+            "  %l = phi i64 [ 2, %change ], [ %k, %same ]\n",
+            "  %j = add i64 %i, 1\n",
+            "  br label %loop\n",
+            "exit:\n",
+            "  ret i64 %k\n",
+            "}\n",
+        );
+        let (changes, propagated) = propagate(source);
+        assert_eq!(changes, Changes::ControlFlow);
+        assert!(propagated.contains("  ret i64 1\n"));
+        assert!(propagated.contains("  %i = phi i64 [ 0, %entry ], [ %j, %next ]\n"));
+        assert!(!propagated.contains("%k") && !propagated.contains("change:"));
+    }
+
+    #[test]
+    fn nothing_known() {
+        let source = concat!(
+            "define i64 @f(i64 %a, i64* %p) {\n",
+            "  %x = load i64, i64* %p\n",
+            "  %y = add i64 %x, %a\n",
+            "  ret i64 %y\n",
+            "}\n",
+        );
+        assert_eq!(propagate(source).0, Changes::Nothing);
+    }
+}
@@ -0,0 +1,247 @@
+//! Tests running the programs in `sample-files` before and after the passes,
+//! which must not change what they do, and checking that constants are
+//! folded as the interpreter evaluates them.
+
+use std::fs;
+
+use ir_passes::mem2reg::Mem2Reg;
+use ir_passes::sccp::Sccp;
+use ir_passes::PassManager;
+use llinterp::{Error, Interpreter};
+use llvmlite::{verify, Instruction, Operand, Program, Terminator, Type};
+use oat2llvmlite::monomorphize::monomorphize;
+use oat2llvmlite::{compile_program, Options};
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+use oat_typecheck::elaborate;
+
+fn sample(name: &str) -> String {
+    let path = format!("{}/../sample-files/{}", env!("CARGO_MANIFEST_DIR"), name);
+    fs::read_to_string(path).expect("sample file should exist")
+}
+
+/// Compile `source` as by the driver, without optimizations.
+fn compile(source: &str) -> Program {
+    let program = parse_program(source).expect("test program should parse");
+    let elaborated = elaborate(&program).expect("test program should type check");
+    compile_program(
+        &monomorphize(&elaborated).expect("test program should monomorphize"),
+        &Options::default(),
+    )
+}
+
+fn promoted(mut program: Program) -> Program {
+    let mut manager = PassManager::new();
+    manager.add(Mem2Reg);
+    manager.run(&mut program);
+    verify(&program).expect("promoted program should verify");
+    program
+}
+
+/// Run the passes of the driver at `-O1` over `program`.
+fn optimized(mut program: Program) -> Program {
+    let mut manager = PassManager::new();
+    manager.add(Mem2Reg);
+    manager.add(Sccp);
+    manager.run(&mut program);
+    verify(&program).expect("optimized program should verify");
+    program
+}
+
+/// Run `entry` of `program` with `args`, returning its result and output.
+fn run(program: &Program, entry: &str, args: &[&str]) -> Result<(i64, String), Error> {
+    let mut interpreter = Interpreter::new(program)?;
+    let result = interpreter.run(entry, args)?;
+    let output = String::from_utf8(interpreter.output().to_vec()).unwrap();
+    Ok((result, output))
+}
+
+/// Check that the passes keep what `entry` of `source` does, and return
+/// what it does.
+fn same_after_passes(source: &str, entry: &str, args: &[&str]) -> Result<(i64, String), Error> {
+    create_session_if_not_set_then(|_| {
+        let expected = run(&compile(source), entry, args);
+        assert_eq!(run(&promoted(compile(source)), entry, args), expected);
+        assert_eq!(run(&optimized(compile(source)), entry, args), expected);
+        expected
+    })
+}
+
+#[test]
+fn fib() {
+    create_session_if_not_set_then(|_| {
+        let source = sample("fib.oat");
+        let program = compile(&source);
+        let promoted = promoted(compile(&source));
+        for n in [0, 1, 2, 10, 50] {
+            let before = Interpreter::new(&program).unwrap().call("fib_loop", &[n]);
+            let after = Interpreter::new(&promoted).unwrap().call("fib_loop", &[n]);
+            assert_eq!(before, after);
+        }
+        let after = Interpreter::new(&promoted).unwrap().call("fib_rec", &[10]);
+        assert_eq!(after, Ok(55));
+
+        // Every local of the loop lives in a register
+        let fib_loop = &promoted.functions["fib_loop"];
+        let instructions = std::iter::once(&fib_loop.cfg.entry)
+            .chain(fib_loop.cfg.blocks.values())
+            .flat_map(|block| &block.instructions);
+        for (_, instruction) in instructions {
+            assert!(!matches!(
+                instruction,
+                Instruction::Alloca(_) | Instruction::Load(..) | Instruction::Store(..)
+            ));
+        }
+    })
+}
+
+#[test]
+fn samples() {
+    assert_eq!(
+        same_after_passes(&sample("strings.oat"), "program", &[]),
+        Ok((0, "hello, world12hello, ".to_string()))
+    );
+    assert_eq!(
+        same_after_passes(&sample("globals.oat"), "program", &[]),
+        Ok((1, "originunit".to_string()))
+    );
+    assert_eq!(
+        same_after_passes(&sample("ifq.oat"), "program", &[]),
+        Ok((5, String::new()))
+    );
+}
+
+#[test]
+fn nested_loops() {
+    let source = r#"
+        int program(int argc, string[] argv) {
+            var total = 0;
+            var a = new int[]{3, 1, 4, 1, 5};
+            for (var i = 0; i < length(a); i = i + 1) {
+                var j = 0;
+                while (j < a[i]) {
+                    if (j == 2) {
+                        total = total + 10;
+                    } else {
+                        total = total + j;
+                    }
+                    j = j + 1;
+                }
+                print_string(argv[i >> 2]);
+            }
+            return total;
+        }
+    "#;
+    assert_eq!(
+        same_after_passes(source, "program", &["x", "y"]),
+        Ok((43, "xxxxy".to_string()))
+    );
+}
+
+#[test]
+fn constants_through_locals() {
+    let source = r#"
+        int program(int argc, string[] argv) {
+            var x = 3;
+            var y = x * 4;
+            if (y > 10) {
+                y = y - 1;
+            } else {
+                y = 0;
+            }
+            return y;
+        }
+    "#;
+    assert_eq!(
+        same_after_passes(source, "program", &[]),
+        Ok((11, String::new()))
+    );
+    create_session_if_not_set_then(|_| {
+        let program = optimized(compile(source));
+        let cfg = &program.functions["program"].cfg;
+        assert_eq!(cfg.entry.instructions, vec![]);
+        assert_eq!(
+            cfg.blocks.values().last().unwrap().terminator.1,
+            Terminator::Ret(Type::I64, Some(Operand::Const(11)))
+        );
+    })
+}
+
+/// Check that folding each operator on constants gives what the interpreter
+/// computes.
+#[test]
+fn folding() {
+    let operators = [
+        "add", "sub", "mul", "shl", "lshr", "ashr", "and", "or", "xor", "icmp eq", "icmp ne",
+        "icmp slt", "icmp sle", "icmp sgt", "icmp sge",
+    ];
+    let constants = [
+        0,
+        1,
+        -1,
+        2,
+        3,
+        63,
+        64,
+        127,
+        128,
+        -129,
+        255,
+        i64::MAX,
+        i64::MIN,
+    ];
+    for t in ["i1", "i8", "i64"] {
+        for operator in operators {
+            for left in constants {
+                for right in constants {
+                    let result = match operator.starts_with("icmp") {
+                        true => "i1",
+                        false => t,
+                    };
+                    let source = format!(
+                        "define {r} @f() {{\n  %x = {o} {t} {left}, {right}\n  ret {r} %x\n}}\n",
+                        r = result,
+                        o = operator,
+                        t = t,
+                        left = left,
+                        right = right,
+                    );
+                    create_session_if_not_set_then(|_| {
+                        let mut program = llvmlite::parse_program(&source).unwrap();
+                        let expected = Interpreter::new(&program).unwrap().call("f", &[]);
+                        let mut manager = PassManager::new();
+                        manager.add(Sccp);
+                        manager.run(&mut program);
+                        let f = &program.functions["f"];
+                        assert!(f.cfg.entry.instructions.is_empty(), "{}", source);
+                        let folded = Interpreter::new(&program).unwrap().call("f", &[]);
+                        assert_eq!(folded, expected, "{}", source);
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// Locals and globals named like the names generated by lowering
+#[test]
+fn names_like_generated_ones() {
+    let source = r#"
+        global _str0 = "a";
+        int program(int argc, string[] argv) {
+            var tmp1 = argc;
+            var tmp2 = tmp1 + 1;
+            var tmp3 = tmp2 * 2;
+            while (tmp3 > tmp1) {
+                tmp3 = tmp3 - 1;
+            }
+            print_string(_str0);
+            print_string("b");
+            return tmp3 + tmp2;
+        }
+    "#;
+    assert_eq!(
+        same_after_passes(source, "program", &["x"]),
+        Ok((3, "ab".to_string()))
+    );
+}
@@ -0,0 +1,41 @@
+use llvmlite::{Gid, Label, Type, Uid};
+use thiserror::Error;
+
+/// Errors that stop the execution of a program
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("Unknown function @{0}")]
+    UnknownFunction(Gid),
+
+    #[error("Unknown global @{0}")]
+    UnknownGlobal(Gid),
+
+    #[error("Undefined local %{0}")]
+    UndefinedLocal(Uid),
+
+    #[error("Unknown label %{0}")]
+    UnknownLabel(Label),
+
+    #[error("@{0} is called with the wrong number of arguments")]
+    ArityMismatch(Gid),
+
+    #[error("@{0} is called more than {max} calls deep", max = crate::MAX_CALL_DEPTH)]
+    CallDepthExceeded(Gid),
+
+    #[error("Phi nodes must come before the other instructions of their block")]
+    MisplacedPhi,
+
+    #[error("{0} is not a function")]
+    NotAFunction(i64),
+
+    #[error("Invalid access of {size} bytes at address {address:#x}")]
+    InvalidAddress { address: i64, size: u64 },
+
+    #[error("Values of type {0} cannot be loaded, stored or allocated")]
+    UnsupportedType(Type),
+
+    #[error("Index {index} out of bounds for an array of length {length}")]
+    IndexOutOfBounds { index: i64, length: i64 },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
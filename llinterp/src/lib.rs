@@ -0,0 +1,564 @@
+//! A reference interpreter for LLVMLite programs, which runs them without
+//! any backend and serves as the oracle the backends are tested against.
+//!
+//! Every value is represented as an `i64`: `i1` values are 0 or 1, `i8`
+//! values are sign extended, and pointers are addresses in the
+//! [`memory`](memory) of the interpreter. Functions have addresses of their
+//! own, so that they can be stored and called through pointers. The runtime
+//! functions of Oat are implemented by the interpreter, and the output of
+//! `print_string` is collected rather than printed.
+//!
+//! The program is assumed to be well formed, as checked by
+//! [`llvmlite::verify`]; an ill-formed program stops with an error when it
+//! reaches the problem, if it ever does.
+
+use std::collections::HashMap;
+
+use llvmlite::{
+    BinaryOperator, Block, Condition, FunctionDecl, GlobalInitializer, Instruction, Operand,
+    Program, Terminator, Type, ENTRY_LABEL,
+};
+
+mod errors;
+pub use errors::{Error, Result};
+
+mod memory;
+use memory::{size_of, Memory};
+
+mod runtime;
+use runtime::call_runtime;
+
+/// The address of the first function. Function `i` is at `FUNCTION_BASE + i`,
+/// above both regions of memory.
+const FUNCTION_BASE: i64 = 1 << 48;
+
+/// The deepest nesting of calls a program may reach, beyond which it is
+/// assumed to recurse forever and stops with an error.
+pub const MAX_CALL_DEPTH: usize = 100_000;
+
+/// Bring `value` to the representation of values of type `t`.
+fn normalize(t: &Type, value: i64) -> i64 {
+    match t {
+        Type::I1 => value & 1,
+        Type::I8 => value as i8 as i64,
+        _ => value,
+    }
+}
+
+/// The signed value of the representation `value` of type `t`.
+fn signed(t: &Type, value: i64) -> i64 {
+    match t {
+        Type::I1 => -(value & 1),
+        _ => value,
+    }
+}
+
+fn pointee(t: &Type) -> Result<&Type> {
+    match t {
+        Type::Ptr(t) => Ok(t),
+        t => Err(Error::UnsupportedType(t.clone())),
+    }
+}
+
+pub struct Interpreter<'a> {
+    program: &'a Program,
+    memory: Memory,
+    /// The addresses of the globals and functions
+    globals: HashMap<&'a str, i64>,
+    /// The names of the functions, indexed by their address
+    functions: Vec<&'a str>,
+    output: Vec<u8>,
+}
+
+/// A call of a program function that has not returned yet.
+struct Frame<'a> {
+    fdecl: &'a FunctionDecl,
+    locals: HashMap<&'a str, i64>,
+    /// The top of the stack when the function was called, to which it is
+    /// brought back when the function returns
+    top: usize,
+    block: &'a Block,
+    /// The labels of the current block and of the block that control came
+    /// from, which selects the operands of phi nodes
+    previous: &'a str,
+    current: &'a str,
+    /// The index of the next instruction of `block` to execute
+    next: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Prepare to run `program`, laying out its globals in memory.
+    pub fn new(program: &'a Program) -> Result<Self> {
+        let mut interpreter = Interpreter {
+            program,
+            memory: Memory::default(),
+            globals: HashMap::new(),
+            functions: vec![],
+            output: vec![],
+        };
+        let functions = program.functions.keys().chain(program.externals.keys());
+        for (i, name) in functions.enumerate() {
+            interpreter.functions.push(name);
+            interpreter.globals.insert(name, FUNCTION_BASE + i as i64);
+        }
+        // Initializers can refer to any global, so every global is allocated
+        // before any is initialized
+        for (name, global) in &program.globals {
+            let size = size_of(&program.types, &global.0)?;
+            let address = interpreter.memory.allocate(size);
+            interpreter.globals.insert(name, address);
+        }
+        for (name, global) in &program.globals {
+            let address = interpreter.globals[name.as_str()];
+            interpreter.initialize(address, &global.0, &global.1)?;
+        }
+        Ok(interpreter)
+    }
+
+    /// The output printed by the program so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Write the constant `init` of type `t` at `address`.
+    fn initialize(&mut self, address: i64, t: &Type, init: &GlobalInitializer) -> Result<()> {
+        use GlobalInitializer::*;
+        let types = &self.program.types;
+        match init {
+            Null => self.memory.write(address, 8, 0),
+            Gid(gid) => {
+                let value = self.global(gid)?;
+                self.memory.write(address, 8, value)
+            }
+            Int(n) => {
+                let size = size_of(types, t)?;
+                self.memory.write(address, size, normalize(t, *n))
+            }
+            String(s) => self.memory.write_bytes(address, s.as_bytes()),
+            Array(elements) | Struct(elements) => {
+                let mut offset = 0;
+                for (t, init) in elements {
+                    self.initialize(address + offset, t, init)?;
+                    offset += size_of(&self.program.types, t)? as i64;
+                }
+                Ok(())
+            }
+            Bitcast(from, init, _) => self.initialize(address, from, init),
+        }
+    }
+
+    fn global(&self, gid: &str) -> Result<i64> {
+        self.globals
+            .get(gid)
+            .copied()
+            .ok_or_else(|| Error::UnknownGlobal(gid.to_string()))
+    }
+
+    /// Run the Oat entry point `entry`, e.g. `program`, with the command line
+    /// arguments `args`, which are passed as an Oat array of strings.
+    pub fn run(&mut self, entry: &str, args: &[&str]) -> Result<i64> {
+        let argv = self.memory.allocate(8 * (args.len() as u64 + 1));
+        self.memory.write(argv, 8, args.len() as i64)?;
+        for (i, arg) in args.iter().enumerate() {
+            let s = self.memory.allocate_string(arg.as_bytes())?;
+            self.memory.write(argv + 8 * (i as i64 + 1), 8, s)?;
+        }
+        self.call(entry, &[args.len() as i64, argv])
+    }
+
+    /// Call the function or runtime function `name` with `args`.
+    pub fn call(&mut self, name: &str, args: &[i64]) -> Result<i64> {
+        match self.program.functions.get(name) {
+            Some(fdecl) => self.call_function(name, fdecl, args),
+            None => match call_runtime(self, name, args)? {
+                Some(result) => Ok(result),
+                None => Err(Error::UnknownFunction(name.to_string())),
+            },
+        }
+    }
+
+    /// Run the program function `name`. The functions it calls in turn are
+    /// run on a stack of frames rather than by recursion, so that deep
+    /// recursion in the program cannot overflow the stack of the interpreter.
+    fn call_function(&mut self, name: &str, fdecl: &'a FunctionDecl, args: &[i64]) -> Result<i64> {
+        let program: &'a Program = self.program;
+        let mut frames = vec![self.frame(name, fdecl, args)?];
+        loop {
+            let frame = frames.last_mut().expect("a function is running");
+            if frame.next == 0 {
+                frame.next = self.enter_block(frame)?;
+            }
+            if let Some((uid, instruction)) = frame.block.instructions.get(frame.next) {
+                frame.next += 1;
+                let value = match instruction {
+                    Instruction::Call(_, fun, args) => {
+                        let (callee, args) = self.callee(fun, args, &frame.locals)?;
+                        if let Some(fdecl) = program.functions.get(callee) {
+                            if frames.len() == MAX_CALL_DEPTH {
+                                return Err(Error::CallDepthExceeded(callee.to_string()));
+                            }
+                            frames.push(self.frame(callee, fdecl, &args)?);
+                            continue;
+                        }
+                        self.call(callee, &args)?
+                    }
+                    instruction => self.execute(instruction, &frame.locals)?,
+                };
+                frame.locals.insert(uid, value);
+                continue;
+            }
+            let label = match &frame.block.terminator.1 {
+                Terminator::Ret(_, op) => {
+                    let result = match op {
+                        Some(op) => self.operand(op, &frame.locals)?,
+                        None => 0,
+                    };
+                    self.memory.pop(frame.top);
+                    frames.pop();
+                    match frames.last_mut() {
+                        // The caller is just past the call, whose result this is
+                        Some(caller) => {
+                            let (uid, _) = &caller.block.instructions[caller.next - 1];
+                            caller.locals.insert(uid, result);
+                            continue;
+                        }
+                        None => return Ok(result),
+                    }
+                }
+                Terminator::Break(label) => label,
+                Terminator::CondBreak(op, then, else_) => match self.operand(op, &frame.locals)? {
+                    0 => else_,
+                    _ => then,
+                },
+            };
+            frame.block = frame
+                .fdecl
+                .cfg
+                .blocks
+                .get(label)
+                .ok_or_else(|| Error::UnknownLabel(label.clone()))?;
+            frame.previous = std::mem::replace(&mut frame.current, label);
+            frame.next = 0;
+        }
+    }
+
+    /// A frame for a call of the program function `name` with `args`, which
+    /// is about to run the entry block.
+    fn frame(&self, name: &str, fdecl: &'a FunctionDecl, args: &[i64]) -> Result<Frame<'a>> {
+        if args.len() != fdecl.parameters.len() {
+            return Err(Error::ArityMismatch(name.to_string()));
+        }
+        let locals = fdecl
+            .parameters
+            .iter()
+            .map(String::as_str)
+            .zip(args.iter().copied())
+            .collect();
+        Ok(Frame {
+            fdecl,
+            locals,
+            top: self.memory.stack_top(),
+            block: &fdecl.cfg.entry,
+            previous: ENTRY_LABEL,
+            current: ENTRY_LABEL,
+            next: 0,
+        })
+    }
+
+    /// Run the phi nodes at the start of the block of `frame`, returning how
+    /// many there are.
+    fn enter_block(&self, frame: &mut Frame<'a>) -> Result<usize> {
+        // The phi nodes all read the locals as they were at the end of the
+        // previous block
+        let phis = frame
+            .block
+            .instructions
+            .iter()
+            .take_while(|(_, instruction)| matches!(instruction, Instruction::Phi(..)));
+        let mut values = vec![];
+        for (uid, instruction) in phis {
+            if let Instruction::Phi(_, incoming) = instruction {
+                let op = incoming
+                    .iter()
+                    .find(|(_, label)| label == frame.previous)
+                    .map(|(op, _)| op)
+                    .ok_or_else(|| Error::UnknownLabel(frame.previous.to_string()))?;
+                values.push((uid.as_str(), self.operand(op, &frame.locals)?));
+            }
+        }
+        let phis = values.len();
+        frame.locals.extend(values);
+        Ok(phis)
+    }
+
+    /// The name of the function called by `fun` and the values of `args`.
+    fn callee(
+        &self,
+        fun: &Operand,
+        args: &[(Type, Operand)],
+        locals: &HashMap<&str, i64>,
+    ) -> Result<(&'a str, Vec<i64>)> {
+        let address = self.operand(fun, locals)?;
+        let name = usize::try_from(address - FUNCTION_BASE)
+            .ok()
+            .and_then(|i| self.functions.get(i))
+            .ok_or(Error::NotAFunction(address))?;
+        let args = args
+            .iter()
+            .map(|(_, op)| self.operand(op, locals))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((name, args))
+    }
+
+    fn operand(&self, op: &Operand, locals: &HashMap<&str, i64>) -> Result<i64> {
+        match op {
+            Operand::Null => Ok(0),
+            Operand::Const(n) => Ok(*n),
+            Operand::Gid(gid) => self.global(gid),
+            Operand::Id(uid) => locals
+                .get(uid.as_str())
+                .copied()
+                .ok_or_else(|| Error::UndefinedLocal(uid.clone())),
+        }
+    }
+
+    /// Execute `instruction`, returning its result, which is 0 if it has none.
+    /// Phi nodes are run with the other phi nodes of their block, and calls
+    /// by [`Interpreter::call_function`].
+    fn execute(&mut self, instruction: &Instruction, locals: &HashMap<&str, i64>) -> Result<i64> {
+        use Instruction::*;
+        let types = &self.program.types;
+        Ok(match instruction {
+            Binop(op, t, left, right) => {
+                let (left, right) = (self.operand(left, locals)?, self.operand(right, locals)?);
+                // Shift amounts are taken modulo 64, as on x86
+                let shift = (right & 63) as u32;
+                let value = match op {
+                    BinaryOperator::Add => left.wrapping_add(right),
+                    BinaryOperator::Sub => left.wrapping_sub(right),
+                    BinaryOperator::Mul => left.wrapping_mul(right),
+                    BinaryOperator::Shl => left << shift,
+                    BinaryOperator::Lshr => ((left as u64) >> shift) as i64,
+                    BinaryOperator::Ashr => left >> shift,
+                    BinaryOperator::And => left & right,
+                    BinaryOperator::Or => left | right,
+                    BinaryOperator::Xor => left ^ right,
+                };
+                normalize(t, value)
+            }
+            Alloca(t) => self.memory.alloca(size_of(types, t)?),
+            Load(t, ptr) => {
+                let t = pointee(t)?;
+                let value = self
+                    .memory
+                    .read(self.operand(ptr, locals)?, size_of(types, t)?)?;
+                normalize(t, value)
+            }
+            Store(t, value, ptr) => {
+                let (value, ptr) = (self.operand(value, locals)?, self.operand(ptr, locals)?);
+                self.memory.write(ptr, size_of(types, t)?, value)?;
+                0
+            }
+            Icmp(condition, t, left, right) => {
+                let left = signed(t, self.operand(left, locals)?);
+                let right = signed(t, self.operand(right, locals)?);
+                let holds = match condition {
+                    Condition::Eq => left == right,
+                    Condition::Ne => left != right,
+                    Condition::Slt => left < right,
+                    Condition::Sle => left <= right,
+                    Condition::Sgt => left > right,
+                    Condition::Sge => left >= right,
+                };
+                holds as i64
+            }
+            Call(..) => unreachable!("calls are run by call_function"),
+            Bitcast(_, op, _) => self.operand(op, locals)?,
+            Gep(t, ptr, path) => {
+                let mut address = self.operand(ptr, locals)?;
+                let mut t = pointee(t)?;
+                let mut indices = path.iter();
+                // The first index steps over whole values of the pointee type
+                if let Some(index) = indices.next() {
+                    address += self.operand(index, locals)? * size_of(types, t)? as i64;
+                }
+                for index in indices {
+                    let index = self.operand(index, locals)?;
+                    if let Type::Namedt(tid) = t {
+                        t = types
+                            .get(tid)
+                            .ok_or_else(|| Error::UnsupportedType(t.clone()))?;
+                    }
+                    match t {
+                        Type::Struct(fields) => {
+                            for field in &fields[..index as usize] {
+                                address += size_of(types, field)? as i64;
+                            }
+                            t = &fields[index as usize];
+                        }
+                        Type::Array(_, element) => {
+                            address += index * size_of(types, element)? as i64;
+                            t = element;
+                        }
+                        t => return Err(Error::UnsupportedType(t.clone())),
+                    }
+                }
+                address
+            }
+            Phi(..) => return Err(Error::MisplacedPhi),
+        })
+    }
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use super::*;
+    use llvmlite::parse_program;
+    use oat_symbol::create_session_if_not_set_then;
+
+    /// Call `f` in `source` with `args`, returning its result and output.
+    fn call(source: &str, args: &[i64]) -> Result<(i64, String)> {
+        create_session_if_not_set_then(|_| {
+            let program = parse_program(source).expect("test program should parse");
+            let mut interpreter = Interpreter::new(&program)?;
+            let result = interpreter.call("f", args)?;
+            let output = String::from_utf8(interpreter.output().to_vec()).unwrap();
+            Ok((result, output))
+        })
+    }
+
+    #[test]
+    fn arithmetic() {
+        let source = concat!(
+            "define i64 @f(i64 %x) {\n",
+            "  %a = mul i64 %x, 3\n",
+            "  %b = sub i64 %a, 10\n",
+            "  %c = ashr i64 %b, 1\n",
+            "  %d = lshr i64 -1, 63\n",
+            "  %e = add i64 %c, %d\n",
+            "  ret i64 %e\n",
+            "}\n",
+        );
+        assert_eq!(call(source, &[2]), Ok((-1, String::new())));
+    }
+
+    #[test]
+    fn loops_and_locals() {
+        let source = concat!(
+            "define i64 @f(i64 %n) {\n",
+            "  %sum = alloca i64\n",
+            "  %i = alloca i64\n",
+            "  store i64 0, i64* %sum\n",
+            "  store i64 0, i64* %i\n",
+            "  br label %loop\n",
+            "loop:\n",
+            "  %iv = load i64, i64* %i\n",
+            "  %c = icmp slt i64 %iv, %n\n",
+            "  br i1 %c, label %body, label %exit\n",
+            "body:\n",
+            "  %s = load i64, i64* %sum\n",
+            "  %s1 = add i64 %s, %iv\n",
+            "  store i64 %s1, i64* %sum\n",
+            "  %iv1 = add i64 %iv, 1\n",
+            "  store i64 %iv1, i64* %i\n",
+            "  br label %loop\n",
+            "exit:\n",
+            "  %r = load i64, i64* %sum\n",
+            "  ret i64 %r\n",
+            "}\n",
+        );
+        assert_eq!(call(source, &[10]), Ok((45, String::new())));
+    }
+
+    #[test]
+    fn structs_and_globals() {
+        let source = concat!(
+            "%point = type { i64, i1, i64 }\n",
+            "@p = global %point { i64 1, i1 1, i64 3 }\n",
+            "@s = global [3 x i8] c\"hi\\00\"\n",
+            "define i64 @f() {\n",
+            "  %z = getelementptr %point, %point* @p, i32 0, i32 2\n",
+            "  %v = load i64, i64* %z\n",
+            "  %c = getelementptr [3 x i8], [3 x i8]* @s, i32 0, i32 1\n",
+            "  %i = load i8, i8* %c\n",
+            "  %s = bitcast [3 x i8]* @s to i8*\n",
+            "  call void @print_string(i8* %s)\n",
+            "  %r = add i64 %v, 100\n",
+            "  ret i64 %r\n",
+            "}\n",
+            "declare void @print_string(i8*)\n",
+        );
+        assert_eq!(call(source, &[]), Ok((103, "hi".to_string())));
+    }
+
+    #[test]
+    fn function_pointers() {
+        let source = concat!(
+            "@g = global i64 (i64)* @double\n",
+            "define i64 @double(i64 %x) {\n",
+            "  %y = add i64 %x, %x\n",
+            "  ret i64 %y\n",
+            "}\n",
+            "define i64 @f(i64 %x) {\n",
+            "  %g = load i64 (i64)*, i64 (i64)** @g\n",
+            "  %y = call i64 %g(i64 %x)\n",
+            "  ret i64 %y\n",
+            "}\n",
+        );
+        assert_eq!(call(source, &[21]), Ok((42, String::new())));
+    }
+
+    #[test]
+    fn phi_nodes() {
+        // The phi nodes of a block are read at once, so that %a and %b swap
+        let source = concat!(
+            "define i64 @f(i64 %n) {\n",
+            "  br label %loop\n",
+            "loop:\n",
+            "  %i = phi i64 [ 0, %entry ], [ %j, %loop ]\n",
+            "  %a = phi i64 [ 1, %entry ], [ %b, %loop ]\n",
+            "  %b = phi i64 [ 2, %entry ], [ %a, %loop ]\n",
+            "  %j = add i64 %i, 1\n",
+            "  %c = icmp slt i64 %j, %n\n",
+            "  br i1 %c, label %loop, label %exit\n",
+            "exit:\n",
+            "  %r = mul i64 %a, 10\n",
+            "  %s = add i64 %r, %b\n",
+            "  ret i64 %s\n",
+            "}\n",
+        );
+        assert_eq!(call(source, &[1]), Ok((12, String::new())));
+        assert_eq!(call(source, &[2]), Ok((21, String::new())));
+    }
+
+    #[test]
+    fn dangling_stack_pointer() {
+        let source = concat!(
+            "define i64* @local() {\n",
+            "  %x = alloca i64\n",
+            "  ret i64* %x\n",
+            "}\n",
+            "define i64 @f() {\n",
+            "  %p = call i64* @local()\n",
+            "  %v = load i64, i64* %p\n",
+            "  ret i64 %v\n",
+            "}\n",
+        );
+        assert!(matches!(
+            call(source, &[]),
+            Err(Error::InvalidAddress { size: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn null_dereference() {
+        let source = "define i64 @f() {\n  %v = load i64, i64* null\n  ret i64 %v\n}\n";
+        assert_eq!(
+            call(source, &[]),
+            Err(Error::InvalidAddress {
+                address: 0,
+                size: 8
+            })
+        );
+    }
+}
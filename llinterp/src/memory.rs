@@ -0,0 +1,124 @@
+//! The byte-addressed memory of the interpreter.
+//!
+//! Sizes follow the x86 backend: `i8` takes a byte, and `i1`, `i64` and
+//! pointers take 8 bytes, with no padding within structs. Memory is split
+//! into two regions. The heap holds the globals and the allocations of the
+//! runtime, and is never freed. The stack holds the allocas of the running
+//! functions, and shrinks back when a function returns, so that a pointer to
+//! the frame of a returned function is no longer valid. The address 0 is
+//! null, and is in neither region.
+
+use llvmlite::{Type, TypeContext};
+
+use crate::errors::{Error, Result};
+
+const HEAP_BASE: i64 = 0x1000;
+const STACK_BASE: i64 = 1 << 40;
+
+/// The number of bytes taken by a value of type `t`.
+pub(crate) fn size_of(types: &TypeContext, t: &Type) -> Result<u64> {
+    use Type::*;
+    Ok(match t {
+        I8 => 1,
+        I1 | I64 | Ptr(_) => 8,
+        Struct(fields) => fields
+            .iter()
+            .map(|t| size_of(types, t))
+            .sum::<Result<u64>>()?,
+        Array(n, t) => *n as u64 * size_of(types, t)?,
+        Namedt(tid) => match types.get(tid) {
+            Some(t) => size_of(types, t)?,
+            None => return Err(Error::UnsupportedType(Namedt(*tid))),
+        },
+        Void | Fun(..) => return Err(Error::UnsupportedType(t.clone())),
+    })
+}
+
+#[derive(Default)]
+pub(crate) struct Memory {
+    heap: Vec<u8>,
+    stack: Vec<u8>,
+}
+
+impl Memory {
+    /// Allocate `size` zeroed bytes on the heap, returning their address.
+    pub(crate) fn allocate(&mut self, size: u64) -> i64 {
+        let address = HEAP_BASE + self.heap.len() as i64;
+        self.heap.resize(self.heap.len() + size as usize, 0);
+        address
+    }
+
+    /// Allocate `size` zeroed bytes on the stack, returning their address.
+    pub(crate) fn alloca(&mut self, size: u64) -> i64 {
+        let address = STACK_BASE + self.stack.len() as i64;
+        self.stack.resize(self.stack.len() + size as usize, 0);
+        address
+    }
+
+    /// The top of the stack, to which it is brought back by [`Memory::pop`]
+    /// when the function which is about to run returns.
+    pub(crate) fn stack_top(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub(crate) fn pop(&mut self, top: usize) {
+        self.stack.truncate(top);
+    }
+
+    fn region(&mut self, address: i64, size: u64) -> Result<&mut [u8]> {
+        let invalid = Error::InvalidAddress { address, size };
+        let (region, base) = if address >= STACK_BASE {
+            (&mut self.stack, STACK_BASE)
+        } else if address >= HEAP_BASE {
+            (&mut self.heap, HEAP_BASE)
+        } else {
+            return Err(invalid);
+        };
+        let start = (address - base) as usize;
+        region.get_mut(start..start + size as usize).ok_or(invalid)
+    }
+
+    /// Read the `size` bytes at `address` as a signed integer.
+    pub(crate) fn read(&mut self, address: i64, size: u64) -> Result<i64> {
+        let bytes = self.region(address, size)?;
+        let mut buffer = [0; 8];
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        let value = i64::from_le_bytes(buffer);
+        // Sign extend
+        let unused = 64 - 8 * size as u32;
+        Ok(value << unused >> unused)
+    }
+
+    /// Write the `size` lowest bytes of `value` at `address`.
+    pub(crate) fn write(&mut self, address: i64, size: u64, value: i64) -> Result<()> {
+        let bytes = self.region(address, size)?;
+        bytes.copy_from_slice(&value.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+
+    /// Write `bytes` at `address`.
+    pub(crate) fn write_bytes(&mut self, address: i64, bytes: &[u8]) -> Result<()> {
+        self.region(address, bytes.len() as u64)?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// The bytes of the null-terminated string at `address`, without the
+    /// null byte.
+    pub(crate) fn read_string(&mut self, address: i64) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        loop {
+            match self.read(address + bytes.len() as i64, 1)? as u8 {
+                0 => return Ok(bytes),
+                byte => bytes.push(byte),
+            }
+        }
+    }
+
+    /// Allocate a null-terminated copy of `bytes` on the heap.
+    pub(crate) fn allocate_string(&mut self, bytes: &[u8]) -> Result<i64> {
+        let address = self.allocate(bytes.len() as u64 + 1);
+        self.write_bytes(address, bytes)?;
+        Ok(address)
+    }
+}
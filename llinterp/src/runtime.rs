@@ -0,0 +1,43 @@
+//! The functions of the Oat runtime, which programs declare as externals.
+
+use crate::errors::{Error, Result};
+use crate::Interpreter;
+
+/// Call the runtime function `name`, returning `None` if there is none.
+pub(crate) fn call_runtime(
+    interpreter: &mut Interpreter,
+    name: &str,
+    args: &[i64],
+) -> Result<Option<i64>> {
+    let arg = |i: usize| {
+        args.get(i)
+            .copied()
+            .ok_or_else(|| Error::ArityMismatch(name.to_string()))
+    };
+    let memory = &mut interpreter.memory;
+    let result = match name {
+        "oat_malloc" => memory.allocate(arg(0)? as u64),
+        // Arrays start with their length
+        "oat_assert_array_length" => {
+            let (length, index) = (memory.read(arg(0)?, 8)?, arg(1)?);
+            if index < 0 || index >= length {
+                return Err(Error::IndexOutOfBounds { index, length });
+            }
+            0
+        }
+        "length_of_string" => memory.read_string(arg(0)?)?.len() as i64,
+        "string_of_int" => memory.allocate_string(arg(0)?.to_string().as_bytes())?,
+        "string_cat" => {
+            let mut s = memory.read_string(arg(0)?)?;
+            s.extend(memory.read_string(arg(1)?)?);
+            memory.allocate_string(&s)?
+        }
+        "print_string" => {
+            let s = memory.read_string(arg(0)?)?;
+            interpreter.output.extend(s);
+            0
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(result))
+}
@@ -0,0 +1,154 @@
+//! Tests running the programs in `sample-files`, compiled as by the driver.
+
+use std::fs;
+
+use llinterp::{Error, Interpreter};
+use oat2llvmlite::monomorphize::monomorphize;
+use oat2llvmlite::{compile_program, Options};
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+use oat_typecheck::elaborate;
+
+/// Compile `source`, then call `entry` with `args` and return its result and
+/// output.
+fn run(source: &str, entry: &str, args: &[&str]) -> Result<(i64, String), Error> {
+    create_session_if_not_set_then(|_| {
+        let program = parse_program(source).expect("test program should parse");
+        let elaborated = elaborate(&program).expect("test program should type check");
+        let program = compile_program(
+            &monomorphize(&elaborated).expect("test program should monomorphize"),
+            &Options::default(),
+        );
+        let mut interpreter = Interpreter::new(&program)?;
+        let result = interpreter.run(entry, args)?;
+        let output = String::from_utf8(interpreter.output().to_vec()).unwrap();
+        Ok((result, output))
+    })
+}
+
+fn sample(name: &str) -> String {
+    let path = format!("{}/../sample-files/{}", env!("CARGO_MANIFEST_DIR"), name);
+    fs::read_to_string(path).expect("sample file should exist")
+}
+
+fn run_sample(name: &str, entry: &str) -> Result<(i64, String), Error> {
+    run(&sample(name), entry, &[])
+}
+
+#[test]
+fn fib() {
+    let source = sample("fib.oat");
+    create_session_if_not_set_then(|_| {
+        let program = parse_program(&source).expect("sample file should parse");
+        let program = compile_program(&program, &Options::default());
+        let mut interpreter = Interpreter::new(&program).unwrap();
+        let fibs: Vec<_> = (0..10)
+            .map(|n| interpreter.call("fib_rec", &[n]).unwrap())
+            .collect();
+        assert_eq!(fibs, vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34]);
+        assert_eq!(interpreter.call("fib_loop", &[50]), Ok(12586269025));
+    })
+}
+
+#[test]
+fn strings() {
+    assert_eq!(
+        run_sample("strings.oat", "program"),
+        Ok((0, "hello, world12hello, ".to_string()))
+    );
+}
+
+#[test]
+fn globals() {
+    assert_eq!(
+        run_sample("globals.oat", "program"),
+        Ok((1, "originunit".to_string()))
+    );
+}
+
+#[test]
+fn ifq() {
+    assert_eq!(run_sample("ifq.oat", "program"), Ok((5, String::new())));
+}
+
+#[test]
+fn arguments() {
+    let source = r#"
+        int program(int argc, string[] argv) {
+            for (var i = 0; i < argc; i = i + 1) {
+                print_string(argv[i]);
+            }
+            return length(argv);
+        }
+    "#;
+    assert_eq!(
+        run(source, "program", &["a", "bc"]),
+        Ok((2, "abc".to_string()))
+    );
+}
+
+#[test]
+fn out_of_bounds() {
+    let source = r#"
+        int program(int argc, string[] argv) {
+            var a = new int[]{1, 2, 3};
+            return a[argc + 3];
+        }
+    "#;
+    assert_eq!(
+        run(source, "program", &[]),
+        Err(Error::IndexOutOfBounds {
+            index: 3,
+            length: 3
+        })
+    );
+}
+
+#[test]
+fn generic_reverse() {
+    let source = r#"
+        T[] reverse<T>(T[] a) {
+            var n = length(a);
+            var r = new T[n];
+            for (var i = 0; i < n; i = i + 1) {
+                r[i] = a[n - (i + 1)];
+            }
+            return r;
+        }
+
+        int program(int argc, string[] argv) {
+            var r = reverse(new int[]{1, 2, 3});
+            return (r[0] * 100) + ((r[1] * 10) + r[2]);
+        }
+    "#;
+    assert_eq!(run(source, "program", &[]), Ok((321, String::new())));
+}
+
+#[test]
+fn deep_recursion() {
+    let source = |n: usize| {
+        format!(
+            "int down(int n) {{
+                if (n == 0) {{
+                    return 0;
+                }}
+                return down(n - 1) + 1;
+            }}
+
+            int program(int argc, string[] argv) {{
+                return down({});
+            }}",
+            n
+        )
+    };
+    // `program` and `down(n)` down to `down(0)` are all active at once
+    let deepest = llinterp::MAX_CALL_DEPTH - 2;
+    assert_eq!(
+        run(&source(deepest), "program", &[]),
+        Ok((deepest as i64, String::new()))
+    );
+    assert_eq!(
+        run(&source(llinterp::MAX_CALL_DEPTH), "program", &[]),
+        Err(Error::CallDepthExceeded("down".to_string()))
+    );
+}
@@ -13,8 +13,12 @@ pub type Uid = String;
 /// Labels for functions and constants
 pub type Label = String;
 
+/// The label by which phi nodes refer to the entry block, which has no label
+/// of its own. No other block can have this label.
+pub const ENTRY_LABEL: &str = "entry";
+
 /// LLVM types
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Type {
     Void,
     I1,
@@ -35,7 +39,7 @@ pub struct FunctionType {
 }
 
 /// Syntactic Values
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Operand {
     Null,
     Const(i64),
@@ -56,7 +60,7 @@ impl PartialOrd for Operand {
 }
 
 /// Binary i64 operations
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum BinaryOperator {
     Add,
     Sub,
@@ -70,7 +74,7 @@ pub enum BinaryOperator {
 }
 
 /// Comparison Operators
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Condition {
     Eq,
     Ne,
@@ -91,6 +95,52 @@ pub enum Instruction {
     Call(Type, Operand, Vec<(Type, Operand)>),
     Bitcast(Type, Operand, Type),
     Gep(Type, Operand, Vec<Operand>),
+    /// The operand paired with the block that control came from. Phi nodes
+    /// come before the other instructions of their block.
+    Phi(Type, Vec<(Operand, Label)>),
+}
+
+impl Instruction {
+    /// Whether the instruction defines a value, so that its result is named.
+    pub fn has_result(&self) -> bool {
+        !matches!(
+            self,
+            Instruction::Store(..) | Instruction::Call(Type::Void, ..)
+        )
+    }
+
+    /// The operands used by the instruction, in order.
+    pub fn operands(&self) -> Vec<&Operand> {
+        use Instruction::*;
+        match self {
+            Binop(_, _, left, right) | Icmp(_, _, left, right) | Store(_, left, right) => {
+                vec![left, right]
+            }
+            Alloca(_) => vec![],
+            Load(_, op) | Bitcast(_, op, _) => vec![op],
+            Call(_, fun, args) => std::iter::once(fun)
+                .chain(args.iter().map(|(_, op)| op))
+                .collect(),
+            Gep(_, op, path) => std::iter::once(op).chain(path).collect(),
+            Phi(_, incoming) => incoming.iter().map(|(op, _)| op).collect(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        use Instruction::*;
+        match self {
+            Binop(_, _, left, right) | Icmp(_, _, left, right) | Store(_, left, right) => {
+                vec![left, right]
+            }
+            Alloca(_) => vec![],
+            Load(_, op) | Bitcast(_, op, _) => vec![op],
+            Call(_, fun, args) => std::iter::once(fun)
+                .chain(args.iter_mut().map(|(_, op)| op))
+                .collect(),
+            Gep(_, op, path) => std::iter::once(op).chain(path).collect(),
+            Phi(_, incoming) => incoming.iter_mut().map(|(op, _)| op).collect(),
+        }
+    }
 }
 
 /// Terminators of a block
@@ -101,6 +151,23 @@ pub enum Terminator {
     CondBreak(Operand, Label, Label),
 }
 
+impl Terminator {
+    /// The operands used by the terminator.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Ret(_, Some(op)) | Terminator::CondBreak(op, ..) => vec![op],
+            Terminator::Ret(_, None) | Terminator::Break(_) => vec![],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Ret(_, Some(op)) | Terminator::CondBreak(op, ..) => vec![op],
+            Terminator::Ret(_, None) | Terminator::Break(_) => vec![],
+        }
+    }
+}
+
 /// Blocks
 #[derive(Debug, PartialEq, Eq)]
 pub struct Block {
@@ -144,7 +211,7 @@ pub struct GlobalDeclaration(pub Type, pub GlobalInitializer);
 pub type TypeContext = IndexMap<Tid, Type>;
 
 /// An LLVMLite Program
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct Program {
     pub types: TypeContext,
     pub globals: IndexMap<Gid, GlobalDeclaration>,
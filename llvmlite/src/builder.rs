@@ -0,0 +1,528 @@
+//! Building LLVMLite functions and programs one instruction at a time.
+//!
+//! A [`FunctionBuilder`] appends instructions to the end of its current
+//! block, naming their results with fresh locals, and a [`ModuleBuilder`]
+//! collects the definitions of a program. Mistakes such as leaving a block
+//! without a terminator are not reported by the methods which build the code,
+//! but by `finish`, so that building code never needs to handle errors.
+
+use thiserror::Error;
+
+use crate::ast::*;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BuildError {
+    #[error("The entry block has no terminator")]
+    UnterminatedEntry,
+
+    #[error("The block %{0} has no terminator")]
+    UnterminatedBlock(Label),
+
+    #[error("%{0} is built after the terminator of its block")]
+    AfterTerminator(Uid),
+
+    #[error("%{0} branches to the entry block")]
+    BranchToEntry(Uid),
+
+    #[error("The label %{0} is used for more than one block")]
+    DuplicateLabel(Label),
+
+    #[error("@{0} is defined more than once")]
+    RedefinedGlobal(Gid),
+
+    #[error("In @{function}: {error}")]
+    In {
+        function: Gid,
+        error: Box<BuildError>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, BuildError>;
+
+fn ptr(t: Type) -> Type {
+    Type::Ptr(Box::new(t))
+}
+
+/// A block of a function being built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockId(usize);
+
+#[derive(Debug)]
+struct PartialBlock {
+    /// The label of the block, which is `None` for the entry block
+    label: Option<Label>,
+    instructions: Vec<(Uid, Instruction)>,
+    terminator: Option<(Uid, Terminator)>,
+}
+
+/// Builder of the control flow graph of a function.
+#[derive(Debug)]
+pub struct FunctionBuilder {
+    type_signature: FunctionType,
+    parameters: Vec<Uid>,
+    blocks: Vec<PartialBlock>,
+    current: BlockId,
+    /// The number of instructions hoisted to the start of the entry block
+    hoisted: usize,
+    fresh: usize,
+    /// The first mistake made while building
+    error: Option<BuildError>,
+}
+
+impl FunctionBuilder {
+    /// Start building a function, positioned at the end of its empty entry
+    /// block.
+    pub fn new(type_signature: FunctionType, parameters: Vec<Uid>) -> Self {
+        FunctionBuilder {
+            type_signature,
+            parameters,
+            blocks: vec![PartialBlock {
+                label: None,
+                instructions: vec![],
+                terminator: None,
+            }],
+            current: BlockId(0),
+            hoisted: 0,
+            fresh: 0,
+            error: None,
+        }
+    }
+
+    /// A local or label that has not been generated before, starting with
+    /// `prefix`.
+    pub fn fresh(&mut self, prefix: &str) -> String {
+        let n = self.fresh;
+        self.fresh += 1;
+        format!("_{}{}", prefix, n)
+    }
+
+    fn fail(&mut self, error: BuildError) {
+        self.error.get_or_insert(error);
+    }
+
+    pub fn entry_block(&self) -> BlockId {
+        BlockId(0)
+    }
+
+    pub fn current_block(&self) -> BlockId {
+        self.current
+    }
+
+    /// The label of `block`, which is `None` for the entry block.
+    pub fn label(&self, block: BlockId) -> Option<&Label> {
+        self.blocks[block.0].label.as_ref()
+    }
+
+    /// Add an empty block with a fresh label starting with `prefix`.
+    pub fn append_block(&mut self, prefix: &str) -> BlockId {
+        let label = self.fresh(prefix);
+        self.append_block_with_label(label)
+    }
+
+    /// Add an empty block labelled `label`.
+    pub fn append_block_with_label(&mut self, label: Label) -> BlockId {
+        if self
+            .blocks
+            .iter()
+            .any(|block| block.label.as_ref() == Some(&label))
+        {
+            self.fail(BuildError::DuplicateLabel(label.clone()));
+        }
+        self.blocks.push(PartialBlock {
+            label: Some(label),
+            instructions: vec![],
+            terminator: None,
+        });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    /// Build the following instructions at the end of `block`.
+    pub fn position_at_end(&mut self, block: BlockId) {
+        self.current = block;
+    }
+
+    /// Whether the current block already has its terminator, so that code
+    /// built there can never run.
+    pub fn is_terminated(&self) -> bool {
+        self.blocks[self.current.0].terminator.is_some()
+    }
+
+    /// Add `instruction` defining `uid` at the end of the current block.
+    pub fn insert(&mut self, uid: Uid, instruction: Instruction) {
+        if self.is_terminated() {
+            self.fail(BuildError::AfterTerminator(uid.clone()));
+        }
+        self.blocks[self.current.0]
+            .instructions
+            .push((uid, instruction));
+    }
+
+    /// Add `instruction` defining `uid` to the entry block, after the
+    /// instructions hoisted before it but before any other instruction. This
+    /// is where the `alloca`s of local variables go, so that they are only
+    /// run once.
+    pub fn insert_at_entry(&mut self, uid: Uid, instruction: Instruction) {
+        self.blocks[0]
+            .instructions
+            .insert(self.hoisted, (uid, instruction));
+        self.hoisted += 1;
+    }
+
+    /// End the current block with `terminator`, named `uid`.
+    pub fn terminate(&mut self, uid: Uid, terminator: Terminator) {
+        if self.is_terminated() {
+            self.fail(BuildError::AfterTerminator(uid.clone()));
+            return;
+        }
+        self.blocks[self.current.0].terminator = Some((uid, terminator));
+    }
+
+    /// Add `instruction` with a fresh name starting with `prefix`, returning
+    /// its result.
+    fn build(&mut self, prefix: &str, instruction: Instruction) -> Operand {
+        let uid = self.fresh(prefix);
+        self.insert(uid.clone(), instruction);
+        Operand::Id(uid)
+    }
+
+    pub fn build_binop(
+        &mut self,
+        op: BinaryOperator,
+        t: Type,
+        left: Operand,
+        right: Operand,
+    ) -> Operand {
+        self.build("tmp", Instruction::Binop(op, t, left, right))
+    }
+
+    pub fn build_icmp(
+        &mut self,
+        condition: Condition,
+        t: Type,
+        left: Operand,
+        right: Operand,
+    ) -> Operand {
+        self.build("tmp", Instruction::Icmp(condition, t, left, right))
+    }
+
+    /// Allocate a slot for a value of type `t` in the current block.
+    pub fn build_alloca(&mut self, t: Type) -> Operand {
+        self.build("slot", Instruction::Alloca(t))
+    }
+
+    /// Allocate a slot for a value of type `t` in the entry block.
+    pub fn build_entry_alloca(&mut self, t: Type) -> Operand {
+        let uid = self.fresh("slot");
+        self.insert_at_entry(uid.clone(), Instruction::Alloca(t));
+        Operand::Id(uid)
+    }
+
+    /// Load the value of type `t` that `ptr` points to.
+    pub fn build_load(&mut self, t: Type, ptr: Operand) -> Operand {
+        self.build("tmp", Instruction::Load(self::ptr(t), ptr))
+    }
+
+    /// Store `value` of type `t` where `ptr` points to.
+    pub fn build_store(&mut self, t: Type, value: Operand, ptr: Operand) {
+        self.build("store", Instruction::Store(t, value, ptr));
+    }
+
+    /// The address of the element at `path` in the value of type `t` that
+    /// `ptr` points to.
+    pub fn build_gep(&mut self, t: Type, ptr: Operand, path: Vec<Operand>) -> Operand {
+        self.build("tmp", Instruction::Gep(self::ptr(t), ptr, path))
+    }
+
+    /// Call `fun`, returning `ret_type`, with `args`. Calls of functions
+    /// returning `void` have no result.
+    pub fn build_call(
+        &mut self,
+        ret_type: Type,
+        fun: Operand,
+        args: Vec<(Type, Operand)>,
+    ) -> Option<Operand> {
+        if ret_type == Type::Void {
+            self.build("call", Instruction::Call(ret_type, fun, args));
+            None
+        } else {
+            Some(self.build("tmp", Instruction::Call(ret_type, fun, args)))
+        }
+    }
+
+    pub fn build_bitcast(&mut self, from: Type, op: Operand, to: Type) -> Operand {
+        self.build("tmp", Instruction::Bitcast(from, op, to))
+    }
+
+    /// The label to branch to `block`, which cannot be the entry block.
+    fn target(&mut self, uid: &Uid, block: BlockId) -> Label {
+        match self.label(block) {
+            Some(label) => label.clone(),
+            None => {
+                self.fail(BuildError::BranchToEntry(uid.clone()));
+                Label::new()
+            }
+        }
+    }
+
+    pub fn build_ret(&mut self, t: Type, value: Option<Operand>) {
+        let uid = self.fresh("term");
+        self.terminate(uid, Terminator::Ret(t, value));
+    }
+
+    pub fn build_br(&mut self, block: BlockId) {
+        let uid = self.fresh("term");
+        let label = self.target(&uid, block);
+        self.terminate(uid, Terminator::Break(label));
+    }
+
+    /// Branch to `then` if `condition` holds, and to `else_` otherwise.
+    pub fn build_cond_br(&mut self, condition: Operand, then: BlockId, else_: BlockId) {
+        let uid = self.fresh("term");
+        let then = self.target(&uid, then);
+        let else_ = self.target(&uid, else_);
+        self.terminate(uid, Terminator::CondBreak(condition, then, else_));
+    }
+
+    /// The function that was built, unless a mistake was made while building
+    /// it or one of its blocks has no terminator.
+    pub fn finish(self) -> Result<FunctionDecl> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut blocks = self.blocks.into_iter().map(|block| {
+            let terminator = match (block.terminator, &block.label) {
+                (Some(terminator), _) => terminator,
+                (None, None) => return Err(BuildError::UnterminatedEntry),
+                (None, Some(label)) => return Err(BuildError::UnterminatedBlock(label.clone())),
+            };
+            let label = block.label;
+            Ok((
+                label,
+                Block {
+                    instructions: block.instructions,
+                    terminator,
+                },
+            ))
+        });
+        let (_, entry) = blocks.next().expect("there is always an entry block")?;
+        let blocks = blocks
+            .map(|block| {
+                block.map(|(label, block)| (label.expect("only the entry is unlabelled"), block))
+            })
+            .collect::<Result<_>>()?;
+        Ok(FunctionDecl {
+            type_signature: self.type_signature,
+            parameters: self.parameters,
+            cfg: ControlFlowGraph { entry, blocks },
+        })
+    }
+}
+
+/// Builder of a whole program.
+#[derive(Debug, Default)]
+pub struct ModuleBuilder {
+    program: Program,
+    fresh: usize,
+    /// The first mistake made while building
+    error: Option<BuildError>,
+}
+
+impl ModuleBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// A global that has not been generated before, starting with `prefix`.
+    pub fn fresh_global(&mut self, prefix: &str) -> Gid {
+        let n = self.fresh;
+        self.fresh += 1;
+        format!("_{}{}", prefix, n)
+    }
+
+    fn fail(&mut self, error: BuildError) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Report `gid` if it is already defined, since globals, functions and
+    /// externals share a namespace.
+    fn define(&mut self, gid: &Gid) {
+        let Program {
+            globals,
+            functions,
+            externals,
+            ..
+        } = &self.program;
+        if globals.contains_key(gid) || functions.contains_key(gid) || externals.contains_key(gid) {
+            self.fail(BuildError::RedefinedGlobal(gid.clone()));
+        }
+    }
+
+    pub fn declare_type(&mut self, tid: Tid, t: Type) {
+        self.program.types.insert(tid, t);
+    }
+
+    pub fn define_global(&mut self, gid: Gid, t: Type, init: GlobalInitializer) {
+        self.define(&gid);
+        self.program.globals.insert(gid, GlobalDeclaration(t, init));
+    }
+
+    /// Declare the external global or function `gid` of type `t`.
+    pub fn declare_external(&mut self, gid: Gid, t: Type) {
+        self.define(&gid);
+        self.program.externals.insert(gid, t);
+    }
+
+    pub fn define_function(&mut self, gid: Gid, function: FunctionBuilder) {
+        self.define(&gid);
+        match function.finish() {
+            Ok(fdecl) => {
+                self.program.functions.insert(gid, fdecl);
+            }
+            Err(error) => self.fail(BuildError::In {
+                function: gid,
+                error: Box::new(error),
+            }),
+        }
+    }
+
+    /// The program that was built, unless a mistake was made while building
+    /// it.
+    pub fn finish(self) -> Result<Program> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.program),
+        }
+    }
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use super::*;
+    use crate::{parse_program, verify};
+    use oat_symbol::create_session_if_not_set_then;
+
+    fn signature(arg_types: Vec<Type>, ret_type: Type) -> FunctionType {
+        FunctionType {
+            arg_types,
+            ret_type,
+        }
+    }
+
+    fn id(uid: &str) -> Operand {
+        Operand::Id(uid.to_string())
+    }
+
+    #[test]
+    fn builds_functions() {
+        create_session_if_not_set_then(|_| {
+            let mut f =
+                FunctionBuilder::new(signature(vec![Type::I64], Type::I64), vec!["n".into()]);
+            let then = f.append_block("then");
+            let exit = f.append_block("exit");
+            let positive = f.build_icmp(Condition::Sgt, Type::I64, id("n"), Operand::Const(0));
+            let slot = f.build_entry_alloca(Type::I64);
+            f.build_store(Type::I64, id("n"), slot.clone());
+            f.build_cond_br(positive, then, exit);
+            f.position_at_end(then);
+            f.build_store(Type::I64, Operand::Const(0), slot.clone());
+            f.build_br(exit);
+            f.position_at_end(exit);
+            let result = f.build_load(Type::I64, slot);
+            f.build_call(
+                Type::Void,
+                Operand::Gid("print_int".into()),
+                vec![(Type::I64, result.clone())],
+            );
+            f.build_ret(Type::I64, Some(result));
+
+            let mut module = ModuleBuilder::new();
+            module.declare_external(
+                "print_int".into(),
+                Type::Fun(vec![Type::I64], Box::new(Type::Void)),
+            );
+            module.define_function("f".into(), f);
+            let program = module.finish().unwrap();
+            let expected = concat!(
+                "define i64 @f(i64 %n) {\n",
+                "  %_slot3 = alloca i64\n",
+                "  %_tmp2 = icmp sgt i64 %n, 0\n",
+                "  store i64 %n, i64* %_slot3\n",
+                "  br i1 %_tmp2, label %_then0, label %_exit1\n",
+                "_then0:\n",
+                "  store i64 0, i64* %_slot3\n",
+                "  br label %_exit1\n",
+                "_exit1:\n",
+                "  %_tmp8 = load i64, i64* %_slot3\n",
+                "  call void @print_int(i64 %_tmp8)\n",
+                "  ret i64 %_tmp8\n",
+                "}\n",
+            );
+            let printed = program.to_string();
+            assert!(printed.starts_with(expected));
+            assert_eq!(parse_program(&printed).unwrap().to_string(), printed);
+            assert_eq!(verify(&program), Ok(()));
+        })
+    }
+
+    #[test]
+    fn unterminated_blocks() {
+        let mut f = FunctionBuilder::new(signature(vec![], Type::Void), vec![]);
+        assert_eq!(f.finish(), Err(BuildError::UnterminatedEntry));
+
+        f = FunctionBuilder::new(signature(vec![], Type::Void), vec![]);
+        let exit = f.append_block("exit");
+        f.build_br(exit);
+        assert_eq!(
+            f.finish(),
+            Err(BuildError::UnterminatedBlock("_exit0".into()))
+        );
+    }
+
+    #[test]
+    fn building_after_terminator() {
+        let mut f = FunctionBuilder::new(signature(vec![], Type::I64), vec![]);
+        f.build_ret(Type::I64, Some(Operand::Const(0)));
+        f.build_binop(
+            BinaryOperator::Add,
+            Type::I64,
+            Operand::Const(1),
+            Operand::Const(2),
+        );
+        f.build_ret(Type::I64, Some(Operand::Const(3)));
+        assert_eq!(f.finish(), Err(BuildError::AfterTerminator("_tmp1".into())));
+    }
+
+    #[test]
+    fn branch_to_entry() {
+        let mut f = FunctionBuilder::new(signature(vec![], Type::Void), vec![]);
+        let entry = f.entry_block();
+        f.build_br(entry);
+        assert_eq!(f.finish(), Err(BuildError::BranchToEntry("_term0".into())));
+    }
+
+    #[test]
+    fn redefined_globals() {
+        let mut f = FunctionBuilder::new(signature(vec![], Type::Void), vec![]);
+        f.build_ret(Type::Void, None);
+        let mut module = ModuleBuilder::new();
+        module.define_global("f".into(), Type::I64, GlobalInitializer::Int(0));
+        module.define_function("f".into(), f);
+        assert_eq!(
+            module.finish(),
+            Err(BuildError::RedefinedGlobal("f".into()))
+        );
+
+        let mut module = ModuleBuilder::new();
+        module.define_function(
+            "g".into(),
+            FunctionBuilder::new(signature(vec![], Type::Void), vec![]),
+        );
+        assert_eq!(
+            module.finish(),
+            Err(BuildError::In {
+                function: "g".into(),
+                error: Box::new(BuildError::UnterminatedEntry)
+            })
+        );
+    }
+}
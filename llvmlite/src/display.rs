@@ -0,0 +1,474 @@
+//! Printing of LLVMLite programs as textual LLVM IR, which can be read by the
+//! LLVM tools, e.g. `clang program.ll runtime.c`.
+//!
+//! Named types and identifiers are printed by name, so printing must happen
+//! within the symbol session the program was built in.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::*;
+
+/// Write `items` separated by commas.
+fn comma_separated<T: Display>(
+    f: &mut Formatter<'_>,
+    items: impl IntoIterator<Item = T>,
+) -> fmt::Result {
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// Write the bytes of `s` within quotes, where `"`, `\` and unprintable
+/// bytes are written as `\XX` in hexadecimal.
+fn quoted(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{:02X}", byte)?,
+            b' '..=b'~' => write!(f, "{}", byte as char)?,
+            _ => write!(f, "\\{:02X}", byte)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// The name of a global, local, label or named type, quoted if it contains
+/// characters which LLVM does not allow in a bare name.
+struct Name<'a>(&'a str);
+
+impl Display for Name<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let bare = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '$' | '.' | '_');
+        let starts_with_digit = self.0.starts_with(|c: char| c.is_ascii_digit());
+        if !self.0.is_empty() && !starts_with_digit && self.0.chars().all(bare) {
+            write!(f, "{}", self.0)
+        } else {
+            quoted(f, self.0)
+        }
+    }
+}
+
+/// The type pointed to by the pointer type `t`.
+fn pointee(t: &Type) -> &Type {
+    match t {
+        Type::Ptr(t) => t,
+        t => panic!("{} is not a pointer type", t),
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use Type::*;
+        match self {
+            Void => write!(f, "void"),
+            I1 => write!(f, "i1"),
+            I8 => write!(f, "i8"),
+            I64 => write!(f, "i64"),
+            Ptr(t) => write!(f, "{}*", t),
+            Struct(fields) => {
+                write!(f, "{{ ")?;
+                comma_separated(f, fields)?;
+                write!(f, " }}")
+            }
+            Array(n, t) => write!(f, "[{} x {}]", n, t),
+            Fun(args, ret) => {
+                write!(f, "{} (", ret)?;
+                comma_separated(f, args)?;
+                write!(f, ")")
+            }
+            Namedt(tid) => write!(f, "%{}", Name(tid.name())),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use Operand::*;
+        match self {
+            Null => write!(f, "null"),
+            Const(n) => write!(f, "{}", n),
+            Gid(gid) => write!(f, "@{}", Name(gid)),
+            Id(uid) => write!(f, "%{}", Name(uid)),
+        }
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use BinaryOperator::*;
+        match self {
+            Add => write!(f, "add"),
+            Sub => write!(f, "sub"),
+            Mul => write!(f, "mul"),
+            Shl => write!(f, "shl"),
+            Lshr => write!(f, "lshr"),
+            Ashr => write!(f, "ashr"),
+            And => write!(f, "and"),
+            Or => write!(f, "or"),
+            Xor => write!(f, "xor"),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use Condition::*;
+        match self {
+            Eq => write!(f, "eq"),
+            Ne => write!(f, "ne"),
+            Slt => write!(f, "slt"),
+            Sle => write!(f, "sle"),
+            Sge => write!(f, "sge"),
+            Sgt => write!(f, "sgt"),
+        }
+    }
+}
+
+/// An index of a `getelementptr`. Indices into structs have to be `i32`
+/// constants, and constant indices into arrays may be `i32` as well.
+struct GepIndex<'a>(&'a Operand);
+
+impl Display for GepIndex<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Operand::Const(n) => write!(f, "i32 {}", n),
+            op => write!(f, "i64 {}", op),
+        }
+    }
+}
+
+/// The instruction, without the name of its result.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match self {
+            Binop(op, t, left, right) => write!(f, "{} {} {}, {}", op, t, left, right),
+            Alloca(t) => write!(f, "alloca {}", t),
+            Load(t, ptr) => write!(f, "load {}, {} {}", pointee(t), t, ptr),
+            Store(t, value, ptr) => write!(f, "store {} {}, {}* {}", t, value, t, ptr),
+            Icmp(cnd, t, left, right) => write!(f, "icmp {} {} {}, {}", cnd, t, left, right),
+            Call(ret, fun, args) => {
+                write!(f, "call {} {}(", ret, fun)?;
+                comma_separated(f, args.iter().map(|(t, op)| format!("{} {}", t, op)))?;
+                write!(f, ")")
+            }
+            Bitcast(from, op, to) => write!(f, "bitcast {} {} to {}", from, op, to),
+            Gep(t, ptr, indices) => {
+                write!(f, "getelementptr {}, {} {}", pointee(t), t, ptr)?;
+                for index in indices {
+                    write!(f, ", {}", GepIndex(index))?;
+                }
+                Ok(())
+            }
+            Phi(t, incoming) => {
+                write!(f, "phi {} ", t)?;
+                let incoming = incoming
+                    .iter()
+                    .map(|(op, label)| format!("[ {}, %{} ]", op, Name(label)));
+                comma_separated(f, incoming)
+            }
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use Terminator::*;
+        match self {
+            Ret(t, None) => write!(f, "ret {}", t),
+            Ret(t, Some(op)) => write!(f, "ret {} {}", t, op),
+            Break(label) => write!(f, "br label %{}", Name(label)),
+            CondBreak(op, then, else_) => write!(
+                f,
+                "br i1 {}, label %{}, label %{}",
+                op,
+                Name(then),
+                Name(else_)
+            ),
+        }
+    }
+}
+
+/// The instructions of the block, one per line.
+impl Display for Block {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (uid, instruction) in &self.instructions {
+            if instruction.has_result() {
+                writeln!(f, "  %{} = {}", Name(uid), instruction)?;
+            } else {
+                writeln!(f, "  {}", instruction)?;
+            }
+        }
+        writeln!(f, "  {}", self.terminator.1)
+    }
+}
+
+impl Display for ControlFlowGraph {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // The entry block is only labelled when phi nodes refer to it
+        let phis = std::iter::once(&self.entry)
+            .chain(self.blocks.values())
+            .flat_map(|block| &block.instructions)
+            .filter_map(|(_, instruction)| match instruction {
+                Instruction::Phi(_, incoming) => Some(incoming),
+                _ => None,
+            });
+        if phis.flatten().any(|(_, label)| label == ENTRY_LABEL) {
+            writeln!(f, "{}:", ENTRY_LABEL)?;
+        }
+        write!(f, "{}", self.entry)?;
+        for (label, block) in &self.blocks {
+            write!(f, "{}:\n{}", Name(label), block)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for GlobalInitializer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use GlobalInitializer::*;
+        let typed = |(t, init): &(Type, GlobalInitializer)| format!("{} {}", t, init);
+        match self {
+            Null => write!(f, "null"),
+            Gid(gid) => write!(f, "@{}", Name(gid)),
+            Int(n) => write!(f, "{}", n),
+            // Strings are terminated by a null byte
+            String(s) => {
+                write!(f, "c")?;
+                quoted(f, &format!("{}\0", s))
+            }
+            Array(elements) => {
+                write!(f, "[")?;
+                comma_separated(f, elements.iter().map(typed))?;
+                write!(f, "]")
+            }
+            Struct(fields) => {
+                write!(f, "{{ ")?;
+                comma_separated(f, fields.iter().map(typed))?;
+                write!(f, " }}")
+            }
+            Bitcast(from, init, to) => write!(f, "bitcast ({} {} to {})", from, init, to),
+        }
+    }
+}
+
+/// The type and initializer of the global.
+impl Display for GlobalDeclaration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.0, self.1)
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (tid, t) in &self.types {
+            writeln!(f, "%{} = type {}", Name(tid.name()), t)?;
+        }
+        if !self.types.is_empty() {
+            writeln!(f)?;
+        }
+        for (gid, global) in &self.globals {
+            writeln!(f, "@{} = global {}", Name(gid), global)?;
+        }
+        if !self.globals.is_empty() {
+            writeln!(f)?;
+        }
+        for (gid, fdecl) in &self.functions {
+            let FunctionType {
+                arg_types,
+                ret_type,
+            } = &fdecl.type_signature;
+            write!(f, "define {} @{}(", ret_type, Name(gid))?;
+            let parameters = arg_types
+                .iter()
+                .zip(&fdecl.parameters)
+                .map(|(t, uid)| format!("{} %{}", t, Name(uid)));
+            comma_separated(f, parameters)?;
+            writeln!(f, ") {{\n{}}}\n", fdecl.cfg)?;
+        }
+        for (gid, t) in &self.externals {
+            match t {
+                Type::Fun(args, ret) => {
+                    write!(f, "declare {} @{}(", ret, Name(gid))?;
+                    comma_separated(f, args)?;
+                    writeln!(f, ")")?;
+                }
+                t => writeln!(f, "@{} = external global {}", Name(gid), t)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod display_tests {
+    use super::*;
+    use indexmap::IndexMap;
+    use oat_symbol::create_session_if_not_set_then;
+
+    fn ptr(t: Type) -> Type {
+        Type::Ptr(Box::new(t))
+    }
+
+    #[test]
+    fn types() {
+        create_session_if_not_set_then(|_| {
+            let array = ptr(Type::Struct(vec![
+                Type::I64,
+                Type::Array(0, Box::new(Type::Namedt("point".into()))),
+            ]));
+            assert_eq!(array.to_string(), "{ i64, [0 x %point] }*");
+            let fun = ptr(Type::Fun(
+                vec![Type::I64, ptr(Type::I8)],
+                Box::new(Type::Void),
+            ));
+            assert_eq!(fun.to_string(), "void (i64, i8*)*");
+            assert_eq!(Type::Namedt("list$int$".into()).to_string(), "%list$int$");
+            assert_eq!(Type::Namedt("a b".into()).to_string(), "%\"a b\"");
+        })
+    }
+
+    #[test]
+    fn instructions() {
+        let load = Instruction::Load(ptr(Type::I64), Operand::Id("x".into()));
+        assert_eq!(load.to_string(), "load i64, i64* %x");
+        let store = Instruction::Store(Type::I1, Operand::Const(1), Operand::Id("b".into()));
+        assert_eq!(store.to_string(), "store i1 1, i1* %b");
+        let call = Instruction::Call(
+            Type::Void,
+            Operand::Gid("print_string".into()),
+            vec![(ptr(Type::I8), Operand::Null)],
+        );
+        assert_eq!(call.to_string(), "call void @print_string(i8* null)");
+        let gep = Instruction::Gep(
+            ptr(Type::Struct(vec![
+                Type::I64,
+                Type::Array(0, Box::new(Type::I64)),
+            ])),
+            Operand::Id("a".into()),
+            vec![
+                Operand::Const(0),
+                Operand::Const(1),
+                Operand::Id("i".into()),
+            ],
+        );
+        assert_eq!(
+            gep.to_string(),
+            "getelementptr { i64, [0 x i64] }, { i64, [0 x i64] }* %a, i32 0, i32 1, i64 %i"
+        );
+        let phi = Instruction::Phi(
+            Type::I64,
+            vec![
+                (Operand::Const(0), ENTRY_LABEL.into()),
+                (Operand::Id("x".into()), "loop".into()),
+            ],
+        );
+        assert_eq!(phi.to_string(), "phi i64 [ 0, %entry ], [ %x, %loop ]");
+    }
+
+    #[test]
+    fn globals() {
+        let string = GlobalDeclaration(
+            Type::Array(5, Box::new(Type::I8)),
+            GlobalInitializer::String("a\"\n\\".into()),
+        );
+        assert_eq!(string.to_string(), "[5 x i8] c\"a\\22\\0A\\5C\\00\"");
+        let array = GlobalInitializer::Bitcast(
+            ptr(Type::Struct(vec![
+                Type::I64,
+                Type::Array(1, Box::new(Type::I64)),
+            ])),
+            Box::new(GlobalInitializer::Gid("_array0".into())),
+            ptr(Type::Struct(vec![
+                Type::I64,
+                Type::Array(0, Box::new(Type::I64)),
+            ])),
+        );
+        assert_eq!(
+            array.to_string(),
+            "bitcast ({ i64, [1 x i64] }* @_array0 to { i64, [0 x i64] }*)"
+        );
+    }
+
+    #[test]
+    fn program() {
+        create_session_if_not_set_then(|_| {
+            let mut blocks = IndexMap::new();
+            blocks.insert(
+                "_exit".to_string(),
+                Block {
+                    instructions: vec![],
+                    terminator: (
+                        "_term2".into(),
+                        Terminator::Ret(Type::I64, Some(Operand::Id("_tmp1".into()))),
+                    ),
+                },
+            );
+            let mut functions = IndexMap::new();
+            functions.insert(
+                "program".to_string(),
+                FunctionDecl {
+                    type_signature: FunctionType {
+                        arg_types: vec![Type::I64],
+                        ret_type: Type::I64,
+                    },
+                    parameters: vec!["argc".into()],
+                    cfg: ControlFlowGraph {
+                        entry: Block {
+                            instructions: vec![(
+                                "_tmp1".into(),
+                                Instruction::Binop(
+                                    BinaryOperator::Add,
+                                    Type::I64,
+                                    Operand::Id("argc".into()),
+                                    Operand::Const(3),
+                                ),
+                            )],
+                            terminator: ("_term0".into(), Terminator::Break("_exit".into())),
+                        },
+                        blocks,
+                    },
+                },
+            );
+            let mut types = IndexMap::new();
+            types.insert("point".into(), Type::Struct(vec![Type::I64, Type::I64]));
+            let mut globals = IndexMap::new();
+            globals.insert(
+                "n".to_string(),
+                GlobalDeclaration(Type::I64, GlobalInitializer::Int(3)),
+            );
+            let mut externals = IndexMap::new();
+            externals.insert(
+                "oat_malloc".to_string(),
+                Type::Fun(vec![Type::I64], Box::new(ptr(Type::I64))),
+            );
+            let program = Program {
+                types,
+                globals,
+                functions,
+                externals,
+            };
+            assert_eq!(
+                program.to_string(),
+                concat!(
+                    "%point = type { i64, i64 }\n",
+                    "\n",
+                    "@n = global i64 3\n",
+                    "\n",
+                    "define i64 @program(i64 %argc) {\n",
+                    "  %_tmp1 = add i64 %argc, 3\n",
+                    "  br label %_exit\n",
+                    "_exit:\n",
+                    "  ret i64 %_tmp1\n",
+                    "}\n",
+                    "\n",
+                    "declare i64* @oat_malloc(i64)\n",
+                )
+            );
+        })
+    }
+}
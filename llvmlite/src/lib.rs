@@ -1,2 +1,8 @@
 pub mod ast;
+pub mod builder;
+mod display;
+mod parse;
+mod verify;
 pub use ast::*;
+pub use parse::{parse_instruction, parse_program, parse_terminator, parse_type, ParseError};
+pub use verify::{verify, VerifyError};
@@ -0,0 +1,739 @@
+//! Parsing of the LLVMLite subset of textual LLVM IR, as printed by the
+//! [`Display`](std::fmt::Display) implementation of [`Program`].
+//!
+//! Stores and calls of `void` functions have no result in LLVM, and
+//! terminators have no name, but they are named in LLVMLite: they get fresh
+//! names such as `_store0`. Named types are interned as symbols, so parsing
+//! must happen within a symbol session.
+
+use indexmap::IndexMap;
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while},
+    character::complete::{
+        char, i64 as integer, multispace1, not_line_ending, satisfy, u64 as natural,
+    },
+    combinator::{all_consuming, cut, map, map_opt, not, opt, recognize, value, verify},
+    multi::{fold_many0, many0, separated_list0},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
+    Finish, IResult,
+};
+use oat_symbol::Symbol;
+use thiserror::Error;
+
+use crate::ast::*;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Unexpected input at line {line}: {text:?}")]
+    UnexpectedInput { line: usize, text: String },
+}
+
+/// Whitespace and comments, which run from `;` to the end of the line.
+fn space(input: &str) -> IResult<&str, ()> {
+    value(
+        (),
+        many0(alt((multispace1, preceded(char(';'), not_line_ending)))),
+    )(input)
+}
+
+/// A combinator consuming the whitespace following `inner`.
+fn lexeme<'a, O>(
+    inner: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+    terminated(inner, space)
+}
+
+fn symbol<'a>(c: char) -> impl FnMut(&'a str) -> IResult<&'a str, char> {
+    lexeme(char(c))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '$' | '.' | '_')
+}
+
+/// A keyword, which is not the start of a longer word.
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, ()> {
+    value(
+        (),
+        lexeme(terminated(tag(word), not(satisfy(is_name_char)))),
+    )
+}
+
+/// The bytes of a quoted string, in which `\XX` is the byte `XX` in
+/// hexadecimal.
+fn quoted(input: &str) -> IResult<&str, Vec<u8>> {
+    map_opt(
+        delimited(char('"'), take_while(|c| c != '"'), char('"')),
+        |s: &str| {
+            let mut bytes = vec![];
+            let mut rest = s.as_bytes();
+            while let Some((&byte, tail)) = rest.split_first() {
+                if byte == b'\\' {
+                    let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+                    bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                    rest = &tail[2..];
+                } else {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+            Some(bytes)
+        },
+    )(input)
+}
+
+/// A bare or quoted name, following the sigil of a global or local.
+fn name(input: &str) -> IResult<&str, String> {
+    alt((
+        map(
+            recognize(pair(
+                satisfy(|c| is_name_char(c) && !c.is_ascii_digit()),
+                take_while(is_name_char),
+            )),
+            String::from,
+        ),
+        map(recognize(natural), String::from),
+        map_opt(quoted, |bytes| String::from_utf8(bytes).ok()),
+    ))(input)
+}
+
+fn gid(input: &str) -> IResult<&str, Gid> {
+    lexeme(preceded(char('@'), name))(input)
+}
+
+fn uid(input: &str) -> IResult<&str, Uid> {
+    lexeme(preceded(char('%'), name))(input)
+}
+
+/// The label introducing a block.
+fn label(input: &str) -> IResult<&str, Label> {
+    lexeme(terminated(name, char(':')))(input)
+}
+
+fn comma_separated<'a, O>(
+    inner: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<O>> {
+    separated_list0(symbol(','), inner)
+}
+
+fn parenthesized<'a, O>(
+    inner: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+    delimited(symbol('('), inner, symbol(')'))
+}
+
+fn base_type(input: &str) -> IResult<&str, Type> {
+    alt((
+        value(Type::Void, keyword("void")),
+        value(Type::I1, keyword("i1")),
+        value(Type::I8, keyword("i8")),
+        value(Type::I64, keyword("i64")),
+        map(uid, |name| Type::Namedt(Symbol::intern(&name))),
+        map(
+            delimited(symbol('{'), comma_separated(parse_type), symbol('}')),
+            Type::Struct,
+        ),
+        map(
+            delimited(
+                symbol('['),
+                separated_pair(lexeme(natural), keyword("x"), parse_type),
+                symbol(']'),
+            ),
+            |(n, t)| Type::Array(n as usize, Box::new(t)),
+        ),
+    ))(input)
+}
+
+/// Parse a type. Pointer and function types are written after the type they
+/// point to or return, e.g. `i64 (i8*)*`.
+pub fn parse_type(input: &str) -> IResult<&str, Type> {
+    let (input, t) = base_type(input)?;
+    fold_many0(
+        alt((
+            map(symbol('*'), |_| None),
+            map(parenthesized(comma_separated(parse_type)), Some),
+        )),
+        move || t.clone(),
+        |t, suffix| match suffix {
+            None => Type::Ptr(Box::new(t)),
+            Some(args) => Type::Fun(args, Box::new(t)),
+        },
+    )(input)
+}
+
+fn operand(input: &str) -> IResult<&str, Operand> {
+    alt((
+        value(Operand::Null, keyword("null")),
+        value(Operand::Const(1), keyword("true")),
+        value(Operand::Const(0), keyword("false")),
+        map(lexeme(integer), Operand::Const),
+        map(gid, Operand::Gid),
+        map(uid, Operand::Id),
+    ))(input)
+}
+
+fn typed_operand(input: &str) -> IResult<&str, (Type, Operand)> {
+    pair(parse_type, operand)(input)
+}
+
+/// A pointer type and an operand of that type, where the pointer must point
+/// to `pointee`.
+fn pointer_operand<'a>(pointee: &Type, input: &'a str) -> IResult<&'a str, (Type, Operand)> {
+    verify(typed_operand, |(t, _)| match t {
+        Type::Ptr(t) => **t == *pointee,
+        _ => false,
+    })(input)
+}
+
+fn binary_operator(input: &str) -> IResult<&str, BinaryOperator> {
+    use BinaryOperator::*;
+    alt((
+        value(Add, keyword("add")),
+        value(Sub, keyword("sub")),
+        value(Mul, keyword("mul")),
+        value(Shl, keyword("shl")),
+        value(Lshr, keyword("lshr")),
+        value(Ashr, keyword("ashr")),
+        value(And, keyword("and")),
+        value(Or, keyword("or")),
+        value(Xor, keyword("xor")),
+    ))(input)
+}
+
+fn condition(input: &str) -> IResult<&str, Condition> {
+    use Condition::*;
+    alt((
+        value(Eq, keyword("eq")),
+        value(Ne, keyword("ne")),
+        value(Slt, keyword("slt")),
+        value(Sle, keyword("sle")),
+        value(Sge, keyword("sge")),
+        value(Sgt, keyword("sgt")),
+    ))(input)
+}
+
+fn load(input: &str) -> IResult<&str, Instruction> {
+    let (input, t) = preceded(keyword("load"), parse_type)(input)?;
+    let (input, (ptr_type, ptr)) = preceded(symbol(','), |i| pointer_operand(&t, i))(input)?;
+    Ok((input, Instruction::Load(ptr_type, ptr)))
+}
+
+fn store(input: &str) -> IResult<&str, Instruction> {
+    let (input, (t, value)) = preceded(keyword("store"), typed_operand)(input)?;
+    let (input, (_, ptr)) = preceded(symbol(','), |i| pointer_operand(&t, i))(input)?;
+    Ok((input, Instruction::Store(t, value, ptr)))
+}
+
+fn getelementptr(input: &str) -> IResult<&str, Instruction> {
+    let (input, t) = preceded(keyword("getelementptr"), parse_type)(input)?;
+    let (input, (ptr_type, ptr)) = preceded(symbol(','), |i| pointer_operand(&t, i))(input)?;
+    let index = preceded(alt((keyword("i32"), keyword("i64"))), operand);
+    let (input, indices) = many0(preceded(symbol(','), index))(input)?;
+    Ok((input, Instruction::Gep(ptr_type, ptr, indices)))
+}
+
+fn phi(input: &str) -> IResult<&str, Instruction> {
+    let incoming = delimited(
+        symbol('['),
+        separated_pair(operand, symbol(','), uid),
+        symbol(']'),
+    );
+    map(
+        preceded(keyword("phi"), pair(parse_type, comma_separated(incoming))),
+        |(t, incoming)| Instruction::Phi(t, incoming),
+    )(input)
+}
+
+/// Parse an instruction, without the name of its result.
+pub fn parse_instruction(input: &str) -> IResult<&str, Instruction> {
+    alt((
+        map(
+            tuple((binary_operator, parse_type, operand, symbol(','), operand)),
+            |(op, t, left, _, right)| Instruction::Binop(op, t, left, right),
+        ),
+        map(preceded(keyword("alloca"), parse_type), Instruction::Alloca),
+        load,
+        store,
+        map(
+            tuple((
+                keyword("icmp"),
+                condition,
+                parse_type,
+                operand,
+                symbol(','),
+                operand,
+            )),
+            |(_, cnd, t, left, _, right)| Instruction::Icmp(cnd, t, left, right),
+        ),
+        map(
+            tuple((
+                keyword("call"),
+                parse_type,
+                operand,
+                parenthesized(comma_separated(typed_operand)),
+            )),
+            |(_, t, fun, args)| Instruction::Call(t, fun, args),
+        ),
+        map(
+            tuple((
+                keyword("bitcast"),
+                parse_type,
+                operand,
+                keyword("to"),
+                parse_type,
+            )),
+            |(_, from, op, _, to)| Instruction::Bitcast(from, op, to),
+        ),
+        getelementptr,
+        phi,
+    ))(input)
+}
+
+/// Parse a terminator.
+pub fn parse_terminator(input: &str) -> IResult<&str, Terminator> {
+    alt((
+        preceded(
+            keyword("ret"),
+            alt((
+                map(keyword("void"), |_| Terminator::Ret(Type::Void, None)),
+                map(typed_operand, |(t, op)| Terminator::Ret(t, Some(op))),
+            )),
+        ),
+        preceded(
+            keyword("br"),
+            alt((
+                map(preceded(keyword("label"), uid), Terminator::Break),
+                map(
+                    tuple((
+                        preceded(keyword("i1"), operand),
+                        preceded(pair(symbol(','), keyword("label")), uid),
+                        preceded(pair(symbol(','), keyword("label")), uid),
+                    )),
+                    |(op, then, else_)| Terminator::CondBreak(op, then, else_),
+                ),
+            )),
+        ),
+    ))(input)
+}
+
+/// A block whose unnamed instructions are not named yet.
+type UnnamedBlock = (Vec<(Option<Uid>, Instruction)>, Terminator);
+
+fn block(input: &str) -> IResult<&str, UnnamedBlock> {
+    let named = map(
+        separated_pair(uid, symbol('='), parse_instruction),
+        |(uid, instruction)| (Some(uid), instruction),
+    );
+    let unnamed = map(parse_instruction, |instruction| (None, instruction));
+    pair(many0(alt((named, unnamed))), parse_terminator)(input)
+}
+
+/// A function whose unnamed instructions are not named yet.
+struct UnnamedFunction {
+    type_signature: FunctionType,
+    parameters: Vec<Uid>,
+    entry_label: Option<Label>,
+    entry: UnnamedBlock,
+    blocks: Vec<(Label, UnnamedBlock)>,
+}
+
+fn function(input: &str) -> IResult<&str, (Gid, UnnamedFunction)> {
+    let (input, (ret_type, name)) = preceded(keyword("define"), cut(pair(parse_type, gid)))(input)?;
+    let (input, parameters) = cut(parenthesized(comma_separated(pair(parse_type, uid))))(input)?;
+    // A label for the entry block is allowed, but cannot be branched to
+    let (input, ((entry_label, entry), blocks)) = cut(delimited(
+        symbol('{'),
+        pair(pair(opt(label), block), many0(pair(label, block))),
+        symbol('}'),
+    ))(input)?;
+    let (arg_types, parameters) = parameters.into_iter().unzip();
+    let function = UnnamedFunction {
+        type_signature: FunctionType {
+            arg_types,
+            ret_type,
+        },
+        parameters,
+        entry_label,
+        entry,
+        blocks,
+    };
+    Ok((input, (name, function)))
+}
+
+fn global_initializer(input: &str) -> IResult<&str, GlobalInitializer> {
+    let typed_initializer = || pair(parse_type, global_initializer);
+    alt((
+        map(keyword("null"), |_| GlobalInitializer::Null),
+        map(gid, GlobalInitializer::Gid),
+        map(lexeme(integer), GlobalInitializer::Int),
+        map(keyword("true"), |_| GlobalInitializer::Int(1)),
+        map(keyword("false"), |_| GlobalInitializer::Int(0)),
+        // Strings are terminated by a null byte, which is not part of them
+        map_opt(lexeme(preceded(char('c'), quoted)), |mut bytes| {
+            if bytes.pop()? != 0 {
+                return None;
+            }
+            String::from_utf8(bytes).ok().map(GlobalInitializer::String)
+        }),
+        map(
+            delimited(
+                symbol('['),
+                comma_separated(typed_initializer()),
+                symbol(']'),
+            ),
+            GlobalInitializer::Array,
+        ),
+        map(
+            delimited(
+                symbol('{'),
+                comma_separated(typed_initializer()),
+                symbol('}'),
+            ),
+            GlobalInitializer::Struct,
+        ),
+        map(
+            preceded(
+                keyword("bitcast"),
+                parenthesized(tuple((
+                    parse_type,
+                    global_initializer,
+                    keyword("to"),
+                    parse_type,
+                ))),
+            ),
+            |(from, init, _, to)| GlobalInitializer::Bitcast(from, Box::new(init), to),
+        ),
+    ))(input)
+}
+
+enum Declaration {
+    Type(Tid, Type),
+    Global(Gid, GlobalDeclaration),
+    Function(Gid, UnnamedFunction),
+    External(Gid, Type),
+}
+
+fn declaration(input: &str) -> IResult<&str, Declaration> {
+    alt((
+        map(
+            separated_pair(uid, pair(symbol('='), keyword("type")), cut(parse_type)),
+            |(name, t)| Declaration::Type(Symbol::intern(&name), t),
+        ),
+        map(
+            separated_pair(
+                gid,
+                tuple((symbol('='), keyword("external"), keyword("global"))),
+                cut(parse_type),
+            ),
+            |(name, t)| Declaration::External(name, t),
+        ),
+        map(
+            separated_pair(
+                gid,
+                pair(symbol('='), keyword("global")),
+                cut(pair(parse_type, global_initializer)),
+            ),
+            |(name, (t, init))| Declaration::Global(name, GlobalDeclaration(t, init)),
+        ),
+        map(
+            preceded(
+                keyword("declare"),
+                cut(tuple((
+                    parse_type,
+                    gid,
+                    parenthesized(comma_separated(parse_type)),
+                ))),
+            ),
+            |(ret, name, args)| Declaration::External(name, Type::Fun(args, Box::new(ret))),
+        ),
+        map(function, |(name, function)| {
+            Declaration::Function(name, function)
+        }),
+    ))(input)
+}
+
+/// Names the results of stores and calls of `void` functions, and the
+/// terminators, which are unnamed in the textual IR.
+#[derive(Default)]
+struct Namer(usize);
+
+impl Namer {
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.0 += 1;
+        format!("_{}{}", prefix, self.0 - 1)
+    }
+
+    fn block(&mut self, (instructions, terminator): UnnamedBlock) -> Block {
+        let instructions = instructions
+            .into_iter()
+            .map(|(uid, instruction)| {
+                let uid = uid.unwrap_or_else(|| match instruction {
+                    Instruction::Store(..) => self.fresh("store"),
+                    _ => self.fresh("call"),
+                });
+                (uid, instruction)
+            })
+            .collect();
+        Block {
+            instructions,
+            terminator: (self.fresh("term"), terminator),
+        }
+    }
+
+    fn function(&mut self, function: UnnamedFunction) -> FunctionDecl {
+        let mut entry = self.block(function.entry);
+        let mut blocks: IndexMap<_, _> = function
+            .blocks
+            .into_iter()
+            .map(|(label, block)| (label, self.block(block)))
+            .collect();
+        // Phi nodes refer to the entry block by its own label in the text
+        if let Some(entry_label) = function.entry_label {
+            let blocks = std::iter::once(&mut entry).chain(blocks.values_mut());
+            for (_, instruction) in blocks.flat_map(|block| &mut block.instructions) {
+                if let Instruction::Phi(_, incoming) = instruction {
+                    for (_, label) in incoming {
+                        if *label == entry_label {
+                            *label = ENTRY_LABEL.to_string();
+                        }
+                    }
+                }
+            }
+        }
+        FunctionDecl {
+            type_signature: function.type_signature,
+            parameters: function.parameters,
+            cfg: ControlFlowGraph { entry, blocks },
+        }
+    }
+}
+
+/// Parse an LLVMLite program. Declarations can come in any order.
+pub fn parse_program(input: &str) -> Result<Program, ParseError> {
+    let result = all_consuming(preceded(space, many0(declaration)))(input).finish();
+    let declarations = result
+        .map_err(|e| {
+            let offset = input.len() - e.input.len();
+            ParseError::UnexpectedInput {
+                line: input[..offset].matches('\n').count() + 1,
+                text: e.input.lines().next().unwrap_or_default().to_string(),
+            }
+        })?
+        .1;
+
+    let mut namer = Namer::default();
+    let mut program = Program {
+        types: IndexMap::new(),
+        globals: IndexMap::new(),
+        functions: IndexMap::new(),
+        externals: IndexMap::new(),
+    };
+    for declaration in declarations {
+        match declaration {
+            Declaration::Type(name, t) => {
+                program.types.insert(name, t);
+            }
+            Declaration::Global(name, global) => {
+                program.globals.insert(name, global);
+            }
+            Declaration::Function(name, function) => {
+                program.functions.insert(name, namer.function(function));
+            }
+            Declaration::External(name, t) => {
+                program.externals.insert(name, t);
+            }
+        }
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod parse_tests {
+    use super::*;
+    use oat_symbol::create_session_if_not_set_then;
+
+    fn ptr(t: Type) -> Type {
+        Type::Ptr(Box::new(t))
+    }
+
+    fn parsed<O>(parser: impl FnMut(&str) -> IResult<&str, O>, input: &str) -> O {
+        all_consuming(parser)(input)
+            .finish()
+            .expect("test input should parse")
+            .1
+    }
+
+    #[test]
+    fn types() {
+        create_session_if_not_set_then(|_| {
+            assert_eq!(
+                parsed(parse_type, "{ i64, [0 x %point] }*"),
+                ptr(Type::Struct(vec![
+                    Type::I64,
+                    Type::Array(0, Box::new(Type::Namedt("point".into()))),
+                ]))
+            );
+            assert_eq!(
+                parsed(parse_type, "void (i64, i8*)*"),
+                ptr(Type::Fun(
+                    vec![Type::I64, ptr(Type::I8)],
+                    Box::new(Type::Void)
+                ))
+            );
+            assert_eq!(
+                parsed(parse_type, "%\"a\\22b\""),
+                Type::Namedt("a\"b".into())
+            );
+        })
+    }
+
+    #[test]
+    fn instructions() {
+        assert_eq!(
+            parsed(parse_instruction, "load i64, i64* %x"),
+            Instruction::Load(ptr(Type::I64), Operand::Id("x".into()))
+        );
+        assert_eq!(
+            parsed(parse_instruction, "store i1 true, i1* @b"),
+            Instruction::Store(Type::I1, Operand::Const(1), Operand::Gid("b".into()))
+        );
+        assert_eq!(
+            parsed(
+                parse_instruction,
+                "getelementptr {i64}, {i64}* %a, i32 0, i64 %i"
+            ),
+            Instruction::Gep(
+                ptr(Type::Struct(vec![Type::I64])),
+                Operand::Id("a".into()),
+                vec![Operand::Const(0), Operand::Id("i".into())],
+            )
+        );
+        // The pointer has to point to the loaded type
+        assert!(all_consuming(parse_instruction)("load i64, i1* %x").is_err());
+    }
+
+    #[test]
+    fn program() {
+        create_session_if_not_set_then(|_| {
+            let source = concat!(
+                "; A comment\n",
+                "%point = type { i64, i64 }\n",
+                "@s = global [3 x i8] c\"hi\\00\"\n",
+                "define void @f(i64 %x) {\n",
+                "entry:\n",
+                "  store i64 %x, i64* @n\n",
+                "  %c = icmp slt i64 %x, -1 ; negative\n",
+                "  br i1 %c, label %then, label %exit\n",
+                "then:\n",
+                "  call void @print_string(i8* null)\n",
+                "  br label %exit\n",
+                "exit:\n",
+                "  ret void\n",
+                "}\n",
+                "@n = global i64 0\n",
+                "declare void @print_string(i8*)\n",
+            );
+            let program = parse_program(source).unwrap();
+            assert_eq!(
+                program.globals["s"],
+                GlobalDeclaration(
+                    Type::Array(3, Box::new(Type::I8)),
+                    GlobalInitializer::String("hi".into())
+                )
+            );
+            let f = &program.functions["f"];
+            assert_eq!(f.parameters, vec!["x".to_string()]);
+            let uids: Vec<_> = f
+                .cfg
+                .entry
+                .instructions
+                .iter()
+                .map(|(uid, _)| uid)
+                .collect();
+            assert_eq!(uids, vec!["_store0", "c"]);
+            assert_eq!(
+                f.cfg.blocks.keys().collect::<Vec<_>>(),
+                vec!["then", "exit"]
+            );
+            assert_eq!(f.cfg.blocks["then"].instructions[0].0, "_call2".to_string());
+            assert_eq!(
+                program.externals["print_string"],
+                Type::Fun(vec![ptr(Type::I8)], Box::new(Type::Void))
+            );
+        })
+    }
+
+    #[test]
+    fn printed_program_parses_back() {
+        create_session_if_not_set_then(|_| {
+            let source = concat!(
+                "%\"odd name\" = type { i64, %\"odd name\"* }\n",
+                "\n",
+                "@_array0 = global { i64, [2 x i64] } { i64 2, [2 x i64] [i64 1, i64 -2] }\n",
+                "@xs = global { i64, [0 x i64] }* bitcast ({ i64, [2 x i64] }* @_array0 to { i64, [0 x i64] }*)\n",
+                "\n",
+                "define i64 @program(i64 %argc, { i64, [0 x i8*] }* %argv) {\n",
+                "  %_tmp0 = load { i64, [0 x i64] }*, { i64, [0 x i64] }** @xs\n",
+                "  %_tmp1 = getelementptr { i64, [0 x i64] }, { i64, [0 x i64] }* %_tmp0, i32 0, i32 1, i64 %argc\n",
+                "  %_tmp2 = load i64, i64* %_tmp1\n",
+                "  %_tmp3 = call i64 @f(i64 %_tmp2)\n",
+                "  ret i64 %_tmp3\n",
+                "}\n",
+                "\n",
+                "declare i64 @f(i64)\n",
+            );
+            assert_eq!(parse_program(source).unwrap().to_string(), source);
+        })
+    }
+
+    #[test]
+    fn phi_nodes() {
+        create_session_if_not_set_then(|_| {
+            // The entry block can have any label, and phi nodes refer to it
+            // by that label
+            let source = concat!(
+                "define i64 @f(i64 %n) {\n",
+                "start:\n",
+                "  br label %loop\n",
+                "loop:\n",
+                "  %i = phi i64 [ 0, %start ], [ %j, %loop ]\n",
+                "  %j = add i64 %i, 1\n",
+                "  %c = icmp slt i64 %j, %n\n",
+                "  br i1 %c, label %loop, label %exit\n",
+                "exit:\n",
+                "  ret i64 %j\n",
+                "}\n\n",
+            );
+            let program = parse_program(source).unwrap();
+            let f = &program.functions["f"];
+            assert_eq!(
+                f.cfg.blocks["loop"].instructions[0].1,
+                Instruction::Phi(
+                    Type::I64,
+                    vec![
+                        (Operand::Const(0), ENTRY_LABEL.into()),
+                        (Operand::Id("j".into()), "loop".into()),
+                    ]
+                )
+            );
+            let printed = program.to_string();
+            assert_eq!(printed, source.replace("start", ENTRY_LABEL));
+            assert_eq!(parse_program(&printed).unwrap(), program);
+        })
+    }
+
+    #[test]
+    fn errors() {
+        create_session_if_not_set_then(|_| {
+            let source = "define void @f() {\n  %x = frobnicate i64 1\n  ret void\n}\n";
+            assert_eq!(
+                parse_program(source),
+                Err(ParseError::UnexpectedInput {
+                    line: 2,
+                    text: "%x = frobnicate i64 1".into(),
+                })
+            );
+        })
+    }
+}
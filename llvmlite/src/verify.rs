@@ -0,0 +1,775 @@
+//! Checking that LLVMLite programs are well formed, so that the mistakes of
+//! a pass are reported where they are made rather than as a crash of a
+//! backend.
+//!
+//! Named types are nominal: `%point` is not the same type as its definition.
+//! A local has to be defined before it is used on every path to the use, that
+//! is its definition has to dominate its uses. Blocks which cannot be reached
+//! are dominated by every block.
+
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::ast::*;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum VerifyError {
+    #[error("Unknown named type %{}", .0.name())]
+    UnknownType(Tid),
+
+    #[error("%{} is an alias of itself", .0.name())]
+    CyclicType(Tid),
+
+    #[error("Unknown global @{0}")]
+    UnknownGlobal(Gid),
+
+    #[error("@{0} is defined more than once")]
+    RedefinedGlobal(Gid),
+
+    #[error("Undefined local %{0}")]
+    UndefinedLocal(Uid),
+
+    #[error("%{0} is defined more than once")]
+    RedefinedLocal(Uid),
+
+    #[error("%{0} is used before it is defined")]
+    UseBeforeDefinition(Uid),
+
+    #[error("Unknown label %{0}")]
+    UnknownLabel(Label),
+
+    #[error("The label %{0} is reserved for the entry block")]
+    ReservedLabel(Label),
+
+    #[error("Phi nodes must come before the other instructions of their block")]
+    MisplacedPhi,
+
+    #[error("The incoming blocks of the phi node are not the predecessors of its block")]
+    PhiPredecessors,
+
+    #[error("Expected an operand of type {expected}, found {operand}")]
+    OperandType { operand: Operand, expected: Type },
+
+    #[error("Invalid type {0} for the instruction")]
+    InvalidType(Type),
+
+    #[error("Invalid getelementptr path on {0}")]
+    InvalidGep(Type),
+
+    #[error("The call of {0} does not match its type")]
+    CallMismatch(Operand),
+
+    #[error("The signature of the function does not match its parameters")]
+    SignatureMismatch,
+
+    #[error("Invalid initializer for @{0}")]
+    InvalidInitializer(Gid),
+
+    #[error("In @{function}, at %{uid}: {error}")]
+    At {
+        function: Gid,
+        uid: Uid,
+        error: Box<VerifyError>,
+    },
+}
+
+type Result<T> = std::result::Result<T, VerifyError>;
+
+fn is_integer(t: &Type) -> bool {
+    matches!(t, Type::I1 | Type::I8 | Type::I64)
+}
+
+fn is_pointer(t: &Type) -> bool {
+    matches!(t, Type::Ptr(_))
+}
+
+/// The types that appear in an instruction.
+fn instruction_types(instruction: &Instruction) -> Vec<&Type> {
+    use Instruction::*;
+    match instruction {
+        Binop(_, t, ..) | Alloca(t) | Load(t, _) | Store(t, ..) | Icmp(_, t, ..) | Gep(t, ..) => {
+            vec![t]
+        }
+        Call(t, _, args) => std::iter::once(t)
+            .chain(args.iter().map(|(t, _)| t))
+            .collect(),
+        Bitcast(from, _, to) => vec![from, to],
+        Phi(t, _) => vec![t],
+    }
+}
+
+struct Verifier<'a> {
+    program: &'a Program,
+    /// The types of the globals, which are pointers to their contents
+    globals: HashMap<&'a str, Type>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(program: &'a Program) -> Result<Self> {
+        let mut globals = HashMap::new();
+        let declared = program
+            .globals
+            .iter()
+            .map(|(gid, GlobalDeclaration(t, _))| (gid, t.clone()))
+            .chain(program.functions.iter().map(|(gid, fdecl)| {
+                let FunctionType {
+                    arg_types,
+                    ret_type,
+                } = &fdecl.type_signature;
+                (
+                    gid,
+                    Type::Fun(arg_types.clone(), Box::new(ret_type.clone())),
+                )
+            }))
+            .chain(program.externals.iter().map(|(gid, t)| (gid, t.clone())));
+        for (gid, t) in declared {
+            if globals
+                .insert(gid.as_str(), Type::Ptr(Box::new(t)))
+                .is_some()
+            {
+                return Err(VerifyError::RedefinedGlobal(gid.clone()));
+            }
+        }
+        Ok(Verifier { program, globals })
+    }
+
+    /// Check that the named types within `t` are defined.
+    fn check_type(&self, t: &Type) -> Result<()> {
+        use Type::*;
+        match t {
+            Void | I1 | I8 | I64 => Ok(()),
+            Ptr(t) | Array(_, t) => self.check_type(t),
+            Struct(fields) => fields.iter().try_for_each(|t| self.check_type(t)),
+            Fun(args, ret) => {
+                args.iter().try_for_each(|t| self.check_type(t))?;
+                self.check_type(ret)
+            }
+            Namedt(tid) => match self.program.types.contains_key(tid) {
+                true => Ok(()),
+                false => Err(VerifyError::UnknownType(*tid)),
+            },
+        }
+    }
+
+    /// Check that following the aliases from the named type `tid` ends at a
+    /// type that is not named, so that [`Verifier::resolve`] terminates.
+    fn check_alias(&self, tid: Tid) -> Result<()> {
+        let mut seen = HashSet::from([tid]);
+        let mut t = self.program.types.get(&tid);
+        while let Some(Type::Namedt(next)) = t {
+            if !seen.insert(*next) {
+                return Err(VerifyError::CyclicType(*next));
+            }
+            t = self.program.types.get(next);
+        }
+        Ok(())
+    }
+
+    /// The definition of `t` if it is a named type, and `t` otherwise.
+    fn resolve<'t>(&'t self, t: &'t Type) -> Result<&'t Type> {
+        match t {
+            Type::Namedt(tid) => match self.program.types.get(tid) {
+                Some(t) => self.resolve(t),
+                None => Err(VerifyError::UnknownType(*tid)),
+            },
+            t => Ok(t),
+        }
+    }
+
+    /// The type of the pointer computed by a `getelementptr` on the pointer
+    /// type `t` with `path`. Indices into structs have to be constants.
+    fn gep_type(&self, t: &Type, path: &[Operand]) -> Result<Type> {
+        let invalid = || VerifyError::InvalidGep(t.clone());
+        let mut current = match (t, path) {
+            (Type::Ptr(pointee), [_, ..]) => pointee.as_ref(),
+            _ => return Err(invalid()),
+        };
+        for index in &path[1..] {
+            current = match (self.resolve(current)?, index) {
+                (Type::Struct(fields), Operand::Const(i)) => usize::try_from(*i)
+                    .ok()
+                    .and_then(|i| fields.get(i))
+                    .ok_or_else(invalid)?,
+                (Type::Array(_, element), _) => element,
+                _ => return Err(invalid()),
+            };
+        }
+        Ok(Type::Ptr(Box::new(current.clone())))
+    }
+
+    /// Whether `init` is a constant of type `t`.
+    fn check_initializer(&self, t: &Type, init: &GlobalInitializer) -> Result<bool> {
+        use GlobalInitializer::*;
+        let fields_match = |types: Vec<&Type>, fields: &[(Type, GlobalInitializer)]| {
+            if types.len() != fields.len() {
+                return Ok(false);
+            }
+            for (t, (field_type, init)) in types.into_iter().zip(fields) {
+                if t != field_type || !self.check_initializer(t, init)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        Ok(match (self.resolve(t)?, init) {
+            (Type::Ptr(_), Null) => true,
+            (_, Gid(gid)) => match self.globals.get(gid.as_str()) {
+                Some(global_type) => global_type == t,
+                None => return Err(VerifyError::UnknownGlobal(gid.clone())),
+            },
+            (t, Int(_)) => is_integer(t),
+            (Type::Array(n, element), String(s)) => *n == s.len() + 1 && **element == Type::I8,
+            (Type::Array(n, element), Array(elements)) => {
+                *n == elements.len() && fields_match(vec![element.as_ref(); *n], elements)?
+            }
+            (Type::Struct(types), Struct(fields)) => fields_match(types.iter().collect(), fields)?,
+            (_, Bitcast(from, init, to)) => {
+                is_pointer(from)
+                    && is_pointer(to)
+                    && to == t
+                    && self.check_initializer(from, init)?
+            }
+            _ => false,
+        })
+    }
+
+    fn check_program(&self) -> Result<()> {
+        let program = self.program;
+        for (tid, t) in &program.types {
+            self.check_type(t)?;
+            self.check_alias(*tid)?;
+        }
+        for t in program.externals.values() {
+            self.check_type(t)?;
+        }
+        for (gid, GlobalDeclaration(t, init)) in &program.globals {
+            self.check_type(t)?;
+            if !self.check_initializer(t, init)? {
+                return Err(VerifyError::InvalidInitializer(gid.clone()));
+            }
+        }
+        for (gid, fdecl) in &program.functions {
+            FunctionVerifier::new(self, gid, fdecl)?.check()?;
+        }
+        Ok(())
+    }
+}
+
+/// The position of an instruction: the index of its block, where the entry
+/// block is 0, and its index in the block, where the terminator comes last.
+type Position = (usize, usize);
+
+struct FunctionVerifier<'a> {
+    verifier: &'a Verifier<'a>,
+    name: &'a Gid,
+    fdecl: &'a FunctionDecl,
+    blocks: Vec<&'a Block>,
+    /// The types of the locals, and where they are defined, which is `None`
+    /// for parameters
+    locals: HashMap<&'a str, (Type, Option<Position>)>,
+    predecessors: Vec<Vec<usize>>,
+    /// The blocks dominating each block
+    dominators: Vec<HashSet<usize>>,
+}
+
+impl<'a> FunctionVerifier<'a> {
+    fn new(verifier: &'a Verifier<'a>, name: &'a Gid, fdecl: &'a FunctionDecl) -> Result<Self> {
+        let blocks: Vec<_> = std::iter::once(&fdecl.cfg.entry)
+            .chain(fdecl.cfg.blocks.values())
+            .collect();
+        let mut function = FunctionVerifier {
+            verifier,
+            name,
+            fdecl,
+            blocks,
+            locals: HashMap::new(),
+            predecessors: vec![],
+            dominators: vec![],
+        };
+        if fdecl.cfg.blocks.contains_key(ENTRY_LABEL) {
+            return Err(VerifyError::ReservedLabel(ENTRY_LABEL.to_string()));
+        }
+        let FunctionType {
+            arg_types,
+            ret_type,
+        } = &fdecl.type_signature;
+        if arg_types.len() != fdecl.parameters.len() {
+            return Err(VerifyError::SignatureMismatch);
+        }
+        verifier.check_type(ret_type)?;
+        for (t, uid) in arg_types.iter().zip(&fdecl.parameters) {
+            verifier.check_type(t)?;
+            function.define(uid, t.clone(), None)?;
+        }
+        for (b, block) in function.blocks.clone().into_iter().enumerate() {
+            for (i, (uid, instruction)) in block.instructions.iter().enumerate() {
+                let t = function
+                    .result_type(instruction)
+                    .map_err(|e| function.at(uid, e))?;
+                if t != Type::Void {
+                    function
+                        .define(uid, t, Some((b, i)))
+                        .map_err(|e| function.at(uid, e))?;
+                }
+            }
+        }
+        function.predecessors = function.predecessors()?;
+        function.dominators = function.dominators();
+        Ok(function)
+    }
+
+    fn define(&mut self, uid: &'a str, t: Type, position: Option<Position>) -> Result<()> {
+        match self.locals.insert(uid, (t, position)) {
+            Some(_) => Err(VerifyError::RedefinedLocal(uid.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Attribute `error` to the instruction or terminator `uid`.
+    fn at(&self, uid: &str, error: VerifyError) -> VerifyError {
+        VerifyError::At {
+            function: self.name.clone(),
+            uid: uid.to_string(),
+            error: Box::new(error),
+        }
+    }
+
+    fn block_index(&self, label: &Label) -> Result<usize> {
+        match self.fdecl.cfg.blocks.get_index_of(label) {
+            Some(i) => Ok(i + 1),
+            None => Err(VerifyError::UnknownLabel(label.clone())),
+        }
+    }
+
+    fn successors(&self, terminator: &Terminator) -> Result<Vec<usize>> {
+        match terminator {
+            Terminator::Ret(..) => Ok(vec![]),
+            Terminator::Break(label) => Ok(vec![self.block_index(label)?]),
+            Terminator::CondBreak(_, then, else_) => {
+                Ok(vec![self.block_index(then)?, self.block_index(else_)?])
+            }
+        }
+    }
+
+    fn predecessors(&self) -> Result<Vec<Vec<usize>>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for (b, block) in self.blocks.iter().enumerate() {
+            let (uid, terminator) = &block.terminator;
+            for successor in self.successors(terminator).map_err(|e| self.at(uid, e))? {
+                if !predecessors[successor].contains(&b) {
+                    predecessors[successor].push(b);
+                }
+            }
+        }
+        Ok(predecessors)
+    }
+
+    /// The dominators of every block, computed by iterating to a fixed point.
+    fn dominators(&self) -> Vec<HashSet<usize>> {
+        let n = self.blocks.len();
+        let predecessors = &self.predecessors;
+        let all: HashSet<usize> = (0..n).collect();
+        let mut dominators = vec![all; n];
+        dominators[0] = HashSet::from([0]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in 1..n {
+                let mut new = match predecessors[b].split_first() {
+                    Some((first, rest)) => rest.iter().fold(dominators[*first].clone(), |d, p| {
+                        d.intersection(&dominators[*p]).copied().collect()
+                    }),
+                    None => continue,
+                };
+                new.insert(b);
+                if new != dominators[b] {
+                    dominators[b] = new;
+                    changed = true;
+                }
+            }
+        }
+        dominators
+    }
+
+    /// The type of the result of `instruction`, which is `void` if it has
+    /// none.
+    fn result_type(&self, instruction: &Instruction) -> Result<Type> {
+        use Instruction::*;
+        for t in instruction_types(instruction) {
+            self.verifier.check_type(t)?;
+        }
+        let invalid = |t: &Type| Err(VerifyError::InvalidType(t.clone()));
+        Ok(match instruction {
+            Binop(_, t, ..) if is_integer(t) => t.clone(),
+            Alloca(t) => Type::Ptr(Box::new(t.clone())),
+            Load(Type::Ptr(t), _) => t.as_ref().clone(),
+            Store(..) => Type::Void,
+            Icmp(_, t, ..) if is_integer(t) || is_pointer(t) => Type::I1,
+            Call(t, ..) => t.clone(),
+            Bitcast(from, _, to) if is_pointer(from) && is_pointer(to) => to.clone(),
+            Gep(t, _, path) => self.verifier.gep_type(t, path)?,
+            Phi(t, _) if is_integer(t) || is_pointer(t) => t.clone(),
+            Binop(_, t, ..) | Load(t, _) | Icmp(_, t, ..) | Phi(t, _) => return invalid(t),
+            Bitcast(from, ..) => return invalid(from),
+        })
+    }
+
+    /// The type of `op`, used at `position`.
+    fn operand_type(&self, op: &Operand, position: Position) -> Result<Option<&Type>> {
+        match op {
+            Operand::Null | Operand::Const(_) => Ok(None),
+            Operand::Gid(gid) => match self.verifier.globals.get(gid.as_str()) {
+                Some(t) => Ok(Some(t)),
+                None => Err(VerifyError::UnknownGlobal(gid.clone())),
+            },
+            Operand::Id(uid) => {
+                let (t, definition) = match self.locals.get(uid.as_str()) {
+                    Some(local) => local,
+                    None => return Err(VerifyError::UndefinedLocal(uid.clone())),
+                };
+                let (block, index) = position;
+                let dominates = match *definition {
+                    None => true,
+                    Some((b, i)) if b == block => i < index,
+                    Some((b, _)) => self.dominators[block].contains(&b),
+                };
+                match dominates {
+                    true => Ok(Some(t)),
+                    false => Err(VerifyError::UseBeforeDefinition(uid.clone())),
+                }
+            }
+        }
+    }
+
+    /// Check that `op`, used at `position`, has the type `expected`.
+    fn check_operand(&self, op: &Operand, expected: &Type, position: Position) -> Result<()> {
+        let matches = match (op, self.operand_type(op, position)?) {
+            (Operand::Null, _) => is_pointer(expected),
+            (Operand::Const(_), _) => is_integer(expected),
+            (_, t) => t == Some(expected),
+        };
+        match matches {
+            true => Ok(()),
+            false => Err(VerifyError::OperandType {
+                operand: op.clone(),
+                expected: expected.clone(),
+            }),
+        }
+    }
+
+    fn check_instruction(&self, instruction: &Instruction, position: Position) -> Result<()> {
+        use Instruction::*;
+        let check = |op, t| self.check_operand(op, t, position);
+        match instruction {
+            Binop(_, t, left, right) | Icmp(_, t, left, right) => {
+                check(left, t)?;
+                check(right, t)
+            }
+            Alloca(_) => Ok(()),
+            Load(t, ptr) => check(ptr, t),
+            Store(t, value, ptr) => {
+                check(value, t)?;
+                check(ptr, &Type::Ptr(Box::new(t.clone())))
+            }
+            Call(ret, fun, args) => {
+                let arg_types = args.iter().map(|(t, _)| t.clone()).collect();
+                let expected = Type::Ptr(Box::new(Type::Fun(arg_types, Box::new(ret.clone()))));
+                match check(fun, &expected) {
+                    Err(VerifyError::OperandType { .. }) => {
+                        return Err(VerifyError::CallMismatch(fun.clone()))
+                    }
+                    result => result?,
+                }
+                args.iter().try_for_each(|(t, op)| check(op, t))
+            }
+            Bitcast(from, op, _) => check(op, from),
+            Gep(t, ptr, path) => {
+                check(ptr, t)?;
+                path.iter().try_for_each(|index| check(index, &Type::I64))
+            }
+            Phi(t, incoming) => self.check_phi(t, incoming, position),
+        }
+    }
+
+    /// Check a phi node, whose operands are used at the end of the block
+    /// they come from.
+    fn check_phi(&self, t: &Type, incoming: &[(Operand, Label)], position: Position) -> Result<()> {
+        let (block, index) = position;
+        let instructions = &self.blocks[block].instructions;
+        if instructions[..index]
+            .iter()
+            .any(|(_, instruction)| !matches!(instruction, Instruction::Phi(..)))
+        {
+            return Err(VerifyError::MisplacedPhi);
+        }
+        let mut sources = vec![];
+        for (op, label) in incoming {
+            let source = match label.as_str() {
+                ENTRY_LABEL => 0,
+                _ => self.block_index(label)?,
+            };
+            self.check_operand(op, t, (source, self.blocks[source].instructions.len()))?;
+            sources.push(source);
+        }
+        sources.sort_unstable();
+        let mut predecessors = self.predecessors[block].clone();
+        predecessors.sort_unstable();
+        match sources == predecessors {
+            true => Ok(()),
+            false => Err(VerifyError::PhiPredecessors),
+        }
+    }
+
+    fn check_terminator(&self, terminator: &Terminator, position: Position) -> Result<()> {
+        let ret_type = &self.fdecl.type_signature.ret_type;
+        match terminator {
+            Terminator::Ret(t, _) if t != ret_type => Err(VerifyError::InvalidType(t.clone())),
+            Terminator::Ret(Type::Void, None) => Ok(()),
+            Terminator::Ret(t, Some(op)) if *t != Type::Void => self.check_operand(op, t, position),
+            Terminator::Ret(t, _) => Err(VerifyError::InvalidType(t.clone())),
+            // The labels were checked when computing dominators
+            Terminator::Break(_) => Ok(()),
+            Terminator::CondBreak(op, ..) => self.check_operand(op, &Type::I1, position),
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        for (b, block) in self.blocks.iter().enumerate() {
+            for (i, (uid, instruction)) in block.instructions.iter().enumerate() {
+                self.check_instruction(instruction, (b, i))
+                    .map_err(|e| self.at(uid, e))?;
+            }
+            let (uid, terminator) = &block.terminator;
+            self.check_terminator(terminator, (b, block.instructions.len()))
+                .map_err(|e| self.at(uid, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that `program` is well formed.
+pub fn verify(program: &Program) -> Result<()> {
+    Verifier::new(program)?.check_program()
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use super::*;
+    use crate::parse_program;
+    use oat_symbol::create_session_if_not_set_then;
+
+    fn verified(source: &str) -> Result<()> {
+        create_session_if_not_set_then(|_| {
+            verify(&parse_program(source).expect("test program should parse"))
+        })
+    }
+
+    /// The error of the instruction or terminator `uid` of `@f`.
+    fn at(uid: &str, error: VerifyError) -> Result<()> {
+        Err(VerifyError::At {
+            function: "f".into(),
+            uid: uid.into(),
+            error: Box::new(error),
+        })
+    }
+
+    #[test]
+    fn well_formed() {
+        let source = concat!(
+            "%point = type { i64, i64 }\n",
+            "@origin = global %point { i64 0, i64 0 }\n",
+            "@s = global [3 x i8] c\"hi\\00\"\n",
+            "define i64 @f(i1 %b, { i64, [0 x %point*] }* %a) {\n",
+            "  %p = getelementptr { i64, [0 x %point*] }, { i64, [0 x %point*] }* %a, i32 0, i32 1, i64 2\n",
+            "  %q = load %point*, %point** %p\n",
+            "  %y = getelementptr %point, %point* %q, i32 0, i32 1\n",
+            "  %s = bitcast [3 x i8]* @s to i8*\n",
+            "  call void @print_string(i8* %s)\n",
+            "  br i1 %b, label %then, label %exit\n",
+            "then:\n",
+            "  store i64 1, i64* %y\n",
+            "  br label %exit\n",
+            "exit:\n",
+            "  %v = load i64, i64* %y\n",
+            "  ret i64 %v\n",
+            "}\n",
+            "declare void @print_string(i8*)\n",
+        );
+        assert_eq!(verified(source), Ok(()));
+    }
+
+    #[test]
+    fn unknown_type() {
+        let source = "@p = global %point* null\n";
+        create_session_if_not_set_then(|_| {
+            assert_eq!(
+                verify(&parse_program(source).unwrap()),
+                Err(VerifyError::UnknownType("point".into()))
+            );
+        })
+    }
+
+    #[test]
+    fn cyclic_aliases() {
+        let source = "%a = type %b\n%b = type %a\n@p = global %a 0\n";
+        create_session_if_not_set_then(|_| {
+            let error = verify(&parse_program(source).unwrap());
+            assert!(
+                error == Err(VerifyError::CyclicType("a".into()))
+                    || error == Err(VerifyError::CyclicType("b".into())),
+                "unexpected result {:?}",
+                error
+            );
+            assert_eq!(
+                verify(&parse_program("%a = type %a\n").unwrap()),
+                Err(VerifyError::CyclicType("a".into()))
+            );
+        })
+    }
+
+    #[test]
+    fn use_before_definition() {
+        let source = concat!(
+            "define i64 @f(i1 %b) {\n",
+            "  br i1 %b, label %then, label %exit\n",
+            "then:\n",
+            "  %x = add i64 1, 2\n",
+            "  br label %exit\n",
+            "exit:\n",
+            "  ret i64 %x\n",
+            "}\n",
+        );
+        assert_eq!(
+            verified(source),
+            at("_term2", VerifyError::UseBeforeDefinition("x".into()))
+        );
+    }
+
+    #[test]
+    fn phi_nodes() {
+        let phi = |incoming: &str| {
+            format!(
+                concat!(
+                    "define i64 @f(i1 %b) {{\n",
+                    "  br i1 %b, label %then, label %exit\n",
+                    "then:\n",
+                    "  %x = add i64 1, 2\n",
+                    "  br label %exit\n",
+                    "exit:\n",
+                    "  %y = phi i64 {}\n",
+                    "  ret i64 %y\n",
+                    "}}\n",
+                ),
+                incoming
+            )
+        };
+        assert_eq!(verified(&phi("[ 0, %entry ], [ %x, %then ]")), Ok(()));
+        assert_eq!(
+            verified(&phi("[ %x, %entry ], [ %x, %then ]")),
+            at("y", VerifyError::UseBeforeDefinition("x".into()))
+        );
+        assert_eq!(
+            verified(&phi("[ %x, %then ]")),
+            at("y", VerifyError::PhiPredecessors)
+        );
+        assert_eq!(
+            verified(&phi("[ 0, %entry ], [ %x, %nowhere ]")),
+            at("y", VerifyError::UnknownLabel("nowhere".into()))
+        );
+
+        let misplaced = concat!(
+            "define i64 @f() {\n",
+            "  br label %exit\n",
+            "exit:\n",
+            "  %x = add i64 1, 2\n",
+            "  %y = phi i64 [ 0, %entry ]\n",
+            "  ret i64 %y\n",
+            "}\n",
+        );
+        assert_eq!(verified(misplaced), at("y", VerifyError::MisplacedPhi));
+
+        let reserved = "define void @f() {\n  br label %entry\nentry:\n  ret void\n}\n";
+        assert_eq!(
+            verified(reserved),
+            Err(VerifyError::ReservedLabel(ENTRY_LABEL.into()))
+        );
+    }
+
+    #[test]
+    fn redefined_local() {
+        let source = "define void @f(i64 %x) {\n  %x = add i64 1, 2\n  ret void\n}\n";
+        assert_eq!(
+            verified(source),
+            at("x", VerifyError::RedefinedLocal("x".into()))
+        );
+    }
+
+    #[test]
+    fn operand_types() {
+        let source = "define i64 @f(i1 %b) {\n  %x = add i64 %b, 2\n  ret i64 %x\n}\n";
+        assert_eq!(
+            verified(source),
+            at(
+                "x",
+                VerifyError::OperandType {
+                    operand: Operand::Id("b".into()),
+                    expected: Type::I64,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_label() {
+        let source = "define void @f() {\n  br label %nowhere\n}\n";
+        assert_eq!(
+            verified(source),
+            at("_term0", VerifyError::UnknownLabel("nowhere".into()))
+        );
+    }
+
+    #[test]
+    fn invalid_gep() {
+        let source = concat!(
+            "define void @f({ i64 }* %p) {\n",
+            "  %x = getelementptr { i64 }, { i64 }* %p, i32 0, i32 1\n",
+            "  ret void\n",
+            "}\n",
+        );
+        create_session_if_not_set_then(|_| {
+            let struct_type = Type::Ptr(Box::new(Type::Struct(vec![Type::I64])));
+            assert_eq!(
+                verify(&parse_program(source).unwrap()),
+                at("x", VerifyError::InvalidGep(struct_type))
+            );
+        })
+    }
+
+    #[test]
+    fn call_mismatch() {
+        let source = concat!(
+            "define void @f() {\n",
+            "  call void @g(i1 0)\n",
+            "  ret void\n",
+            "}\n",
+            "declare void @g(i64)\n",
+        );
+        assert_eq!(
+            verified(source),
+            at(
+                "_call0",
+                VerifyError::CallMismatch(Operand::Gid("g".into()))
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_initializer() {
+        let source = "@s = global [2 x i8] c\"hi\\00\"\n";
+        assert_eq!(
+            verified(source),
+            Err(VerifyError::InvalidInitializer("s".into()))
+        );
+    }
+}
@@ -8,6 +8,8 @@ pub type Id = Symbol;
 
 pub mod macros;
 
+pub mod visit;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum UnaryOp {
     Neg,
@@ -38,6 +40,10 @@ pub enum BinaryOp {
     Gte,
     And,
     Or,
+    /// `&&`, which only evaluates its right operand if the left one is true
+    AndAlso,
+    /// `||`, which only evaluates its right operand if the left one is false
+    OrElse,
     IAnd,
     IOr,
     Shl,
@@ -56,7 +62,7 @@ impl BinaryOp {
                 Some(((Type::Int, Type::Int), Type::Int))
             }
             Lt | Lte | Gt | Gte => Some(((Type::Int, Type::Int), Type::Bool)),
-            And | Or => Some(((Type::Bool, Type::Bool), Type::Bool)),
+            And | Or | AndAlso | OrElse => Some(((Type::Bool, Type::Bool), Type::Bool)),
             Eq | Neq => None,
         }
     }
@@ -70,18 +76,29 @@ pub enum Type {
     Int,
     Ref(ReferenceType),
     NullRef(ReferenceType),
+    /// A type parameter of a generic function or struct
+    #[display(fmt = "{}", "_0.name()")]
+    Param(Id),
 }
 
 impl Type {
     pub const fn is_nullable(&self) -> bool {
         matches!(self, Type::NullRef(_))
     }
+
+    /// Whether values of the type have a default, so that arrays of it can
+    /// be created without initializers.
+    pub const fn has_default_value(&self) -> bool {
+        matches!(self, Type::Int | Type::Bool | Type::NullRef(_))
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ReferenceType {
     String,
     Struct(Id),
+    /// An instantiation of a generic struct, e.g. `box<int>`
+    GenericStruct(Id, Vec<Type>),
     Array(Box<Type>),
     Function(Vec<Type>, Box<ReturnType>),
 }
@@ -91,6 +108,14 @@ impl std::fmt::Display for ReferenceType {
         match self {
             ReferenceType::String => write!(f, "string"),
             ReferenceType::Struct(id) => write!(f, "{}", id.name()),
+            ReferenceType::GenericStruct(id, type_args) => {
+                write!(f, "{}<", id.name())?;
+                type_args.iter().enumerate().try_for_each(|(i, t)| match i {
+                    0 => write!(f, "{}", t),
+                    _ => write!(f, ", {}", t),
+                })?;
+                write!(f, ">")
+            }
             ReferenceType::Array(t) => write!(f, "{}[]", t),
             ReferenceType::Function(arg_types, return_type) => {
                 write!(f, "(")?;
@@ -127,7 +152,11 @@ pub enum Expression {
         index: Box<Expression>,
     },
     Length(Box<Expression>),
-    CStruct(Id, Vec<(Id, Expression)>),
+    /// Struct literal. The type arguments are empty for non-generic structs,
+    /// and are filled in by the type checker when they are inferred.
+    CStruct(Id, Vec<Type>, Vec<(Id, Expression)>),
+    /// Explicit instantiation of a generic function, e.g. `reverse<int>`
+    Instantiate(Id, Vec<Type>),
     Proj(Box<Expression>, Id),
     Call(Box<Expression>, Vec<Expression>),
     Binary {
@@ -187,7 +216,10 @@ impl From<String> for Expression {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Statement {
     Assignment(Expression, Expression),
-    Declaration(Id, Expression),
+    /// Declaration of a local variable, either with an explicit type
+    /// (`int x = 3;`) or with the type inferred from the initializer
+    /// (`var x = 3;`)
+    Declaration(Option<Type>, Id, Expression),
     SCall(Expression, Vec<Expression>),
     If {
         condition: Expression,
@@ -234,10 +266,19 @@ pub struct GlobalDeclaration {
     pub init: Expression,
 }
 
+/// An attribute attached to a declaration, e.g. `#[allow(unused_locals)]`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Attribute {
+    pub name: Id,
+    pub args: Vec<Id>,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct FunctionDecl {
+    pub attributes: Vec<Attribute>,
     pub return_type: ReturnType,
     pub name: Id,
+    pub type_params: Vec<Id>,
     pub args: Vec<(Type, Id)>,
     pub body: Block,
 }
@@ -251,6 +292,7 @@ pub struct FunctionDecl {
 #[derive(Debug, PartialEq, Clone, Eq)]
 pub struct TypeDeclaration {
     pub name: Id,
+    pub type_params: Vec<Id>,
     pub fields: IndexMap<Id, Type>,
 }
 
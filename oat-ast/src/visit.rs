@@ -0,0 +1,284 @@
+//! In-place traversal of the AST.
+//!
+//! Passes that rewrite the types or expressions of a program, such as the
+//! substitution of type parameters, implement their rewrite as a closure and
+//! apply it with [`VisitMut::visit_types_mut`] or
+//! [`VisitMut::visit_expressions_mut`]. Nodes are visited innermost first, so
+//! a replacement node is not visited again.
+
+use crate::*;
+
+/// The callbacks applied while walking the AST.
+pub struct Visitor<'a> {
+    types: Option<&'a mut dyn FnMut(&mut Type)>,
+    expressions: Option<&'a mut dyn FnMut(&mut Expression)>,
+}
+
+impl Visitor<'_> {
+    fn type_(&mut self, t: &mut Type) {
+        if let Some(f) = &mut self.types {
+            f(t)
+        }
+    }
+
+    fn expression(&mut self, e: &mut Expression) {
+        if let Some(f) = &mut self.expressions {
+            f(e)
+        }
+    }
+}
+
+pub trait VisitMut {
+    fn walk(&mut self, visitor: &mut Visitor<'_>);
+
+    /// Call `f` on every type in `self`, including the types appearing in
+    /// expressions and statements.
+    fn visit_types_mut(&mut self, mut f: impl FnMut(&mut Type))
+    where
+        Self: Sized,
+    {
+        self.walk(&mut Visitor {
+            types: Some(&mut f),
+            expressions: None,
+        })
+    }
+
+    /// Call `f` on every expression in `self`.
+    fn visit_expressions_mut(&mut self, mut f: impl FnMut(&mut Expression))
+    where
+        Self: Sized,
+    {
+        self.walk(&mut Visitor {
+            types: None,
+            expressions: Some(&mut f),
+        })
+    }
+}
+
+impl<T: VisitMut> VisitMut for Vec<T> {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        self.iter_mut().for_each(|node| node.walk(visitor))
+    }
+}
+
+impl<T: VisitMut> VisitMut for Option<T> {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        if let Some(node) = self {
+            node.walk(visitor)
+        }
+    }
+}
+
+impl<T: VisitMut> VisitMut for Box<T> {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        self.as_mut().walk(visitor)
+    }
+}
+
+fn walk_reference_type_children(rt: &mut ReferenceType, visitor: &mut Visitor<'_>) {
+    match rt {
+        ReferenceType::String | ReferenceType::Struct(_) => {}
+        ReferenceType::GenericStruct(_, type_args) => type_args.walk(visitor),
+        ReferenceType::Array(t) => t.walk(visitor),
+        ReferenceType::Function(arg_types, ret) => {
+            arg_types.walk(visitor);
+            ret.walk(visitor);
+        }
+    }
+}
+
+impl VisitMut for Type {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        if let Type::Ref(rt) | Type::NullRef(rt) = self {
+            walk_reference_type_children(rt, visitor);
+        }
+        visitor.type_(self)
+    }
+}
+
+/// A reference type is visited as the type `Ref(rt)`. A rewrite of it into
+/// anything other than a reference type is ignored.
+impl VisitMut for ReferenceType {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        walk_reference_type_children(self, visitor);
+        let mut t = Type::Ref(self.clone());
+        visitor.type_(&mut t);
+        if let Type::Ref(rt) = t {
+            *self = rt;
+        }
+    }
+}
+
+impl VisitMut for ReturnType {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        if let ReturnType::ReturnValue(t) = self {
+            t.walk(visitor)
+        }
+    }
+}
+
+impl VisitMut for Expression {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        use Expression::*;
+        match self {
+            CBool(_) | CInt(_) | CStr(_) | Id(_) => {}
+            CNull(rt) => rt.walk(visitor),
+            CArr(t, elements) => {
+                t.walk(visitor);
+                elements.walk(visitor);
+            }
+            NewArr(t, length) => {
+                t.walk(visitor);
+                length.walk(visitor);
+            }
+            Index { value, index } => {
+                value.walk(visitor);
+                index.walk(visitor);
+            }
+            Length(e) | Proj(e, _) | Unary(_, e) => e.walk(visitor),
+            CStruct(_, type_args, fields) => {
+                type_args.walk(visitor);
+                fields.iter_mut().for_each(|(_, e)| e.walk(visitor));
+            }
+            Instantiate(_, type_args) => type_args.walk(visitor),
+            Call(fun, args) => {
+                fun.walk(visitor);
+                args.walk(visitor);
+            }
+            Binary { left, right, .. } => {
+                left.walk(visitor);
+                right.walk(visitor);
+            }
+        }
+        visitor.expression(self)
+    }
+}
+
+impl VisitMut for Statement {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        use Statement::*;
+        match self {
+            Assignment(target, value) => {
+                target.walk(visitor);
+                value.walk(visitor);
+            }
+            Declaration(t, _, init) => {
+                t.walk(visitor);
+                init.walk(visitor);
+            }
+            SCall(fun, args) => {
+                fun.walk(visitor);
+                args.walk(visitor);
+            }
+            If {
+                condition,
+                then,
+                else_,
+            } => {
+                condition.walk(visitor);
+                then.walk(visitor);
+                else_.walk(visitor);
+            }
+            Cast(rt, _, e, then, else_) => {
+                rt.walk(visitor);
+                e.walk(visitor);
+                then.walk(visitor);
+                else_.walk(visitor);
+            }
+            For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                init.iter_mut().for_each(|(_, e)| e.walk(visitor));
+                condition.walk(visitor);
+                update.walk(visitor);
+                body.walk(visitor);
+            }
+            While { condition, body } => {
+                condition.walk(visitor);
+                body.walk(visitor);
+            }
+            Return(e) => e.walk(visitor),
+        }
+    }
+}
+
+impl VisitMut for GlobalDeclaration {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        self.init.walk(visitor)
+    }
+}
+
+impl VisitMut for FunctionDecl {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        self.return_type.walk(visitor);
+        self.args.iter_mut().for_each(|(t, _)| t.walk(visitor));
+        self.body.walk(visitor);
+    }
+}
+
+impl VisitMut for TypeDeclaration {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        self.fields.values_mut().for_each(|t| t.walk(visitor))
+    }
+}
+
+impl VisitMut for Declaration {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        match self {
+            Declaration::Variable(global) => global.walk(visitor),
+            Declaration::Function(fdecl) => fdecl.walk(visitor),
+            Declaration::Type(tdecl) => tdecl.walk(visitor),
+        }
+    }
+}
+
+impl VisitMut for Program {
+    fn walk(&mut self, visitor: &mut Visitor<'_>) {
+        self.declarations.walk(visitor)
+    }
+}
+
+/// Replace the type parameters `params` by the corresponding `type_args`.
+pub fn substitute<T: VisitMut>(node: &mut T, params: &[Id], type_args: &[Type]) {
+    node.visit_types_mut(|t| {
+        if let Type::Param(name) = t {
+            if let Some(i) = params.iter().position(|param| param == name) {
+                *t = type_args[i].clone();
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod visit_tests {
+    use super::*;
+    use oat_symbol::create_session_if_not_set_then;
+
+    #[test]
+    fn substitute_nested() {
+        create_session_if_not_set_then(|_| {
+            let t: Id = "T".into();
+            let mut e = Expression::CArr(
+                Type::Ref(ReferenceType::Array(Box::new(Type::Param(t)))),
+                vec![Expression::CNull(ReferenceType::GenericStruct(
+                    "box".into(),
+                    vec![Type::Param(t)],
+                ))],
+            );
+            substitute(&mut e, &[t], &[Type::Int]);
+            assert_eq!(
+                e,
+                Expression::CArr(
+                    Type::Ref(ReferenceType::Array(Box::new(Type::Int))),
+                    vec![Expression::CNull(ReferenceType::GenericStruct(
+                        "box".into(),
+                        vec![Type::Int],
+                    ))],
+                )
+            );
+        })
+    }
+}
@@ -6,6 +6,9 @@ pub use parser::ParseError;
 mod types;
 pub use types::TypeError;
 
+mod lints;
+pub use lints::{Lint, Warning};
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Type error: {0}")]
@@ -13,4 +16,7 @@ pub enum Error {
 
     #[error("Parser Error: {0}")]
     ParserError(#[from] ParseError),
+
+    #[error("Denied warning [{}]: {0}", .0.lint())]
+    Lint(#[from] Warning),
 }
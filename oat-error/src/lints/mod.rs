@@ -0,0 +1,103 @@
+use oat_ast::Id;
+use thiserror::Error;
+
+/// The categories of warnings, which can be allowed, warned about or denied
+/// individually.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Lint {
+    UnusedLocals,
+    UnusedParameters,
+    UnusedFunctions,
+    UnusedFields,
+    UnusedGlobals,
+    UselessAssignments,
+    ConstantConditions,
+    Shadowing,
+    UnknownLints,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 9] = [
+        Lint::UnusedLocals,
+        Lint::UnusedParameters,
+        Lint::UnusedFunctions,
+        Lint::UnusedFields,
+        Lint::UnusedGlobals,
+        Lint::UselessAssignments,
+        Lint::ConstantConditions,
+        Lint::Shadowing,
+        Lint::UnknownLints,
+    ];
+
+    /// The name of the lint used on the command line and in attributes
+    pub const fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedLocals => "unused_locals",
+            Lint::UnusedParameters => "unused_parameters",
+            Lint::UnusedFunctions => "unused_functions",
+            Lint::UnusedFields => "unused_fields",
+            Lint::UnusedGlobals => "unused_globals",
+            Lint::UselessAssignments => "useless_assignments",
+            Lint::ConstantConditions => "constant_conditions",
+            Lint::Shadowing => "shadowing",
+            Lint::UnknownLints => "unknown_lints",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(PartialEq, Debug, Error)]
+pub enum Warning {
+    #[error("Variable {} in {} is never used", .name.name(), .function.name())]
+    UnusedLocal { function: Id, name: Id },
+
+    #[error("Parameter {} of {} is never used", .name.name(), .function.name())]
+    UnusedParameter { function: Id, name: Id },
+
+    #[error("Function {} is never called", .0.name())]
+    UnusedFunction(Id),
+
+    #[error("Field {} of struct {} is never read", .field.name(), .struct_name.name())]
+    UnusedField { struct_name: Id, field: Id },
+
+    #[error("Global {} is never used", .0.name())]
+    UnusedGlobal(Id),
+
+    #[error("Value assigned to {} in {} is never read", .name.name(), .function.name())]
+    UselessAssignment { function: Id, name: Id },
+
+    #[error("Condition in {} is always {value}", .function.name())]
+    ConstantCondition { function: Id, value: bool },
+
+    #[error("Local {} in {} shadows a global or function", .name.name(), .function.name())]
+    Shadowing { function: Id, name: Id },
+
+    #[error("Unknown lint {} in attribute of {}", .name.name(), .function.name())]
+    UnknownLint { function: Id, name: Id },
+}
+
+impl Warning {
+    /// The lint that produced this warning
+    pub const fn lint(&self) -> Lint {
+        match self {
+            Warning::UnusedLocal { .. } => Lint::UnusedLocals,
+            Warning::UnusedParameter { .. } => Lint::UnusedParameters,
+            Warning::UnusedFunction(_) => Lint::UnusedFunctions,
+            Warning::UnusedField { .. } => Lint::UnusedFields,
+            Warning::UnusedGlobal(_) => Lint::UnusedGlobals,
+            Warning::UselessAssignment { .. } => Lint::UselessAssignments,
+            Warning::ConstantCondition { .. } => Lint::ConstantConditions,
+            Warning::Shadowing { .. } => Lint::Shadowing,
+            Warning::UnknownLint { .. } => Lint::UnknownLints,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use oat_ast::{Id, Type};
+use oat_ast::{BinaryOp, Id, Type, UnaryOp};
 use thiserror::Error;
 
 #[derive(PartialEq, Debug, Error)]
@@ -18,6 +18,12 @@ pub enum TypeError {
     #[error("Field {1:?} not found for type {0:?}")]
     FieldNotFound(Type, Id),
 
+    #[error("Cannot project field {1:?} from possibly null {0:?}")]
+    ProjectionOnNullable(Type, Id),
+
+    #[error("Struct literal of type {0:?} has no field {1:?}")]
+    ExtraField(Type, Id),
+
     #[error("Duplicate field {0:?}")]
     DuplicateField(Id),
 
@@ -30,15 +36,63 @@ pub enum TypeError {
     #[error("Undefined varibale {0}")]
     UndefinedVariable(String),
 
+    #[error("Variable {name:?} is declared as {declared:?} but initialized with {given:?}")]
+    IncompatibleInitializer {
+        name: Id,
+        declared: Type,
+        given: Type,
+    },
+
+    #[error("Variable {0:?} is already declared in this block")]
+    Redeclaration(Id),
+
+    #[error("Variable {0:?} shadows a parameter of the same name")]
+    ShadowsParameter(Id),
+
+    #[error("Variable {0:?} is used before its declaration")]
+    UseBeforeDeclaration(Id),
+
+    #[error("Only variables, array elements and struct fields can be assigned to")]
+    NotAssignable,
+
     #[error("Array length must be integer, not {0:?}")]
     ArrayLength(Type),
 
+    #[error("Arrays of {0:?} must be created with explicit initializers")]
+    NonDefaultArrayElement(Type),
+
     #[error("{array:?} array elements cannot be {elt:?}")]
     IncompatibleArrayElement { array: Type, elt: Type },
 
+    #[error("{name:?} expects {expected} type arguments, found {given}")]
+    TypeArgumentCount {
+        name: Id,
+        expected: usize,
+        given: usize,
+    },
+
+    #[error("Cannot infer the type arguments of {0:?}")]
+    CannotInferTypeArguments(Id),
+
+    #[error("Generic function {0:?} must be called or given type arguments")]
+    MissingTypeArguments(Id),
+
     #[error("Can only call functions")]
     CanOnlyCallFunctions,
 
+    #[error("Argument of type {given:?} is not compatible with parameter of type {expected:?}")]
+    IncompatibleArgument { expected: Type, given: Type },
+
+    #[error("Operator {op:?} cannot be applied to {operand:?}")]
+    InvalidUnaryOperand { op: UnaryOp, operand: Type },
+
+    #[error("Operator {op:?} cannot be applied to {left:?} and {right:?}")]
+    InvalidBinaryOperands {
+        op: BinaryOp,
+        left: Type,
+        right: Type,
+    },
+
     #[error("Cannot use void as an expression")]
     VoidExpression,
 
@@ -54,11 +108,16 @@ pub enum TypeError {
     #[error("Function call expected {expected} args, found {given}")]
     IncompatibleFunctionArgCounts { expected: usize, given: usize },
 
-    #[error("Dead code after return")]
-    DeadCodeAfterReturn,
+    #[error("Unreachable statement in {}: {statement}", .function.name())]
+    UnreachableStatement { function: Id, statement: String },
 
-    #[error("Function does not return, but return value of type {expected_ret_type} is required")]
+    #[error("Function {} does not return a value of type {expected_ret_type} when {path}", .function.name())]
     DidNotReturn {
+        function: Id,
         expected_ret_type: oat_ast::ReturnType,
+        path: String,
     },
+
+    #[error("Global {} must be initialized with a constant, a function, or an array or struct of those", .0.name())]
+    NonConstantGlobal(Id),
 }
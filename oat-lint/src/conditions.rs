@@ -0,0 +1,107 @@
+//! Conditions that always have the same value, such as `while (false)`.
+//!
+//! `while (true)` and a `for` loop with a `true` condition are the usual way
+//! of writing an infinite loop and are not reported.
+
+use oat_ast as oat;
+use oat_ast::{BinaryOp, Expression, Statement, UnaryOp};
+use oat_error::Warning;
+
+use crate::FunctionLints;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Constant {
+    Int(i64),
+    Bool(bool),
+}
+
+/// The value of an expression built only out of constants.
+fn constant(e: &Expression) -> Option<Constant> {
+    use Constant::*;
+    Some(match e {
+        Expression::CInt(i) => Int(*i),
+        Expression::CBool(b) => Bool(*b),
+        Expression::Unary(op, e) => match (op, constant(e)?) {
+            (UnaryOp::Neg, Int(i)) => Int(i.wrapping_neg()),
+            (UnaryOp::Bitnot, Int(i)) => Int(!i),
+            (UnaryOp::Lognot, Bool(b)) => Bool(!b),
+            _ => return None,
+        },
+        Expression::Binary { op, left, right } => {
+            use BinaryOp::*;
+            match (op, constant(left)?, constant(right)?) {
+                (Add, Int(l), Int(r)) => Int(l.wrapping_add(r)),
+                (Sub, Int(l), Int(r)) => Int(l.wrapping_sub(r)),
+                (Mul, Int(l), Int(r)) => Int(l.wrapping_mul(r)),
+                (IAnd, Int(l), Int(r)) => Int(l & r),
+                (IOr, Int(l), Int(r)) => Int(l | r),
+                (Shl, Int(l), Int(r)) => Int(l.wrapping_shl(r as u32)),
+                (Shr, Int(l), Int(r)) => Int(((l as u64).wrapping_shr(r as u32)) as i64),
+                (Sar, Int(l), Int(r)) => Int(l.wrapping_shr(r as u32)),
+                (Lt, Int(l), Int(r)) => Bool(l < r),
+                (Lte, Int(l), Int(r)) => Bool(l <= r),
+                (Gt, Int(l), Int(r)) => Bool(l > r),
+                (Gte, Int(l), Int(r)) => Bool(l >= r),
+                (And | AndAlso, Bool(l), Bool(r)) => Bool(l && r),
+                (Or | OrElse, Bool(l), Bool(r)) => Bool(l || r),
+                (Eq, l, r) => Bool(l == r),
+                (Neq, l, r) => Bool(l != r),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    })
+}
+
+fn constant_condition(e: &Expression) -> Option<bool> {
+    match constant(e)? {
+        Constant::Bool(b) => Some(b),
+        Constant::Int(_) => None,
+    }
+}
+
+fn statements(block: &[Statement], lints: &mut FunctionLints) {
+    block.iter().for_each(|stmt| statement(stmt, lints))
+}
+
+fn statement(stmt: &Statement, lints: &mut FunctionLints) {
+    let function = lints.function;
+    match stmt {
+        Statement::If {
+            condition,
+            then,
+            else_,
+        } => {
+            if let Some(value) = constant_condition(condition) {
+                lints.warn(Warning::ConstantCondition { function, value });
+            }
+            statements(then, lints);
+            statements(else_, lints);
+        }
+        Statement::While { condition, body }
+        | Statement::For {
+            condition: Some(condition),
+            body,
+            ..
+        } => {
+            if let Some(false) = constant_condition(condition) {
+                lints.warn(Warning::ConstantCondition {
+                    function,
+                    value: false,
+                });
+            }
+            statements(body, lints);
+        }
+        Statement::For { body, .. } => statements(body, lints),
+        Statement::Cast(_, _, _, then, else_) => {
+            statements(then, lints);
+            statements(else_, lints);
+        }
+        _ => {}
+    }
+}
+
+/// Report the conditions of a function that are constant.
+pub(crate) fn lint_conditions(fdecl: &oat::FunctionDecl, lints: &mut FunctionLints) {
+    statements(&fdecl.body, lints)
+}
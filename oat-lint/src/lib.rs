@@ -0,0 +1,154 @@
+//! Lints for Oat programs.
+//!
+//! Lints run over a program that has already been type checked, and report
+//! [`Warning`]s for code that is legal but probably a mistake. Each warning
+//! belongs to a [`Lint`], whose [`Level`] decides whether it is ignored,
+//! reported, or turned into an error. Functions can allow lints within their
+//! body with an attribute:
+//!
+//! ```oat
+//! #[allow(unused_parameters)]
+//! int first(int a, int b) { return a; }
+//! ```
+//!
+//! The entry is the [`lint`] function.
+
+use std::collections::{HashMap, HashSet};
+
+use indexmap::IndexMap;
+
+use oat_ast as oat;
+use oat_ast::Id;
+use oat_error::{Lint, Warning};
+
+mod conditions;
+mod liveness;
+mod shadowing;
+mod usage;
+
+/// What to do with the warnings of a lint
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// The level of every lint. Lints warn by default.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels(HashMap<Lint, Level>);
+
+impl LintLevels {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        self.0.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.0.insert(lint, level);
+    }
+
+    /// Set the level of the lint called `name`, where `warnings` stands for
+    /// every lint. Returns `false` if there is no such lint.
+    pub fn set_by_name(&mut self, name: &str, level: Level) -> bool {
+        match name {
+            "warnings" => Lint::ALL.into_iter().for_each(|lint| self.set(lint, level)),
+            name => match Lint::from_name(name) {
+                Some(lint) => self.set(lint, level),
+                None => return false,
+            },
+        }
+        true
+    }
+}
+
+/// Collects the warnings of a function, leaving out the lints it allows.
+pub(crate) struct FunctionLints {
+    pub function: Id,
+    allowed: HashSet<Lint>,
+    pub warnings: Vec<Warning>,
+}
+
+impl FunctionLints {
+    fn new(fdecl: &oat::FunctionDecl) -> Self {
+        let mut lints = FunctionLints {
+            function: fdecl.name,
+            allowed: HashSet::new(),
+            warnings: vec![],
+        };
+        let allow = Id::intern("allow");
+        for attribute in fdecl.attributes.iter().filter(|a| a.name == allow) {
+            for name in attribute.args.iter() {
+                match Lint::from_name(name.name()) {
+                    Some(lint) => {
+                        lints.allowed.insert(lint);
+                    }
+                    None => lints.warnings.push(Warning::UnknownLint {
+                        function: fdecl.name,
+                        name: *name,
+                    }),
+                }
+            }
+        }
+        lints
+    }
+
+    pub fn allows(&self, lint: Lint) -> bool {
+        self.allowed.contains(&lint)
+    }
+
+    pub fn warn(&mut self, warning: Warning) {
+        if !self.allows(warning.lint()) {
+            self.warnings.push(warning)
+        }
+    }
+}
+
+/// Run every lint over a type checked program.
+///
+/// # Return
+///
+/// Returns the warnings to report, or the first warning of a denied lint as
+/// an error.
+pub fn lint(program: &oat::Program, levels: &LintLevels) -> Result<Vec<Warning>, Warning> {
+    let globals = shadowing::global_names(program);
+    let mut function_lints = IndexMap::new();
+    let mut references = IndexMap::new();
+    for decl in program.declarations.iter() {
+        if let oat::Declaration::Function(fdecl) = decl {
+            let mut lints = FunctionLints::new(fdecl);
+            references.insert(fdecl.name, usage::lint_locals(fdecl, &mut lints));
+            liveness::lint_assignments(fdecl, &mut lints);
+            conditions::lint_conditions(fdecl, &mut lints);
+            shadowing::lint_shadowing(fdecl, &globals, &mut lints);
+            function_lints.insert(fdecl.name, lints);
+        }
+    }
+
+    let mut program_warnings = vec![];
+    for warning in usage::lint_declarations(program, &references) {
+        match warning {
+            // An unused function can allow the warning about itself
+            Warning::UnusedFunction(name) => function_lints[&name].warn(warning),
+            warning => program_warnings.push(warning),
+        }
+    }
+    let mut warnings: Vec<Warning> = function_lints
+        .into_values()
+        .flat_map(|lints| lints.warnings)
+        .collect();
+    warnings.extend(program_warnings);
+
+    let mut reported = vec![];
+    for warning in warnings {
+        match levels.level(warning.lint()) {
+            Level::Allow => {}
+            Level::Warn => reported.push(warning),
+            Level::Deny => return Err(warning),
+        }
+    }
+    Ok(reported)
+}
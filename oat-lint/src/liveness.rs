@@ -0,0 +1,194 @@
+//! Useless assignments: values assigned to a local variable that are never
+//! read afterwards.
+//!
+//! The variables live at each point of a function are computed backwards over
+//! the structured statements. Variables declared in a block are removed from
+//! the live set at its boundary, so that a variable shadowed by a declaration
+//! in the block keeps its liveness across the block. Loops are iterated until
+//! the variables live at the loop head stop changing.
+
+use std::collections::HashSet;
+
+use oat_ast as oat;
+use oat_ast::{Expression, Id, Statement};
+use oat_error::Warning;
+
+use crate::FunctionLints;
+
+type Live = HashSet<Id>;
+
+fn uses(e: &Expression, live: &mut Live) {
+    use Expression::*;
+    match e {
+        CNull(_) | CBool(_) | CInt(_) | CStr(_) | Instantiate(..) => {}
+        Id(name) => {
+            live.insert(*name);
+        }
+        CArr(_, elements) => elements.iter().for_each(|e| uses(e, live)),
+        NewArr(_, e) | Length(e) | Unary(_, e) | Proj(e, _) => uses(e, live),
+        Index { value, index } => {
+            uses(value, live);
+            uses(index, live);
+        }
+        CStruct(_, _, fields) => fields.iter().for_each(|(_, e)| uses(e, live)),
+        Call(fun, args) => {
+            uses(fun, live);
+            args.iter().for_each(|e| uses(e, live));
+        }
+        Binary { left, right, .. } => {
+            uses(left, live);
+            uses(right, live);
+        }
+    }
+}
+
+/// The variables live before a block that declares `declared`, given those
+/// live before its first statement.
+fn leave_scope(inner: Live, declared: &HashSet<Id>, after: &Live) -> Live {
+    let mut live: Live = inner.difference(declared).copied().collect();
+    live.extend(after.intersection(declared).copied());
+    live
+}
+
+/// Find the variables live at the head of a loop, where `iteration` computes
+/// them from the variables live at the head of the next iteration. Useless
+/// assignments are only reported once the loop head is known.
+fn loop_head(iteration: impl Fn(&Live, &mut Vec<Id>) -> Live, useless: &mut Vec<Id>) -> Live {
+    let mut head = Live::new();
+    loop {
+        let next = iteration(&head, &mut vec![]);
+        if next == head {
+            break;
+        }
+        head = next;
+    }
+    iteration(&head, useless)
+}
+
+fn block(stmts: &[Statement], in_scope: &Live, after: &Live, useless: &mut Vec<Id>) -> Live {
+    let declared_before = |i: usize| -> Live {
+        stmts[..i]
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::Declaration(_, name, _) => Some(*name),
+                _ => None,
+            })
+            .chain(in_scope.iter().copied())
+            .collect()
+    };
+    let declared: Live = declared_before(stmts.len())
+        .difference(in_scope)
+        .copied()
+        .collect();
+
+    let mut live: Live = after.difference(&declared).copied().collect();
+    for (i, stmt) in stmts.iter().enumerate().rev() {
+        live = statement(stmt, &declared_before(i), &live, useless);
+    }
+    leave_scope(live, &declared, after)
+}
+
+fn statement(stmt: &Statement, in_scope: &Live, after: &Live, useless: &mut Vec<Id>) -> Live {
+    use Statement::*;
+    let mut live = after.clone();
+    match stmt {
+        Assignment(Expression::Id(name), value) => {
+            if in_scope.contains(name) {
+                if !after.contains(name) && !name.name().starts_with('_') {
+                    useless.push(*name);
+                }
+                live.remove(name);
+            }
+            uses(value, &mut live);
+        }
+        Assignment(target, value) => {
+            uses(target, &mut live);
+            uses(value, &mut live);
+        }
+        Declaration(_, name, init) => {
+            live.remove(name);
+            uses(init, &mut live);
+        }
+        SCall(fun, args) => {
+            uses(fun, &mut live);
+            args.iter().for_each(|e| uses(e, &mut live));
+        }
+        Return(e) => {
+            live.clear();
+            if let Some(e) = e {
+                uses(e, &mut live);
+            }
+        }
+        If {
+            condition,
+            then,
+            else_,
+        } => {
+            live = block(then, in_scope, after, useless);
+            live.extend(block(else_, in_scope, after, useless));
+            uses(condition, &mut live);
+        }
+        Cast(_, name, e, then, else_) => {
+            let declared = Live::from([*name]);
+            let then_scope: Live = in_scope.union(&declared).copied().collect();
+            let then_after: Live = after.difference(&declared).copied().collect();
+            let then_live = block(then, &then_scope, &then_after, useless);
+            live = leave_scope(then_live, &declared, after);
+            live.extend(block(else_, in_scope, after, useless));
+            uses(e, &mut live);
+        }
+        While { condition, body } => {
+            live = loop_head(
+                |head, useless| {
+                    let mut live = block(body, in_scope, head, useless);
+                    live.extend(after.iter().copied());
+                    uses(condition, &mut live);
+                    live
+                },
+                useless,
+            );
+        }
+        For {
+            init,
+            condition,
+            update,
+            body,
+        } => {
+            let declared: Live = init.iter().map(|(name, _)| *name).collect();
+            let for_scope: Live = in_scope.union(&declared).copied().collect();
+            let exit: Live = after.difference(&declared).copied().collect();
+            live = loop_head(
+                |head, useless| {
+                    let before_update = match update {
+                        Some(update) => statement(update, &for_scope, head, useless),
+                        None => head.clone(),
+                    };
+                    let mut live = block(body, &for_scope, &before_update, useless);
+                    if let Some(condition) = condition {
+                        live.extend(exit.iter().copied());
+                        uses(condition, &mut live);
+                    }
+                    live
+                },
+                useless,
+            );
+            for (name, e) in init.iter().rev() {
+                live.remove(name);
+                uses(e, &mut live);
+            }
+            live = leave_scope(live, &declared, after);
+        }
+    }
+    live
+}
+
+/// Report the assignments of a function whose values are never read.
+pub(crate) fn lint_assignments(fdecl: &oat::FunctionDecl, lints: &mut FunctionLints) {
+    let parameters: Live = fdecl.args.iter().map(|(_, name)| *name).collect();
+    let mut useless = vec![];
+    let _ = block(&fdecl.body, &parameters, &Live::new(), &mut useless);
+    for name in useless {
+        let function = lints.function;
+        lints.warn(Warning::UselessAssignment { function, name });
+    }
+}
@@ -0,0 +1,75 @@
+//! Locals and parameters named like a global or a function of the program,
+//! which hide it within their scope.
+
+use std::collections::HashSet;
+
+use oat_ast as oat;
+use oat_ast::{Id, Statement};
+use oat_error::Warning;
+
+use crate::FunctionLints;
+
+/// The names of the globals and functions declared by `program`.
+pub(crate) fn global_names(program: &oat::Program) -> HashSet<Id> {
+    program
+        .declarations
+        .iter()
+        .filter_map(|decl| match decl {
+            oat::Declaration::Variable(global) => Some(global.name),
+            oat::Declaration::Function(fdecl) => Some(fdecl.name),
+            oat::Declaration::Type(_) => None,
+        })
+        .collect()
+}
+
+fn check(name: Id, globals: &HashSet<Id>, lints: &mut FunctionLints) {
+    if globals.contains(&name) {
+        let function = lints.function;
+        lints.warn(Warning::Shadowing { function, name })
+    }
+}
+
+fn block(body: &[Statement], globals: &HashSet<Id>, lints: &mut FunctionLints) {
+    body.iter().for_each(|stmt| statement(stmt, globals, lints))
+}
+
+fn statement(stmt: &Statement, globals: &HashSet<Id>, lints: &mut FunctionLints) {
+    use Statement::*;
+    match stmt {
+        Declaration(_, name, _) => check(*name, globals, lints),
+        If { then, else_, .. } => {
+            block(then, globals, lints);
+            block(else_, globals, lints);
+        }
+        Cast(_, name, _, then, else_) => {
+            check(*name, globals, lints);
+            block(then, globals, lints);
+            block(else_, globals, lints);
+        }
+        For {
+            init, update, body, ..
+        } => {
+            init.iter()
+                .for_each(|(name, _)| check(*name, globals, lints));
+            if let Some(update) = update {
+                statement(update, globals, lints);
+            }
+            block(body, globals, lints);
+        }
+        While { body, .. } => block(body, globals, lints),
+        Assignment(..) | SCall(..) | Return(_) => {}
+    }
+}
+
+/// Report the parameters and locals of a function that shadow one of
+/// `globals`.
+pub(crate) fn lint_shadowing(
+    fdecl: &oat::FunctionDecl,
+    globals: &HashSet<Id>,
+    lints: &mut FunctionLints,
+) {
+    for (_, name) in fdecl.args.iter() {
+        check(*name, globals, lints);
+    }
+    block(&fdecl.body, globals, lints);
+}
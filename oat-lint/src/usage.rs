@@ -0,0 +1,280 @@
+//! Unused locals, parameters, functions, struct fields and globals.
+//!
+//! A name is used when it is read. Assigning to a variable does not count as a
+//! use of it. Struct fields are matched by name only, so a field counts as
+//! read when a field of that name is read from any struct. Names starting
+//! with an underscore are never reported.
+
+use std::collections::HashSet;
+
+use indexmap::IndexMap;
+
+use oat_ast as oat;
+use oat_ast::{Expression, Id, Statement};
+use oat_error::Warning;
+
+use crate::FunctionLints;
+
+/// The name of the entry point, which is called by the runtime
+const ENTRY_POINT: &str = "program";
+
+/// Names and fields read outside of any local scope
+#[derive(Debug, Default)]
+pub(crate) struct References {
+    names: HashSet<Id>,
+    fields: HashSet<Id>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Local,
+    Parameter,
+}
+
+struct Binding {
+    name: Id,
+    kind: Kind,
+    used: bool,
+}
+
+#[derive(Default)]
+struct Usage {
+    scopes: Vec<Vec<Binding>>,
+    references: References,
+    unused: Vec<(Id, Kind)>,
+}
+
+fn is_exempt(name: Id) -> bool {
+    name.name().starts_with('_')
+}
+
+impl Usage {
+    fn push_scope(&mut self) {
+        self.scopes.push(vec![])
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap_or_default();
+        self.unused.extend(
+            scope
+                .into_iter()
+                .filter(|binding| !binding.used && !is_exempt(binding.name))
+                .map(|binding| (binding.name, binding.kind)),
+        );
+    }
+
+    fn bind(&mut self, name: Id, kind: Kind) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding {
+                name,
+                kind,
+                used: false,
+            })
+        }
+    }
+
+    fn use_name(&mut self, name: Id) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.iter_mut().rev().find(|b| b.name == name) {
+                binding.used = true;
+                return;
+            }
+        }
+        self.references.names.insert(name);
+    }
+
+    fn read(&mut self, e: &Expression) {
+        use Expression::*;
+        match e {
+            CNull(_) | CBool(_) | CInt(_) | CStr(_) => {}
+            Id(name) => self.use_name(*name),
+            Instantiate(name, _) => {
+                self.references.names.insert(*name);
+            }
+            CArr(_, elements) => elements.iter().for_each(|e| self.read(e)),
+            NewArr(_, length) => self.read(length),
+            Index { value, index } => {
+                self.read(value);
+                self.read(index);
+            }
+            Length(e) | Unary(_, e) => self.read(e),
+            CStruct(_, _, fields) => fields.iter().for_each(|(_, e)| self.read(e)),
+            Proj(e, field) => {
+                self.references.fields.insert(*field);
+                self.read(e);
+            }
+            Call(fun, args) => {
+                self.read(fun);
+                args.iter().for_each(|e| self.read(e));
+            }
+            Binary { left, right, .. } => {
+                self.read(left);
+                self.read(right);
+            }
+        }
+    }
+
+    fn block(&mut self, block: &[Statement]) {
+        self.push_scope();
+        block.iter().for_each(|stmt| self.statement(stmt));
+        self.pop_scope();
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        use Statement::*;
+        match stmt {
+            Assignment(target, value) => {
+                match target {
+                    Expression::Id(_) => {}
+                    // Assigning to a field writes it, but reads the struct
+                    Expression::Proj(e, _) => self.read(e),
+                    target => self.read(target),
+                }
+                self.read(value);
+            }
+            Declaration(_, name, init) => {
+                self.read(init);
+                self.bind(*name, Kind::Local);
+            }
+            SCall(fun, args) => {
+                self.read(fun);
+                args.iter().for_each(|e| self.read(e));
+            }
+            If {
+                condition,
+                then,
+                else_,
+            } => {
+                self.read(condition);
+                self.block(then);
+                self.block(else_);
+            }
+            Cast(_, name, e, then, else_) => {
+                self.read(e);
+                self.push_scope();
+                self.bind(*name, Kind::Local);
+                self.block(then);
+                self.pop_scope();
+                self.block(else_);
+            }
+            For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                self.push_scope();
+                for (name, e) in init {
+                    self.read(e);
+                    self.bind(*name, Kind::Local);
+                }
+                if let Some(condition) = condition {
+                    self.read(condition);
+                }
+                if let Some(update) = update {
+                    self.statement(update);
+                }
+                self.block(body);
+                self.pop_scope();
+            }
+            While { condition, body } => {
+                self.read(condition);
+                self.block(body);
+            }
+            Return(e) => {
+                if let Some(e) = e {
+                    self.read(e)
+                }
+            }
+        }
+    }
+}
+
+/// Report the unused locals and parameters of a function, returning the
+/// names and fields it reads from outside of the function.
+pub(crate) fn lint_locals(fdecl: &oat::FunctionDecl, lints: &mut FunctionLints) -> References {
+    let mut usage = Usage::default();
+    usage.push_scope();
+    // The parameters of the entry point are fixed by the runtime
+    let kind = match fdecl.name.name() {
+        ENTRY_POINT => Kind::Local,
+        _ => Kind::Parameter,
+    };
+    for (_, name) in fdecl.args.iter() {
+        usage.bind(*name, kind);
+        if kind == Kind::Local {
+            usage.use_name(*name);
+        }
+    }
+    usage.block(&fdecl.body);
+    usage.pop_scope();
+
+    for (name, kind) in usage.unused {
+        let function = lints.function;
+        lints.warn(match kind {
+            Kind::Local => Warning::UnusedLocal { function, name },
+            Kind::Parameter => Warning::UnusedParameter { function, name },
+        })
+    }
+    usage.references
+}
+
+/// Report the functions, globals and struct fields that are never used, given
+/// the references made by each function.
+pub(crate) fn lint_declarations(
+    program: &oat::Program,
+    function_references: &IndexMap<Id, References>,
+) -> Vec<Warning> {
+    let mut global_references = References::default();
+    let mut globals = vec![];
+    let mut structs = vec![];
+    for decl in program.declarations.iter() {
+        match decl {
+            oat::Declaration::Variable(global) => {
+                let mut usage = Usage::default();
+                usage.read(&global.init);
+                global_references.names.extend(usage.references.names);
+                global_references.fields.extend(usage.references.fields);
+                globals.push(global.name);
+            }
+            oat::Declaration::Type(tdecl) => structs.push(tdecl),
+            oat::Declaration::Function(_) => {}
+        }
+    }
+
+    let is_referenced = |name: Id| {
+        global_references.names.contains(&name)
+            || function_references
+                .iter()
+                .any(|(function, refs)| *function != name && refs.names.contains(&name))
+    };
+    let field_is_read = |field: &Id| {
+        global_references.fields.contains(field)
+            || function_references
+                .values()
+                .any(|refs| refs.fields.contains(field))
+    };
+
+    let mut warnings = vec![];
+    for function in function_references.keys() {
+        if function.name() != ENTRY_POINT && !is_exempt(*function) && !is_referenced(*function) {
+            warnings.push(Warning::UnusedFunction(*function));
+        }
+    }
+    for global in globals {
+        if !is_exempt(global) && !is_referenced(global) {
+            warnings.push(Warning::UnusedGlobal(global));
+        }
+    }
+    for tdecl in structs {
+        for field in tdecl.fields.keys() {
+            if !is_exempt(*field) && !field_is_read(field) {
+                warnings.push(Warning::UnusedField {
+                    struct_name: tdecl.name,
+                    field: *field,
+                });
+            }
+        }
+    }
+    warnings
+}
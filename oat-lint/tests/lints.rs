@@ -0,0 +1,338 @@
+//! Tests for the lints.
+
+use oat_ast::{Declaration, Expression, GlobalDeclaration, Id};
+use oat_error::{Lint, Warning};
+use oat_lint::{lint, Level, LintLevels};
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+
+fn check_with(
+    levels: &LintLevels,
+    source: &str,
+    expected: impl FnOnce() -> Result<Vec<Warning>, Warning>,
+) {
+    check_with_globals(levels, &[], source, expected)
+}
+
+/// A global declared by name, with a function building its initializer
+type Global = (&'static str, fn() -> Expression);
+
+/// Check `source` with the globals `name = init` declared before it.
+fn check_with_globals(
+    levels: &LintLevels,
+    globals: &[Global],
+    source: &str,
+    expected: impl FnOnce() -> Result<Vec<Warning>, Warning>,
+) {
+    create_session_if_not_set_then(|_| {
+        let mut program = parse_program(source).expect("test program should parse");
+        for (i, (name, init)) in globals.iter().enumerate() {
+            let global = GlobalDeclaration {
+                name: id(name),
+                init: init(),
+            };
+            program
+                .declarations
+                .insert(i, Declaration::Variable(global));
+        }
+        assert_eq!(lint(&program, levels), expected());
+    })
+}
+
+fn check(source: &str, expected: impl FnOnce() -> Vec<Warning>) {
+    check_with(&LintLevels::new(), source, || Ok(expected()))
+}
+
+fn program(body: &str) -> String {
+    format!("int program(int argc, string[] argv) {{ {} }}", body)
+}
+
+fn none() -> Vec<Warning> {
+    vec![]
+}
+
+fn id(name: &str) -> Id {
+    Id::intern(name)
+}
+
+#[test]
+fn clean_program() {
+    check(
+        concat!(
+            "struct point { int x; int y; }\n",
+            "int norm(point p) { return p.x * p.x + p.y * p.y; }\n",
+            "int program(int argc, string[] argv) {\n",
+            "  var sum = 0;\n",
+            "  for (var i = 0; i < argc; i = i + 1) { sum = sum + norm(new point { x = i; y = 0 }); }\n",
+            "  return sum;\n",
+            "}\n",
+        ),
+        none,
+    );
+}
+
+#[test]
+fn unused_local() {
+    check(&program("var x = 1; return 0;"), || {
+        vec![Warning::UnusedLocal {
+            function: id("program"),
+            name: id("x"),
+        }]
+    });
+}
+
+#[test]
+fn assigned_local_is_unused() {
+    check(&program("var x = 1; x = 2; return 0;"), || {
+        vec![
+            Warning::UnusedLocal {
+                function: id("program"),
+                name: id("x"),
+            },
+            Warning::UselessAssignment {
+                function: id("program"),
+                name: id("x"),
+            },
+        ]
+    });
+}
+
+#[test]
+fn underscore_is_exempt() {
+    check(&program("var _x = 1; _x = 2; return 0;"), none);
+}
+
+#[test]
+fn unused_parameter() {
+    check(
+        &format!(
+            "int first(int a, int b) {{ return a; }}\n{}",
+            program("return first(1, 2);")
+        ),
+        || {
+            vec![Warning::UnusedParameter {
+                function: id("first"),
+                name: id("b"),
+            }]
+        },
+    );
+}
+
+#[test]
+fn shadowed_local() {
+    check(
+        &program("var x = 1; if (argc > 1) { var x = 2; return x; } return 0;"),
+        || {
+            vec![Warning::UnusedLocal {
+                function: id("program"),
+                name: id("x"),
+            }]
+        },
+    );
+}
+
+#[test]
+fn unused_function() {
+    check(
+        &format!(
+            "int twice(int a) {{ return twice(a); }}\n{}",
+            program("return 0;")
+        ),
+        || vec![Warning::UnusedFunction(id("twice"))],
+    );
+}
+
+#[test]
+fn function_used_by_global() {
+    check_with_globals(
+        &LintLevels::new(),
+        &[("f", || Expression::Id(id("one")))],
+        &format!("int one() {{ return 1; }}\n{}", program("return f();")),
+        || Ok(vec![]),
+    );
+}
+
+#[test]
+fn unused_global_and_field() {
+    check_with_globals(
+        &LintLevels::new(),
+        &[("g", || Expression::CInt(1))],
+        &format!(
+            "struct point {{ int x; int y; }}\n{}",
+            program("var p = new point { x = 1; y = 2 }; p.y = 3; return p.x;")
+        ),
+        || {
+            Ok(vec![
+                Warning::UnusedGlobal(id("g")),
+                Warning::UnusedField {
+                    struct_name: id("point"),
+                    field: id("y"),
+                },
+            ])
+        },
+    );
+}
+
+#[test]
+fn global_assignment_is_not_useless() {
+    check_with_globals(
+        &LintLevels::new(),
+        &[("g", || Expression::CInt(1))],
+        &program("g = 2; return g;"),
+        || Ok(vec![]),
+    );
+}
+
+/// A parameter shadowing a global, and a local shadowing a function
+#[test]
+fn shadowing() {
+    let source = format!(
+        "int helper(int origin) {{ return origin; }}\n{}",
+        program("var x = helper(origin); if (x > 0) { var helper = x; return helper; } return 0;")
+    );
+    let globals: &[Global] = &[("origin", || Expression::CInt(1))];
+    let warnings = || {
+        vec![
+            Warning::Shadowing {
+                function: id("helper"),
+                name: id("origin"),
+            },
+            Warning::Shadowing {
+                function: id("program"),
+                name: id("helper"),
+            },
+        ]
+    };
+    check_with_globals(&LintLevels::new(), globals, &source, || Ok(warnings()));
+
+    let mut levels = LintLevels::new();
+    assert!(levels.set_by_name("shadowing", Level::Deny));
+    check_with_globals(&levels, globals, &source, || Err(warnings().remove(0)));
+
+    assert!(levels.set_by_name("shadowing", Level::Allow));
+    check_with_globals(&levels, globals, &source, || Ok(vec![]));
+}
+
+#[test]
+fn overwritten_value() {
+    check(&program("var x = 1; x = 2; x = 3; return x;"), || {
+        vec![Warning::UselessAssignment {
+            function: id("program"),
+            name: id("x"),
+        }]
+    });
+}
+
+#[test]
+fn assignment_read_by_next_iteration() {
+    check(
+        &program(concat!(
+            "var a = 0; var b = 1;",
+            "for (var i = 0; i < argc; i = i + 1) { var t = a; a = b; b = b + t; }",
+            "return a;",
+        )),
+        none,
+    );
+}
+
+#[test]
+fn assignment_after_last_read() {
+    check(
+        &program("var x = 0; while (x < argc) { x = x + 1; } x = 5; return 0;"),
+        || {
+            vec![Warning::UselessAssignment {
+                function: id("program"),
+                name: id("x"),
+            }]
+        },
+    );
+}
+
+#[test]
+fn assignment_overwritten_after_branch() {
+    check(
+        &program("var x = 0; if (argc > 1) { x = 1; } x = 2; return x;"),
+        || {
+            vec![Warning::UselessAssignment {
+                function: id("program"),
+                name: id("x"),
+            }]
+        },
+    );
+}
+
+#[test]
+fn constant_conditions() {
+    check(
+        &program(concat!(
+            "if (1 < 2) { return 1; }",
+            "while (!true) { }",
+            "while (true) { return 0; }",
+            "return 0;",
+        )),
+        || {
+            vec![
+                Warning::ConstantCondition {
+                    function: id("program"),
+                    value: true,
+                },
+                Warning::ConstantCondition {
+                    function: id("program"),
+                    value: false,
+                },
+            ]
+        },
+    );
+}
+
+#[test]
+fn allow_attribute() {
+    check(
+        &format!(
+            "#[allow(unused_parameters, unused_functions)]\nint f(int a) {{ return 0; }}\n{}",
+            program("return 0;")
+        ),
+        none,
+    );
+}
+
+#[test]
+fn unknown_lint_in_attribute() {
+    check(
+        &format!(
+            "#[allow(unused_thing)]\nint f() {{ return 0; }}\n{}",
+            program("return f();")
+        ),
+        || {
+            vec![Warning::UnknownLint {
+                function: id("f"),
+                name: id("unused_thing"),
+            }]
+        },
+    );
+}
+
+#[test]
+fn levels() {
+    let source = program("var x = 1; if (false) { } return 0;");
+    let mut levels = LintLevels::new();
+    levels.set(Lint::UnusedLocals, Level::Allow);
+    check_with(&levels, &source, || {
+        Ok(vec![Warning::ConstantCondition {
+            function: id("program"),
+            value: false,
+        }])
+    });
+
+    assert!(levels.set_by_name("constant_conditions", Level::Deny));
+    check_with(&levels, &source, || {
+        Err(Warning::ConstantCondition {
+            function: id("program"),
+            value: false,
+        })
+    });
+
+    assert!(levels.set_by_name("warnings", Level::Allow));
+    assert!(!levels.set_by_name("no_such_lint", Level::Allow));
+    check_with(&levels, &source, || Ok(vec![]));
+}
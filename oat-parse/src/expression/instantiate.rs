@@ -0,0 +1,23 @@
+use nom::{
+    character::complete::char,
+    combinator::peek,
+    sequence::{pair, terminated},
+    IResult,
+};
+
+use oat_ast::Expression;
+
+use super::parse_identifier;
+use crate::types::parse_type_args;
+use crate::ws;
+
+/// Parse an explicit instantiation of a generic function, e.g. the
+/// `reverse<int>` in `reverse<int>(a)`. It is only recognized directly before
+/// the arguments of a call, to keep it apart from comparisons.
+pub fn parse_instantiation(input: &str) -> IResult<&str, Expression> {
+    let (input, (name, type_args)) = terminated(
+        pair(parse_identifier, parse_type_args),
+        peek(ws(char('('))),
+    )(input)?;
+    Ok((input, Expression::Instantiate(name, type_args)))
+}
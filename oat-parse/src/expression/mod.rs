@@ -2,12 +2,13 @@ use nom::multi::{fold_many0, separated_list0};
 use nom::sequence::preceded;
 use nom::{
     branch::alt,
+    bytes::complete::tag,
     character::complete::char,
     combinator::{map, opt},
     sequence::{delimited, pair},
     IResult,
 };
-use oat_ast::Expression;
+use oat_ast::{BinaryOp, Expression};
 
 mod identifier;
 pub use identifier::*;
@@ -33,6 +34,12 @@ pub use length::*;
 mod string;
 pub use string::*;
 
+mod structure;
+pub use structure::*;
+
+mod instantiate;
+pub use instantiate::*;
+
 use crate::helper::parse_int;
 use crate::ws;
 
@@ -85,7 +92,38 @@ fn test_suffix() {
     })
 }
 
+/// Parse an expression. `||` binds the loosest, then `&&`, and both group to
+/// the left. The other binary operators bind tighter than either.
 pub fn parse_expression(input: &str) -> IResult<&str, Expression> {
+    parse_left_associative(input, "||", BinaryOp::OrElse, parse_and_also)
+}
+
+fn parse_and_also(input: &str) -> IResult<&str, Expression> {
+    parse_left_associative(input, "&&", BinaryOp::AndAlso, parse_binary)
+}
+
+/// Parse operands separated by `token`, combining them from the left with
+/// `op`.
+fn parse_left_associative<'a>(
+    input: &'a str,
+    token: &'static str,
+    op: BinaryOp,
+    operand: fn(&'a str) -> IResult<&'a str, Expression>,
+) -> IResult<&'a str, Expression> {
+    let (input, first) = operand(input)?;
+    fold_many0(
+        preceded(ws(tag(token)), operand),
+        move || first.clone(),
+        move |left, right| Expression::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+    )(input)
+}
+
+/// Parse an expression without `&&` or `||` at its top level.
+fn parse_binary(input: &str) -> IResult<&str, Expression> {
     let (input, prefix) = opt(ws(parse_unop))(input)?;
 
     let (input, base) = ws(alt((
@@ -93,7 +131,10 @@ pub fn parse_expression(input: &str) -> IResult<&str, Expression> {
         parse_null,
         map(parse_int, Expression::CInt),
         parse_length,
+        parse_array,
+        parse_struct_literal,
         // parse_call,
+        parse_instantiation,
         map(parse_identifier, Expression::Id),
         map(parse_string, Expression::CStr),
         delimited(char('('), ws(parse_expression), char(')')),
@@ -117,7 +158,7 @@ pub fn parse_expression(input: &str) -> IResult<&str, Expression> {
         None => exp,
     };
 
-    let (input, next) = opt(pair(ws(parse_binop), parse_expression))(input)?;
+    let (input, next) = opt(pair(ws(parse_binop), parse_binary))(input)?;
 
     Ok((
         input,
@@ -204,6 +245,28 @@ mod expression_tests {
         })
     }
 
+    #[test]
+    fn explicit_instantiation() {
+        assert_parses!(
+            "reverse<int>(a)",
+            Call(
+                Box::new(Instantiate("reverse".into(), vec![oat_ast::Type::Int])),
+                vec!["a".into()]
+            )
+        )
+    }
+
+    #[test]
+    fn comparison_is_not_instantiation() {
+        assert_parses!("a < b", {
+            Binary {
+                op: oat_ast::BinaryOp::Lt,
+                left: Box::new("a".into()),
+                right: Box::new("b".into()),
+            }
+        })
+    }
+
     #[test]
     fn binary_expression() {
         assert_parses!(
@@ -216,6 +279,72 @@ mod expression_tests {
         )
     }
 
+    #[test]
+    fn longest_operator() {
+        assert_parses!("a <= b >>> 2", {
+            Binary {
+                op: oat_ast::BinaryOp::Lte,
+                left: Box::new("a".into()),
+                right: Box::new(Binary {
+                    op: oat_ast::BinaryOp::Sar,
+                    left: Box::new("b".into()),
+                    right: Box::new(CInt(2)),
+                }),
+            }
+        })
+    }
+
+    #[test]
+    fn short_circuit() {
+        assert_parses!("a && b || c", {
+            Binary {
+                op: BinaryOp::OrElse,
+                left: Box::new(Binary {
+                    op: BinaryOp::AndAlso,
+                    left: Box::new("a".into()),
+                    right: Box::new("b".into()),
+                }),
+                right: Box::new("c".into()),
+            }
+        });
+        assert_parses!("a || b && c && d", {
+            let b_and_c = Binary {
+                op: BinaryOp::AndAlso,
+                left: Box::new("b".into()),
+                right: Box::new("c".into()),
+            };
+            Binary {
+                op: BinaryOp::OrElse,
+                left: Box::new("a".into()),
+                right: Box::new(Binary {
+                    op: BinaryOp::AndAlso,
+                    left: Box::new(b_and_c),
+                    right: Box::new("d".into()),
+                }),
+            }
+        })
+    }
+
+    #[test]
+    fn short_circuit_with_comparisons() {
+        assert_parses!("a == 1 || b < c && d != e", {
+            let comparison = |op, left: &str, right: Expression| Binary {
+                op,
+                left: Box::new(left.into()),
+                right: Box::new(right),
+            };
+            Binary {
+                op: BinaryOp::OrElse,
+                left: Box::new(comparison(BinaryOp::Eq, "a", CInt(1))),
+                right: Box::new(Binary {
+                    op: BinaryOp::AndAlso,
+                    left: Box::new(comparison(BinaryOp::Lt, "b", "c".into())),
+                    right: Box::new(comparison(BinaryOp::Neq, "d", "e".into())),
+                }),
+            }
+        })
+    }
+
     #[test]
     fn complex_call() {
         assert_parses!("request.headers[\"User-Agent\"].browser()", {
@@ -1,5 +1,6 @@
 use crate::types::parse_reftype;
 use nom::{
+    branch::alt,
     bytes::complete::tag,
     character::complete::multispace1,
     combinator::map,
@@ -9,7 +10,7 @@ use nom::{
 use oat_ast::Expression;
 
 pub fn parse_null(input: &str) -> IResult<&str, Expression> {
-    let null = tag("NULL");
+    let null = alt((tag("null"), tag("NULL")));
     map(
         terminated(parse_reftype, tuple((multispace1, null))),
         Expression::CNull,
@@ -38,4 +39,12 @@ mod null_tests {
             ))
         );
     }
+
+    #[test]
+    fn lowercase() {
+        assert_eq!(
+            parse_null("string null"),
+            Ok(("", Expression::CNull(ReferenceType::String)))
+        );
+    }
 }
@@ -1,7 +1,9 @@
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    combinator::{map, value},
+    character::complete::char,
+    combinator::{map, not, value},
+    sequence::terminated,
     IResult,
 };
 
@@ -15,6 +17,9 @@ pub fn parse_unop(input: &str) -> IResult<&str, UnaryOp> {
     ))(input)
 }
 
+/// Parse a binary operator other than `&&` and `||`, which have their own
+/// precedence levels. Operators that are a prefix of another, like `<` of
+/// `<<` and `<=`, are tried last.
 pub fn parse_binop(input: &str) -> IResult<&str, BinaryOp> {
     alt((
         value(BinaryOp::Add, tag("+")),
@@ -22,16 +27,16 @@ pub fn parse_binop(input: &str) -> IResult<&str, BinaryOp> {
         value(BinaryOp::Mul, tag("*")),
         value(BinaryOp::Eq, tag("==")),
         value(BinaryOp::Neq, tag("!=")),
-        value(BinaryOp::Lt, tag("<")),
+        value(BinaryOp::Shl, tag("<<")),
         value(BinaryOp::Lte, tag("<=")),
-        value(BinaryOp::Gt, tag(">")),
+        value(BinaryOp::Lt, tag("<")),
+        value(BinaryOp::Sar, tag(">>>")),
+        value(BinaryOp::Shr, tag(">>")),
         value(BinaryOp::Gte, tag(">=")),
-        value(BinaryOp::And, tag("&")),
-        value(BinaryOp::Or, tag("|")),
+        value(BinaryOp::Gt, tag(">")),
+        value(BinaryOp::And, terminated(tag("&"), not(char('&')))),
+        value(BinaryOp::Or, terminated(tag("|"), not(char('|')))),
         value(BinaryOp::IAnd, tag("[&]")),
         value(BinaryOp::IOr, tag("[|]")),
-        value(BinaryOp::Shl, tag("<<")),
-        value(BinaryOp::Shr, tag(">>")),
-        value(BinaryOp::Sar, tag(">>>")),
     ))(input)
 }
@@ -0,0 +1,82 @@
+use nom::{
+    bytes::complete::tag,
+    character::complete::{char, multispace1},
+    combinator::opt,
+    multi::separated_list0,
+    sequence::{delimited, separated_pair, terminated, tuple},
+    IResult,
+};
+
+use oat_ast::Expression;
+
+use super::{parse_expression, parse_identifier};
+use crate::types::parse_type_args;
+use crate::ws;
+
+/// Parse a struct literal, e.g. `new point { x = 1; y = 2 }`. The type
+/// arguments of a generic struct may be given explicitly, as in
+/// `new box<int> { value = 3 }`, or left for the type checker to infer.
+pub fn parse_struct_literal(input: &str) -> IResult<&str, Expression> {
+    let (input, _) = tuple((tag("new"), multispace1))(input)?;
+    let (input, name) = parse_identifier(input)?;
+    let (input, type_args) = opt(parse_type_args)(input)?;
+    let (input, fields) = delimited(
+        ws(char('{')),
+        terminated(
+            separated_list0(
+                ws(char(';')),
+                separated_pair(ws(parse_identifier), char('='), ws(parse_expression)),
+            ),
+            opt(ws(char(';'))),
+        ),
+        char('}'),
+    )(input)?;
+    Ok((
+        input,
+        Expression::CStruct(name, type_args.unwrap_or_default(), fields),
+    ))
+}
+
+#[cfg(test)]
+mod struct_literal_tests {
+    use super::*;
+    use oat_ast::{Id, Type};
+    use oat_symbol::create_session_if_not_set_then;
+
+    #[test]
+    fn point() {
+        create_session_if_not_set_then(|_| {
+            assert_eq!(
+                parse_struct_literal("new point { x = 1; y = 2; }"),
+                Ok((
+                    "",
+                    Expression::CStruct(
+                        Id::from("point"),
+                        vec![],
+                        vec![
+                            (Id::from("x"), 1i64.into()),
+                            (Id::from("y"), 2i64.into())
+                        ]
+                    )
+                ))
+            );
+        })
+    }
+
+    #[test]
+    fn generic() {
+        create_session_if_not_set_then(|_| {
+            assert_eq!(
+                parse_struct_literal("new box<int>{value = 3}"),
+                Ok((
+                    "",
+                    Expression::CStruct(
+                        Id::from("box"),
+                        vec![Type::Int],
+                        vec![(Id::from("value"), 3i64.into())]
+                    )
+                ))
+            );
+        })
+    }
+}
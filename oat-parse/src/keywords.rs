@@ -13,8 +13,10 @@ macro_rules! keyword {
 }
 
 keyword!(var, "var");
+keyword!(global, "global");
 keyword!(for_, "for");
 keyword!(while_, "while");
 keyword!(if_, "if");
+keyword!(ifq, "if?");
 keyword!(else_, "else");
 keyword!(return_, "return");
@@ -66,6 +66,12 @@ pub(crate) fn next_token(input: &str) -> IResult<&str, Token> {
             ">" => Token::Gt,
             ";" => Token::Semi,
         },
+        simple_tokens! {
+            "&&" => Token::Ampamp,
+            "&" => Token::Ampersand,
+            "||" => Token::Barbar,
+            "|" => Token::Bar,
+        },
         map(parse_identifier, Token::Ident),
         map(parse_int, Token::Int),
     ))(input)
@@ -12,12 +12,13 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{char, multispace0},
-    combinator::{map, map_opt, opt, value},
+    combinator::{map, map_opt, opt, peek, value},
     multi::{many0, separated_list0, separated_list1},
-    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
+use oat_ast::visit::VisitMut;
 use oat_ast::*;
 use oat_error::ParseError;
 
@@ -28,9 +29,9 @@ mod expression;
 use expression::*;
 
 mod keywords;
-use keywords::{else_, if_, return_, var, while_};
+use keywords::{else_, for_, global, if_, ifq, return_, var, while_};
 
-use types::{parse_return_type, parse_type};
+use types::{parse_reftype, parse_return_type, parse_type};
 
 mod types;
 
@@ -91,6 +92,26 @@ fn parse_for_loop_update(input: &str) -> IResult<&str, Option<Box<Statement>>> {
     .map(|(input, stmt)| (input, stmt.map(Box::new)))
 }
 
+/// Parse a declaration with an explicit type, e.g. `int x = 3;`. A bare `null`
+/// initializer takes its type from the declaration.
+fn parse_typed_declaration(input: &str) -> IResult<&str, Statement> {
+    let (input, type_) = ws(parse_type)(input)?;
+    let (input, name) = ws(parse_identifier)(input)?;
+    let (input, _) = eq(input)?;
+    let (input, init) = match &type_ {
+        Type::Ref(rt) | Type::NullRef(rt) => alt((
+            value(
+                Expression::CNull(rt.clone()),
+                terminated(ws(tag("null")), peek(semi)),
+            ),
+            parse_expression,
+        ))(input)?,
+        _ => parse_expression(input)?,
+    };
+    let (input, _) = semi(input)?;
+    Ok((input, Statement::Declaration(Some(type_), name, init)))
+}
+
 fn parse_statement(input: &str) -> IResult<&str, Statement> {
     // let return_ = || ws(tag("return"));
     #[inline]
@@ -109,7 +130,25 @@ fn parse_statement(input: &str) -> IResult<&str, Statement> {
                 preceded(var, separated_pair(parse_identifier, eq, parse_expression)),
                 semi,
             ),
-            |(id, init)| Statement::Declaration(id, init),
+            |(id, init)| Statement::Declaration(None, id, init),
+        ),
+        parse_typed_declaration,
+        map(
+            tuple((
+                ifq,
+                ws(parenthesized(tuple((
+                    ws(parse_reftype),
+                    ws(parse_identifier),
+                    preceded(eq, parse_expression),
+                )))),
+                parse_block,
+                map(opt(preceded(else_, parse_block)), |else_| {
+                    else_.unwrap_or_default()
+                }),
+            )),
+            |(_, (ref_type, name, value), then, else_)| {
+                Statement::Cast(ref_type, name, value, then, else_)
+            },
         ),
         map(
             tuple((
@@ -133,6 +172,25 @@ fn parse_statement(input: &str) -> IResult<&str, Statement> {
         map(simple_stmt(preceded(return_, parse_expression)), |e| {
             Statement::Return(Some(e))
         }),
+        map(
+            preceded(
+                for_,
+                tuple((
+                    ws(parenthesized(tuple((
+                        terminated(ws(parse_for_loop_init), semi),
+                        terminated(opt(parse_expression), semi),
+                        ws(parse_for_loop_update),
+                    )))),
+                    parse_block,
+                )),
+            ),
+            |((init, condition, update), body)| Statement::For {
+                init,
+                condition,
+                update,
+                body,
+            },
+        ),
         map(
             preceded(
                 while_,
@@ -179,6 +237,42 @@ mod statement_tests {
         })
     }
 
+    #[test]
+    fn declaration() {
+        assert_parses!(
+            "var x = 3;",
+            Statement::Declaration(None, "x".into(), 3i64.into())
+        )
+    }
+
+    #[test]
+    fn typed_declaration() {
+        assert_parses!(
+            "int x = 3;",
+            Statement::Declaration(Some(Type::Int), "x".into(), 3i64.into())
+        )
+    }
+
+    #[test]
+    fn typed_null_declaration() {
+        assert_parses!("point? p = null;", {
+            let point = ReferenceType::Struct("point".into());
+            Statement::Declaration(
+                Some(Type::NullRef(point.clone())),
+                "p".into(),
+                Expression::CNull(point),
+            )
+        })
+    }
+
+    #[test]
+    fn typed_struct_declaration() {
+        assert_parses!("point p = q;", {
+            let point = ReferenceType::Struct("point".into());
+            Statement::Declaration(Some(Type::Ref(point)), "p".into(), "q".into())
+        })
+    }
+
     #[test]
     fn if_() {
         assert_parses!("if (x == 0) { y = 1; } else { y = 2; }", {
@@ -197,6 +291,22 @@ mod statement_tests {
         })
     }
 
+    #[test]
+    fn ifq() {
+        assert_parses!("if? (string s = x) { y = 1; } else { y = 2; }", {
+            let x: Expression = "x".into();
+            let y: Expression = "y".into();
+
+            Statement::Cast(
+                ReferenceType::String,
+                "s".into(),
+                x,
+                vec![Statement::Assignment(y.clone(), 1i64.into())],
+                vec![Statement::Assignment(y.clone(), 2i64.into())],
+            )
+        })
+    }
+
     #[test]
     fn for_() {
         assert_parses!("for (var x = 0; x < 10; x = x + 1) { f(x); }", {
@@ -211,7 +321,7 @@ mod statement_tests {
                 }),
                 update: Some(Box::new(Statement::Assignment(
                     x.clone(),
-                    x.clone() + 0_i64,
+                    x.clone() + 1_i64,
                 ))),
                 body: vec![Statement::SCall(f, vec![x.clone()])],
             }
@@ -270,29 +380,79 @@ fn parse_argspec(input: &str) -> IResult<&str, (Type, Id)> {
     Ok((input, (type_, name)))
 }
 
+/// Parse the type parameters of a generic declaration, e.g. `<T, U>`
+fn parse_type_params(input: &str) -> IResult<&str, Vec<Id>> {
+    map(
+        opt(delimited(
+            ws(char('<')),
+            separated_list1(ws(char(',')), ws(parse_identifier)),
+            ws(char('>')),
+        )),
+        Option::unwrap_or_default,
+    )(input)
+}
+
+/// Type parameters are parsed as struct names, so replace the uses of
+/// `type_params` within `node` by [`Type::Param`].
+fn bind_type_params(node: &mut impl VisitMut, type_params: &[Id]) {
+    node.visit_types_mut(|t| {
+        if let Type::Ref(ReferenceType::Struct(name)) = t {
+            if type_params.contains(name) {
+                *t = Type::Param(*name);
+            }
+        }
+    })
+}
+
+/// Parse an attribute, e.g. `#[allow(unused_locals, unused_parameters)]`
+fn parse_attribute(input: &str) -> IResult<&str, Attribute> {
+    map(
+        delimited(
+            tag("#["),
+            pair(
+                ws(parse_identifier),
+                map(
+                    opt(delimited(
+                        ws(char('(')),
+                        separated_list0(ws(char(',')), ws(parse_identifier)),
+                        char(')'),
+                    )),
+                    Option::unwrap_or_default,
+                ),
+            ),
+            ws(char(']')),
+        ),
+        |(name, args)| Attribute { name, args },
+    )(input)
+}
+
 fn parse_function_declaration(input: &str) -> IResult<&str, FunctionDecl> {
+    let (input, attributes) = many0(ws(parse_attribute))(input)?;
     let (input, return_type) = parse_return_type(input)?;
     let (input, name) = ws(parse_identifier)(input)?;
+    let (input, type_params) = parse_type_params(input)?;
     let (input, args) = delimited(
         char('('),
         separated_list0(ws(char(',')), parse_argspec),
         char(')'),
     )(input)?;
     let (input, body) = ws(parse_block)(input)?;
-    Ok((
-        input,
-        FunctionDecl {
-            return_type,
-            name,
-            args,
-            body,
-        },
-    ))
+    let mut fdecl = FunctionDecl {
+        attributes,
+        return_type,
+        name,
+        type_params: type_params.clone(),
+        args,
+        body,
+    };
+    bind_type_params(&mut fdecl, &type_params);
+    Ok((input, fdecl))
 }
 
 fn parse_type_declaration(input: &str) -> IResult<&str, TypeDeclaration> {
     let (input, _) = tag("struct")(input)?;
     let (input, name) = ws(parse_identifier)(input)?;
+    let (input, type_params) = parse_type_params(input)?;
     let (input, field_decls) = delimited(
         char('{'),
         many0(ws(terminated(parse_argspec, tag(";")))),
@@ -304,18 +464,30 @@ fn parse_type_declaration(input: &str) -> IResult<&str, TypeDeclaration> {
         fields.insert(name, type_);
     }
 
-    Ok((input, TypeDeclaration { name, fields }))
+    let mut tdecl = TypeDeclaration {
+        name,
+        type_params: type_params.clone(),
+        fields,
+    };
+    bind_type_params(&mut tdecl, &type_params);
+    Ok((input, tdecl))
 }
 
-fn parse_global_def(_input: &str) -> IResult<&str, GlobalDeclaration> {
-    todo!("Global variable")
+/// Parse a global variable, e.g. `global xs = new int[]{1, 2};`
+fn parse_global_def(input: &str) -> IResult<&str, GlobalDeclaration> {
+    let (input, _) = global(input)?;
+    let (input, name) = ws(parse_identifier)(input)?;
+    let (input, _) = eq(input)?;
+    let (input, init) = parse_expression(input)?;
+    let (input, _) = semi(input)?;
+    Ok((input, GlobalDeclaration { name, init }))
 }
 
 fn parse_declaration(input: &str) -> IResult<&str, Declaration> {
     alt((
         map(parse_function_declaration, Declaration::Function),
         map(parse_type_declaration, Declaration::Type),
-        //map(parse_global_def, Declaration::Variable),
+        map(parse_global_def, Declaration::Variable),
     ))(input)
 }
 
@@ -335,8 +507,10 @@ mod declaration_tests {
     fn simple_function() {
         test_declaration("void f() {}", || {
             Declaration::Function(FunctionDecl {
+                attributes: vec![],
                 return_type: ReturnType::ReturnVoid,
                 name: "f".into(),
+                type_params: vec![],
                 args: vec![],
                 body: vec![],
             })
@@ -347,8 +521,10 @@ mod declaration_tests {
     fn one_arg() {
         test_declaration("void f(int x) {}", || {
             Declaration::Function(FunctionDecl {
+                attributes: vec![],
                 return_type: ReturnType::ReturnVoid,
                 name: "f".into(),
+                type_params: vec![],
                 args: vec![(Type::Int, "x".into())],
                 body: vec![],
             })
@@ -360,6 +536,7 @@ mod declaration_tests {
         test_declaration("struct empty {}", || {
             Declaration::Type(TypeDeclaration {
                 name: "empty".into(),
+                type_params: vec![],
                 fields: Default::default(),
             })
         })
@@ -387,6 +564,7 @@ mod declaration_tests {
 
             let expected = TypeDeclaration {
                 name: "point".into(),
+                type_params: vec![],
                 fields: indexmap! {
                     "x".into() => Type::Int,
                     "y".into() => Type::Int,
@@ -396,6 +574,93 @@ mod declaration_tests {
             Declaration::Type(expected)
         })
     }
+
+    #[test]
+    fn generic_function() {
+        test_declaration("T[] reverse<T>(T[] a) { return a; }", || {
+            let t: Id = "T".into();
+            let t_array = Type::Ref(ReferenceType::Array(Box::new(Type::Param(t))));
+            Declaration::Function(FunctionDecl {
+                attributes: vec![],
+                return_type: ReturnType::ReturnValue(t_array.clone()),
+                name: "reverse".into(),
+                type_params: vec![t],
+                args: vec![(t_array, "a".into())],
+                body: vec![Statement::Return(Some("a".into()))],
+            })
+        })
+    }
+
+    #[test]
+    fn attributes() {
+        test_declaration(
+            "#[allow(unused_locals, constant_conditions)]\n#[inline] void f() {}",
+            || {
+                Declaration::Function(FunctionDecl {
+                    attributes: vec![
+                        Attribute {
+                            name: "allow".into(),
+                            args: vec!["unused_locals".into(), "constant_conditions".into()],
+                        },
+                        Attribute {
+                            name: "inline".into(),
+                            args: vec![],
+                        },
+                    ],
+                    return_type: ReturnType::ReturnVoid,
+                    name: "f".into(),
+                    type_params: vec![],
+                    args: vec![],
+                    body: vec![],
+                })
+            },
+        )
+    }
+
+    #[test]
+    fn generic_struct() {
+        test_declaration("struct pair<A, B> { A first; B second; pair<B, A>? swapped; }", || {
+            use indexmap::indexmap;
+
+            let a: Id = "A".into();
+            let b: Id = "B".into();
+            Declaration::Type(TypeDeclaration {
+                name: "pair".into(),
+                type_params: vec![a, b],
+                fields: indexmap! {
+                    "first".into() => Type::Param(a),
+                    "second".into() => Type::Param(b),
+                    "swapped".into() => Type::NullRef(ReferenceType::GenericStruct(
+                        "pair".into(),
+                        vec![Type::Param(b), Type::Param(a)],
+                    )),
+                },
+            })
+        })
+    }
+
+    #[test]
+    fn global_int() {
+        test_declaration("global x = 3;", || {
+            Declaration::Variable(GlobalDeclaration {
+                name: "x".into(),
+                init: Expression::CInt(3),
+            })
+        })
+    }
+
+    #[test]
+    fn global_array() {
+        test_declaration("global names = new string[]{\"a\", \"b\"};", || {
+            Declaration::Variable(GlobalDeclaration {
+                name: "names".into(),
+                init: Expression::CArr(
+                    Type::Ref(ReferenceType::String),
+                    vec![Expression::CStr("a".into()), Expression::CStr("b".into())],
+                ),
+            })
+        })
+    }
 }
 
 fn parse_program_internal(input: &str) -> IResult<&str, Program> {
@@ -45,7 +45,9 @@ pub enum Token {
     Gt,
     Gteq,
     Ampersand,
+    Ampamp,
     Bar,
+    Barbar,
     IAnd,
     IOr,
     Arrow,
@@ -2,9 +2,9 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::char,
-    combinator::{map, map_res, value},
-    multi::{fold_many0, separated_list0},
-    sequence::{delimited, separated_pair},
+    combinator::{map, map_res, opt, value},
+    multi::{fold_many0, separated_list0, separated_list1},
+    sequence::{delimited, pair, separated_pair},
     IResult,
 };
 
@@ -125,12 +125,26 @@ fn parse_type_suffix(input: &str) -> IResult<&str, TypeSuffix> {
     ))(input)
 }
 
+/// Parse the type arguments of a generic struct or function, e.g. `<int, T>`
+pub fn parse_type_args(input: &str) -> IResult<&str, Vec<Type>> {
+    delimited(
+        ws(char('<')),
+        separated_list1(ws(char(',')), ws(parse_type)),
+        char('>'),
+    )(input)
+}
+
 pub fn parse_type(input: &str) -> IResult<&str, Type> {
     let (input, init) = alt((
         map(tag("bool"), |_: &str| Type::Bool),
         map(tag("int"), |_: &str| Type::Int),
         map(tag("string"), |_| Type::Ref(ReferenceType::String)),
-        map(parse_identifier, |id| Type::Ref(ReferenceType::Struct(id))),
+        map(pair(parse_identifier, opt(parse_type_args)), |(id, type_args)| {
+            Type::Ref(match type_args {
+                Some(type_args) => ReferenceType::GenericStruct(id, type_args),
+                None => ReferenceType::Struct(id),
+            })
+        }),
         map(
             separated_pair(
                 delimited(
@@ -216,6 +230,35 @@ mod type_tests {
             ))
         );
     }
+    #[test]
+    fn generic_struct() {
+        create_session_if_not_set_then(|_| {
+            let box_int = ReferenceType::GenericStruct(Id::from("box"), vec![Type::Int]);
+            assert_eq!(
+                parse_type("pair<box<int>, bool>[]"),
+                Ok((
+                    "",
+                    Type::Ref(ReferenceType::Array(Box::new(Type::Ref(
+                        ReferenceType::GenericStruct(
+                            Id::from("pair"),
+                            vec![Type::Ref(box_int), Type::Bool]
+                        )
+                    ))))
+                ))
+            );
+        })
+    }
+
+    #[test]
+    fn comparison_is_not_generic() {
+        create_session_if_not_set_then(|_| {
+            assert_eq!(
+                parse_type("a < b"),
+                Ok((" < b", Type::Ref(ReferenceType::Struct(Id::from("a")))))
+            );
+        })
+    }
+
     #[test]
     fn my_class() {
         create_session_if_not_set_then(|_| {
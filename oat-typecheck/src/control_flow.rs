@@ -0,0 +1,255 @@
+//! Statement-level control flow of function bodies.
+//!
+//! Each statement of a body is a node of a control-flow graph, with an edge to
+//! every statement that can run right after it. A `for` loop has a second node
+//! for its condition, and its update is a node of its own. The graph has an
+//! entry node before the first statement and an exit node reached by falling
+//! off the end of the body; `return` statements have no successors.
+//!
+//! A loop whose condition is the literal `true`, or a `for` loop without a
+//! condition, never exits, so `while (true) { ... return x; }` returns on
+//! every path.
+
+use std::collections::VecDeque;
+
+use oat_ast::{Expression, Statement};
+
+const ENTRY: usize = 0;
+const EXIT: usize = 1;
+
+/// An edge out of `from`. The decision describes the branch taken by `from`
+/// when it has more than one successor.
+type Dangling = (usize, Option<&'static str>);
+
+struct Node {
+    /// Description of the statement, naming its position in the body
+    label: String,
+    /// Whether the node is a statement written in a block. The update of a
+    /// `for` loop is not: it is skipped when every iteration returns.
+    in_block: bool,
+    successors: Vec<Dangling>,
+}
+
+pub(crate) struct ControlFlowGraph {
+    nodes: Vec<Node>,
+    reachable: Vec<bool>,
+}
+
+/// A short description of the kind of a statement.
+fn kind(stmt: &Statement) -> String {
+    match stmt {
+        Statement::Assignment(Expression::Id(name), _) => {
+            format!("assignment to `{}`", name.name())
+        }
+        Statement::Assignment(..) => "assignment".to_string(),
+        Statement::Declaration(_, name, _) => format!("declaration of `{}`", name.name()),
+        Statement::SCall(Expression::Id(name), _) => format!("call to `{}`", name.name()),
+        Statement::SCall(..) => "call".to_string(),
+        Statement::If { .. } => "`if`".to_string(),
+        Statement::Cast(..) => "`if?`".to_string(),
+        Statement::For { .. } => "`for` loop".to_string(),
+        Statement::While { .. } => "`while` loop".to_string(),
+        Statement::Return(_) => "`return`".to_string(),
+    }
+}
+
+fn is_true(condition: Option<&Expression>) -> bool {
+    matches!(condition, None | Some(Expression::CBool(true)))
+}
+
+impl ControlFlowGraph {
+    /// The graph of a function body.
+    pub fn new(body: &[Statement]) -> Self {
+        let mut cfg = ControlFlowGraph {
+            nodes: vec![],
+            reachable: vec![],
+        };
+        cfg.add("the start of the body".to_string(), false, vec![]);
+        cfg.add("the end of the body".to_string(), false, vec![]);
+        let exits = cfg.block(body, "", vec![(ENTRY, None)]);
+        cfg.connect(exits, EXIT);
+        cfg.reachable = cfg.find_reachable();
+        cfg
+    }
+
+    fn add(&mut self, label: String, in_block: bool, predecessors: Vec<Dangling>) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            label,
+            in_block,
+            successors: vec![],
+        });
+        self.connect(predecessors, node);
+        node
+    }
+
+    fn connect(&mut self, predecessors: Vec<Dangling>, node: usize) {
+        for (from, decision) in predecessors {
+            self.nodes[from].successors.push((node, decision));
+        }
+    }
+
+    /// Add the statements of `block`, where `within` describes the position
+    /// of the block. Returns the edges leaving the block.
+    fn block(
+        &mut self,
+        block: &[Statement],
+        within: &str,
+        mut exits: Vec<Dangling>,
+    ) -> Vec<Dangling> {
+        for (i, stmt) in block.iter().enumerate() {
+            let label = format!("the {} at statement {}{}", kind(stmt), i + 1, within);
+            exits = self.statement(stmt, label, true, exits);
+        }
+        exits
+    }
+
+    fn statement(
+        &mut self,
+        stmt: &Statement,
+        label: String,
+        in_block: bool,
+        predecessors: Vec<Dangling>,
+    ) -> Vec<Dangling> {
+        let node = self.add(label.clone(), in_block, predecessors);
+        match stmt {
+            Statement::Assignment(..) | Statement::Declaration(..) | Statement::SCall(..) => {
+                vec![(node, None)]
+            }
+            Statement::Return(_) => vec![],
+            Statement::If { then, else_, .. } => {
+                let mut exits = self.block(
+                    then,
+                    &format!(" of the then block of {}", label),
+                    vec![(node, Some("takes its then branch"))],
+                );
+                exits.extend(self.block(
+                    else_,
+                    &format!(" of the else block of {}", label),
+                    vec![(node, Some("takes its else branch"))],
+                ));
+                exits
+            }
+            Statement::Cast(_, _, _, then, else_) => {
+                let mut exits = self.block(
+                    then,
+                    &format!(" of the then block of {}", label),
+                    vec![(node, Some("finds a non-null value"))],
+                );
+                exits.extend(self.block(
+                    else_,
+                    &format!(" of the else block of {}", label),
+                    vec![(node, Some("finds null"))],
+                ));
+                exits
+            }
+            Statement::While { condition, body } => {
+                let body_exits = self.block(
+                    body,
+                    &format!(" of the body of {}", label),
+                    vec![(node, Some("enters its body"))],
+                );
+                self.connect(body_exits, node);
+                match is_true(Some(condition)) {
+                    true => vec![],
+                    false => vec![(node, Some("exits"))],
+                }
+            }
+            Statement::For {
+                condition,
+                update,
+                body,
+                ..
+            } => {
+                let head = self.add(label.clone(), false, vec![(node, None)]);
+                let mut exits = self.block(
+                    body,
+                    &format!(" of the body of {}", label),
+                    vec![(head, Some("enters its body"))],
+                );
+                if let Some(update) = update {
+                    let update_label = format!("the update of {}", label);
+                    exits = self.statement(update, update_label, false, exits);
+                }
+                self.connect(exits, head);
+                match is_true(condition.as_ref()) {
+                    true => vec![],
+                    false => vec![(head, Some("exits"))],
+                }
+            }
+        }
+    }
+
+    fn find_reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.nodes.len()];
+        let mut stack = vec![ENTRY];
+        reachable[ENTRY] = true;
+        while let Some(node) = stack.pop() {
+            for (next, _) in self.nodes[node].successors.iter() {
+                if !reachable[*next] {
+                    reachable[*next] = true;
+                    stack.push(*next);
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Whether control can fall off the end of the body.
+    pub fn completes(&self) -> bool {
+        self.reachable[EXIT]
+    }
+
+    /// The first statement of the body, in source order, that can never run.
+    pub fn first_unreachable(&self) -> Option<&str> {
+        self.nodes
+            .iter()
+            .zip(self.reachable.iter())
+            .find(|(node, reachable)| node.in_block && !**reachable)
+            .map(|(node, _)| node.label.as_str())
+    }
+
+    /// Describe a shortest path from the start of the body to its end by the
+    /// branches taken along it.
+    pub fn path_to_end(&self) -> Option<String> {
+        let mut parent: Vec<Option<(usize, Option<&'static str>)>> = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::from([ENTRY]);
+        while let Some(node) = queue.pop_front() {
+            if node == EXIT {
+                break;
+            }
+            for (next, decision) in self.nodes[node].successors.iter() {
+                if *next != ENTRY && parent[*next].is_none() {
+                    parent[*next] = Some((node, *decision));
+                    queue.push_back(*next);
+                }
+            }
+        }
+
+        let mut decisions = vec![];
+        let (last, _) = parent[EXIT]?;
+        let mut node = EXIT;
+        while let Some((previous, decision)) = parent[node] {
+            if let Some(decision) = decision {
+                decisions.push(format!("{} {}", self.nodes[previous].label, decision));
+            }
+            node = previous;
+        }
+        if decisions.is_empty() {
+            if last == ENTRY {
+                return Some("the body is empty".to_string());
+            }
+            return Some(format!(
+                "control falls through after {}",
+                self.nodes[last].label
+            ));
+        }
+        decisions.reverse();
+        Some(decisions.join(", then "))
+    }
+}
+
+/// Whether control can fall off the end of `block`.
+pub(crate) fn completes(block: &[Statement]) -> bool {
+    ControlFlowGraph::new(block).completes()
+}
@@ -0,0 +1,892 @@
+//! Typing rules for Oat expressions.
+
+use std::collections::HashSet;
+
+use indexmap::IndexMap;
+
+use oat::{BinaryOp, Expression, ReferenceType, ReturnType};
+use oat_ast as oat;
+use oat_ast::Type;
+use oat_typecontext::TypingContext;
+
+use oat_error::TypeError;
+
+use crate::locals_context::LocalsContext;
+use crate::narrowing::condition_facts;
+use crate::TypeCheck;
+
+fn check_duplicate_fields(
+    fields: &mut [(oat::Id, oat::Expression)],
+) -> Result<IndexMap<oat::Id, &mut oat::Expression>, TypeError> {
+    let mut field_names: HashSet<oat::Id> = HashSet::new();
+    for (field_name, _) in fields.iter() {
+        if field_names.contains(field_name) {
+            return Err(TypeError::DuplicateField(*field_name));
+        }
+        field_names.insert(*field_name);
+    }
+    Ok(fields.iter_mut().map(|(name, e)| (*name, e)).collect())
+}
+
+/// Check that the arguments of a call match the parameter types of the
+/// function being called.
+pub(crate) fn type_check_arguments(
+    arg_types: &[Type],
+    args: &mut [Expression],
+    tc: &mut TypingContext,
+    lc: &mut LocalsContext<Type>,
+) -> Result<(), TypeError> {
+    if arg_types.len() != args.len() {
+        return Err(TypeError::IncompatibleFunctionArgCounts {
+            expected: arg_types.len(),
+            given: args.len(),
+        });
+    }
+
+    let passed_types = args
+        .iter_mut()
+        .map(|arg| arg.type_check(tc, lc))
+        .collect::<Result<Vec<_>, _>>()?;
+    check_argument_types(arg_types, &passed_types, tc)
+}
+
+fn check_argument_types(
+    arg_types: &[Type],
+    passed_types: &[Type],
+    tc: &TypingContext,
+) -> Result<(), TypeError> {
+    std::iter::zip(arg_types.iter(), passed_types.iter()).try_for_each(|(arg_type, passed_type)| {
+        if !tc.is_subtype(passed_type, arg_type)? {
+            return Err(TypeError::IncompatibleArgument {
+                expected: arg_type.clone(),
+                given: passed_type.clone(),
+            });
+        }
+        Ok(())
+    })
+}
+
+/// Match the type `pattern`, containing the type parameters `type_params`,
+/// against the type `actual`, binding the parameters that occur in it. The
+/// first binding of a parameter wins; the caller checks the instantiated types
+/// against the actual ones afterwards.
+fn unify(
+    pattern: &Type,
+    actual: &Type,
+    type_params: &[oat::Id],
+    bindings: &mut IndexMap<oat::Id, Type>,
+) {
+    match (pattern, actual) {
+        (Type::Param(name), _) if type_params.contains(name) => {
+            bindings.entry(*name).or_insert_with(|| actual.clone());
+        }
+        (Type::Ref(p), Type::Ref(a))
+        | (Type::NullRef(p), Type::NullRef(a))
+        | (Type::NullRef(p), Type::Ref(a)) => unify_ref(p, a, type_params, bindings),
+        _ => {}
+    }
+}
+
+fn unify_ref(
+    pattern: &ReferenceType,
+    actual: &ReferenceType,
+    type_params: &[oat::Id],
+    bindings: &mut IndexMap<oat::Id, Type>,
+) {
+    match (pattern, actual) {
+        (ReferenceType::Array(p), ReferenceType::Array(a)) => unify(p, a, type_params, bindings),
+        (ReferenceType::GenericStruct(n1, ps), ReferenceType::GenericStruct(n2, as_))
+            if n1 == n2 =>
+        {
+            std::iter::zip(ps, as_).for_each(|(p, a)| unify(p, a, type_params, bindings))
+        }
+        (ReferenceType::Function(ps, p_ret), ReferenceType::Function(as_, a_ret)) => {
+            std::iter::zip(ps, as_).for_each(|(p, a)| unify(p, a, type_params, bindings));
+            if let (ReturnType::ReturnValue(p), ReturnType::ReturnValue(a)) =
+                (p_ret.as_ref(), a_ret.as_ref())
+            {
+                unify(p, a, type_params, bindings)
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Infer the type arguments of the generic `name` from the types of the
+/// values passed where `patterns` are expected.
+fn infer_type_args(
+    name: oat::Id,
+    type_params: &[oat::Id],
+    patterns: &[Type],
+    actuals: &[Type],
+) -> Result<Vec<Type>, TypeError> {
+    let mut bindings = IndexMap::new();
+    std::iter::zip(patterns, actuals)
+        .for_each(|(pattern, actual)| unify(pattern, actual, type_params, &mut bindings));
+    type_params
+        .iter()
+        .map(|param| {
+            bindings
+                .get(param)
+                .cloned()
+                .ok_or(TypeError::CannotInferTypeArguments(name))
+        })
+        .collect()
+}
+
+/// The generic function called by `fun`, if it names one that is not
+/// shadowed by a variable.
+pub(crate) fn generic_callee(
+    fun: &Expression,
+    tc: &TypingContext,
+    lc: &LocalsContext<Type>,
+) -> Option<oat::Id> {
+    match fun {
+        Expression::Id(name)
+            if lc.lookup(*name).is_none() && tc.generic_function(name).is_some() =>
+        {
+            Some(*name)
+        }
+        _ => None,
+    }
+}
+
+/// The type of a call of the generic function `name` on `args`. The callee
+/// `fun` is replaced by the instance for the inferred type arguments.
+pub(crate) fn type_check_generic_call(
+    fun: &mut Expression,
+    name: oat::Id,
+    args: &mut [Expression],
+    tc: &mut TypingContext,
+    lc: &mut LocalsContext<Type>,
+) -> Result<ReturnType, TypeError> {
+    let generic = tc
+        .generic_function(&name)
+        .cloned()
+        .ok_or_else(|| TypeError::UndefinedVariable(name.name().to_string()))?;
+    if generic.arg_types.len() != args.len() {
+        return Err(TypeError::IncompatibleFunctionArgCounts {
+            expected: generic.arg_types.len(),
+            given: args.len(),
+        });
+    }
+    let passed_types = args
+        .iter_mut()
+        .map(|arg| arg.type_check(tc, lc))
+        .collect::<Result<Vec<_>, _>>()?;
+    let type_args = infer_type_args(
+        name,
+        &generic.type_params,
+        &generic.arg_types,
+        &passed_types,
+    )?;
+    let (arg_types, ret_type) = generic.instantiate(&type_args);
+    check_argument_types(&arg_types, &passed_types, tc)?;
+    *fun = Expression::Instantiate(name, type_args);
+    Ok(ret_type)
+}
+
+/// Check the type arguments given to the generic `name` with `type_params`.
+fn type_check_type_args(
+    name: oat::Id,
+    type_params: &[oat::Id],
+    type_args: &mut [Type],
+    tc: &mut TypingContext,
+    lc: &mut LocalsContext<Type>,
+) -> Result<(), TypeError> {
+    if type_params.len() != type_args.len() {
+        return Err(TypeError::TypeArgumentCount {
+            name,
+            expected: type_params.len(),
+            given: type_args.len(),
+        });
+    }
+    type_args.iter_mut().try_for_each(|t| t.type_check(tc, lc))
+}
+
+/// The type of `e`, ignoring any narrowing of a variable. Used where the
+/// nullability of the variable itself matters, such as in comparisons against
+/// `null`.
+pub(crate) fn declared_type(
+    e: &mut Expression,
+    tc: &mut TypingContext,
+    lc: &mut LocalsContext<Type>,
+) -> Result<Type, TypeError> {
+    match e {
+        Expression::Id(name) => lc.resolve(*name),
+        e => e.type_check(tc, lc),
+    }
+}
+
+impl TypeCheck for oat::Expression {
+    type Output = Type;
+
+    fn type_check(
+        &mut self,
+        tc: &mut TypingContext,
+        lc: &mut LocalsContext<Type>,
+    ) -> Result<Type, TypeError> {
+        use oat_ast::Expression::*;
+        Ok(match self {
+            CNull(rt) => {
+                let mut type_ = Type::NullRef(rt.clone());
+                type_.type_check(tc, lc)?;
+                type_
+            }
+            CBool(_) => Type::Bool,
+            CInt(_) => Type::Int,
+            CStr(_) => Type::Ref(ReferenceType::String),
+            Id(name) => match lc.resolve(*name) {
+                Ok(Type::NullRef(rt)) if lc.non_null().contains(*name) => Type::Ref(rt),
+                Ok(t) => t,
+                Err(TypeError::UndefinedVariable(_)) if tc.generic_function(name).is_some() => {
+                    return Err(TypeError::MissingTypeArguments(*name))
+                }
+                Err(e) => return Err(e),
+            },
+            Instantiate(name, type_args) => {
+                let generic = tc
+                    .generic_function(name)
+                    .cloned()
+                    .ok_or_else(|| TypeError::UndefinedVariable(name.name().to_string()))?;
+                type_check_type_args(*name, &generic.type_params, type_args, tc, lc)?;
+                let (arg_types, ret_type) = generic.instantiate(type_args);
+                Type::Ref(ReferenceType::Function(arg_types, Box::new(ret_type)))
+            }
+            Length(e) => match e.type_check(tc, lc)? {
+                Type::Ref(ReferenceType::Array(_)) => Type::Int,
+                t => return Err(TypeError::CannotGetLength(t)),
+            },
+            Index { value, index } => match value.type_check(tc, lc)? {
+                Type::Ref(ReferenceType::Array(t)) => match index.type_check(tc, lc)? {
+                    Type::Int => *t,
+                    ti => return Err(TypeError::NonIntegerIndex(ti)),
+                },
+                t => return Err(TypeError::CannotSubscript(t)),
+            },
+            CStruct(struct_name, type_args, fields) => {
+                let mut instance = check_duplicate_fields(fields)?;
+                let generic_def = tc
+                    .get_type(struct_name)
+                    .ok_or(TypeError::StructNotFound(*struct_name))?
+                    .clone();
+                let type_params = tc.type_params(struct_name).to_vec();
+
+                if let Some(extra) = instance.keys().find(|f| !generic_def.contains_key(*f)) {
+                    let struct_type = Type::Ref(ReferenceType::Struct(*struct_name));
+                    return Err(TypeError::ExtraField(struct_type, *extra));
+                }
+
+                let mut field_types = IndexMap::new();
+                for field_name in generic_def.keys() {
+                    if let Some(expr) = instance.get_mut(field_name) {
+                        field_types.insert(*field_name, expr.type_check(tc, lc)?);
+                    }
+                }
+
+                // The type arguments of a generic struct are inferred from
+                // the fields when they are not given
+                if type_args.is_empty() && !type_params.is_empty() {
+                    let (patterns, actuals): (Vec<Type>, Vec<Type>) = field_types
+                        .iter()
+                        .map(|(name, t)| (generic_def[name].clone(), t.clone()))
+                        .unzip();
+                    *type_args = infer_type_args(*struct_name, &type_params, &patterns, &actuals)?;
+                } else {
+                    type_check_type_args(*struct_name, &type_params, type_args, tc, lc)?;
+                }
+                let type_args = type_args.clone();
+                let struct_type = Type::Ref(match type_args.as_slice() {
+                    [] => ReferenceType::Struct(*struct_name),
+                    _ => ReferenceType::GenericStruct(*struct_name, type_args),
+                });
+                let struct_def = match &struct_type {
+                    Type::Ref(rt) => tc.struct_fields(rt)?,
+                    _ => unreachable!(),
+                };
+
+                for (field_name, field_type) in struct_def.iter() {
+                    let type_ = field_types
+                        .get(field_name)
+                        .ok_or_else(|| TypeError::MissingField(struct_type.clone(), *field_name))?;
+                    if !tc.is_subtype(type_, field_type)? {
+                        return Err(TypeError::IncompatibleType);
+                    }
+                }
+                struct_type
+            }
+            Proj(e, field) => match e.type_check(tc, lc)? {
+                Type::Ref(rt @ (ReferenceType::Struct(_) | ReferenceType::GenericStruct(..))) => {
+                    let struct_def = tc.struct_fields(&rt)?;
+                    struct_def
+                        .get(field)
+                        .ok_or(TypeError::FieldNotFound(Type::Ref(rt.clone()), *field))?
+                        .clone()
+                }
+                t @ Type::NullRef(ReferenceType::Struct(_) | ReferenceType::GenericStruct(..)) => {
+                    return Err(TypeError::ProjectionOnNullable(t, *field))
+                }
+                t => return Err(TypeError::FieldNotFound(t, *field)),
+            },
+            Unary(op, nested) => {
+                let nested_type = nested.type_check(tc, lc)?;
+                let (expected_type, resulting_type) = op.op_type();
+
+                if nested_type != expected_type {
+                    return Err(TypeError::InvalidUnaryOperand {
+                        op: *op,
+                        operand: nested_type,
+                    });
+                }
+
+                resulting_type
+            }
+            Binary { op, left, right } => {
+                let (left_type, right_type) = match op.op_type() {
+                    // The right operand of `&&` only runs when the left one
+                    // is true, and the right operand of `||` when it is false
+                    Some(_) if matches!(op, BinaryOp::AndAlso | BinaryOp::OrElse) => {
+                        let left_type = left.type_check(tc, lc)?;
+                        let (when_true, when_false) = condition_facts(left, lc);
+                        let facts = match op {
+                            BinaryOp::AndAlso => when_true,
+                            _ => when_false,
+                        };
+                        let mut right_lc = lc.with_non_null(lc.non_null().join(&facts));
+                        (left_type, right.type_check(tc, &mut right_lc)?)
+                    }
+                    Some(_) => (left.type_check(tc, lc)?, right.type_check(tc, lc)?),
+                    None => (declared_type(left, tc, lc)?, declared_type(right, tc, lc)?),
+                };
+                let well_typed = match op.op_type() {
+                    Some(((expected_left, expected_right), _)) => {
+                        left_type == expected_left && right_type == expected_right
+                    }
+                    // Equality requires each side to be a subtype of the other
+                    None => {
+                        tc.is_subtype(&left_type, &right_type)?
+                            && tc.is_subtype(&right_type, &left_type)?
+                    }
+                };
+                if !well_typed {
+                    return Err(TypeError::InvalidBinaryOperands {
+                        op: *op,
+                        left: left_type,
+                        right: right_type,
+                    });
+                }
+                op.op_type().map_or(Type::Bool, |(_, output)| output)
+            }
+            NewArr(type_, e) => {
+                type_.type_check(tc, lc)?;
+                // Type parameters are checked when they are instantiated, by
+                // monomorphization
+                if !matches!(type_, Type::Param(_)) && !type_.has_default_value() {
+                    return Err(TypeError::NonDefaultArrayElement(type_.clone()));
+                }
+                match e.type_check(tc, lc)? {
+                    Type::Int => Type::Ref(ReferenceType::Array(Box::new(type_.clone()))),
+                    t => return Err(TypeError::ArrayLength(t)),
+                }
+            }
+            CArr(type_, elements) => {
+                type_.type_check(tc, lc)?;
+                elements.iter_mut().try_for_each(|e| {
+                    let e_ty = e.type_check(tc, lc)?;
+                    if !tc.is_subtype(&e_ty, type_)? {
+                        return Err(TypeError::IncompatibleArrayElement {
+                            array: type_.clone(),
+                            elt: e_ty,
+                        });
+                    }
+                    Ok(())
+                })?;
+                Type::Ref(ReferenceType::Array(Box::new(type_.clone())))
+            }
+            Call(fun, args) => {
+                let ret_type = match generic_callee(fun, tc, lc) {
+                    Some(name) => type_check_generic_call(fun, name, args, tc, lc)?,
+                    None => {
+                        let (arg_types, ret_type) = match fun.type_check(tc, lc)? {
+                            Type::Ref(ReferenceType::Function(arg_types, ret_type)) => {
+                                (arg_types, ret_type)
+                            }
+                            _ => return Err(TypeError::CanOnlyCallFunctions),
+                        };
+                        type_check_arguments(&arg_types, args, tc, lc)?;
+                        *ret_type
+                    }
+                };
+                match ret_type {
+                    ReturnType::ReturnVoid => return Err(TypeError::VoidExpression),
+                    ReturnType::ReturnValue(t) => t,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod expression_tests {
+    use super::*;
+    use oat_ast::{BinaryOp, UnaryOp};
+    use oat_symbol::create_session_if_not_set_then;
+
+    use BinaryOp::*;
+    use Expression::*;
+    use UnaryOp::*;
+
+    fn id(name: &str) -> oat::Id {
+        name.into()
+    }
+
+    fn var(name: &str) -> Expression {
+        name.into()
+    }
+
+    fn binary(op: BinaryOp, left: Expression, right: Expression) -> Expression {
+        Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn unary(op: UnaryOp, e: Expression) -> Expression {
+        Unary(op, Box::new(e))
+    }
+
+    fn call(fun: &str, args: Vec<Expression>) -> Expression {
+        Call(Box::new(var(fun)), args)
+    }
+
+    fn proj(e: Expression, field: &str) -> Expression {
+        Proj(Box::new(e), id(field))
+    }
+
+    fn strukt(name: &str) -> ReferenceType {
+        ReferenceType::Struct(id(name))
+    }
+
+    fn array(t: Type) -> Type {
+        Type::Ref(ReferenceType::Array(Box::new(t)))
+    }
+
+    fn function(args: Vec<Type>, ret: ReturnType) -> Type {
+        Type::Ref(ReferenceType::Function(args, Box::new(ret)))
+    }
+
+    fn string() -> Type {
+        Type::Ref(ReferenceType::String)
+    }
+
+    /// Typing context shared by every case: `point` and its extension
+    /// `point3`, plus a local of every interesting type.
+    fn context() -> (TypingContext, LocalsContext<Type>) {
+        let point: oat::TypeDeclaration = oat::TypeDeclaration {
+            name: id("point"),
+            type_params: vec![],
+            fields: IndexMap::from_iter([(id("x"), Type::Int), (id("y"), Type::Int)]),
+        };
+        let point3 = oat::TypeDeclaration {
+            name: id("point3"),
+            type_params: vec![],
+            fields: IndexMap::from_iter([
+                (id("x"), Type::Int),
+                (id("y"), Type::Int),
+                (id("z"), Type::Int),
+            ]),
+        };
+        let tc = TypingContext::from_declarations(&vec![point, point3]);
+
+        let mut lc = LocalsContext::new();
+        lc.set(id("i"), Type::Int);
+        lc.set(id("b"), Type::Bool);
+        lc.set(id("s"), string());
+        lc.set(id("ns"), Type::NullRef(ReferenceType::String));
+        lc.set(id("p"), Type::Ref(strukt("point")));
+        lc.set(id("p3"), Type::Ref(strukt("point3")));
+        lc.set(id("np"), Type::NullRef(strukt("point")));
+        lc.set(id("arr"), array(Type::Int));
+        lc.set(
+            id("narr"),
+            Type::NullRef(ReferenceType::Array(Box::new(Type::Int))),
+        );
+        lc.set(
+            id("f"),
+            function(
+                vec![Type::Int, Type::Bool],
+                ReturnType::ReturnValue(Type::Int),
+            ),
+        );
+        lc.set(
+            id("g"),
+            function(
+                vec![Type::NullRef(strukt("point"))],
+                ReturnType::ReturnValue(Type::Bool),
+            ),
+        );
+        lc.set(id("h"), function(vec![], ReturnType::ReturnVoid));
+        (tc, lc)
+    }
+
+    fn check_cases(cases: impl FnOnce() -> Vec<(Expression, Result<Type, TypeError>)>) {
+        create_session_if_not_set_then(|_| {
+            let (mut tc, mut lc) = context();
+            for (mut expression, expected) in cases() {
+                assert_eq!(
+                    expression.type_check(&mut tc, &mut lc),
+                    expected,
+                    "while checking {:?}",
+                    expression
+                );
+            }
+        })
+    }
+
+    #[test]
+    fn constants() {
+        check_cases(|| {
+            vec![
+                (CInt(3), Ok(Type::Int)),
+                (CBool(true), Ok(Type::Bool)),
+                (CStr("hello".to_string()), Ok(string())),
+                (CNull(strukt("point")), Ok(Type::NullRef(strukt("point")))),
+                (
+                    CNull(strukt("line")),
+                    Err(TypeError::StructNotFound(id("line"))),
+                ),
+            ]
+        })
+    }
+
+    #[test]
+    fn identifiers() {
+        check_cases(|| {
+            vec![
+                (var("i"), Ok(Type::Int)),
+                (var("np"), Ok(Type::NullRef(strukt("point")))),
+                (
+                    var("missing"),
+                    Err(TypeError::UndefinedVariable("missing".to_string())),
+                ),
+            ]
+        })
+    }
+
+    #[test]
+    fn integer_operators() {
+        check_cases(|| {
+            let mut cases = vec![];
+            for op in [Add, Sub, Mul, IAnd, IOr, Shl, Shr, Sar] {
+                cases.push((binary(op, var("i"), CInt(1)), Ok(Type::Int)));
+                cases.push((
+                    binary(op, var("i"), var("b")),
+                    Err(TypeError::InvalidBinaryOperands {
+                        op,
+                        left: Type::Int,
+                        right: Type::Bool,
+                    }),
+                ));
+            }
+            for op in [Lt, Lte, Gt, Gte] {
+                cases.push((binary(op, var("i"), CInt(1)), Ok(Type::Bool)));
+                cases.push((
+                    binary(op, var("s"), CInt(1)),
+                    Err(TypeError::InvalidBinaryOperands {
+                        op,
+                        left: string(),
+                        right: Type::Int,
+                    }),
+                ));
+            }
+            cases
+        })
+    }
+
+    #[test]
+    fn boolean_operators() {
+        check_cases(|| {
+            let mut cases = vec![];
+            for op in [And, Or] {
+                cases.push((binary(op, CBool(true), CBool(false)), Ok(Type::Bool)));
+                cases.push((
+                    binary(op, var("b"), var("i")),
+                    Err(TypeError::InvalidBinaryOperands {
+                        op,
+                        left: Type::Bool,
+                        right: Type::Int,
+                    }),
+                ));
+            }
+            cases
+        })
+    }
+
+    #[test]
+    fn equality() {
+        check_cases(|| {
+            let mut cases = vec![];
+            for op in [Eq, Neq] {
+                cases.push((binary(op, var("i"), CInt(0)), Ok(Type::Bool)));
+                cases.push((binary(op, var("b"), CBool(false)), Ok(Type::Bool)));
+                cases.push((binary(op, var("s"), var("s")), Ok(Type::Bool)));
+                cases.push((
+                    binary(op, var("np"), CNull(strukt("point"))),
+                    Ok(Type::Bool),
+                ));
+                // `point <: point?`, but not the other way around
+                cases.push((
+                    binary(op, var("p"), CNull(strukt("point"))),
+                    Err(TypeError::InvalidBinaryOperands {
+                        op,
+                        left: Type::Ref(strukt("point")),
+                        right: Type::NullRef(strukt("point")),
+                    }),
+                ));
+                cases.push((
+                    binary(op, var("p3"), var("p")),
+                    Err(TypeError::InvalidBinaryOperands {
+                        op,
+                        left: Type::Ref(strukt("point3")),
+                        right: Type::Ref(strukt("point")),
+                    }),
+                ));
+                cases.push((
+                    binary(op, var("i"), var("b")),
+                    Err(TypeError::InvalidBinaryOperands {
+                        op,
+                        left: Type::Int,
+                        right: Type::Bool,
+                    }),
+                ));
+            }
+            cases
+        })
+    }
+
+    #[test]
+    fn unary_operators() {
+        check_cases(|| {
+            vec![
+                (unary(Neg, var("i")), Ok(Type::Int)),
+                (unary(Bitnot, var("i")), Ok(Type::Int)),
+                (unary(Lognot, var("b")), Ok(Type::Bool)),
+                (
+                    unary(Neg, var("b")),
+                    Err(TypeError::InvalidUnaryOperand {
+                        op: Neg,
+                        operand: Type::Bool,
+                    }),
+                ),
+                (
+                    unary(Lognot, var("i")),
+                    Err(TypeError::InvalidUnaryOperand {
+                        op: Lognot,
+                        operand: Type::Int,
+                    }),
+                ),
+            ]
+        })
+    }
+
+    #[test]
+    fn calls() {
+        check_cases(|| {
+            vec![
+                (call("f", vec![CInt(1), CBool(true)]), Ok(Type::Int)),
+                // Arguments may be subtypes of the parameters
+                (call("g", vec![var("p")]), Ok(Type::Bool)),
+                (call("g", vec![var("p3")]), Ok(Type::Bool)),
+                (
+                    call("f", vec![CInt(1)]),
+                    Err(TypeError::IncompatibleFunctionArgCounts {
+                        expected: 2,
+                        given: 1,
+                    }),
+                ),
+                (
+                    call("f", vec![CInt(1), CBool(true), CInt(2)]),
+                    Err(TypeError::IncompatibleFunctionArgCounts {
+                        expected: 2,
+                        given: 3,
+                    }),
+                ),
+                (
+                    call("f", vec![CBool(true), CBool(true)]),
+                    Err(TypeError::IncompatibleArgument {
+                        expected: Type::Int,
+                        given: Type::Bool,
+                    }),
+                ),
+                (call("h", vec![]), Err(TypeError::VoidExpression)),
+                (call("i", vec![]), Err(TypeError::CanOnlyCallFunctions)),
+            ]
+        })
+    }
+
+    #[test]
+    fn arrays() {
+        check_cases(|| {
+            let index = |value: Expression, index: Expression| Index {
+                value: Box::new(value),
+                index: Box::new(index),
+            };
+            vec![
+                (NewArr(Type::Int, Box::new(CInt(3))), Ok(array(Type::Int))),
+                (
+                    NewArr(Type::NullRef(ReferenceType::String), Box::new(var("i"))),
+                    Ok(array(Type::NullRef(ReferenceType::String))),
+                ),
+                (
+                    NewArr(string(), Box::new(CInt(3))),
+                    Err(TypeError::NonDefaultArrayElement(string())),
+                ),
+                (
+                    NewArr(Type::Int, Box::new(var("b"))),
+                    Err(TypeError::ArrayLength(Type::Bool)),
+                ),
+                (
+                    CArr(Type::Ref(strukt("point")), vec![var("p"), var("p3")]),
+                    Ok(array(Type::Ref(strukt("point")))),
+                ),
+                (
+                    CArr(Type::Int, vec![CInt(1), var("b")]),
+                    Err(TypeError::IncompatibleArrayElement {
+                        array: Type::Int,
+                        elt: Type::Bool,
+                    }),
+                ),
+                (
+                    CArr(Type::Ref(strukt("line")), vec![]),
+                    Err(TypeError::StructNotFound(id("line"))),
+                ),
+                (index(var("arr"), CInt(0)), Ok(Type::Int)),
+                (
+                    index(var("arr"), var("b")),
+                    Err(TypeError::NonIntegerIndex(Type::Bool)),
+                ),
+                (
+                    index(var("narr"), CInt(0)),
+                    Err(TypeError::CannotSubscript(Type::NullRef(
+                        ReferenceType::Array(Box::new(Type::Int)),
+                    ))),
+                ),
+                (Length(Box::new(var("arr"))), Ok(Type::Int)),
+                (
+                    Length(Box::new(var("s"))),
+                    Err(TypeError::CannotGetLength(string())),
+                ),
+            ]
+        })
+    }
+
+    /// The expression returned by `bool f() { return <source>; }`
+    fn parsed(source: &str) -> Expression {
+        let program = oat_parse::parse_program(&format!("bool f() {{ return {}; }}", source))
+            .expect("test expression should parse");
+        match program.declarations.as_slice() {
+            [oat::Declaration::Function(f)] => match f.body.as_slice() {
+                [oat::Statement::Return(Some(e))] => e.clone(),
+                body => panic!("unexpected body {:?}", body),
+            },
+            declarations => panic!("unexpected declarations {:?}", declarations),
+        }
+    }
+
+    /// Guards need no parentheses, since `&&` and `||` bind looser than
+    /// comparisons
+    #[test]
+    fn unparenthesized_guards() {
+        create_session_if_not_set_then(|_| {
+            let (mut tc, lc) = context();
+            // Only locals are narrowed
+            let mut lc = lc.new_child();
+            lc.set(id("n"), Type::NullRef(strukt("point")));
+            let cases = [
+                ("n != point null && n.x > 0", Ok(Type::Bool)),
+                ("n == point null || n.y == 0", Ok(Type::Bool)),
+                ("b || n != point null && n.x > 0", Ok(Type::Bool)),
+                (
+                    "n != point null || n.x > 0",
+                    Err(TypeError::ProjectionOnNullable(
+                        Type::NullRef(strukt("point")),
+                        id("x"),
+                    )),
+                ),
+            ];
+            for (source, expected) in cases {
+                assert_eq!(
+                    parsed(source).type_check(&mut tc, &mut lc),
+                    expected,
+                    "while checking {}",
+                    source
+                );
+            }
+        })
+    }
+
+    #[test]
+    fn structs() {
+        check_cases(|| {
+            let point = |fields: Vec<(&str, Expression)>| {
+                CStruct(
+                    id("point"),
+                    vec![],
+                    fields.into_iter().map(|(f, e)| (id(f), e)).collect(),
+                )
+            };
+            vec![
+                (
+                    point(vec![("x", CInt(1)), ("y", CInt(2))]),
+                    Ok(Type::Ref(strukt("point"))),
+                ),
+                // Field order in the literal does not matter
+                (
+                    point(vec![("y", CInt(2)), ("x", CInt(1))]),
+                    Ok(Type::Ref(strukt("point"))),
+                ),
+                (
+                    point(vec![("x", CInt(1))]),
+                    Err(TypeError::MissingField(Type::Ref(strukt("point")), id("y"))),
+                ),
+                (
+                    point(vec![("x", CInt(1)), ("y", CInt(2)), ("z", CInt(3))]),
+                    Err(TypeError::ExtraField(Type::Ref(strukt("point")), id("z"))),
+                ),
+                (
+                    point(vec![("x", CInt(1)), ("x", CInt(2))]),
+                    Err(TypeError::DuplicateField(id("x"))),
+                ),
+                (
+                    point(vec![("x", CBool(true)), ("y", CInt(2))]),
+                    Err(TypeError::IncompatibleType),
+                ),
+                (
+                    CStruct(id("line"), vec![], vec![]),
+                    Err(TypeError::StructNotFound(id("line"))),
+                ),
+                (proj(var("p"), "x"), Ok(Type::Int)),
+                (proj(var("p3"), "z"), Ok(Type::Int)),
+                (
+                    proj(var("p"), "z"),
+                    Err(TypeError::FieldNotFound(
+                        Type::Ref(strukt("point")),
+                        id("z"),
+                    )),
+                ),
+                (
+                    proj(var("np"), "x"),
+                    Err(TypeError::ProjectionOnNullable(
+                        Type::NullRef(strukt("point")),
+                        id("x"),
+                    )),
+                ),
+                (
+                    proj(var("i"), "x"),
+                    Err(TypeError::FieldNotFound(Type::Int, id("x"))),
+                ),
+            ]
+        })
+    }
+}
@@ -5,28 +5,36 @@
 //! [`type_check`]: fn@type_check
 
 use std::borrow::Borrow;
-use std::collections::{HashSet, LinkedList};
+use std::collections::LinkedList;
 
-use indexmap::IndexMap;
-
-use oat::{Expression, FunctionDecl, GlobalDeclaration, ReferenceType, ReturnType, Statement};
+use oat::{Expression, FunctionDecl, ReturnType, Statement};
 use oat_ast as oat;
 use oat_ast::{Id, Type};
-use oat_typecontext::TypingContext;
+use oat_typecontext::{builtins, GenericFunction, TypingContext};
 
 use oat_error::TypeError;
 
 mod locals_context;
 use locals_context::LocalsContext;
 
+mod expression;
+use expression::{declared_type, generic_callee, type_check_arguments, type_check_generic_call};
+
+mod control_flow;
+use control_flow::{completes, ControlFlowGraph};
+
+mod narrowing;
+use narrowing::{condition_facts, merge_branches, NonNull};
+
 /// Trait for making sure things can be type-checked.
 ///
 /// Associated type `Output` is for whatever extra information needs to be
-/// returned.
+/// returned. Checking fills in the type arguments it infers, so the checked
+/// node is the elaborated one.
 trait TypeCheck {
     type Output;
     fn type_check(
-        &self,
+        &mut self,
         tc: &mut TypingContext,
         lc: &mut LocalsContext<Type>,
     ) -> Result<Self::Output, TypeError>;
@@ -38,11 +46,22 @@ fn tc_type(
     lc: &mut LocalsContext<Type>,
     seen: &mut LinkedList<Id>,
 ) -> Result<(), TypeError> {
-    use oat_ast::ReferenceType::{Array, Function, Struct};
+    use oat_ast::ReferenceType::{Array, Function, GenericStruct, Struct};
     use oat_ast::Type::{NullRef, Ref};
     match type_ {
-        Ref(Struct(name)) | NullRef(Struct(name)) if seen.contains(name) => Ok(()),
-        Ref(Struct(name)) | NullRef(Struct(name)) => {
+        Ref(rt @ (Struct(name) | GenericStruct(name, _)))
+        | NullRef(rt @ (Struct(name) | GenericStruct(name, _))) => {
+            if let GenericStruct(_, type_args) = rt {
+                for type_arg in type_args.iter() {
+                    tc_type(type_arg, tc, lc, seen)?;
+                }
+            }
+            // Checks that the struct exists with the right number of type
+            // arguments
+            tc.struct_fields(rt)?;
+            if seen.contains(name) {
+                return Ok(());
+            }
             seen.push_front(*name);
             tc.get_type(name)
                 .ok_or(TypeError::StructNotFound(*name))
@@ -76,7 +95,7 @@ fn tc_type(
 impl TypeCheck for oat::Type {
     type Output = ();
     fn type_check(
-        &self,
+        &mut self,
         tc: &mut TypingContext,
         lc: &mut LocalsContext<Type>,
     ) -> Result<(), TypeError> {
@@ -84,208 +103,61 @@ impl TypeCheck for oat::Type {
     }
 }
 
-fn check_duplicate_fields(
-    fields: Vec<(oat::Id, oat::Expression)>,
-) -> Result<IndexMap<oat::Id, oat::Expression>, TypeError> {
-    let mut field_names: HashSet<oat::Id> = HashSet::new();
-    for (field_name, _) in fields.iter() {
-        if field_names.contains(field_name) {
-            return Err(TypeError::DuplicateField(*field_name));
-        }
-        field_names.insert(*field_name);
-    }
-    Ok(IndexMap::from_iter(fields))
-}
-
-impl TypeCheck for oat::Expression {
-    type Output = Type;
-
-    fn type_check(
-        &self,
-        tc: &mut TypingContext,
-        lc: &mut LocalsContext<Type>,
-    ) -> Result<Type, TypeError> {
-        use oat_ast::Expression::*;
-        Ok(match self {
-            CNull(rt) => Type::NullRef(rt.clone()),
-            CBool(_) => Type::Bool,
-            CInt(_) => Type::Int,
-            CStr(_) => Type::Ref(oat_ast::ReferenceType::String),
-            Id(name) => lc
-                .lookup(*name)
-                .ok_or_else(|| TypeError::UndefinedVariable(name.name().to_string()))?,
-            Length(e) => match e.type_check(tc, lc)? {
-                Type::Ref(oat_ast::ReferenceType::Array(_)) => Type::Int,
-                t => return Err(TypeError::CannotGetLength(t)),
-            },
-            Index { value, index } => match value.type_check(tc, lc)? {
-                Type::Ref(oat::ReferenceType::Array(t)) => match index.type_check(tc, lc)? {
-                    Type::Int => *t,
-                    ti => return Err(TypeError::NonIntegerIndex(ti)),
-                },
-                t => return Err(TypeError::CannotSubscript(t)),
-            },
-            CStruct(struct_name, fields) => {
-                let instance = check_duplicate_fields(fields.to_vec())?;
-                let tc_ = tc.clone();
-                let struct_def = tc_
-                    .get_type(struct_name)
-                    .ok_or(TypeError::StructNotFound(*struct_name))?;
-
-                for (field_name, field_type) in struct_def.iter() {
-                    let expected_type = Type::Ref(oat::ReferenceType::Struct(*struct_name));
-                    let expr = instance
-                        .get(field_name)
-                        .ok_or(TypeError::MissingField(expected_type, *field_name))?;
-                    let type_ = expr.type_check(tc, lc)?;
-                    let compatible = tc.is_subtype(&type_, field_type)?;
-                    if !compatible {
-                        return Err(TypeError::IncompatibleType);
-                    }
-                }
-                Type::Ref(oat::ReferenceType::Struct(*struct_name))
-            }
-            Proj(e, field) => match e.type_check(tc, lc)? {
-                Type::Ref(oat::ReferenceType::Struct(struct_name)) => {
-                    let struct_def = tc
-                        .get_type(&struct_name)
-                        .ok_or(TypeError::StructNotFound(struct_name))?;
-                    let struct_type = Type::Ref(oat::ReferenceType::Struct(struct_name));
-                    struct_def
-                        .get(field)
-                        .ok_or(TypeError::FieldNotFound(struct_type, *field))?
-                        .clone()
-                }
-                _ => return Err(TypeError::IncompatibleType),
-            },
-            Unary(unop, nested) => {
-                let nested_type = nested.type_check(tc, lc)?;
-                let (expected_type, resulting_type) = unop.op_type();
-
-                if nested_type != expected_type {
-                    return Err(TypeError::IncompatibleType);
-                }
-
-                resulting_type
-            }
-            Binary { op, left, right } => {
-                use oat_ast::BinaryOp::{Eq, Neq};
-                match (
-                    op.op_type(),
-                    left.type_check(tc, lc)?,
-                    right.type_check(tc, lc)?,
-                ) {
-                    (Some(((Type::Int, Type::Int), outt)), Type::Int, Type::Int) => outt,
-                    (None, lt, rt) if lt == rt && matches!(op, Eq | Neq) => Type::Bool,
-                    _ => return Err(TypeError::IncompatibleType),
-                }
-            }
-            NewArr(type_, e) => match e.type_check(tc, lc)? {
-                Type::Int => Type::Ref(ReferenceType::Array(Box::new(type_.clone()))),
-                t => return Err(TypeError::ArrayLength(t)),
-            },
-            CArr(type_, elements) => {
-                elements.iter().try_for_each(|e| {
-                    let e_ty = e.type_check(tc, lc)?;
-                    if !tc.is_subtype(&e_ty, type_)? {
-                        return Err(TypeError::IncompatibleArrayElement {
-                            array: type_.clone(),
-                            elt: e_ty,
-                        });
-                    }
-                    Ok(())
-                })?;
-                Type::Ref(ReferenceType::Array(Box::new(type_.clone())))
-            }
-            Call(fun, args) => {
-                let (arg_types, ret_type) = match fun.type_check(tc, lc)? {
-                    Type::Ref(ReferenceType::Function(arg_types, ret_type)) => {
-                        (arg_types, ret_type)
-                    }
-                    _ => return Err(TypeError::CanOnlyCallFunctions),
-                };
-                std::iter::zip(arg_types.iter(), args.iter()).try_for_each(|(arg_type, arg)| {
-                    let type_ = arg.type_check(tc, lc)?;
-                    if !tc.is_subtype(&type_, arg_type)? {
-                        return Err(TypeError::IncompatibleType);
-                    }
-                    Ok(())
-                })?;
-                match ret_type.borrow() {
-                    ReturnType::ReturnVoid => return Err(TypeError::VoidExpression),
-                    ReturnType::ReturnValue(t) => t.clone(),
-                }
-            }
-        })
-    }
-}
-
-fn type_check_arguments(
-    arg_types: Vec<Type>,
-    args: &Vec<Expression>,
-    tc: &mut TypingContext,
-    lc: &mut LocalsContext<Type>,
-) -> Result<(), TypeError> {
-    if arg_types.len() != args.len() {
-        return Err(TypeError::IncompatibleFunctionArgCounts {
-            expected: arg_types.len(),
-            given: args.len(),
-        });
-    }
-
-    std::iter::zip(arg_types.iter(), args.iter()).try_for_each(|(arg_type, arg)| {
-        let passed_type = arg.type_check(tc, lc)?;
-        if !tc.is_subtype(&passed_type, arg_type)? {
-            return Err(TypeError::IncompatibleType);
-        }
-        Ok(())
-    })?;
-    Ok(())
-}
-
-#[must_use]
-#[repr(transparent)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct Returns(bool);
-
-impl std::ops::BitAnd<Returns> for Returns {
-    type Output = Returns;
-
-    fn bitand(self, other: Returns) -> Self::Output {
-        Returns(self.0 && other.0)
-    }
-}
-
-impl std::ops::BitOr<Returns> for Returns {
-    type Output = Returns;
-
-    fn bitor(self, other: Returns) -> Self::Output {
-        Returns(self.0 || other.0)
-    }
-}
-
 fn type_check_statement(
-    stmt: &Statement,
+    stmt: &mut Statement,
     tc: &mut TypingContext,
     lc: &mut LocalsContext<Type>,
     should_return: oat_ast::ReturnType,
-) -> Result<Returns, TypeError> {
+) -> Result<(), TypeError> {
     use oat_ast::ReferenceType::Function;
     use oat_ast::Statement::*;
     use oat_ast::Type::Ref;
 
     let is_function = |name: &Id| matches!(lc.lookup(*name), Some(Ref(Function(..))));
 
-    Ok(match stmt {
+    match stmt {
         Assignment(Expression::Id(name), _) if is_function(name) => {
             return Err(TypeError::CannotAssignFunction)
         }
-        Declaration(name, e) => {
+        Assignment(target, value) => {
+            let target_type = match target {
+                // The assignment replaces the value, so any narrowing of the
+                // variable does not apply
+                Expression::Id(name) => lc.resolve(*name)?,
+                Expression::Index { .. } | Expression::Proj(..) => target.type_check(tc, lc)?,
+                _ => return Err(TypeError::NotAssignable),
+            };
+            let value_type = value.type_check(tc, lc)?;
+            if !tc.is_subtype(&value_type, &target_type)? {
+                return Err(TypeError::IncompatibleType);
+            }
+            if let Expression::Id(name) = target {
+                match value_type {
+                    Type::Ref(_) if lc.is_local(*name) => lc.non_null_mut().insert(*name),
+                    _ => lc.non_null_mut().remove(*name),
+                }
+            }
+        }
+        Declaration(None, name, e) => {
             let type_ = e.type_check(tc, lc)?;
-            lc.set(*name, type_);
-            Returns(false)
+            lc.declare(*name, type_)?;
         }
-        Return(None) if should_return == oat_ast::ReturnType::ReturnVoid => Returns(true),
+        Declaration(Some(declared), name, e) => {
+            declared.type_check(tc, lc)?;
+            let type_ = e.type_check(tc, lc)?;
+            if !tc.is_subtype(&type_, declared)? {
+                return Err(TypeError::IncompatibleInitializer {
+                    name: *name,
+                    declared: declared.clone(),
+                    given: type_,
+                });
+            }
+            lc.declare(*name, declared.clone())?;
+            if matches!((declared, type_), (Type::NullRef(_), Type::Ref(_))) {
+                lc.non_null_mut().insert(*name);
+            }
+        }
+        Return(None) if should_return == oat_ast::ReturnType::ReturnVoid => {}
         Return(None) => return Err(TypeError::ReturnValueMissing),
         Return(Some(rv)) => {
             let type_ = rv.type_check(tc, lc)?;
@@ -294,20 +166,25 @@ fn type_check_statement(
                     return Err(TypeError::ReturnValueProvidedInVoidFunction)
                 }
                 oat_ast::ReturnType::ReturnValue(ret_ty) => {
-                    if tc.is_subtype(&type_, &ret_ty)? {
-                        Returns(true)
-                    } else {
+                    if !tc.is_subtype(&type_, &ret_ty)? {
                         return Err(TypeError::IncompatibleType);
                     }
                 }
             }
         }
-        SCall(fun, args) => match fun.type_check(tc, lc)? {
-            Ref(Function(arg_types, ret_type)) if *ret_type == oat_ast::ReturnType::ReturnVoid => {
-                type_check_arguments(arg_types, args, tc, lc)?;
-                Returns(false)
-            }
-            _ => return Err(TypeError::IncompatibleType),
+        SCall(fun, args) => match generic_callee(fun, tc, lc) {
+            Some(name) => match type_check_generic_call(fun, name, args, tc, lc)? {
+                oat_ast::ReturnType::ReturnVoid => {}
+                _ => return Err(TypeError::IncompatibleType),
+            },
+            None => match fun.type_check(tc, lc)? {
+                Ref(Function(arg_types, ret_type))
+                    if *ret_type == oat_ast::ReturnType::ReturnVoid =>
+                {
+                    type_check_arguments(&arg_types, args, tc, lc)?;
+                }
+                _ => return Err(TypeError::IncompatibleType),
+            },
         },
         If {
             condition,
@@ -318,40 +195,165 @@ fn type_check_statement(
                 Type::Bool => {}
                 _ => return Err(TypeError::IncompatibleType),
             }
-            let then_returns = type_check_block(then, tc, lc, should_return.clone())?;
-            let else_returns = type_check_block(else_, tc, lc, should_return.clone())?;
-            Returns(then_returns.0 && else_returns.0)
+            let (when_true, when_false) = condition_facts(condition, lc);
+            let then_lc = lc.with_non_null(lc.non_null().join(&when_true));
+            let else_lc = lc.with_non_null(lc.non_null().join(&when_false));
+            let then_facts = type_check_block(then, tc, &then_lc, should_return.clone())?;
+            let else_facts = type_check_block(else_, tc, &else_lc, should_return)?;
+            lc.set_non_null(merge_branches(
+                (completes(then), then_facts),
+                (completes(else_), else_facts),
+            ));
         }
         While { condition, body } => {
-            match condition.type_check(tc, lc)? {
-                Type::Bool => {}
+            type_check_loop(Some(condition), body, None, tc, lc, should_return)?
+        }
+        For {
+            init,
+            condition,
+            update,
+            body,
+        } => {
+            let mut for_lc = lc.clone().new_child();
+            for_lc.expect_declarations(init.iter().map(|(name, _)| *name));
+            for (name, e) in init.iter_mut() {
+                let type_ = e.type_check(tc, &mut for_lc)?;
+                for_lc.declare(*name, type_)?;
+            }
+            type_check_loop(
+                condition.as_mut(),
+                body,
+                update.as_deref_mut(),
+                tc,
+                &mut for_lc,
+                should_return,
+            )?;
+            lc.set_non_null(for_lc.into_non_null());
+        }
+        Cast(ref_type, name, e, then, else_) => {
+            let mut cast_type = Type::Ref(ref_type.clone());
+            cast_type.type_check(tc, lc)?;
+            match declared_type(e, tc, lc)? {
+                Type::NullRef(rt) if tc.is_subtype(&Type::Ref(rt.clone()), &cast_type)? => {}
                 _ => return Err(TypeError::IncompatibleType),
             }
-            type_check_block(body, tc, lc, should_return)?
+
+            // A local being cast is also known to be non-null in the first block
+            let mut then_facts = lc.non_null().clone();
+            if let Expression::Id(cast_local) = e {
+                if lc.is_local(*cast_local) {
+                    then_facts.insert(*cast_local);
+                }
+            }
+            let mut then_lc = lc.with_non_null(then_facts).new_child();
+            then_lc.declare(*name, cast_type)?;
+
+            let mut then_facts = type_check_block(then, tc, &then_lc, should_return.clone())?;
+            then_facts.remove(*name);
+            let else_facts = type_check_block(else_, tc, lc, should_return)?;
+            lc.set_non_null(merge_branches(
+                (completes(then), then_facts),
+                (completes(else_), else_facts),
+            ));
         }
+    }
+    Ok(())
+}
 
-        _ => return Err(TypeError::IncompatibleType),
-    })
+/// Type check one iteration of a loop: the condition, the body, and then the
+/// update of a `for` loop. Returns the facts that hold when the condition is
+/// evaluated again, or `None` if the body never completes.
+fn type_check_loop_iteration(
+    condition: Option<&mut Expression>,
+    body: &mut [Statement],
+    update: Option<&mut Statement>,
+    tc: &mut TypingContext,
+    lc: &LocalsContext<Type>,
+    should_return: ReturnType,
+) -> Result<Option<NonNull>, TypeError> {
+    let mut lc = lc.clone();
+    if let Some(condition) = condition {
+        match condition.type_check(tc, &mut lc)? {
+            Type::Bool => {}
+            _ => return Err(TypeError::IncompatibleType),
+        }
+        let (when_true, _) = condition_facts(condition, &lc);
+        lc.set_non_null(lc.non_null().join(&when_true));
+    }
+    let facts = type_check_block(body, tc, &lc, should_return.clone())?;
+    lc.set_non_null(facts);
+    if let Some(update) = update {
+        type_check_statement(update, tc, &mut lc, should_return)?;
+    }
+    Ok(completes(body).then(|| lc.non_null().clone()))
+}
+
+/// Type check a loop. The facts holding at the loop condition are found by
+/// iterating until they no longer change. Facts can only be removed by an
+/// iteration, so this terminates.
+///
+/// Checking fills in the type arguments it infers, which depend on the facts,
+/// so the iterations check a copy of the loop. Only an iteration with the
+/// facts of the fixpoint checks the loop itself.
+fn type_check_loop(
+    mut condition: Option<&mut Expression>,
+    body: &mut [Statement],
+    update: Option<&mut Statement>,
+    tc: &mut TypingContext,
+    lc: &mut LocalsContext<Type>,
+    should_return: ReturnType,
+) -> Result<(), TypeError> {
+    let mut head = lc.non_null().clone();
+    loop {
+        let facts = type_check_loop_iteration(
+            condition.as_deref().cloned().as_mut(),
+            &mut body.to_vec(),
+            update.as_deref().cloned().as_mut(),
+            tc,
+            &lc.with_non_null(head.clone()),
+            should_return.clone(),
+        )?;
+        let next = match facts {
+            Some(facts) => head.meet(&facts),
+            None => head.clone(),
+        };
+        if next == head {
+            break;
+        }
+        head = next;
+    }
+    type_check_loop_iteration(
+        condition.as_deref_mut(),
+        body,
+        update,
+        tc,
+        &lc.with_non_null(head.clone()),
+        should_return,
+    )?;
+
+    let exit_facts = match condition {
+        Some(condition) => head.join(&condition_facts(condition, lc).1),
+        None => head,
+    };
+    lc.set_non_null(exit_facts);
+    Ok(())
 }
 
 fn type_check_block(
-    block: &Vec<Statement>,
+    block: &mut [Statement],
     tc: &mut TypingContext,
     lc: &LocalsContext<Type>,
     should_return: ReturnType,
-) -> Result<Returns, TypeError> {
-    let mut returns = false;
+) -> Result<NonNull, TypeError> {
     let mut lc = lc.clone().new_child();
-    for stmt in block {
-        if returns {
-            return Err(TypeError::DeadCodeAfterReturn);
-        }
-        match type_check_statement(stmt, tc, &mut lc, should_return.clone())? {
-            Returns(true) => returns = true,
-            _ => {}
-        }
+    lc.expect_declarations(block.iter().filter_map(|stmt| match stmt {
+        Statement::Declaration(_, name, _) => Some(*name),
+        _ => None,
+    }));
+    for stmt in block.iter_mut() {
+        type_check_statement(stmt, tc, &mut lc, should_return.clone())?;
     }
-    Ok(Returns(returns))
+    Ok(lc.into_non_null())
 }
 
 // impl TypeCheck for oat::Statement {
@@ -378,7 +380,7 @@ impl TypeCheck for oat::FunctionDecl {
     type Output = ();
 
     fn type_check(
-        &self,
+        &mut self,
         tc: &mut TypingContext,
         lc: &mut LocalsContext<Type>,
     ) -> Result<(), TypeError> {
@@ -387,24 +389,33 @@ impl TypeCheck for oat::FunctionDecl {
             args,
             body,
             name,
+            ..
         } = self;
 
-        let mut lc = {
-            let mut lc = lc.clone().new_child();
-            args.into_iter().for_each(|(t, a)| lc.set(*a, t.clone()));
-            lc
-        };
+        let mut lc = lc.clone().new_parameters();
+        for (t, a) in args {
+            lc.declare(*a, t.clone())?;
+        }
 
         let must_return = *return_type != ReturnType::ReturnVoid;
-        let returns = type_check_block(body, tc, &mut lc, return_type.clone())?;
+        type_check_block(body, tc, &lc, return_type.clone())?;
 
-        // dbg!(must_return);
-        // let _ = dbg!(returns);
-        if must_return && !returns.0 {
-            return Err(TypeError::DidNotReturn {
-                expected_ret_type: return_type.clone(),
+        let cfg = ControlFlowGraph::new(body);
+        if let Some(statement) = cfg.first_unreachable() {
+            return Err(TypeError::UnreachableStatement {
+                function: *name,
+                statement: statement.to_string(),
             });
         }
+        if must_return {
+            if let Some(path) = cfg.path_to_end() {
+                return Err(TypeError::DidNotReturn {
+                    function: *name,
+                    expected_ret_type: return_type.clone(),
+                    path,
+                });
+            }
+        }
 
         println!("Succeeded type checking {}", name.name());
 
@@ -416,12 +427,12 @@ impl TypeCheck for oat::Program {
     type Output = ();
 
     fn type_check(
-        &self,
+        &mut self,
         tc: &mut TypingContext,
         lc: &mut LocalsContext<Type>,
     ) -> Result<(), TypeError> {
         self.declarations
-            .iter()
+            .iter_mut()
             .filter(|decl| matches!(decl, oat::Declaration::Function(..)))
             .try_for_each(|decl| {
                 Ok(match decl {
@@ -433,27 +444,35 @@ impl TypeCheck for oat::Program {
     }
 }
 
+/// Whether `init` can be computed before the program runs: a constant, a
+/// function of `functions`, or an array or struct literal of those.
+fn is_global_initializer(init: &Expression, functions: &LocalsContext<Type>) -> bool {
+    use oat_ast::Expression::*;
+    match init {
+        CNull(_) | CBool(_) | CInt(_) | CStr(_) => true,
+        Id(name) => functions.lookup(*name).is_some(),
+        CArr(_, elements) => elements.iter().all(|e| is_global_initializer(e, functions)),
+        CStruct(_, _, fields) => fields
+            .iter()
+            .all(|(_, e)| is_global_initializer(e, functions)),
+        _ => false,
+    }
+}
+
+/// Globals are checked in a context holding only the functions of the
+/// program, which are the only names their initializers can refer to.
 impl TypeCheck for oat::GlobalDeclaration {
     type Output = Type;
 
     fn type_check(
-        &self,
+        &mut self,
         tc: &mut TypingContext,
         lc: &mut LocalsContext<Type>,
     ) -> Result<Type, TypeError> {
-        use oat_ast::Expression::*;
-
-        Ok(match &self.init {
-            CNull(rt) => Type::NullRef(rt.clone()),
-            CBool(_) => Type::Bool,
-            CInt(_) => Type::Int,
-            CStr(_) => Type::Ref(ReferenceType::String),
-            Id(name) => match lc.lookup(name.clone()) {
-                Some(t) => t,
-                None => return Err(TypeError::UndefinedVariable(name.name().to_string())),
-            },
-            _ => todo!("TODO: Unimplemented global expression"),
-        })
+        if !is_global_initializer(&self.init, lc) {
+            return Err(TypeError::NonConstantGlobal(self.name));
+        }
+        self.init.type_check(tc, lc)
     }
 }
 
@@ -473,6 +492,26 @@ impl TypeCheck for oat::GlobalDeclaration {
 /// [`TypeError`]: enum@oat_error::TypeError
 /// [`oat_error::Error`]: enum@oat_error::Error
 pub fn type_check(prog: &oat::Program) -> Result<(), TypeError> {
+    check_program(&mut prog.clone())
+}
+
+/// Type check a [`Program`], and make the type arguments of generic functions
+/// and structs explicit wherever they were inferred.
+///
+/// Calls of generic functions with inferred type arguments are rewritten to
+/// calls of an [`Instantiate`] of the function, and struct literals have their
+/// inferred type arguments filled in.
+///
+/// [`Program`]: struct@oat_ast::Program
+/// [`Instantiate`]: oat_ast::Expression::Instantiate
+pub fn elaborate(prog: &oat::Program) -> Result<oat::Program, TypeError> {
+    let mut program = prog.clone();
+    check_program(&mut program)?;
+    Ok(program)
+}
+
+/// Type check a program, filling in the type arguments inferred in it.
+fn check_program(prog: &mut oat::Program) -> Result<(), TypeError> {
     let type_declarations: Vec<oat::TypeDeclaration> = prog
         .clone()
         .declarations
@@ -485,8 +524,28 @@ pub fn type_check(prog: &oat::Program) -> Result<(), TypeError> {
     let mut tc: TypingContext = TypingContext::from_declarations(&type_declarations);
     let mut lc: LocalsContext<Type> = {
         let mut global = LocalsContext::<Type>::default();
-        prog.clone().declarations.into_iter().try_for_each(|decl| {
-            Ok(match decl {
+        for (name, t) in builtins() {
+            global.set(name, t);
+        }
+        for decl in prog.clone().declarations {
+            match decl {
+                oat::Declaration::Function(oat::FunctionDecl {
+                    return_type,
+                    name,
+                    type_params,
+                    args,
+                    ..
+                }) if !type_params.is_empty() => {
+                    let arg_types: Vec<Type> = args.into_iter().map(|(t, _)| t).collect();
+                    tc.add_generic_function(
+                        name,
+                        GenericFunction {
+                            type_params,
+                            arg_types,
+                            return_type,
+                        },
+                    )
+                }
                 oat::Declaration::Function(oat::FunctionDecl {
                     return_type,
                     name,
@@ -502,16 +561,22 @@ pub fn type_check(prog: &oat::Program) -> Result<(), TypeError> {
                         )),
                     )
                 }
-                oat::Declaration::Variable(global_decl @ GlobalDeclaration { .. }) => {
-                    let type_ = global_decl.type_check(&mut tc, &mut global)?;
-                    global.set(global_decl.name, type_);
-                }
                 _ => {}
-            })
-        })?;
+            }
+        }
+        // Checked in the program itself, so that the type arguments inferred
+        // for the struct literals of globals are filled in
+        let mut functions = global.clone();
+        for decl in prog.declarations.iter_mut() {
+            if let oat::Declaration::Variable(global_decl) = decl {
+                let type_ = global_decl.type_check(&mut tc, &mut functions)?;
+                global.set(global_decl.name, type_);
+            }
+        }
         global
     };
     // dbg!(tc.clone());
     // dbg!(lc.clone());
-    prog.type_check(&mut tc, &mut lc)
+    prog.type_check(&mut tc, &mut lc)?;
+    Ok(())
 }
@@ -1,8 +1,12 @@
+use std::collections::HashSet;
 use std::rc::Rc;
 
 use indexmap::IndexMap;
 
 use oat_ast::Id;
+use oat_error::TypeError;
+
+use crate::narrowing::NonNull;
 
 #[derive(Debug, Clone)]
 pub(crate) struct LocalsContext<T>
@@ -11,6 +15,13 @@ where
 {
     parent: Option<Rc<LocalsContext<T>>>,
     locals: IndexMap<Id, T>,
+    /// Names declared later in this scope, which may not be used until their
+    /// declaration is reached.
+    pending: HashSet<Id>,
+    /// Whether this scope holds the parameters of a function
+    parameters: bool,
+    /// Locals known to be non-null at the current point
+    non_null: NonNull,
 }
 
 impl<T: Clone> LocalsContext<T> {
@@ -18,13 +29,27 @@ impl<T: Clone> LocalsContext<T> {
         LocalsContext {
             parent: None,
             locals: Default::default(),
+            pending: Default::default(),
+            parameters: false,
+            non_null: Default::default(),
         }
     }
 
     pub fn new_child(self) -> LocalsContext<T> {
+        let non_null = self.non_null.clone();
         LocalsContext {
             parent: Some(Rc::new(self)),
-            locals: Default::default(),
+            non_null,
+            ..LocalsContext::new()
+        }
+    }
+
+    /// Create the scope holding the parameters of a function. Blocks directly
+    /// inside of it are not allowed to shadow the parameters.
+    pub fn new_parameters(self) -> LocalsContext<T> {
+        LocalsContext {
+            parameters: true,
+            ..self.new_child()
         }
     }
 
@@ -35,9 +60,82 @@ impl<T: Clone> LocalsContext<T> {
         self.parent.as_ref().and_then(|parent| parent.lookup(name))
     }
 
+    /// Look up the value of `name`, reporting whether it is undefined or has
+    /// not been declared yet.
+    pub fn resolve(&self, name: Id) -> Result<T, TypeError> {
+        if let Some(v) = self.locals.get(&name) {
+            return Ok(v.clone());
+        }
+        if self.pending.contains(&name) {
+            return Err(TypeError::UseBeforeDeclaration(name));
+        }
+        match &self.parent {
+            Some(parent) => parent.resolve(name),
+            None => Err(TypeError::UndefinedVariable(name.name().to_string())),
+        }
+    }
+
     pub fn set(&mut self, name: Id, value: T) {
         self.locals.insert(name, value);
     }
+
+    /// Declare `name` in the innermost scope, rejecting redeclarations in the
+    /// same scope and shadowing of parameters.
+    pub fn declare(&mut self, name: Id, value: T) -> Result<(), TypeError> {
+        if self.locals.contains_key(&name) {
+            return Err(TypeError::Redeclaration(name));
+        }
+        if let Some(parent) = &self.parent {
+            if parent.parameters && parent.locals.contains_key(&name) {
+                return Err(TypeError::ShadowsParameter(name));
+            }
+        }
+        self.pending.remove(&name);
+        self.non_null.remove(name);
+        self.locals.insert(name, value);
+        Ok(())
+    }
+
+    /// Whether `name` refers to a local variable or parameter, rather than a
+    /// global.
+    pub fn is_local(&self, name: Id) -> bool {
+        match &self.parent {
+            Some(parent) => self.locals.contains_key(&name) || parent.is_local(name),
+            None => false,
+        }
+    }
+
+    pub fn non_null(&self) -> &NonNull {
+        &self.non_null
+    }
+
+    pub fn non_null_mut(&mut self) -> &mut NonNull {
+        &mut self.non_null
+    }
+
+    pub fn set_non_null(&mut self, facts: NonNull) {
+        self.non_null = facts
+    }
+
+    /// A copy of this context with different facts.
+    pub fn with_non_null(&self, non_null: NonNull) -> LocalsContext<T> {
+        LocalsContext {
+            non_null,
+            ..self.clone()
+        }
+    }
+
+    /// The facts at the end of this scope that still hold in its parent.
+    pub fn into_non_null(self) -> NonNull {
+        let mut facts = self.non_null;
+        self.locals.keys().for_each(|name| facts.remove(*name));
+        facts
+    }
+
+    /// Record the names that will be declared in this scope.
+    pub fn expect_declarations(&mut self, names: impl IntoIterator<Item = Id>) {
+        self.pending.extend(names)
+    }
 }
 
 impl<T: Clone> Default for LocalsContext<T> {
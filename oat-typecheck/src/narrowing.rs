@@ -0,0 +1,107 @@
+//! Flow-sensitive narrowing of nullable locals.
+//!
+//! The facts tracked are the set of locals known to hold non-null values at a
+//! point in a function. Locals in the set with a type `t?` are treated as `t`
+//! when they are used. Facts are generated by comparisons against `null` in
+//! conditions and by assignments of non-null values, and are merged by
+//! intersection where control flow joins.
+
+use std::collections::HashSet;
+
+use oat_ast::{BinaryOp, Expression, Id, Type, UnaryOp};
+
+use crate::locals_context::LocalsContext;
+
+/// Locals known to be non-null.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct NonNull(HashSet<Id>);
+
+impl NonNull {
+    pub fn contains(&self, name: Id) -> bool {
+        self.0.contains(&name)
+    }
+
+    pub fn insert(&mut self, name: Id) {
+        self.0.insert(name);
+    }
+
+    pub fn remove(&mut self, name: Id) {
+        self.0.remove(&name);
+    }
+
+    /// Facts that hold on either of two paths.
+    pub fn meet(&self, other: &NonNull) -> NonNull {
+        NonNull(self.0.intersection(&other.0).copied().collect())
+    }
+
+    /// Facts that hold on a single path.
+    pub fn join(&self, other: &NonNull) -> NonNull {
+        NonNull(self.0.union(&other.0).copied().collect())
+    }
+}
+
+/// Facts that hold after the branches of a conditional. A branch that does
+/// not complete normally does not contribute anything to the merge.
+pub(crate) fn merge_branches(
+    (then_completes, then_facts): (bool, NonNull),
+    (else_completes, else_facts): (bool, NonNull),
+) -> NonNull {
+    match (then_completes, else_completes) {
+        (true, true) => then_facts.meet(&else_facts),
+        (true, false) => then_facts,
+        (false, true) => else_facts,
+        (false, false) => then_facts.join(&else_facts),
+    }
+}
+
+/// The local compared against `null` by `left op right`, if any.
+fn null_comparison(left: &Expression, right: &Expression, lc: &LocalsContext<Type>) -> Option<Id> {
+    match (left, right) {
+        (Expression::Id(name), Expression::CNull(_))
+        | (Expression::CNull(_), Expression::Id(name))
+            if lc.is_local(*name) =>
+        {
+            Some(*name)
+        }
+        _ => None,
+    }
+}
+
+/// Compute the facts that hold when `condition` evaluates to `true` and to
+/// `false` respectively.
+pub(crate) fn condition_facts(
+    condition: &Expression,
+    lc: &LocalsContext<Type>,
+) -> (NonNull, NonNull) {
+    use BinaryOp::*;
+    match condition {
+        Expression::Binary { op, left, right } => match op {
+            Eq | Neq => {
+                let mut facts = NonNull::default();
+                if let Some(name) = null_comparison(left, right, lc) {
+                    facts.insert(name);
+                }
+                match op {
+                    Neq => (facts, NonNull::default()),
+                    _ => (NonNull::default(), facts),
+                }
+            }
+            And | AndAlso => {
+                let (left_true, left_false) = condition_facts(left, lc);
+                let (right_true, right_false) = condition_facts(right, lc);
+                (left_true.join(&right_true), left_false.meet(&right_false))
+            }
+            Or | OrElse => {
+                let (left_true, left_false) = condition_facts(left, lc);
+                let (right_true, right_false) = condition_facts(right, lc);
+                (left_true.meet(&right_true), left_false.join(&right_false))
+            }
+            _ => Default::default(),
+        },
+        Expression::Unary(UnaryOp::Lognot, e) => {
+            let (when_true, when_false) = condition_facts(e, lc);
+            (when_false, when_true)
+        }
+        _ => Default::default(),
+    }
+}
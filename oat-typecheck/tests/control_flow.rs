@@ -0,0 +1,146 @@
+//! Tests for reachability and return analysis of function bodies.
+
+use oat_ast::{ReturnType, Type};
+use oat_error::TypeError;
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+use oat_typecheck::type_check;
+
+fn check(source: &str, expected: impl FnOnce() -> Result<(), TypeError>) {
+    create_session_if_not_set_then(|_| {
+        let program = parse_program(source).expect("test program should parse");
+        assert_eq!(type_check(&program), expected());
+    })
+}
+
+fn ok() -> Result<(), TypeError> {
+    Ok(())
+}
+
+fn unreachable(statement: &str) -> Result<(), TypeError> {
+    Err(TypeError::UnreachableStatement {
+        function: "f".into(),
+        statement: statement.to_string(),
+    })
+}
+
+fn did_not_return(path: &str) -> Result<(), TypeError> {
+    Err(TypeError::DidNotReturn {
+        function: "f".into(),
+        expected_ret_type: ReturnType::ReturnValue(Type::Int),
+        path: path.to_string(),
+    })
+}
+
+#[test]
+fn return_in_infinite_while() {
+    check(
+        "int f(int x) { while (true) { if (x > 3) { return x; } x = x + 1; } }",
+        ok,
+    );
+}
+
+#[test]
+fn return_in_infinite_for() {
+    check(
+        "int f(int x) { for (var i = 0; ; i = i + 1) { return i; } }",
+        ok,
+    );
+}
+
+#[test]
+fn return_in_both_branches() {
+    check(
+        "int f(int x) { if (x > 3) { return 1; } else { return 2; } }",
+        ok,
+    );
+}
+
+#[test]
+fn return_in_both_cast_branches() {
+    check(
+        "int f(string? s) { if? (string t = s) { return 1; } else { return 0; } }",
+        ok,
+    );
+}
+
+#[test]
+fn void_function_can_fall_off() {
+    check("void f(int x) { x = x + 1; }", ok);
+}
+
+#[test]
+fn empty_body() {
+    check("int f() { }", || did_not_return("the body is empty"));
+}
+
+#[test]
+fn straight_line_body() {
+    check("int f(int x) { x = x + 1; }", || {
+        did_not_return("control falls through after the assignment to `x` at statement 1")
+    });
+}
+
+#[test]
+fn missing_else() {
+    check("int f(int x) { if (x > 3) { return 1; } }", || {
+        did_not_return("the `if` at statement 1 takes its else branch")
+    });
+}
+
+#[test]
+fn loop_exits() {
+    check(
+        "int f(int x) { if (x > 3) { return 1; } else { while (x < 3) { return 2; } } }",
+        || {
+            did_not_return(concat!(
+                "the `if` at statement 1 takes its else branch, then ",
+                "the `while` loop at statement 1 of the else block of the `if` at statement 1 exits"
+            ))
+        },
+    );
+}
+
+#[test]
+fn null_cast_falls_off() {
+    check(
+        "int f(string? s) { if? (string t = s) { return 1; } }",
+        || did_not_return("the `if?` at statement 1 finds null"),
+    );
+}
+
+#[test]
+fn code_after_return() {
+    check("int f() { return 1; var x = 2; }", || {
+        unreachable("the declaration of `x` at statement 2")
+    });
+}
+
+#[test]
+fn code_after_infinite_loop() {
+    check(
+        "int f(int x) { while (true) { x = x + 1; } return x; }",
+        || unreachable("the `return` at statement 2"),
+    );
+}
+
+#[test]
+fn code_after_returning_branches() {
+    check(
+        "int f(int x) { while (x < 3) { if (x > 1) { return 1; } else { return 2; } x = 4; } return 0; }",
+        || {
+            unreachable(concat!(
+                "the assignment to `x` at statement 2 ",
+                "of the body of the `while` loop at statement 1"
+            ))
+        },
+    );
+}
+
+#[test]
+fn update_after_returning_body() {
+    check(
+        "int f(int n) { for (var i = 0; i < n; i = i + 1) { return i; } return n; }",
+        ok,
+    );
+}
@@ -0,0 +1,103 @@
+//! Tests for local declarations with explicit types.
+
+use oat_ast::{ReferenceType, Type};
+use oat_error::TypeError;
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+use oat_typecheck::type_check;
+
+const STRUCTS: &str = "struct point { int x; int y; }\nstruct point3 { int x; int y; int z; }\n";
+
+fn check(source: &str, expected: impl FnOnce() -> Result<(), TypeError>) {
+    create_session_if_not_set_then(|_| {
+        let source = format!("{}{}", STRUCTS, source);
+        let program = parse_program(&source).expect("test program should parse");
+        assert_eq!(type_check(&program), expected());
+    })
+}
+
+fn ok() -> Result<(), TypeError> {
+    Ok(())
+}
+
+fn point() -> ReferenceType {
+    ReferenceType::Struct("point".into())
+}
+
+#[test]
+fn int() {
+    check("int f() { int x = 3; return x; }", ok);
+}
+
+#[test]
+fn incompatible_initializer() {
+    check("int f() { int x = true; return x; }", || {
+        Err(TypeError::IncompatibleInitializer {
+            name: "x".into(),
+            declared: Type::Int,
+            given: Type::Bool,
+        })
+    });
+}
+
+#[test]
+fn nullable() {
+    check("int f() { point? p = null; return 0; }", ok);
+}
+
+#[test]
+fn null_for_non_null_type() {
+    check("int f() { point p = null; return 0; }", || {
+        Err(TypeError::IncompatibleInitializer {
+            name: "p".into(),
+            declared: Type::Ref(point()),
+            given: Type::NullRef(point()),
+        })
+    });
+}
+
+#[test]
+fn undefined_struct() {
+    check("int f() { line? l = null; return 0; }", || {
+        Err(TypeError::StructNotFound("line".into()))
+    });
+}
+
+#[test]
+fn widen_to_struct_prefix() {
+    check("int f(point3 q) { point p = q; return p.x + p.y; }", ok);
+}
+
+#[test]
+fn widened_fields_are_hidden() {
+    check("int f(point3 q) { point p = q; return p.z; }", || {
+        Err(TypeError::FieldNotFound(Type::Ref(point()), "z".into()))
+    });
+}
+
+#[test]
+fn widen_to_nullable() {
+    check(
+        "int f(point q) {
+            point? p = q;
+            if (p == point null) {
+                return 0;
+            }
+            p = point null;
+            return 1;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn non_null_initializer_narrows() {
+    check("int f(point q) { point? p = q; return p.x; }", ok);
+}
+
+#[test]
+fn redeclaration() {
+    check("int f() { int x = 1; var x = 2; return x; }", || {
+        Err(TypeError::Redeclaration("x".into()))
+    });
+}
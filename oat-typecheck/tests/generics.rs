@@ -0,0 +1,255 @@
+//! Tests for generic functions and structs.
+
+use oat_ast::{Declaration, Expression, ReferenceType, Statement, Type};
+use oat_error::TypeError;
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+use oat_typecheck::{elaborate, type_check};
+
+const GENERICS: &str = concat!(
+    "struct point { int x; int y; }\n",
+    "struct point3 { int x; int y; int z; }\n",
+    "struct box<T> { T value; }\n",
+    "struct list<T> { T head; list<T>? tail; }\n",
+    "T[] reverse<T>(T[] a) {\n",
+    "  var n = length(a);\n",
+    "  var r = new T[n];\n",
+    "  for (var i = 0; i < n; i = i + 1) {\n",
+    "    r[i] = a[n - (i + 1)];\n",
+    "  }\n",
+    "  return r;\n",
+    "}\n",
+    "T[] empty<T>() { return new T[]{}; }\n",
+    "box<T> wrap<T>(T v) { return new box<T>{ value = v }; }\n",
+    "void ignore<T>(T v) { return; }\n",
+    "T id<T>(T v) { return v; }\n",
+    "struct node { int value; node? next; }\n",
+);
+
+fn check(source: &str, expected: impl FnOnce() -> Result<(), TypeError>) {
+    create_session_if_not_set_then(|_| {
+        let source = format!("{}{}", GENERICS, source);
+        let program = parse_program(&source).expect("test program should parse");
+        assert_eq!(type_check(&program), expected());
+    })
+}
+
+fn ok() -> Result<(), TypeError> {
+    Ok(())
+}
+
+fn array(t: Type) -> Type {
+    Type::Ref(ReferenceType::Array(Box::new(t)))
+}
+
+#[test]
+fn definitions() {
+    check("", ok);
+}
+
+#[test]
+fn inferred_call() {
+    check("int[] f(int[] a) { return reverse(a); }", ok);
+}
+
+#[test]
+fn explicit_call() {
+    check("int[] f(int[] a) { return reverse<int>(a); }", ok);
+}
+
+#[test]
+fn inferred_return_type() {
+    check("bool[] f(int[] a) { return reverse(a); }", || {
+        Err(TypeError::IncompatibleType)
+    });
+}
+
+#[test]
+fn explicit_argument_mismatch() {
+    check("bool[] f(int[] a) { return reverse<bool>(a); }", || {
+        Err(TypeError::IncompatibleArgument {
+            expected: array(Type::Bool),
+            given: array(Type::Int),
+        })
+    });
+}
+
+#[test]
+fn type_argument_count() {
+    check("int[] f(int[] a) { return reverse<int, bool>(a); }", || {
+        Err(TypeError::TypeArgumentCount {
+            name: "reverse".into(),
+            expected: 1,
+            given: 2,
+        })
+    });
+}
+
+#[test]
+fn cannot_infer() {
+    check("int[] f() { return empty(); }", || {
+        Err(TypeError::CannotInferTypeArguments("empty".into()))
+    });
+}
+
+#[test]
+fn explicit_without_arguments() {
+    check("int[] f() { return empty<int>(); }", ok);
+}
+
+#[test]
+fn uninstantiated_function() {
+    check("int f() { var r = reverse; return 0; }", || {
+        Err(TypeError::MissingTypeArguments("reverse".into()))
+    });
+}
+
+#[test]
+fn generic_statement_call() {
+    check("void f() { ignore(3); ignore<bool>(true); return; }", ok);
+}
+
+#[test]
+fn struct_field() {
+    check("int f(box<int> b) { return b.value; }", ok);
+}
+
+#[test]
+fn inferred_struct_literal() {
+    check(
+        "int f() { var b = new box{ value = 3 }; return b.value; }",
+        ok,
+    );
+}
+
+#[test]
+fn explicit_struct_literal_mismatch() {
+    check(
+        "int f() { var b = new box<bool>{ value = 3 }; return 0; }",
+        || Err(TypeError::IncompatibleType),
+    );
+}
+
+#[test]
+fn struct_without_type_arguments() {
+    check("int f() { box? b = null; return 0; }", || {
+        Err(TypeError::TypeArgumentCount {
+            name: "box".into(),
+            expected: 1,
+            given: 0,
+        })
+    });
+}
+
+#[test]
+fn generic_struct_from_generic_function() {
+    check("int f() { return wrap(3).value; }", ok);
+}
+
+#[test]
+fn instance_width_subtyping() {
+    check(
+        "box<point> f(point3 p) { var b = new box<point3>{ value = p }; return b; }",
+        ok,
+    );
+}
+
+#[test]
+fn recursive_instance_subtyping() {
+    check("list<point>? f(list<point3> l) { return l; }", ok);
+}
+
+#[test]
+fn elaborate_inferred_call() {
+    create_session_if_not_set_then(|_| {
+        let source = format!("{}int[] f(int[] a) {{ return reverse(a); }}", GENERICS);
+        let program = parse_program(&source).unwrap();
+        let elaborated = elaborate(&program).unwrap();
+        let body = elaborated
+            .declarations
+            .iter()
+            .find_map(|decl| match decl {
+                Declaration::Function(f) if f.name == "f".into() => Some(f.body.clone()),
+                _ => None,
+            })
+            .unwrap();
+        match body.as_slice() {
+            [Statement::Return(Some(Expression::Call(fun, _)))] => assert_eq!(
+                **fun,
+                Expression::Instantiate("reverse".into(), vec![Type::Int])
+            ),
+            _ => panic!("unexpected body {:?}", body),
+        }
+    })
+}
+
+#[test]
+fn elaborate_inferred_struct_literal() {
+    create_session_if_not_set_then(|_| {
+        let program = parse_program(&format!(
+            "{}{}",
+            GENERICS, "box<int> f() { return new box{ value = 1 }; }"
+        ))
+        .unwrap();
+        let elaborated = elaborate(&program).unwrap();
+        let literal = elaborated.declarations.iter().find_map(|decl| match decl {
+            Declaration::Function(f) if f.name == "f".into() => match f.body.as_slice() {
+                [Statement::Return(Some(e))] => Some(e.clone()),
+                _ => None,
+            },
+            _ => None,
+        });
+        assert!(matches!(
+            literal,
+            Some(Expression::CStruct(_, type_args, _)) if type_args == vec![Type::Int]
+        ));
+    })
+}
+
+#[test]
+fn elaborate_inferred_call_in_loop() {
+    create_session_if_not_set_then(|_| {
+        let program = parse_program(&format!(
+            "{}{}",
+            GENERICS, "int[] f(int[] a) { while (length(a) > 1) { a = reverse(a); } return a; }"
+        ))
+        .unwrap();
+        let elaborated = elaborate(&program).unwrap();
+        let loop_body = elaborated.declarations.iter().find_map(|decl| match decl {
+            Declaration::Function(f) if f.name == "f".into() => match f.body.as_slice() {
+                [Statement::While { body, .. }, _] => Some(body.clone()),
+                _ => None,
+            },
+            _ => None,
+        });
+        match loop_body.as_deref() {
+            Some([Statement::Assignment(_, Expression::Call(fun, _))]) => assert_eq!(
+                **fun,
+                Expression::Instantiate("reverse".into(), vec![Type::Int])
+            ),
+            _ => panic!("unexpected loop body {:?}", loop_body),
+        }
+    })
+}
+
+/// Narrowing is weaker in later iterations of a loop, so type arguments are
+/// only inferred from the facts that hold in every iteration.
+#[test]
+fn inferred_in_loop_with_narrowing() {
+    let body = |call: &str| {
+        format!(
+            "void f(node? n) {{
+                if (n != node null) {{
+                    while (true) {{
+                        var m = {};
+                        n = node null;
+                    }}
+                }}
+                return;
+            }}",
+            call
+        )
+    };
+    check(&body("id<node?>(n)"), ok);
+    check(&body("id(n)"), ok);
+}
@@ -0,0 +1,79 @@
+//! Tests for global variables and their initializers.
+
+use oat_error::TypeError;
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+use oat_typecheck::type_check;
+
+const STRUCTS: &str = "struct point { int x; int y; }\nstruct point3 { int x; int y; int z; }\n";
+
+fn check(source: &str, expected: impl FnOnce() -> Result<(), TypeError>) {
+    create_session_if_not_set_then(|_| {
+        let source = format!("{}{}", STRUCTS, source);
+        let program = parse_program(&source).expect("test program should parse");
+        assert_eq!(type_check(&program), expected());
+    })
+}
+
+fn ok() -> Result<(), TypeError> {
+    Ok(())
+}
+
+#[test]
+fn constants() {
+    check(
+        concat!(
+            "global n = 3;\n",
+            "global b = true;\n",
+            "global s = \"hi\";\n",
+            "global p = point null;\n",
+            "int f() { if (b) { n = n + length_of_string(s); } return n; }\n",
+        ),
+        ok,
+    );
+}
+
+#[test]
+fn arrays_and_structs() {
+    check(
+        concat!(
+            "global names = new string[]{\"a\", \"b\"};\n",
+            "global points = new point[]{new point3{x = 1; y = 2; z = 3}};\n",
+            "int f() { return length(names) + points[0].y; }\n",
+        ),
+        ok,
+    );
+}
+
+#[test]
+fn functions_declared_later() {
+    check(
+        concat!(
+            "global g = f;\n",
+            "int f(int x) { return x; }\n",
+            "int h() { return g(1); }\n",
+        ),
+        ok,
+    );
+}
+
+#[test]
+fn non_constant() {
+    check("global n = 1 + 2;", || {
+        Err(TypeError::NonConstantGlobal("n".into()))
+    });
+}
+
+#[test]
+fn initialized_with_global() {
+    check("global m = 1;\nglobal n = m;", || {
+        Err(TypeError::NonConstantGlobal("n".into()))
+    });
+}
+
+#[test]
+fn assignment_checks_type() {
+    check("global n = 1;\nvoid f() { n = true; return; }", || {
+        Err(TypeError::IncompatibleType)
+    });
+}
@@ -0,0 +1,320 @@
+//! Tests for flow-sensitive narrowing of nullable locals.
+
+use oat_ast::{ReferenceType, Type};
+use oat_error::TypeError;
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+use oat_typecheck::type_check;
+
+const NODE: &str = "struct node { int value; node? next; }\n";
+
+fn check(source: &str, expected: impl FnOnce() -> Result<(), TypeError>) {
+    create_session_if_not_set_then(|_| {
+        let source = format!("{}{}", NODE, source);
+        let program = parse_program(&source).expect("test program should parse");
+        assert_eq!(type_check(&program), expected());
+    })
+}
+
+fn ok() -> Result<(), TypeError> {
+    Ok(())
+}
+
+/// The error for projecting `value` out of a `node?` that was not narrowed.
+fn not_narrowed() -> Result<(), TypeError> {
+    Err(TypeError::ProjectionOnNullable(
+        Type::NullRef(ReferenceType::Struct("node".into())),
+        "value".into(),
+    ))
+}
+
+#[test]
+fn unchecked_projection() {
+    check("int get(node? n) { return n.value; }", not_narrowed);
+}
+
+#[test]
+fn then_branch() {
+    check(
+        "int get(node? n) {
+            if (n != node null) {
+                return n.value;
+            }
+            return 0;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn else_branch() {
+    check(
+        "int get(node? n) {
+            if (n == node null) {
+                return 0;
+            } else {
+                return n.value;
+            }
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn negated_condition() {
+    check(
+        "int get(node? n) {
+            if (!(n == node null)) {
+                return n.value;
+            }
+            return 0;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn early_return() {
+    check(
+        "int get(node? n) {
+            if (n == node null) {
+                return 0;
+            }
+            var v = n.value;
+            return v;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn nested_ifs() {
+    check(
+        "int sum(node? a, node? b) {
+            if (a != node null) {
+                if (b == node null) {
+                    return a.value;
+                }
+                return a.value + b.value;
+            }
+            return 0;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn conjunction() {
+    check(
+        "int sum(node? a, node? b) {
+            if ((a != node null) & (b != node null)) {
+                return a.value + b.value;
+            }
+            return 0;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn disjunction_does_not_narrow() {
+    check(
+        "int sum(node? a, node? b) {
+            if ((a != node null) | (b != node null)) {
+                return a.value;
+            }
+            return 0;
+        }",
+        not_narrowed,
+    );
+}
+
+#[test]
+fn short_circuit_guard() {
+    check(
+        "bool positive(node? n) {
+            return (n != node null) && (n.value > 0);
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn short_circuit_disjunction_guard() {
+    check(
+        "bool empty(node? n) {
+            return (n == node null) || (n.value == 0);
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn eager_guard_does_not_narrow() {
+    check(
+        "bool positive(node? n) {
+            return (n != node null) & (n.value > 0);
+        }",
+        not_narrowed,
+    );
+}
+
+#[test]
+fn short_circuit_condition() {
+    check(
+        "int get(node? n) {
+            if ((n != node null) && (n.value > 0)) {
+                return n.value;
+            }
+            return 0;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn only_one_branch_checked() {
+    check(
+        "int get(node? n, bool b) {
+            if (b) {
+                if (n == node null) {
+                    return 0;
+                }
+            }
+            return n.value;
+        }",
+        not_narrowed,
+    );
+}
+
+#[test]
+fn assigning_null_undoes_narrowing() {
+    check(
+        "int get(node? n) {
+            if (n != node null) {
+                n = node null;
+                return n.value;
+            }
+            return 0;
+        }",
+        not_narrowed,
+    );
+}
+
+#[test]
+fn comparison_after_narrowing() {
+    check(
+        "int get(node? n) {
+            if (n != node null) {
+                if (n == node null) {
+                    return 1;
+                }
+                return n.value;
+            }
+            return 0;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn while_condition() {
+    check(
+        "int sum(node? list) {
+            var total = 0;
+            var cur = list;
+            while (cur != node null) {
+                total = total + cur.value;
+                cur = cur.next;
+            }
+            return total;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn narrowing_survives_loop() {
+    check(
+        "int repeat(node? n) {
+            if (n == node null) {
+                return 0;
+            }
+            var total = 0;
+            while (total < 10) {
+                total = total + n.value;
+            }
+            return total + n.value;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn loop_assignment_undoes_narrowing() {
+    check(
+        "int repeat(node? n) {
+            if (n == node null) {
+                return 0;
+            }
+            var total = 0;
+            while (total < 10) {
+                total = total + n.value;
+                n = n.next;
+            }
+            return total;
+        }",
+        not_narrowed,
+    );
+}
+
+#[test]
+fn after_while_loop() {
+    check(
+        "int last(node? n) {
+            if (n == node null) {
+                return 0;
+            }
+            var next = n.next;
+            while (next != node null) {
+                n = next;
+                next = next.next;
+            }
+            return n.value;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn nested_loops() {
+    check(
+        "int count(node? a, node? b) {
+            var total = 0;
+            while (a != node null) {
+                var cur = b;
+                while (cur != node null) {
+                    total = total + a.value * cur.value;
+                    cur = cur.next;
+                }
+                a = a.next;
+            }
+            return total;
+        }",
+        ok,
+    );
+}
+
+#[test]
+fn for_loop() {
+    check(
+        "int sum(node? list) {
+            var total = 0;
+            for (var cur = list; cur != node null; cur = cur.next) {
+                total = total + cur.value;
+            }
+            return total;
+        }",
+        ok,
+    );
+}
@@ -0,0 +1,95 @@
+//! Regression tests type checking the programs in `sample-files`.
+
+use std::fs;
+
+use oat_error::TypeError;
+use oat_parse::parse_program;
+use oat_symbol::create_session_if_not_set_then;
+use oat_typecheck::type_check;
+
+fn check_sample(name: &str) -> Result<(), TypeError> {
+    let path = format!("{}/../sample-files/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let source = fs::read_to_string(path).expect("sample file should exist");
+    create_session_if_not_set_then(|_| {
+        let program = parse_program(&source).expect("sample file should parse");
+        type_check(&program)
+    })
+}
+
+#[test]
+fn empty() {
+    assert_eq!(check_sample("empty.oat"), Ok(()));
+}
+
+#[test]
+fn ex1() {
+    assert_eq!(check_sample("ex1.oat"), Ok(()));
+}
+
+#[test]
+fn fib() {
+    assert_eq!(check_sample("fib.oat"), Ok(()));
+}
+
+#[test]
+fn geometry() {
+    assert_eq!(check_sample("geometry.oat"), Ok(()));
+}
+
+#[test]
+fn globals() {
+    assert_eq!(check_sample("globals.oat"), Ok(()));
+}
+
+#[test]
+fn ifq() {
+    assert_eq!(check_sample("ifq.oat"), Ok(()));
+}
+
+#[test]
+fn point() {
+    assert_eq!(check_sample("point.oat"), Ok(()));
+}
+
+#[test]
+fn should_fail_local_scoping() {
+    assert_eq!(
+        check_sample("should-fail-local-scoping.oat"),
+        Err(TypeError::UndefinedVariable("y".to_string()))
+    );
+}
+
+#[test]
+fn should_fail_redeclaration() {
+    create_session_if_not_set_then(|_| {
+        assert_eq!(
+            check_sample("should-fail-redeclaration.oat"),
+            Err(TypeError::Redeclaration("x".into()))
+        );
+    })
+}
+
+#[test]
+fn should_fail_shadow_parameter() {
+    create_session_if_not_set_then(|_| {
+        assert_eq!(
+            check_sample("should-fail-shadow-parameter.oat"),
+            Err(TypeError::ShadowsParameter("x".into()))
+        );
+    })
+}
+
+#[test]
+fn should_fail_use_before_declaration() {
+    create_session_if_not_set_then(|_| {
+        assert_eq!(
+            check_sample("should-fail-use-before-declaration.oat"),
+            Err(TypeError::UseBeforeDeclaration("x".into()))
+        );
+    })
+}
+
+#[test]
+fn strings() {
+    assert_eq!(check_sample("strings.oat"), Ok(()));
+}
@@ -5,15 +5,67 @@ use std::iter::zip;
 use indexmap::IndexMap;
 
 use oat_ast as oat;
+use oat_ast::visit::substitute;
 use oat_ast::{ReferenceType, ReturnType, Type};
 
 use oat_error::TypeError;
 
 pub type FieldSet = IndexMap<oat::Id, oat::Type>;
 
+/// The signature of a generic function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenericFunction {
+    pub type_params: Vec<oat::Id>,
+    pub arg_types: Vec<Type>,
+    pub return_type: ReturnType,
+}
+
+impl GenericFunction {
+    /// The argument and return types of the function for the given type
+    /// arguments.
+    pub fn instantiate(&self, type_args: &[Type]) -> (Vec<Type>, ReturnType) {
+        let mut arg_types = self.arg_types.clone();
+        let mut return_type = self.return_type.clone();
+        substitute(&mut arg_types, &self.type_params, type_args);
+        substitute(&mut return_type, &self.type_params, type_args);
+        (arg_types, return_type)
+    }
+}
+
+/// The functions provided by the runtime, which every program can call
+/// without declaring them.
+pub fn builtins() -> Vec<(oat::Id, Type)> {
+    let string = || Type::Ref(ReferenceType::String);
+    let function = |arg_types, ret| Type::Ref(ReferenceType::Function(arg_types, Box::new(ret)));
+    vec![
+        (
+            "length_of_string".into(),
+            function(vec![string()], ReturnType::ReturnValue(Type::Int)),
+        ),
+        (
+            "string_of_int".into(),
+            function(vec![Type::Int], ReturnType::ReturnValue(string())),
+        ),
+        (
+            "string_cat".into(),
+            function(vec![string(), string()], ReturnType::ReturnValue(string())),
+        ),
+        (
+            "print_string".into(),
+            function(vec![string()], ReturnType::ReturnVoid),
+        ),
+    ]
+}
+
 /// The typing context
 #[derive(Default, Clone, Debug)]
-pub struct TypingContext(IndexMap<oat::Id, FieldSet>);
+pub struct TypingContext {
+    structs: IndexMap<oat::Id, FieldSet>,
+    /// Type parameters of the generic structs
+    struct_params: IndexMap<oat::Id, Vec<oat::Id>>,
+    /// Signatures of the generic functions
+    functions: IndexMap<oat::Id, GenericFunction>,
+}
 
 impl TypingContext {
     /// Create a new typing context. Constructs an empty map.
@@ -23,7 +75,51 @@ impl TypingContext {
 
     /// Get the type associated with `name`.
     pub fn get_type(&self, name: &oat::Id) -> Option<&FieldSet> {
-        self.0.get(name)
+        self.structs.get(name)
+    }
+
+    /// The names and fields of every struct, in declaration order.
+    pub fn structs(&self) -> impl Iterator<Item = (&oat::Id, &FieldSet)> {
+        self.structs.iter()
+    }
+
+    /// Get the type parameters of a struct. Non-generic structs have none.
+    pub fn type_params(&self, name: &oat::Id) -> &[oat::Id] {
+        self.struct_params.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// The fields of the struct type `rt`, with the type arguments of a
+    /// generic struct substituted for its parameters.
+    pub fn struct_fields(&self, rt: &ReferenceType) -> Result<FieldSet, TypeError> {
+        let (name, type_args) = match rt {
+            ReferenceType::Struct(name) => (name, &[][..]),
+            ReferenceType::GenericStruct(name, type_args) => (name, type_args.as_slice()),
+            _ => return Err(TypeError::IncompatibleType),
+        };
+        let fields = self
+            .get_type(name)
+            .ok_or(TypeError::StructNotFound(*name))?;
+        let type_params = self.type_params(name);
+        if type_params.len() != type_args.len() {
+            return Err(TypeError::TypeArgumentCount {
+                name: *name,
+                expected: type_params.len(),
+                given: type_args.len(),
+            });
+        }
+        let mut fields = fields.clone();
+        fields
+            .values_mut()
+            .for_each(|t| substitute(t, type_params, type_args));
+        Ok(fields)
+    }
+
+    pub fn add_generic_function(&mut self, name: oat::Id, function: GenericFunction) {
+        self.functions.insert(name, function);
+    }
+
+    pub fn generic_function(&self, name: &oat::Id) -> Option<&GenericFunction> {
+        self.functions.get(name)
     }
 
     /// Return the index and type of a field
@@ -32,7 +128,7 @@ impl TypingContext {
         type_name: &oat::Id,
         field_name: &oat::Id,
     ) -> Option<(usize, &oat::Type)> {
-        let struct_ = self.0.get(type_name)?;
+        let struct_ = self.structs.get(type_name)?;
         let (i, _, type_) = struct_.get_full(field_name)?;
         Some((i, type_))
     }
@@ -41,14 +137,33 @@ impl TypingContext {
     pub fn from_declarations(declarations: &Vec<oat::TypeDeclaration>) -> Self {
         let mut tc = Self::default();
 
-        for oat::TypeDeclaration { name, fields } in declarations.iter() {
-            tc.0.insert(*name, fields.clone());
+        for oat::TypeDeclaration {
+            name,
+            type_params,
+            fields,
+        } in declarations.iter()
+        {
+            tc.structs.insert(*name, fields.clone());
+            if !type_params.is_empty() {
+                tc.struct_params.insert(*name, type_params.clone());
+            }
         }
 
         tc
     }
 
     pub fn is_subtype(&self, sub: &Type, super_: &Type) -> Result<bool, TypeError> {
+        self.is_subtype_assuming(sub, super_, &mut vec![])
+    }
+
+    /// Subtyping of recursive structs is decided by assuming that the pairs of
+    /// structs already being compared are subtypes.
+    fn is_subtype_assuming(
+        &self,
+        sub: &Type,
+        super_: &Type,
+        assumed: &mut Vec<(ReferenceType, ReferenceType)>,
+    ) -> Result<bool, TypeError> {
         use Type::*;
         if sub == super_ {
             return Ok(true);
@@ -56,7 +171,7 @@ impl TypingContext {
         Ok(match (sub, super_) {
             (NullRef(sub), NullRef(super_))
             | (Ref(sub), NullRef(super_))
-            | (Ref(sub), Ref(super_)) => self.is_ref_subtype(sub, super_)?,
+            | (Ref(sub), Ref(super_)) => self.is_ref_subtype(sub, super_, assumed)?,
             _ => false,
         })
     }
@@ -65,36 +180,44 @@ impl TypingContext {
         &self,
         sub: &ReferenceType,
         super_: &ReferenceType,
+        assumed: &mut Vec<(ReferenceType, ReferenceType)>,
     ) -> Result<bool, TypeError> {
         use oat_ast::ReferenceType::*;
         Ok(match (sub, super_) {
             (String, String) => true,
-            (Array(t1), Array(t2)) => self.is_subtype(&*t1, &*t2)?,
-            (Struct(s1), Struct(s2)) => {
-                let sub_struct = self.get_type(&s1).ok_or(TypeError::StructNotFound(*s1))?;
-                let super_struct = self.get_type(&s2).ok_or(TypeError::StructNotFound(*s2))?;
+            (Array(t1), Array(t2)) => self.is_subtype_assuming(t1, t2, assumed)?,
+            (Struct(_) | GenericStruct(..), Struct(_) | GenericStruct(..)) => {
+                let pair = (sub.clone(), super_.clone());
+                if assumed.contains(&pair) {
+                    return Ok(true);
+                }
+                let sub_struct = self.struct_fields(sub)?;
+                let super_struct = self.struct_fields(super_)?;
 
-                if sub_struct.len() > super_struct.len() {
+                // The fields of the supertype must be a prefix of the subtype's
+                if sub_struct.len() < super_struct.len() {
                     return Ok(false);
                 }
 
+                assumed.push(pair);
                 let field_sets = zip(sub_struct.iter(), super_struct.iter());
-
                 for ((f1, t1), (f2, t2)) in field_sets {
-                    if f1 != f2 || !self.is_subtype(t1, t2)? {
+                    if f1 != f2 || !self.is_subtype_assuming(t1, t2, assumed)? {
+                        assumed.pop();
                         return Ok(false);
                     }
                 }
+                assumed.pop();
 
                 true
             }
             (Function(args1, ret1), Function(args2, ret2)) => {
-                if args1.len() != args2.len() || !self.is_return_subtype(ret1, ret2)? {
+                if args1.len() != args2.len() || !self.is_return_subtype(ret1, ret2, assumed)? {
                     return Ok(false);
                 }
                 let arg_pairs = zip(args1.iter(), args2.iter());
                 for (a1, a2) in arg_pairs {
-                    if !self.is_subtype(a2, a1)? {
+                    if !self.is_subtype_assuming(a2, a1, assumed)? {
                         return Ok(false);
                     }
                 }
@@ -104,11 +227,16 @@ impl TypingContext {
         })
     }
 
-    fn is_return_subtype(&self, sub: &ReturnType, super_: &ReturnType) -> Result<bool, TypeError> {
+    fn is_return_subtype(
+        &self,
+        sub: &ReturnType,
+        super_: &ReturnType,
+        assumed: &mut Vec<(ReferenceType, ReferenceType)>,
+    ) -> Result<bool, TypeError> {
         use oat_ast::ReturnType::*;
         Ok(match (sub, super_) {
             (ReturnVoid, ReturnVoid) => true,
-            (ReturnValue(r1), ReturnValue(r2)) => self.is_subtype(&r1, &r2)?,
+            (ReturnValue(r1), ReturnValue(r2)) => self.is_subtype_assuming(r1, r2, assumed)?,
             _ => false,
         })
     }
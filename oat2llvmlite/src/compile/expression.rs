@@ -1,27 +1,843 @@
+//! Lowering of expressions.
+//!
+//! An expression is lowered to the stream of instructions computing it,
+//! together with the operand holding its value and the llvmlite type of that
+//! value. Booleans are `i1`, integers are `i64`, and every other value is a
+//! pointer. An array `T[]` points to its length followed by its elements,
+//! `{ i64, [0 x T] }`. A string is an `i8*` pointing to a global `[N x i8]`,
+//! which is defined once for every distinct literal.
+//!
+//! Unless disabled in the [`Options`], every access to an array element is
+//! preceded by a call to the runtime checking the index against the length
+//! of the array, which aborts the program when it is out of bounds.
+//!
+//! [`Options`]: crate::Options
+
 use crate::{
     context::{Context, TypingContext},
-    Compile,
+    layout::{StructLayout, FIELD_SIZE},
+    Compile, Element, Stream,
 };
 
-use llvmlite as llvm;
-use llvmlite::{Instruction, Operand, Type as LLType};
+use llvmlite as ll;
+use llvmlite::{BinaryOperator, Condition, Instruction, Operand, Terminator, Type as LLType};
 use oat_ast as oat;
 
-impl Compile<(LLType, Operand, Vec<Instruction>)> for oat::Expression {
-    fn compile(
-        self,
-        context: &Context,
-        type_context: &TypingContext,
-    ) -> (LLType, Operand, Vec<Instruction>) {
+/// Runtime function allocating the given number of bytes
+pub(crate) const MALLOC: &str = "oat_malloc";
+/// Runtime function aborting when an index is out of the bounds of an array
+pub(crate) const ASSERT_ARRAY_LENGTH: &str = "oat_assert_array_length";
+
+/// Emit `instruction` into `stream`, returning the operand naming its result.
+pub(crate) fn emit(context: &Context, stream: &mut Stream, instruction: Instruction) -> Operand {
+    let uid = context.gensym("tmp");
+    stream.push(Element::Instruction(uid.clone(), instruction));
+    Operand::Id(uid)
+}
+
+/// Convert `op` of type `from` into a value of type `to`. Subtyping between
+/// references can relate different pointer types, which are bitcast.
+pub(crate) fn coerce(
+    context: &Context,
+    stream: &mut Stream,
+    from: LLType,
+    op: Operand,
+    to: &LLType,
+) -> Operand {
+    if from == *to || op == Operand::Null {
+        op
+    } else {
+        emit(context, stream, Instruction::Bitcast(from, op, to.clone()))
+    }
+}
+
+/// The type of the elements of an array of type `array_type`.
+fn element_type(array_type: &LLType) -> LLType {
+    match array_type {
+        LLType::Ptr(t) => match t.as_ref() {
+            LLType::Struct(fields) => match fields.as_slice() {
+                [LLType::I64, LLType::Array(_, t)] => *t.clone(),
+                _ => unreachable!("arrays are lowered to {{ i64, [0 x t] }}*"),
+            },
+            _ => unreachable!("arrays are lowered to {{ i64, [0 x t] }}*"),
+        },
+        _ => unreachable!("arrays are lowered to {{ i64, [0 x t] }}*"),
+    }
+}
+
+/// The name of the struct pointed to by values of type `struct_type`.
+fn struct_name(struct_type: &LLType) -> oat::Id {
+    match struct_type {
+        LLType::Ptr(t) => match t.as_ref() {
+            LLType::Namedt(name) => *name,
+            _ => unreachable!("structs are lowered to named types"),
+        },
+        _ => unreachable!("structs are lowered to pointers"),
+    }
+}
+
+/// Allocate an array of elements of type `t` and length `length`, and store
+/// its length.
+fn allocate_array(
+    context: &Context,
+    type_context: &TypingContext,
+    stream: &mut Stream,
+    t: oat::Type,
+    length: Operand,
+) -> (LLType, Operand) {
+    let array_type =
+        oat::Type::Ref(oat::ReferenceType::Array(Box::new(t))).compile(context, type_context);
+    let elements_size = emit(
+        context,
+        stream,
+        Instruction::Binop(
+            BinaryOperator::Mul,
+            LLType::I64,
+            length.clone(),
+            Operand::Const(FIELD_SIZE),
+        ),
+    );
+    let size = emit(
+        context,
+        stream,
+        Instruction::Binop(
+            BinaryOperator::Add,
+            LLType::I64,
+            elements_size,
+            Operand::Const(FIELD_SIZE),
+        ),
+    );
+    let raw_type = LLType::Ptr(Box::new(LLType::I64));
+    let raw = emit(
+        context,
+        stream,
+        Instruction::Call(
+            raw_type.clone(),
+            Operand::Gid(MALLOC.to_string()),
+            vec![(LLType::I64, size)],
+        ),
+    );
+    let array = emit(
+        context,
+        stream,
+        Instruction::Bitcast(raw_type, raw, array_type.clone()),
+    );
+    let length_ptr = emit(
+        context,
+        stream,
+        Instruction::Gep(
+            array_type.clone(),
+            array.clone(),
+            vec![Operand::Const(0), Operand::Const(0)],
+        ),
+    );
+    emit(
+        context,
+        stream,
+        Instruction::Store(LLType::I64, length, length_ptr),
+    );
+    (array_type, array)
+}
+
+/// Check that `index` is within the bounds of `array`.
+fn check_bounds(
+    context: &Context,
+    stream: &mut Stream,
+    array_type: &LLType,
+    array: Operand,
+    index: Operand,
+) {
+    let header_type = LLType::Ptr(Box::new(LLType::I64));
+    let header = emit(
+        context,
+        stream,
+        Instruction::Bitcast(array_type.clone(), array, header_type.clone()),
+    );
+    emit(
+        context,
+        stream,
+        Instruction::Call(
+            LLType::Void,
+            Operand::Gid(ASSERT_ARRAY_LENGTH.to_string()),
+            vec![(header_type, header), (LLType::I64, index)],
+        ),
+    );
+}
+
+/// Compile an expression that can be assigned to. Returns the type of the
+/// value stored in the place, and a pointer to it.
+pub(crate) fn compile_place(
+    e: oat::Expression,
+    context: &Context,
+    type_context: &TypingContext,
+) -> (LLType, Operand, Stream) {
+    use oat::Expression::*;
+    match e {
+        Id(name) => match context.lookup(name) {
+            Some((LLType::Ptr(t), op)) => (*t.clone(), op.clone(), vec![]),
+            _ => unreachable!("{} is not bound to a slot", name.name()),
+        },
+        Index { value, index } => {
+            let (array_type, array, mut stream) = (*value).compile(context, type_context);
+            let (_, index, index_stream) = (*index).compile(context, type_context);
+            stream.extend(index_stream);
+            if context.options().bounds_checks {
+                check_bounds(
+                    context,
+                    &mut stream,
+                    &array_type,
+                    array.clone(),
+                    index.clone(),
+                );
+            }
+            let t = element_type(&array_type);
+            let ptr = emit(
+                context,
+                &mut stream,
+                Instruction::Gep(
+                    array_type,
+                    array,
+                    vec![Operand::Const(0), Operand::Const(1), index],
+                ),
+            );
+            (t, ptr, stream)
+        }
+        Proj(e, field) => {
+            let (struct_type, value, mut stream) = (*e).compile(context, type_context);
+            let name = struct_name(&struct_type);
+            let (i, t) = type_context
+                .field_layout(&name, &field)
+                .unwrap_or_else(|| unreachable!("{} has no field {}", name.name(), field.name()));
+            let ptr = emit(
+                context,
+                &mut stream,
+                Instruction::Gep(
+                    struct_type,
+                    value,
+                    vec![Operand::Const(0), Operand::Const(i as i64)],
+                ),
+            );
+            (t, ptr, stream)
+        }
+        _ => unreachable!("{:?} is not assignable", e),
+    }
+}
+
+/// Compile `left && right` or `left || right`. The right operand only runs
+/// when the left one does not decide the result, which is kept in a slot.
+fn compile_short_circuit(
+    op: oat::BinaryOp,
+    left: oat::Expression,
+    right: oat::Expression,
+    context: &Context,
+    type_context: &TypingContext,
+) -> (LLType, Operand, Stream) {
+    let slot = context.gensym("cond");
+    let right_label = context.gensym("right");
+    let merge = context.gensym("merge");
+    let result = Operand::Id(slot.clone());
+
+    let (_, left, mut stream) = left.compile(context, type_context);
+    stream.push(Element::Entry(slot, Instruction::Alloca(LLType::I1)));
+    emit(
+        context,
+        &mut stream,
+        Instruction::Store(LLType::I1, left.clone(), result.clone()),
+    );
+    let (then, else_) = match op {
+        oat::BinaryOp::AndAlso => (right_label.clone(), merge.clone()),
+        _ => (merge.clone(), right_label.clone()),
+    };
+    stream.push(Element::Terminator(
+        context.gensym("term"),
+        Terminator::CondBreak(left, then, else_),
+    ));
+
+    stream.push(Element::Label(right_label));
+    let (_, right, right_stream) = right.compile(context, type_context);
+    stream.extend(right_stream);
+    emit(
+        context,
+        &mut stream,
+        Instruction::Store(LLType::I1, right, result.clone()),
+    );
+    stream.push(Element::Terminator(
+        context.gensym("term"),
+        Terminator::Break(merge.clone()),
+    ));
+
+    stream.push(Element::Label(merge));
+    let op = emit(
+        context,
+        &mut stream,
+        Instruction::Load(LLType::Ptr(Box::new(LLType::I1)), result),
+    );
+    (LLType::I1, op, stream)
+}
+
+fn compile_binary(
+    op: oat::BinaryOp,
+    left: oat::Expression,
+    right: oat::Expression,
+    context: &Context,
+    type_context: &TypingContext,
+) -> (LLType, Operand, Stream) {
+    use oat::BinaryOp::*;
+    if let AndAlso | OrElse = op {
+        return compile_short_circuit(op, left, right, context, type_context);
+    }
+    let (left_type, left, mut stream) = left.compile(context, type_context);
+    let (right_type, right, right_stream) = right.compile(context, type_context);
+    stream.extend(right_stream);
+
+    let binop = |op| Instruction::Binop(op, left_type.clone(), left.clone(), right.clone());
+    let instruction = match op {
+        Add => binop(BinaryOperator::Add),
+        Sub => binop(BinaryOperator::Sub),
+        Mul => binop(BinaryOperator::Mul),
+        IAnd | And => binop(BinaryOperator::And),
+        IOr | Or => binop(BinaryOperator::Or),
+        AndAlso | OrElse => unreachable!("short-circuit operators branch"),
+        Shl => binop(BinaryOperator::Shl),
+        Shr => binop(BinaryOperator::Lshr),
+        Sar => binop(BinaryOperator::Ashr),
+        Eq | Neq | Lt | Lte | Gt | Gte => {
+            let condition = match op {
+                Eq => Condition::Eq,
+                Neq => Condition::Ne,
+                Lt => Condition::Slt,
+                Lte => Condition::Sle,
+                Gt => Condition::Sgt,
+                _ => Condition::Sge,
+            };
+            // References of related types can be compared
+            let right = coerce(context, &mut stream, right_type, right.clone(), &left_type);
+            let op = emit(
+                context,
+                &mut stream,
+                Instruction::Icmp(condition, left_type, left, right),
+            );
+            return (LLType::I1, op, stream);
+        }
+    };
+    let op = emit(context, &mut stream, instruction);
+    (left_type, op, stream)
+}
+
+impl Compile<(LLType, Operand, Stream)> for oat::Expression {
+    fn compile(self, context: &Context, type_context: &TypingContext) -> (LLType, Operand, Stream) {
         use oat::Expression::*;
         match self {
             CInt(i) => (LLType::I64, Operand::Const(i), vec![]),
-            CBool(b) => (LLType::I1, Const(b as i64), vec![]),
+            CBool(b) => (LLType::I1, Operand::Const(b as i64), vec![]),
             CNull(rt) => {
-                let t = oat::Type::ReferenceType(rt).compile(&context, &type_context);
+                let t = oat::Type::NullRef(rt).compile(context, type_context);
                 (t, Operand::Null, vec![])
             }
-            _ => todo!("Implement {:?} expression", self),
+            CStr(s) => {
+                let (gid, new) = context.string_global(&s);
+                let array_type = LLType::Array(s.len() + 1, Box::new(LLType::I8));
+                let mut stream = vec![];
+                if new {
+                    stream.push(Element::Global(
+                        gid.clone(),
+                        ll::GlobalDeclaration(array_type.clone(), ll::GlobalInitializer::String(s)),
+                    ));
+                }
+                let string_type = LLType::Ptr(Box::new(LLType::I8));
+                let op = emit(
+                    context,
+                    &mut stream,
+                    Instruction::Bitcast(
+                        LLType::Ptr(Box::new(array_type)),
+                        Operand::Gid(gid),
+                        string_type.clone(),
+                    ),
+                );
+                (string_type, op, stream)
+            }
+            Id(name) => match context.lookup(name) {
+                // Functions are values already
+                Some((t @ LLType::Ptr(f), op)) if matches!(f.as_ref(), LLType::Fun(..)) => {
+                    (t.clone(), op.clone(), vec![])
+                }
+                _ => {
+                    let (t, ptr, mut stream) = compile_place(Id(name), context, type_context);
+                    let op = emit(
+                        context,
+                        &mut stream,
+                        Instruction::Load(LLType::Ptr(Box::new(t.clone())), ptr),
+                    );
+                    (t, op, stream)
+                }
+            },
+            Instantiate(..) => unreachable!("generic functions are monomorphized before lowering"),
+            e @ (Index { .. } | Proj(..)) => {
+                let (t, ptr, mut stream) = compile_place(e, context, type_context);
+                let op = emit(
+                    context,
+                    &mut stream,
+                    Instruction::Load(LLType::Ptr(Box::new(t.clone())), ptr),
+                );
+                (t, op, stream)
+            }
+            Length(e) => {
+                let (array_type, array, mut stream) = (*e).compile(context, type_context);
+                let ptr = emit(
+                    context,
+                    &mut stream,
+                    Instruction::Gep(
+                        array_type,
+                        array,
+                        vec![Operand::Const(0), Operand::Const(0)],
+                    ),
+                );
+                let op = emit(
+                    context,
+                    &mut stream,
+                    Instruction::Load(LLType::Ptr(Box::new(LLType::I64)), ptr),
+                );
+                (LLType::I64, op, stream)
+            }
+            NewArr(t, length) => {
+                let (_, length, mut stream) = (*length).compile(context, type_context);
+                let (array_type, array) =
+                    allocate_array(context, type_context, &mut stream, t, length);
+                (array_type, array, stream)
+            }
+            CArr(t, elements) => {
+                let mut stream = vec![];
+                let length = Operand::Const(elements.len() as i64);
+                let (array_type, array) =
+                    allocate_array(context, type_context, &mut stream, t, length);
+                let element_type = element_type(&array_type);
+                for (i, e) in elements.into_iter().enumerate() {
+                    let (t, op, e_stream) = e.compile(context, type_context);
+                    stream.extend(e_stream);
+                    let op = coerce(context, &mut stream, t, op, &element_type);
+                    let ptr = emit(
+                        context,
+                        &mut stream,
+                        Instruction::Gep(
+                            array_type.clone(),
+                            array.clone(),
+                            vec![
+                                Operand::Const(0),
+                                Operand::Const(1),
+                                Operand::Const(i as i64),
+                            ],
+                        ),
+                    );
+                    emit(
+                        context,
+                        &mut stream,
+                        Instruction::Store(element_type.clone(), op, ptr),
+                    );
+                }
+                (array_type, array, stream)
+            }
+            CStruct(name, _, fields) => {
+                let mut stream = vec![];
+                let size = type_context
+                    .struct_size(&name)
+                    .unwrap_or_else(|| unreachable!("{} is not a struct", name.name()));
+                let raw_type = LLType::Ptr(Box::new(LLType::I64));
+                let raw = emit(
+                    context,
+                    &mut stream,
+                    Instruction::Call(
+                        raw_type.clone(),
+                        Operand::Gid(MALLOC.to_string()),
+                        vec![(LLType::I64, Operand::Const(size))],
+                    ),
+                );
+                let struct_type = LLType::Ptr(Box::new(LLType::Namedt(name)));
+                let value = emit(
+                    context,
+                    &mut stream,
+                    Instruction::Bitcast(raw_type, raw, struct_type.clone()),
+                );
+                for (field, e) in fields {
+                    let (i, field_type) =
+                        type_context.field_layout(&name, &field).unwrap_or_else(|| {
+                            unreachable!("{} has no field {}", name.name(), field.name())
+                        });
+                    let (t, op, e_stream) = e.compile(context, type_context);
+                    stream.extend(e_stream);
+                    let op = coerce(context, &mut stream, t, op, &field_type);
+                    let ptr = emit(
+                        context,
+                        &mut stream,
+                        Instruction::Gep(
+                            struct_type.clone(),
+                            value.clone(),
+                            vec![Operand::Const(0), Operand::Const(i as i64)],
+                        ),
+                    );
+                    emit(
+                        context,
+                        &mut stream,
+                        Instruction::Store(field_type, op, ptr),
+                    );
+                }
+                (struct_type, value, stream)
+            }
+            Call(fun, args) => {
+                let (fun_type, fun, mut stream) = (*fun).compile(context, type_context);
+                let (arg_types, return_type) = match &fun_type {
+                    LLType::Ptr(f) => match f.as_ref() {
+                        LLType::Fun(arg_types, return_type) => {
+                            (arg_types.clone(), *return_type.clone())
+                        }
+                        _ => unreachable!("only functions can be called"),
+                    },
+                    _ => unreachable!("only functions can be called"),
+                };
+                let mut ll_args = vec![];
+                for (e, arg_type) in args.into_iter().zip(arg_types) {
+                    let (t, op, e_stream) = e.compile(context, type_context);
+                    stream.extend(e_stream);
+                    let op = coerce(context, &mut stream, t, op, &arg_type);
+                    ll_args.push((arg_type, op));
+                }
+                let op = emit(
+                    context,
+                    &mut stream,
+                    Instruction::Call(return_type.clone(), fun, ll_args),
+                );
+                (return_type, op, stream)
+            }
+            Binary { op, left, right } => compile_binary(op, *left, *right, context, type_context),
+            Unary(op, e) => {
+                let (t, value, mut stream) = (*e).compile(context, type_context);
+                let instruction = match op {
+                    oat::UnaryOp::Neg => {
+                        Instruction::Binop(BinaryOperator::Sub, t.clone(), Operand::Const(0), value)
+                    }
+                    oat::UnaryOp::Bitnot => Instruction::Binop(
+                        BinaryOperator::Xor,
+                        t.clone(),
+                        value,
+                        Operand::Const(-1),
+                    ),
+                    oat::UnaryOp::Lognot => {
+                        Instruction::Icmp(Condition::Eq, t.clone(), value, Operand::Const(0))
+                    }
+                };
+                let op = emit(context, &mut stream, instruction);
+                (t, op, stream)
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod expression_tests {
+    use super::*;
+    use crate::Options;
+    use oat_parse::parse_program;
+    use oat_symbol::create_session_if_not_set_then;
+
+    use Instruction::*;
+    use LLType::*;
+    use Operand::Const;
+
+    const STRUCTS: &str = concat!(
+        "struct point { int x; int y; }\n",
+        "struct point3 { int x; int y; int z; }\n",
+    );
+
+    /// Parse `e` along with the struct declarations.
+    fn parse(e: &str) -> (TypingContext, oat::Expression) {
+        let source = format!("{}void f() {{ return {}; }}", STRUCTS, e);
+        let program = parse_program(&source).expect("test expression should parse");
+        let mut structs = vec![];
+        let mut expression = None;
+        for decl in program.declarations {
+            match decl {
+                oat::Declaration::Type(tdecl) => structs.push(tdecl),
+                oat::Declaration::Function(mut fdecl) => match fdecl.body.pop() {
+                    Some(oat::Statement::Return(e)) => expression = e,
+                    _ => unreachable!(),
+                },
+                oat::Declaration::Variable(_) => {}
+            }
+        }
+        (
+            TypingContext::from_declarations(&structs),
+            expression.unwrap(),
+        )
+    }
+
+    fn point(name: &str) -> LLType {
+        Ptr(Box::new(Namedt(name.into())))
+    }
+
+    fn ptr(t: LLType) -> LLType {
+        Ptr(Box::new(t))
+    }
+
+    fn tmp(n: usize) -> Operand {
+        Operand::Id(format!("_tmp.{}", n))
+    }
+
+    fn instructions(instructions: Vec<Instruction>) -> Stream {
+        instructions
+            .into_iter()
+            .enumerate()
+            .map(|(i, instruction)| Element::Instruction(format!("_tmp.{}", i), instruction))
+            .collect()
+    }
+
+    /// A context binding the locals `x: int`, `p: point` and `q: point3`, and
+    /// the function `norm: (point) -> int`.
+    fn context() -> Context {
+        Context::new()
+            .extend_operand("x".into(), ptr(I64), Operand::Id("x".into()))
+            .extend_operand("p".into(), ptr(point("point")), Operand::Id("p".into()))
+            .extend_operand("q".into(), ptr(point("point3")), Operand::Id("q".into()))
+            .extend_operand(
+                "norm".into(),
+                ptr(Fun(vec![point("point")], Box::new(I64))),
+                Operand::Gid("norm".into()),
+            )
+    }
+
+    #[test]
+    fn arithmetic() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("-(1 + x)");
+            assert_eq!(
+                e.compile(&context(), &tc),
+                (
+                    I64,
+                    tmp(2),
+                    instructions(vec![
+                        Load(ptr(I64), Operand::Id("x".into())),
+                        Binop(BinaryOperator::Add, I64, Const(1), tmp(0)),
+                        Binop(BinaryOperator::Sub, I64, Const(0), tmp(1)),
+                    ])
+                )
+            );
+        })
+    }
+
+    #[test]
+    fn comparison() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("!(x < 3)");
+            assert_eq!(
+                e.compile(&context(), &tc),
+                (
+                    I1,
+                    tmp(2),
+                    instructions(vec![
+                        Load(ptr(I64), Operand::Id("x".into())),
+                        Icmp(Condition::Slt, I64, tmp(0), Const(3)),
+                        Icmp(Condition::Eq, I1, tmp(1), Const(0)),
+                    ])
+                )
+            );
+        })
+    }
+
+    #[test]
+    fn short_circuit() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("(x < 3) && (x > 0)");
+            let slot = || Operand::Id("_cond.0".into());
+            let element =
+                |n: usize, instruction| Element::Instruction(format!("_tmp.{}", n), instruction);
+            assert_eq!(
+                e.compile(&context(), &tc),
+                (
+                    I1,
+                    tmp(11),
+                    vec![
+                        element(3, Load(ptr(I64), Operand::Id("x".into()))),
+                        element(4, Icmp(Condition::Slt, I64, tmp(3), Const(3))),
+                        Element::Entry("_cond.0".into(), Alloca(I1)),
+                        element(5, Store(I1, tmp(4), slot())),
+                        Element::Terminator(
+                            "_term.6".into(),
+                            Terminator::CondBreak(tmp(4), "_right.1".into(), "_merge.2".into())
+                        ),
+                        Element::Label("_right.1".into()),
+                        element(7, Load(ptr(I64), Operand::Id("x".into()))),
+                        element(8, Icmp(Condition::Sgt, I64, tmp(7), Const(0))),
+                        element(9, Store(I1, tmp(8), slot())),
+                        Element::Terminator("_term.10".into(), Terminator::Break("_merge.2".into())),
+                        Element::Label("_merge.2".into()),
+                        element(11, Load(ptr(I1), slot())),
+                    ]
+                )
+            );
+        })
+    }
+
+    #[test]
+    fn projection() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("p.y");
+            assert_eq!(
+                e.compile(&context(), &tc),
+                (
+                    I64,
+                    tmp(2),
+                    instructions(vec![
+                        Load(ptr(point("point")), Operand::Id("p".into())),
+                        Gep(point("point"), tmp(0), vec![Const(0), Const(1)]),
+                        Load(ptr(I64), tmp(1)),
+                    ])
+                )
+            );
+        })
+    }
+
+    #[test]
+    fn call_with_subtype() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("norm(q)");
+            assert_eq!(
+                e.compile(&context(), &tc),
+                (
+                    I64,
+                    tmp(2),
+                    instructions(vec![
+                        Load(ptr(point("point3")), Operand::Id("q".into())),
+                        Bitcast(point("point3"), tmp(0), point("point")),
+                        Call(
+                            I64,
+                            Operand::Gid("norm".into()),
+                            vec![(point("point"), tmp(1))]
+                        ),
+                    ])
+                )
+            );
+        })
+    }
+
+    fn array() -> LLType {
+        ptr(Struct(vec![I64, Array(0, Box::new(I64))]))
+    }
+
+    /// The instructions allocating an array of `length`, starting with
+    /// `tmp(n)`. The array is `tmp(n + 3)`.
+    fn allocation(n: usize, length: Operand) -> Vec<Instruction> {
+        vec![
+            Binop(BinaryOperator::Mul, I64, length.clone(), Const(8)),
+            Binop(BinaryOperator::Add, I64, tmp(n), Const(8)),
+            Call(
+                ptr(I64),
+                Operand::Gid(MALLOC.into()),
+                vec![(I64, tmp(n + 1))],
+            ),
+            Bitcast(ptr(I64), tmp(n + 2), array()),
+            Gep(array(), tmp(n + 3), vec![Const(0), Const(0)]),
+            Store(I64, length, tmp(n + 4)),
+        ]
+    }
+
+    #[test]
+    fn array_literal() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("new int[]{7, x}[1]");
+            let element = |i| vec![Const(0), Const(1), Const(i)];
+            let mut expected = allocation(0, Const(2));
+            expected.extend(vec![
+                Gep(array(), tmp(3), element(0)),
+                Store(I64, Const(7), tmp(6)),
+                Load(ptr(I64), Operand::Id("x".into())),
+                Gep(array(), tmp(3), element(1)),
+                Store(I64, tmp(8), tmp(9)),
+                Bitcast(array(), tmp(3), ptr(I64)),
+                Call(
+                    Void,
+                    Operand::Gid(ASSERT_ARRAY_LENGTH.into()),
+                    vec![(ptr(I64), tmp(11)), (I64, Const(1))],
+                ),
+                Gep(array(), tmp(3), element(1)),
+                Load(ptr(I64), tmp(13)),
+            ]);
+            assert_eq!(
+                e.compile(&context(), &tc),
+                (I64, tmp(14), instructions(expected))
+            );
+        })
+    }
+
+    #[test]
+    fn array_without_bounds_checks() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("length(new int[x])");
+            let context = Context::with_options(Options {
+                bounds_checks: false,
+            })
+            .extend_operand("x".into(), ptr(I64), Operand::Id("x".into()));
+            let mut expected = vec![Load(ptr(I64), Operand::Id("x".into()))];
+            expected.extend(allocation(1, tmp(0)));
+            expected.extend(vec![
+                Gep(array(), tmp(4), vec![Const(0), Const(0)]),
+                Load(ptr(I64), tmp(7)),
+            ]);
+            assert_eq!(
+                e.compile(&context, &tc),
+                (I64, tmp(8), instructions(expected))
+            );
+        })
+    }
+
+    #[test]
+    fn struct_literal() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("new point { y = 2; x = 1 }");
+            assert_eq!(
+                e.compile(&context(), &tc),
+                (
+                    point("point"),
+                    tmp(1),
+                    instructions(vec![
+                        Call(
+                            ptr(I64),
+                            Operand::Gid(MALLOC.into()),
+                            vec![(I64, Const(16))]
+                        ),
+                        Bitcast(ptr(I64), tmp(0), point("point")),
+                        Gep(point("point"), tmp(1), vec![Const(0), Const(1)]),
+                        Store(I64, Const(2), tmp(2)),
+                        Gep(point("point"), tmp(1), vec![Const(0), Const(0)]),
+                        Store(I64, Const(1), tmp(4)),
+                    ])
+                )
+            );
+        })
+    }
+
+    #[test]
+    fn string_literal() {
+        create_session_if_not_set_then(|_| {
+            let (tc, e) = parse("\"hi\"");
+            let string = Array(3, Box::new(I8));
+            assert_eq!(
+                e.compile(&context(), &tc),
+                (
+                    ptr(I8),
+                    Operand::Id("_tmp.1".into()),
+                    vec![
+                        Element::Global(
+                            "_str.0".into(),
+                            ll::GlobalDeclaration(
+                                string.clone(),
+                                ll::GlobalInitializer::String("hi".into())
+                            )
+                        ),
+                        Element::Instruction(
+                            "_tmp.1".into(),
+                            Bitcast(ptr(string), Operand::Gid("_str.0".into()), ptr(I8))
+                        ),
+                    ]
+                )
+            );
+        })
+    }
+}
@@ -0,0 +1,141 @@
+//! Lowering of global variables.
+//!
+//! The initializer of a global is lowered to a constant. Strings, arrays and
+//! structs are pointers, so every such literal gets a global of its own
+//! holding its contents, which the initializer points to. An array literal of
+//! length `n` is a global `{ i64, [n x T] }`, cast to the type
+//! `{ i64, [0 x T] }*` of arrays.
+
+use crate::{
+    context::{Context, TypingContext},
+    layout::StructLayout,
+    Compile,
+};
+
+use llvmlite as ll;
+use llvmlite::{GlobalInitializer, Type as LLType};
+use oat_ast as oat;
+
+type Globals = Vec<(ll::Gid, ll::GlobalDeclaration)>;
+
+/// Convert the constant `init` of type `from` into a constant of type `to`,
+/// like [`coerce`](super::coerce) does for operands.
+fn coerce_initializer(from: LLType, init: GlobalInitializer, to: &LLType) -> GlobalInitializer {
+    if from == *to || init == GlobalInitializer::Null {
+        init
+    } else {
+        GlobalInitializer::Bitcast(from, Box::new(init), to.clone())
+    }
+}
+
+/// Lower the initializer of a global, adding the globals holding the contents
+/// of its literals to `globals`.
+fn compile_initializer(
+    init: oat::Expression,
+    context: &Context,
+    type_context: &TypingContext,
+    globals: &mut Globals,
+) -> (LLType, GlobalInitializer) {
+    use oat::Expression::*;
+    match init {
+        CNull(rt) => (
+            oat::Type::NullRef(rt).compile(context, type_context),
+            GlobalInitializer::Null,
+        ),
+        CBool(b) => (LLType::I1, GlobalInitializer::Int(b as i64)),
+        CInt(n) => (LLType::I64, GlobalInitializer::Int(n)),
+        CStr(s) => {
+            let (gid, new) = context.string_global(&s);
+            let array_type = LLType::Array(s.len() + 1, Box::new(LLType::I8));
+            if new {
+                let string =
+                    ll::GlobalDeclaration(array_type.clone(), GlobalInitializer::String(s));
+                globals.push((gid.clone(), string));
+            }
+            let string_type = LLType::Ptr(Box::new(LLType::I8));
+            let init = GlobalInitializer::Bitcast(
+                LLType::Ptr(Box::new(array_type)),
+                Box::new(GlobalInitializer::Gid(gid)),
+                string_type.clone(),
+            );
+            (string_type, init)
+        }
+        Id(name) => match context.lookup(name) {
+            Some((t, ll::Operand::Gid(gid))) => (t.clone(), GlobalInitializer::Gid(gid.clone())),
+            _ => unreachable!("globals can only be initialized with functions"),
+        },
+        CArr(t, elements) => {
+            let element_type = t.clone().compile(context, type_context);
+            let array_type = oat::Type::Ref(oat::ReferenceType::Array(Box::new(t)))
+                .compile(context, type_context);
+            let length = elements.len();
+            let elements = elements
+                .into_iter()
+                .map(|e| {
+                    let (t, init) = compile_initializer(e, context, type_context, globals);
+                    let init = coerce_initializer(t, init, &element_type);
+                    (element_type.clone(), init)
+                })
+                .collect();
+            let contents_type = LLType::Array(length, Box::new(element_type));
+            let literal_type = LLType::Struct(vec![LLType::I64, contents_type.clone()]);
+            let literal = GlobalInitializer::Struct(vec![
+                (LLType::I64, GlobalInitializer::Int(length as i64)),
+                (contents_type, GlobalInitializer::Array(elements)),
+            ]);
+            let gid = context.gensym("array");
+            globals.push((
+                gid.clone(),
+                ll::GlobalDeclaration(literal_type.clone(), literal),
+            ));
+            let init = GlobalInitializer::Bitcast(
+                LLType::Ptr(Box::new(literal_type)),
+                Box::new(GlobalInitializer::Gid(gid)),
+                array_type.clone(),
+            );
+            (array_type, init)
+        }
+        CStruct(name, _, fields) => {
+            let mut layout: Vec<_> = fields
+                .into_iter()
+                .map(|(field, e)| {
+                    let (i, field_type) =
+                        type_context.field_layout(&name, &field).unwrap_or_else(|| {
+                            unreachable!("{} has no field {}", name.name(), field.name())
+                        });
+                    let (t, init) = compile_initializer(e, context, type_context, globals);
+                    (
+                        i,
+                        (field_type.clone(), coerce_initializer(t, init, &field_type)),
+                    )
+                })
+                .collect();
+            layout.sort_by_key(|(i, _)| *i);
+            let literal = GlobalInitializer::Struct(layout.into_iter().map(|(_, f)| f).collect());
+            let gid = context.gensym("struct");
+            globals.push((
+                gid.clone(),
+                ll::GlobalDeclaration(LLType::Namedt(name), literal),
+            ));
+            (
+                LLType::Ptr(Box::new(LLType::Namedt(name))),
+                GlobalInitializer::Gid(gid),
+            )
+        }
+        _ => unreachable!("global initializers are checked to be constant"),
+    }
+}
+
+/// Compile a global variable, returning its type along with the globals it
+/// defines, itself included.
+pub(crate) fn compile_global(
+    gdecl: oat::GlobalDeclaration,
+    context: &Context,
+    type_context: &TypingContext,
+) -> (LLType, Globals) {
+    let mut globals = vec![];
+    let (t, init) = compile_initializer(gdecl.init, context, type_context, &mut globals);
+    let name = gdecl.name.name().to_string();
+    globals.push((name, ll::GlobalDeclaration(t.clone(), init)));
+    (t, globals)
+}
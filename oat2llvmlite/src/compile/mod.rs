@@ -1,9 +1,53 @@
+use llvmlite as ll;
+
 use crate::context::{Context, TypingContext};
 
+mod expression;
+use expression::{coerce, compile_place, emit, ASSERT_ARRAY_LENGTH, MALLOC};
+
+mod statement;
+use statement::{compile_block, declare_local};
+
+mod global;
+use global::compile_global;
+
+mod program;
+pub use program::compile_program;
+
 mod types;
 pub use types::*;
 
+/// Options of the lowering
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Check every array index against the length of the array
+    pub bounds_checks: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            bounds_checks: true,
+        }
+    }
+}
+
 pub(crate) trait Compile<Target> {
     /// Compile
     fn compile(self, context: &Context, type_context: &TypingContext) -> Target;
 }
+
+/// The code produced by lowering, in order. A label starts a new block, which
+/// runs until the next terminator. Entry instructions, i.e. the `alloca`s of
+/// local variables, are hoisted into the entry block and globals out of the
+/// function when it is assembled, so both can appear anywhere in the stream.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Element {
+    Label(ll::Label),
+    Instruction(ll::Uid, ll::Instruction),
+    Terminator(ll::Uid, ll::Terminator),
+    Entry(ll::Uid, ll::Instruction),
+    Global(ll::Gid, ll::GlobalDeclaration),
+}
+
+pub(crate) type Stream = Vec<Element>;
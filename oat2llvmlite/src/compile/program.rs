@@ -0,0 +1,617 @@
+//! Lowering of functions and whole programs.
+
+use indexmap::IndexMap;
+
+use crate::{
+    context::{Context, TypingContext},
+    layout::StructLayout,
+    Compile, Element, Stream,
+};
+
+use super::{compile_block, compile_global, declare_local, Options, ASSERT_ARRAY_LENGTH, MALLOC};
+
+use llvmlite as ll;
+use llvmlite::builder::FunctionBuilder;
+use llvmlite::{Operand, Terminator, Type as LLType};
+use oat_ast as oat;
+use oat_typecontext::builtins;
+
+/// The functions of the runtime called by lowered code.
+fn runtime_externals() -> IndexMap<ll::Gid, LLType> {
+    let words = LLType::Ptr(Box::new(LLType::I64));
+    IndexMap::from([
+        (
+            MALLOC.to_string(),
+            LLType::Fun(vec![LLType::I64], Box::new(words.clone())),
+        ),
+        (
+            ASSERT_ARRAY_LENGTH.to_string(),
+            LLType::Fun(vec![words, LLType::I64], Box::new(LLType::Void)),
+        ),
+    ])
+}
+
+/// A value of type `t`, returned by blocks that can never run to the end of
+/// a function returning `t`.
+fn default_value(t: &LLType) -> Option<Operand> {
+    match t {
+        LLType::Void => None,
+        LLType::I1 | LLType::I64 => Some(Operand::Const(0)),
+        _ => Some(Operand::Null),
+    }
+}
+
+/// Split the stream of a function body into basic blocks, and collect the
+/// globals it defines. Elements after a terminator and before the next label
+/// can never run, and are dropped.
+fn assemble(
+    stream: Stream,
+    context: &Context,
+    type_signature: ll::FunctionType,
+    parameters: Vec<ll::Uid>,
+) -> (ll::FunctionDecl, Vec<(ll::Gid, ll::GlobalDeclaration)>) {
+    let return_type = type_signature.ret_type.clone();
+    let mut builder = FunctionBuilder::new(type_signature, parameters);
+    let mut globals = vec![];
+    for element in stream {
+        match element {
+            Element::Label(label) => {
+                if !builder.is_terminated() {
+                    builder.terminate(context.gensym("term"), Terminator::Break(label.clone()));
+                }
+                let block = builder.append_block_with_label(label);
+                builder.position_at_end(block);
+            }
+            Element::Instruction(uid, instruction) => {
+                if !builder.is_terminated() {
+                    builder.insert(uid, instruction);
+                }
+            }
+            Element::Terminator(uid, terminator) => {
+                if !builder.is_terminated() {
+                    builder.terminate(uid, terminator);
+                }
+            }
+            Element::Entry(uid, instruction) => builder.insert_at_entry(uid, instruction),
+            Element::Global(gid, global) => globals.push((gid, global)),
+        }
+    }
+    if !builder.is_terminated() {
+        let ret = Terminator::Ret(return_type.clone(), default_value(&return_type));
+        builder.terminate(context.gensym("term"), ret);
+    }
+    let fdecl = builder
+        .finish()
+        .expect("every block of a lowered function is terminated");
+    (fdecl, globals)
+}
+
+/// Compile a function, returning it along with the globals it defines.
+fn compile_function(
+    fdecl: oat::FunctionDecl,
+    context: &Context,
+    type_context: &TypingContext,
+) -> (ll::FunctionDecl, Vec<(ll::Gid, ll::GlobalDeclaration)>) {
+    let ret_type = fdecl.return_type.compile(context, type_context);
+    let mut arg_types = vec![];
+    let mut parameters = vec![];
+    let mut stream = vec![];
+    let mut context = context.clone();
+    for (t, name) in fdecl.args {
+        let t = t.compile(&context, type_context);
+        let parameter = name.name().to_string();
+        context = declare_local(
+            &context,
+            &mut stream,
+            name,
+            t.clone(),
+            Operand::Id(parameter.clone()),
+        );
+        arg_types.push(t);
+        parameters.push(parameter);
+    }
+    stream.extend(compile_block(fdecl.body, &context, type_context, &ret_type));
+
+    let type_signature = ll::FunctionType {
+        arg_types,
+        ret_type,
+    };
+    assemble(stream, &context, type_signature, parameters)
+}
+
+/// Compile a program to LLVMLite.
+///
+/// The functions of the runtime, including the builtins that Oat programs can
+/// call, are declared as externals of the program.
+///
+/// The program must have been type checked, and must not contain generics:
+/// generic programs are elaborated by the type checker and then
+/// [`monomorphize`]d.
+///
+/// [`monomorphize`]: fn@crate::monomorphize::monomorphize
+pub fn compile_program(program: &oat::Program, options: &Options) -> ll::Program {
+    let mut type_declarations = vec![];
+    let mut functions = vec![];
+    let mut global_declarations = vec![];
+    for decl in program.declarations.iter() {
+        match decl {
+            oat::Declaration::Type(tdecl) => type_declarations.push(tdecl.clone()),
+            oat::Declaration::Function(fdecl) => functions.push(fdecl.clone()),
+            oat::Declaration::Variable(gdecl) => global_declarations.push(gdecl.clone()),
+        }
+    }
+    let type_context = TypingContext::from_declarations(&type_declarations);
+
+    let mut context = Context::with_options(options.clone());
+    let mut externals = runtime_externals();
+    for (name, t) in builtins() {
+        if functions.iter().any(|fdecl| fdecl.name == name) {
+            continue;
+        }
+        let gid = name.name().to_string();
+        let t = t.compile(&context, &type_context);
+        if let LLType::Ptr(function_type) = &t {
+            externals.insert(gid.clone(), function_type.as_ref().clone());
+        }
+        context = context.extend_operand(name, t, Operand::Gid(gid));
+    }
+    for fdecl in functions.iter() {
+        let arg_types = fdecl.args.iter().map(|(t, _)| t.clone()).collect();
+        let function_type = oat::Type::Ref(oat::ReferenceType::Function(
+            arg_types,
+            Box::new(fdecl.return_type.clone()),
+        ));
+        let function_type = function_type.compile(&context, &type_context);
+        context = context.extend_operand(
+            fdecl.name,
+            function_type,
+            Operand::Gid(fdecl.name.name().to_string()),
+        );
+    }
+
+    // Globals can be initialized with functions, and are used by functions
+    let mut globals = IndexMap::new();
+    for gdecl in global_declarations {
+        let name = gdecl.name;
+        let (t, global_globals) = compile_global(gdecl, &context, &type_context);
+        globals.extend(global_globals);
+        context = context.extend_operand(
+            name,
+            LLType::Ptr(Box::new(t)),
+            Operand::Gid(name.name().to_string()),
+        );
+    }
+
+    let mut ll_functions = IndexMap::new();
+    for fdecl in functions {
+        let name = fdecl.name.name().to_string();
+        let (fdecl, function_globals) = compile_function(fdecl, &context, &type_context);
+        globals.extend(function_globals);
+        ll_functions.insert(name, fdecl);
+    }
+
+    ll::Program {
+        types: type_context.named_types(),
+        globals,
+        functions: ll_functions,
+        externals,
+    }
+}
+
+#[cfg(test)]
+mod program_tests {
+    use super::*;
+    use oat_parse::parse_program;
+    use oat_symbol::create_session_if_not_set_then;
+
+    use ll::GlobalInitializer;
+    use ll::Instruction::*;
+    use LLType::*;
+
+    fn compiled(source: &str) -> ll::Program {
+        compile_program(
+            &parse_program(source).expect("test program should parse"),
+            &Options::default(),
+        )
+    }
+
+    fn id(uid: &str) -> Operand {
+        Operand::Id(uid.to_string())
+    }
+
+    fn ptr(t: LLType) -> LLType {
+        Ptr(Box::new(t))
+    }
+
+    /// The labels of the blocks of `f` with their terminators.
+    fn terminators(fdecl: &ll::FunctionDecl) -> Vec<(&str, &Terminator)> {
+        std::iter::once(("entry", &fdecl.cfg.entry.terminator.1))
+            .chain(
+                fdecl
+                    .cfg
+                    .blocks
+                    .iter()
+                    .map(|(label, block)| (label.as_str(), &block.terminator.1)),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn straight_line() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled("int f(int a) { var b = a + 1; return b; }");
+            let f = &program.functions["f"];
+            assert_eq!(
+                f.type_signature,
+                ll::FunctionType {
+                    arg_types: vec![I64],
+                    ret_type: I64
+                }
+            );
+            assert_eq!(f.parameters, vec!["a".to_string()]);
+            assert!(f.cfg.blocks.is_empty());
+            let instructions: Vec<_> = f.cfg.entry.instructions.iter().collect();
+            assert_eq!(
+                instructions,
+                vec![
+                    &("_a.0".to_string(), Alloca(I64)),
+                    &("_b.4".to_string(), Alloca(I64)),
+                    &("_tmp.1".to_string(), Store(I64, id("a"), id("_a.0"))),
+                    &("_tmp.2".to_string(), Load(ptr(I64), id("_a.0"))),
+                    &(
+                        "_tmp.3".to_string(),
+                        Binop(ll::BinaryOperator::Add, I64, id("_tmp.2"), Operand::Const(1))
+                    ),
+                    &("_tmp.5".to_string(), Store(I64, id("_tmp.3"), id("_b.4"))),
+                    &("_tmp.6".to_string(), Load(ptr(I64), id("_b.4"))),
+                ]
+            );
+            assert_eq!(
+                f.cfg.entry.terminator.1,
+                Terminator::Ret(I64, Some(id("_tmp.6")))
+            );
+        })
+    }
+
+    #[test]
+    fn if_else() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled(concat!(
+                "int f(int a) {\n",
+                "  var b = 0;\n",
+                "  if (a > 0) { b = 1; } else { return 2; }\n",
+                "  return b;\n",
+                "}\n",
+            ));
+            let f = &program.functions["f"];
+            assert_eq!(
+                terminators(f),
+                vec![
+                    (
+                        "entry",
+                        &Terminator::CondBreak(id("_tmp.8"), "_then.4".into(), "_else.5".into())
+                    ),
+                    ("_then.4", &Terminator::Break("_merge.6".into())),
+                    ("_else.5", &Terminator::Ret(I64, Some(Operand::Const(2)))),
+                    ("_merge.6", &Terminator::Ret(I64, Some(id("_tmp.14")))),
+                ]
+            );
+        })
+    }
+
+    #[test]
+    fn unreachable_end() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled("bool f(bool a) { if (a) { return true; } else { return a; } }");
+            let f = &program.functions["f"];
+            let (label, merge) = f.cfg.blocks.last().unwrap();
+            assert!(label.starts_with("_merge"));
+            assert_eq!(
+                merge.terminator.1,
+                Terminator::Ret(I1, Some(Operand::Const(0)))
+            );
+        })
+    }
+
+    #[test]
+    fn loops() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled(concat!(
+                "void f(int n) {\n",
+                "  for (var i = 0; i < n; i = i + 1) { while (false) { } }\n",
+                "}\n",
+            ));
+            let f = &program.functions["f"];
+            let shape: Vec<_> = terminators(f)
+                .into_iter()
+                .map(|(label, terminator)| {
+                    let target = match terminator {
+                        Terminator::Break(l) => l.clone(),
+                        Terminator::CondBreak(_, then, else_) => format!("{}|{}", then, else_),
+                        Terminator::Ret(..) => "ret".to_string(),
+                    };
+                    (label.to_string(), target)
+                })
+                .collect();
+            assert_eq!(
+                shape,
+                vec![
+                    ("entry".to_string(), "_loop.4".to_string()),
+                    ("_loop.4".to_string(), "_body.5|_exit.6".to_string()),
+                    ("_body.5".to_string(), "_loop.12".to_string()),
+                    ("_loop.12".to_string(), "_body.13|_exit.14".to_string()),
+                    ("_body.13".to_string(), "_loop.12".to_string()),
+                    ("_exit.14".to_string(), "_loop.4".to_string()),
+                    ("_exit.6".to_string(), "ret".to_string()),
+                ]
+            );
+        })
+    }
+
+    #[test]
+    fn function_values_and_externals() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled(concat!(
+                "struct point { int x; bool y; }\n",
+                "int g(int a) { return a; }\n",
+                "int f() { var h = g; return h(1); }\n",
+            ));
+            assert_eq!(
+                program.types[&oat::Id::from("point")],
+                Struct(vec![I64, I1])
+            );
+            let f = &program.functions["f"];
+            assert_eq!(
+                f.cfg.entry.instructions[1].1,
+                Store(
+                    ptr(Fun(vec![I64], Box::new(I64))),
+                    Operand::Gid("g".into()),
+                    id("_h.4")
+                )
+            );
+            assert!(program.externals.contains_key(ASSERT_ARRAY_LENGTH));
+        })
+    }
+
+    #[test]
+    fn strings_and_builtins() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled(concat!(
+                "void f() { print_string(\"hi\"); }\n",
+                "string g() { return string_cat(\"hi\", string_of_int(1)); }\n",
+            ));
+            let string = ptr(I8);
+            assert_eq!(
+                program.globals.values().collect::<Vec<_>>(),
+                vec![&ll::GlobalDeclaration(
+                    Array(3, Box::new(I8)),
+                    ll::GlobalInitializer::String("hi".into())
+                )]
+            );
+            let gid = program.globals.keys().next().unwrap();
+            for fdecl in program.functions.values() {
+                assert!(matches!(
+                    &fdecl.cfg.entry.instructions[0].1,
+                    Bitcast(_, Operand::Gid(used), _) if used == gid
+                ));
+            }
+            assert_eq!(
+                program.externals["print_string"],
+                Fun(vec![string.clone()], Box::new(Void))
+            );
+            assert_eq!(
+                program.externals["string_cat"],
+                Fun(vec![string.clone(), string.clone()], Box::new(string))
+            );
+            assert!(program.externals.contains_key("length_of_string"));
+        })
+    }
+
+    #[test]
+    fn if_null_cast() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled(include_str!("../../../sample-files/ifq.oat"));
+            let f = &program.functions["program"];
+            let array = ptr(Struct(vec![I64, Array(0, Box::new(I64))]));
+            // `x` is loaded once, and bound to `y` on the non-null edge
+            assert_eq!(
+                f.cfg.entry.instructions[9..],
+                [
+                    ("_tmp.11".to_string(), Load(ptr(array.clone()), id("_x.4"))),
+                    (
+                        "_tmp.12".to_string(),
+                        Icmp(
+                            ll::Condition::Ne,
+                            array.clone(),
+                            id("_tmp.11"),
+                            Operand::Null
+                        )
+                    ),
+                ]
+            );
+            assert_eq!(
+                f.cfg.blocks["_then.8"].instructions[0],
+                ("_tmp.15".to_string(), Store(array, id("_tmp.11"), id("_y.14")))
+            );
+            assert_eq!(
+                terminators(f),
+                vec![
+                    (
+                        "entry",
+                        &Terminator::CondBreak(id("_tmp.12"), "_then.8".into(), "_else.9".into())
+                    ),
+                    ("_then.8", &Terminator::Break("_merge.10".into())),
+                    ("_else.9", &Terminator::Break("_merge.10".into())),
+                    ("_merge.10", &Terminator::Ret(I64, Some(id("_tmp.20")))),
+                ]
+            );
+        })
+    }
+
+    #[test]
+    fn if_null_cast_branches() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled(concat!(
+                "int f(string? s) {\n",
+                "  if? (string t = s) { print_string(t); return 1; } else { return 0; }\n",
+                "}\n",
+            ));
+            let f = &program.functions["f"];
+            let string = ptr(I8);
+            assert_eq!(
+                f.cfg.entry.instructions[3..],
+                [
+                    ("_tmp.5".to_string(), Load(ptr(string.clone()), id("_s.0"))),
+                    (
+                        "_tmp.6".to_string(),
+                        Icmp(
+                            ll::Condition::Ne,
+                            string.clone(),
+                            id("_tmp.5"),
+                            Operand::Null
+                        )
+                    ),
+                ]
+            );
+            // A value that is not null goes to the then branch, which binds it
+            // to `t` and uses it from there
+            assert_eq!(
+                f.cfg.blocks["_then.2"].instructions,
+                [
+                    (
+                        "_tmp.9".to_string(),
+                        Store(string.clone(), id("_tmp.5"), id("_t.8"))
+                    ),
+                    ("_tmp.10".to_string(), Load(ptr(string.clone()), id("_t.8"))),
+                    (
+                        "_tmp.11".to_string(),
+                        Call(
+                            Void,
+                            Operand::Gid("print_string".into()),
+                            vec![(string, id("_tmp.10"))]
+                        )
+                    ),
+                ]
+            );
+            // Null goes to the else branch, where nothing is bound
+            assert!(f.cfg.blocks["_else.3"].instructions.is_empty());
+            assert_eq!(
+                terminators(f),
+                vec![
+                    (
+                        "entry",
+                        &Terminator::CondBreak(id("_tmp.6"), "_then.2".into(), "_else.3".into())
+                    ),
+                    ("_then.2", &Terminator::Ret(I64, Some(Operand::Const(1)))),
+                    ("_else.3", &Terminator::Ret(I64, Some(Operand::Const(0)))),
+                    ("_merge.4", &Terminator::Ret(I64, Some(Operand::Const(0)))),
+                ]
+            );
+        })
+    }
+
+    /// Generated names have a `.` before their number, which Oat names
+    /// cannot contain, so they never clash with the names of the program.
+    #[test]
+    fn generated_names_do_not_clash() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled(concat!(
+                "global _str0 = \"a\";\n",
+                "global _array2 = new int[]{1};\n",
+                "int f(int tmp1) {\n",
+                "  var tmp2 = tmp1 + 1;\n",
+                "  var loop3 = tmp2 * tmp1;\n",
+                "  while (loop3 > tmp1) { loop3 = loop3 - 1; }\n",
+                "  print_string(\"b\");\n",
+                "  return loop3 + _array2[0];\n",
+                "}\n",
+            ));
+            assert_eq!(ll::verify(&program), Ok(()));
+        })
+    }
+
+    #[test]
+    fn globals() {
+        create_session_if_not_set_then(|_| {
+            let program = compiled(concat!(
+                "struct point { int x; int y; }\n",
+                "global names = new string[]{\"a\", \"b\"};\n",
+                "global origin = new point{y = 1; x = 0};\n",
+                "global n = 3;\n",
+                "int f() { n = n + origin.y; return n; }\n",
+            ));
+            let string = ptr(I8);
+            let literal = Struct(vec![I64, Array(2, Box::new(string.clone()))]);
+            let names = ptr(Struct(vec![I64, Array(0, Box::new(string.clone()))]));
+            assert_eq!(
+                program.globals.keys().collect::<Vec<_>>(),
+                vec!["_str.0", "_str.1", "_array.2", "names", "_struct.3", "origin", "n"]
+            );
+            let element = |gid: &str| {
+                (
+                    string.clone(),
+                    GlobalInitializer::Bitcast(
+                        ptr(Array(2, Box::new(I8))),
+                        Box::new(GlobalInitializer::Gid(gid.into())),
+                        string.clone(),
+                    ),
+                )
+            };
+            assert_eq!(
+                program.globals["_array.2"],
+                ll::GlobalDeclaration(
+                    literal.clone(),
+                    GlobalInitializer::Struct(vec![
+                        (I64, GlobalInitializer::Int(2)),
+                        (
+                            Array(2, Box::new(string.clone())),
+                            GlobalInitializer::Array(vec![element("_str.0"), element("_str.1")])
+                        ),
+                    ])
+                )
+            );
+            assert_eq!(
+                program.globals["names"],
+                ll::GlobalDeclaration(
+                    names.clone(),
+                    GlobalInitializer::Bitcast(
+                        ptr(literal),
+                        Box::new(GlobalInitializer::Gid("_array.2".into())),
+                        names
+                    )
+                )
+            );
+            // Fields are laid out in declaration order
+            assert_eq!(
+                program.globals["_struct.3"].1,
+                GlobalInitializer::Struct(vec![
+                    (I64, GlobalInitializer::Int(0)),
+                    (I64, GlobalInitializer::Int(1)),
+                ])
+            );
+            let instructions = &program.functions["f"].cfg.entry.instructions;
+            assert_eq!(instructions[0].1, Load(ptr(I64), Operand::Gid("n".into())));
+            assert!(instructions
+                .iter()
+                .any(|(_, i)| matches!(i, Store(I64, _, Operand::Gid(n)) if n == "n")));
+        })
+    }
+
+    #[test]
+    fn printed_programs_parse_back() {
+        create_session_if_not_set_then(|_| {
+            let samples = [
+                include_str!("../../../sample-files/fib.oat"),
+                include_str!("../../../sample-files/geometry.oat"),
+                include_str!("../../../sample-files/globals.oat"),
+                include_str!("../../../sample-files/ifq.oat"),
+                include_str!("../../../sample-files/strings.oat"),
+            ];
+            for sample in samples {
+                let printed = compiled(sample).to_string();
+                let parsed = ll::parse_program(&printed).expect("printed program should parse");
+                assert_eq!(parsed.to_string(), printed);
+            }
+        })
+    }
+}
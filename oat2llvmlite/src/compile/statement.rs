@@ -0,0 +1,237 @@
+//! Lowering of statements.
+//!
+//! Statements are lowered to a stream of instructions, labels and
+//! terminators. Every local variable gets a slot allocated in the entry block
+//! of the function, and is read and written through it.
+
+use crate::{
+    context::{Context, TypingContext},
+    Compile, Element, Stream,
+};
+
+use super::{coerce, compile_place, emit};
+
+use llvmlite::{Condition, Instruction, Operand, Terminator, Type as LLType};
+use oat_ast as oat;
+
+fn terminate(context: &Context, stream: &mut Stream, terminator: Terminator) {
+    stream.push(Element::Terminator(context.gensym("term"), terminator));
+}
+
+/// Allocate a slot for the local `name` of type `t` holding `value`, and
+/// return the context with the local bound to it.
+pub(crate) fn declare_local(
+    context: &Context,
+    stream: &mut Stream,
+    name: oat::Id,
+    t: LLType,
+    value: Operand,
+) -> Context {
+    let slot = context.gensym(name.name());
+    stream.push(Element::Entry(slot.clone(), Instruction::Alloca(t.clone())));
+    emit(
+        context,
+        stream,
+        Instruction::Store(t.clone(), value, Operand::Id(slot.clone())),
+    );
+    context.extend_operand(name, LLType::Ptr(Box::new(t)), Operand::Id(slot))
+}
+
+/// Compile `condition`, and branch to `then` if it holds and to `else_`
+/// otherwise.
+fn compile_branch(
+    condition: oat::Expression,
+    then: &str,
+    else_: &str,
+    context: &Context,
+    type_context: &TypingContext,
+) -> Stream {
+    let (_, op, mut stream) = condition.compile(context, type_context);
+    terminate(
+        context,
+        &mut stream,
+        Terminator::CondBreak(op, then.to_string(), else_.to_string()),
+    );
+    stream
+}
+
+/// Compile a loop. The update of a `for` loop runs after the body.
+fn compile_loop(
+    condition: oat::Expression,
+    body: oat::Block,
+    update: Option<oat::Statement>,
+    context: &Context,
+    type_context: &TypingContext,
+    return_type: &LLType,
+) -> Stream {
+    let head = context.gensym("loop");
+    let body_label = context.gensym("body");
+    let exit = context.gensym("exit");
+
+    let mut stream = vec![];
+    terminate(context, &mut stream, Terminator::Break(head.clone()));
+    stream.push(Element::Label(head.clone()));
+    stream.extend(compile_branch(
+        condition,
+        &body_label,
+        &exit,
+        context,
+        type_context,
+    ));
+    stream.push(Element::Label(body_label));
+    stream.extend(compile_block(body, context, type_context, return_type));
+    if let Some(update) = update {
+        let (_, update) = compile_statement(update, context, type_context, return_type);
+        stream.extend(update);
+    }
+    terminate(context, &mut stream, Terminator::Break(head));
+    stream.push(Element::Label(exit));
+    stream
+}
+
+/// Compile a statement, returning the context of the statements following it.
+pub(crate) fn compile_statement(
+    stmt: oat::Statement,
+    context: &Context,
+    type_context: &TypingContext,
+    return_type: &LLType,
+) -> (Context, Stream) {
+    use oat::Statement::*;
+    let stream = match stmt {
+        Assignment(target, value) => {
+            let (t, ptr, mut stream) = compile_place(target, context, type_context);
+            let (value_type, value, value_stream) = value.compile(context, type_context);
+            stream.extend(value_stream);
+            let value = coerce(context, &mut stream, value_type, value, &t);
+            emit(context, &mut stream, Instruction::Store(t, value, ptr));
+            stream
+        }
+        Declaration(declared, name, init) => {
+            let (init_type, value, mut stream) = init.compile(context, type_context);
+            let t = match declared {
+                Some(declared) => declared.compile(context, type_context),
+                None => init_type.clone(),
+            };
+            let value = coerce(context, &mut stream, init_type, value, &t);
+            let context = declare_local(context, &mut stream, name, t, value);
+            return (context, stream);
+        }
+        SCall(fun, args) => {
+            let call = oat::Expression::Call(Box::new(fun), args);
+            let (_, _, stream) = call.compile(context, type_context);
+            stream
+        }
+        Return(None) => {
+            let mut stream = vec![];
+            terminate(context, &mut stream, Terminator::Ret(LLType::Void, None));
+            stream
+        }
+        Return(Some(e)) => {
+            let (t, op, mut stream) = e.compile(context, type_context);
+            let op = coerce(context, &mut stream, t, op, return_type);
+            terminate(
+                context,
+                &mut stream,
+                Terminator::Ret(return_type.clone(), Some(op)),
+            );
+            stream
+        }
+        If {
+            condition,
+            then,
+            else_,
+        } => {
+            let then_label = context.gensym("then");
+            let else_label = context.gensym("else");
+            let merge = context.gensym("merge");
+            let mut stream =
+                compile_branch(condition, &then_label, &else_label, context, type_context);
+            stream.push(Element::Label(then_label));
+            stream.extend(compile_block(then, context, type_context, return_type));
+            terminate(context, &mut stream, Terminator::Break(merge.clone()));
+            stream.push(Element::Label(else_label));
+            stream.extend(compile_block(else_, context, type_context, return_type));
+            terminate(context, &mut stream, Terminator::Break(merge.clone()));
+            stream.push(Element::Label(merge));
+            stream
+        }
+        Cast(rt, name, e, then, else_) => {
+            let then_label = context.gensym("then");
+            let else_label = context.gensym("else");
+            let merge = context.gensym("merge");
+            // The value is computed once, and bound to `name` if it is not null
+            let (t, op, mut stream) = e.compile(context, type_context);
+            let non_null = emit(
+                context,
+                &mut stream,
+                Instruction::Icmp(Condition::Ne, t.clone(), op.clone(), Operand::Null),
+            );
+            terminate(
+                context,
+                &mut stream,
+                Terminator::CondBreak(non_null, then_label.clone(), else_label.clone()),
+            );
+            stream.push(Element::Label(then_label));
+            let declared = oat::Type::Ref(rt).compile(context, type_context);
+            let op = coerce(context, &mut stream, t, op, &declared);
+            let then_context = declare_local(context, &mut stream, name, declared, op);
+            stream.extend(compile_block(
+                then,
+                &then_context,
+                type_context,
+                return_type,
+            ));
+            terminate(context, &mut stream, Terminator::Break(merge.clone()));
+            stream.push(Element::Label(else_label));
+            stream.extend(compile_block(else_, context, type_context, return_type));
+            terminate(context, &mut stream, Terminator::Break(merge.clone()));
+            stream.push(Element::Label(merge));
+            stream
+        }
+        While { condition, body } => {
+            compile_loop(condition, body, None, context, type_context, return_type)
+        }
+        For {
+            init,
+            condition,
+            update,
+            body,
+        } => {
+            // The variables of the loop are only in scope within it
+            let mut stream = vec![];
+            let mut loop_context = context.clone();
+            for (name, e) in init {
+                let (t, value, e_stream) = e.compile(&loop_context, type_context);
+                stream.extend(e_stream);
+                loop_context = declare_local(&loop_context, &mut stream, name, t, value);
+            }
+            stream.extend(compile_loop(
+                condition.unwrap_or(oat::Expression::CBool(true)),
+                body,
+                update.map(|update| *update),
+                &loop_context,
+                type_context,
+                return_type,
+            ));
+            stream
+        }
+    };
+    (context.clone(), stream)
+}
+
+/// Compile the statements of a block, whose declarations are scoped to it.
+pub(crate) fn compile_block(
+    block: oat::Block,
+    context: &Context,
+    type_context: &TypingContext,
+    return_type: &LLType,
+) -> Stream {
+    let mut context = context.clone();
+    let mut stream = vec![];
+    for stmt in block {
+        let (next, stmt_stream) = compile_statement(stmt, &context, type_context, return_type);
+        stream.extend(stmt_stream);
+        context = next;
+    }
+    stream
+}
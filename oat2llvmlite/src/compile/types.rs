@@ -23,6 +23,9 @@ impl Compile<llvm::Type> for oat::Type {
             oat::Type::Ref(rt) | oat::Type::NullRef(rt) => llvm::Type::Ptr(Box::new(match rt {
                 oat::ReferenceType::String => llvm::Type::I8,
                 oat::ReferenceType::Struct(id) => llvm::Type::Namedt(id),
+                oat::ReferenceType::GenericStruct(..) => {
+                    unreachable!("generic structs are monomorphized before lowering")
+                }
                 oat::ReferenceType::Array(rt) => llvm::Type::Struct(vec![
                     llvm::Type::I64,
                     llvm::Type::Array(0, Box::new(rt.compile(&context, &type_context))),
@@ -35,7 +38,13 @@ impl Compile<llvm::Type> for oat::Type {
                     Box::new(ret_type.compile(&context, &type_context)),
                 ),
             })),
+            oat::Type::Param(_) => unreachable!("type parameters are substituted before lowering"),
             // _ => panic!("Cannot represent {:?}", self),
         }
     }
 }
+
+/// Lower a type. The lowering of types does not depend on the context.
+pub(crate) fn lower_type(t: oat::Type) -> llvm::Type {
+    t.compile(&Context::new(), &TypingContext::new())
+}
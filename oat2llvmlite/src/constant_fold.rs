@@ -1,95 +1,313 @@
+//! Constant folding of Oat programs.
+//!
+//! Operations on constants are evaluated with the semantics of the compiled
+//! code: integers are 64-bit and wrap around on overflow, and shifts by an
+//! amount outside of `0..64` are left to run, as their result is not defined.
+//! Boolean identities such as `b & true` are simplified, as long as no side
+//! effect of an operand is dropped.
+//!
+//! Branches of an `if` whose condition is constant are pruned, and so are
+//! `while` loops whose condition is `false`.
+
+use std::mem;
+
 use oat_ast as oat;
-use oat_ast::{BinaryOp, Expression, Statement, UnaryOp};
+use oat_ast::visit::VisitMut;
+use oat_ast::{BinaryOp, Block, Expression, Statement, UnaryOp};
 
-/// Constant fold an expression
-pub fn constant_fold_expression<'a>(e: &'a Expression) -> &'a Expression {
-    use BinaryOp::*;
+/// Whether evaluating `e` has no side effect, so that it can be dropped. Calls
+/// and allocations have side effects, and so may indexing, which can fail.
+fn is_pure(e: &Expression) -> bool {
     use Expression::*;
     match e {
-        Binary { op, left, right } => {
-            let left = constant_fold_expression(left);
-            let right = constant_fold_expression(right);
-            match (op, left, right) {
-                (Add, CInt(i), CInt(j)) => &CInt(i + j),
-                (Mul, CInt(i), CInt(j)) => &CInt(i * j),
-                (Sub, CInt(i), CInt(j)) => &CInt(i - j),
-                (IAnd, CInt(i), CInt(j)) => &CInt(i & j),
-                (IOr, CInt(i), CInt(j)) => &CInt(i | j),
-                (Eq, _, _) => &CBool(left == right),
-                (Neq, _, _) => &CBool(left != right),
-                (Lt, CInt(i), CInt(j)) => &CBool(i < j),
-                (Lte, CInt(i), CInt(j)) => &CBool(i <= j),
-                (Gt, CInt(i), CInt(j)) => &CBool(i > j),
-                (Gte, CInt(i), CInt(j)) => &CBool(i >= j),
-
-                (And, CBool(true), b) | (And, b, CBool(true)) => b,
-                (And, CBool(false), b) | (And, b, CBool(false)) => &CBool(false),
-                (Or, CBool(true), _) | (Or, _, CBool(true)) => &CBool(true),
-                (Or, CBool(false), b) | (Or, b, CBool(false)) => b,
-                (_, _, _) => {
-                    return &Binary {
-                        op: *op,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    }
-                }
-            }
-        }
-        // Unary(op, e) => {
-        // }
-        CArr(t, elements) => &CArr(t, elements.iter().map(constant_fold_expression).collect()),
-        _ => e,
+        CNull(_) | CBool(_) | CInt(_) | Id(_) => true,
+        Unary(_, e) => is_pure(e),
+        Binary { left, right, .. } => is_pure(left) && is_pure(right),
+        _ => false,
     }
 }
 
-pub fn constant_fold_statement(statement: &Statement) -> Vec<Statement> {
+fn fold_integers(op: BinaryOp, i: i64, j: i64) -> Option<Expression> {
+    use BinaryOp::*;
+    use Expression::{CBool, CInt};
+    let shift = u32::try_from(j).ok().filter(|j| *j < 64);
+    Some(match op {
+        Add => CInt(i.wrapping_add(j)),
+        Sub => CInt(i.wrapping_sub(j)),
+        Mul => CInt(i.wrapping_mul(j)),
+        IAnd => CInt(i & j),
+        IOr => CInt(i | j),
+        Shl => CInt(i << shift?),
+        Shr => CInt(((i as u64) >> shift?) as i64),
+        Sar => CInt(i >> shift?),
+        Eq => CBool(i == j),
+        Neq => CBool(i != j),
+        Lt => CBool(i < j),
+        Lte => CBool(i <= j),
+        Gt => CBool(i > j),
+        Gte => CBool(i >= j),
+        And | Or | AndAlso | OrElse => return None,
+    })
+}
+
+fn fold_binary(op: BinaryOp, left: Expression, right: Expression) -> Expression {
+    use BinaryOp::*;
     use Expression::*;
-    use Statement::*;
+    match (op, left, right) {
+        (op, CInt(i), CInt(j)) => match fold_integers(op, i, j) {
+            Some(folded) => folded,
+            None => Binary {
+                op,
+                left: Box::new(CInt(i)),
+                right: Box::new(CInt(j)),
+            },
+        },
+        (Eq, CBool(a), CBool(b)) => CBool(a == b),
+        (Neq, CBool(a), CBool(b)) => CBool(a != b),
+        (And | AndAlso, CBool(true), e) | (And | AndAlso, e, CBool(true)) => e,
+        (Or | OrElse, CBool(false), e) | (Or | OrElse, e, CBool(false)) => e,
+        // The right operand of `&&` and `||` does not run at all
+        (AndAlso, CBool(false), _) => CBool(false),
+        (OrElse, CBool(true), _) => CBool(true),
+        (And, CBool(false), e) | (And | AndAlso, e, CBool(false)) if is_pure(&e) => CBool(false),
+        (Or, CBool(true), e) | (Or | OrElse, e, CBool(true)) if is_pure(&e) => CBool(true),
+        (op, left, right) => Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+    }
+}
 
-    match statement {
-        Assignment(target, value) => {
-            let target = constant_fold_expression(target);
-            let value = constant_fold_expression(value);
-            vec![Assignment(target, value)]
-        }
-        Declaration(name, value) => {
-            let value = constant_fold_expression(value);
-            vec![Declaration(name, value)]
-        }
+/// Fold `e`, whose operands are folded already.
+fn fold(e: Expression) -> Expression {
+    use Expression::*;
+    match e {
+        Unary(op, operand) => match (op, *operand) {
+            (UnaryOp::Neg, CInt(n)) => CInt(n.wrapping_neg()),
+            (UnaryOp::Bitnot, CInt(n)) => CInt(!n),
+            (UnaryOp::Lognot, CBool(b)) => CBool(!b),
+            (op, operand) => Unary(op, Box::new(operand)),
+        },
+        Binary { op, left, right } => fold_binary(op, *left, *right),
+        e => e,
+    }
+}
+
+/// Fold the expressions within `node`.
+fn fold_expressions(node: &mut impl VisitMut) {
+    node.visit_expressions_mut(|e| *e = fold(mem::replace(e, Expression::CInt(0))))
+}
+
+/// Constant fold an expression.
+pub fn constant_fold_expression(mut e: Expression) -> Expression {
+    fold_expressions(&mut e);
+    e
+}
+
+/// The statements replacing the branch `block` of an `if` that is always
+/// taken. The declarations of a block are scoped to it, so a block declaring
+/// variables stays a block of its own.
+fn inline_branch(block: Block) -> Vec<Statement> {
+    let block = constant_fold_block(block);
+    if block
+        .iter()
+        .any(|stmt| matches!(stmt, Statement::Declaration(..)))
+    {
+        vec![Statement::If {
+            condition: Expression::CBool(true),
+            then: block,
+            else_: vec![],
+        }]
+    } else {
+        block
+    }
+}
+
+/// Constant fold a statement, returning the statements replacing it.
+pub fn constant_fold_statement(mut stmt: Statement) -> Vec<Statement> {
+    use Statement::*;
+    fold_expressions(&mut stmt);
+    match stmt {
+        If {
+            condition: Expression::CBool(taken),
+            then,
+            else_,
+        } => inline_branch(if taken { then } else { else_ }),
         If {
             condition,
             then,
             else_,
-        } => {
-            let condition = constant_fold_expression(condition);
-            let then = then.iter().map(constant_fold_statement).flatten().collect();
-            let else_ = else_
-                .iter()
-                .map(constant_fold_statement)
-                .flatten()
-                .collect();
-            match condition {
-                CBool(true) => then,
-                CBool(false) => else_,
-                _ => vec![If {
-                    condition,
-                    then,
-                    else_,
-                }],
+        } => vec![If {
+            condition,
+            then: constant_fold_block(then),
+            else_: constant_fold_block(else_),
+        }],
+        Cast(rt, name, e, then, else_) => vec![Cast(
+            rt,
+            name,
+            e,
+            constant_fold_block(then),
+            constant_fold_block(else_),
+        )],
+        While {
+            condition: Expression::CBool(false),
+            ..
+        } => vec![],
+        While { condition, body } => vec![While {
+            condition,
+            body: constant_fold_block(body),
+        }],
+        For {
+            init,
+            condition,
+            update,
+            body,
+        } => vec![For {
+            init,
+            condition,
+            update,
+            body: constant_fold_block(body),
+        }],
+        stmt => vec![stmt],
+    }
+}
+
+fn constant_fold_block(block: Block) -> Block {
+    block
+        .into_iter()
+        .flat_map(constant_fold_statement)
+        .collect()
+}
+
+/// Constant fold every function body and global initializer of a program.
+pub fn constant_fold(program: oat::Program) -> oat::Program {
+    let declarations = program
+        .declarations
+        .into_iter()
+        .map(|decl| match decl {
+            oat::Declaration::Function(mut fdecl) => {
+                fdecl.body = constant_fold_block(mem::take(&mut fdecl.body));
+                oat::Declaration::Function(fdecl)
             }
-        }
-        While { condition, body } => {
-            let condition = constant_fold_expression(condition);
-            let body = compile_body(body);
-            match condition {
-                CBool(false) => vec![],
-                _ => vec![While { condition, body }],
+            oat::Declaration::Variable(mut gdecl) => {
+                fold_expressions(&mut gdecl);
+                oat::Declaration::Variable(gdecl)
             }
+            decl => decl,
+        })
+        .collect();
+    oat::Program { declarations }
+}
+
+#[cfg(test)]
+mod constant_fold_tests {
+    use super::*;
+    use oat_parse::parse_program;
+    use oat_symbol::create_session_if_not_set_then;
+
+    /// The body of `f` in `source`, folded.
+    fn folded_body(source: &str) -> Block {
+        let program = constant_fold(parse_program(source).expect("test program should parse"));
+        match program.declarations.into_iter().next() {
+            Some(oat::Declaration::Function(fdecl)) => fdecl.body,
+            _ => unreachable!(),
         }
-        _ => vec![statement],
     }
-}
 
-fn compile_body(body: &Vec<Statement>) -> Vec<Statement> {
-    body.iter().map(constant_fold_statement).flatten().collect()
+    /// The expression returned by `int f(int x, bool b)`, folded.
+    fn folded(e: &str) -> Expression {
+        let source = format!("int f(int x, bool b) {{ return {}; }}", e);
+        match folded_body(&source).pop() {
+            Some(Statement::Return(Some(e))) => e,
+            _ => unreachable!(),
+        }
+    }
+
+    fn expression(e: &str) -> Expression {
+        let source = format!("int f(int x, bool b) {{ return {}; }}", e);
+        let program = parse_program(&source).expect("test expression should parse");
+        match program.declarations.into_iter().next() {
+            Some(oat::Declaration::Function(mut fdecl)) => match fdecl.body.pop() {
+                Some(Statement::Return(Some(e))) => e,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn arithmetic_wraps() {
+        create_session_if_not_set_then(|_| {
+            assert_eq!(folded("-(2 * 3)"), Expression::CInt(-6));
+            assert_eq!(folded("~0"), Expression::CInt(-1));
+            assert_eq!(
+                folded("9223372036854775807 + 1"),
+                Expression::CInt(i64::MIN)
+            );
+        })
+    }
+
+    #[test]
+    fn shifts() {
+        create_session_if_not_set_then(|_| {
+            assert_eq!(folded("1 << 4"), Expression::CInt(16));
+            assert_eq!(folded("-16 >>> 2"), Expression::CInt(-4));
+            assert_eq!(folded("-16 >> 60"), Expression::CInt(15));
+            // The result of shifting by 64 or more is not defined
+            assert_eq!(folded("1 << 64"), expression("1 << 64"));
+        })
+    }
+
+    #[test]
+    fn comparisons() {
+        create_session_if_not_set_then(|_| {
+            assert_eq!(folded("!(1 < 2)"), Expression::CBool(false));
+            assert_eq!(folded("true != false"), Expression::CBool(true));
+            assert_eq!(folded("x < 2"), expression("x < 2"));
+        })
+    }
+
+    #[test]
+    fn boolean_identities() {
+        create_session_if_not_set_then(|_| {
+            assert_eq!(folded("b & (1 < 2)"), Expression::Id("b".into()));
+            assert_eq!(folded("false | b"), Expression::Id("b".into()));
+            assert_eq!(folded("b & false"), Expression::CBool(false));
+            // The call still has to run
+            assert_eq!(folded("g() | true"), expression("g() | true"));
+            assert_eq!(folded("(1 > 2) && g()"), Expression::CBool(false));
+            assert_eq!(folded("true || g()"), Expression::CBool(true));
+            assert_eq!(folded("g() && false"), expression("g() && false"));
+        })
+    }
+
+    #[test]
+    fn constant_branches() {
+        create_session_if_not_set_then(|_| {
+            let body = folded_body(concat!(
+                "int f(int x) {\n",
+                "  if (1 > 2) { x = 1; } else { x = 2; }\n",
+                "  while (false) { x = 3; }\n",
+                "  if (true) { var y = 4; x = y; }\n",
+                "  return x;\n",
+                "}\n",
+            ));
+            assert_eq!(
+                body,
+                vec![
+                    Statement::Assignment("x".into(), Expression::CInt(2)),
+                    Statement::If {
+                        condition: Expression::CBool(true),
+                        then: vec![
+                            Statement::Declaration(None, "y".into(), Expression::CInt(4)),
+                            Statement::Assignment("x".into(), "y".into()),
+                        ],
+                        else_: vec![],
+                    },
+                    Statement::Return(Some("x".into())),
+                ]
+            );
+        })
+    }
 }
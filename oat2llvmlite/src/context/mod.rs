@@ -1,26 +1,71 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
 use indexmap::IndexMap;
 
+use crate::Options;
 use llvmlite as ll;
 use oat_ast as oat;
 
-mod type_context;
-pub use type_context::*;
+pub(crate) use oat_typecontext::TypingContext;
 
+/// The operands bound to Oat identifiers while lowering.
+///
+/// Variables are bound to a pointer to the slot holding their value, and
+/// functions to the function itself, with the type `Ptr(Fun(..))`.
 #[derive(Default, Clone)]
 pub(crate) struct Context {
     operands: IndexMap<oat::Id, (ll::Type, ll::Operand)>,
+    /// Counter for fresh names, shared by every copy of the context
+    fresh: Rc<Cell<usize>>,
+    /// The globals holding the string literals lowered so far, shared by
+    /// every copy of the context
+    strings: Rc<RefCell<IndexMap<String, ll::Gid>>>,
+    options: Options,
 }
 
 impl Context {
     pub(crate) fn new() -> Self {
+        Default::default()
+    }
+
+    pub(crate) fn with_options(options: Options) -> Self {
         Context {
-            operands: Default::default(),
+            options,
+            ..Default::default()
         }
     }
 
+    pub(crate) fn options(&self) -> &Options {
+        &self.options
+    }
+
     pub(crate) fn extend_operand(&self, id: oat::Id, type_: ll::Type, op: ll::Operand) -> Self {
         let mut copy = self.clone();
         copy.operands.insert(id, (type_, op));
         copy
     }
+
+    pub(crate) fn lookup(&self, id: oat::Id) -> Option<&(ll::Type, ll::Operand)> {
+        self.operands.get(&id)
+    }
+
+    /// A name that has not been generated before, starting with `prefix`. The
+    /// number is set off by a `.`, which Oat names cannot contain.
+    pub(crate) fn gensym(&self, prefix: &str) -> String {
+        let n = self.fresh.get();
+        self.fresh.set(n + 1);
+        format!("_{}.{}", prefix, n)
+    }
+
+    /// The global holding the string literal `s`, and whether it was created
+    /// by this call, so that each literal is defined only once.
+    pub(crate) fn string_global(&self, s: &str) -> (ll::Gid, bool) {
+        if let Some(gid) = self.strings.borrow().get(s) {
+            return (gid.clone(), false);
+        }
+        let gid = self.gensym("str");
+        self.strings.borrow_mut().insert(s.to_string(), gid.clone());
+        (gid, true)
+    }
 }
@@ -0,0 +1,114 @@
+//! Memory layout of structs.
+//!
+//! The layout is derived from the same [`TypingContext`] the type checker
+//! uses, so both agree on the fields of every struct. A struct `S` is the
+//! named type `%S = type { t1, ..., tn }`, holding its fields in declaration
+//! order, and field `i` of a `%S*` is reached with `getelementptr %S* p, 0,
+//! i`. Width subtyping relies on this: a struct extending another stores the
+//! shared fields at the same indices.
+
+use llvmlite as ll;
+use oat_ast::Id;
+use oat_typecontext::TypingContext;
+
+use crate::compile::lower_type;
+
+/// Every field and array element takes 8 bytes: booleans are stored as
+/// whole words
+pub(crate) const FIELD_SIZE: i64 = 8;
+
+pub trait StructLayout {
+    /// The named types defining every struct.
+    fn named_types(&self) -> ll::TypeContext;
+
+    /// The index and llvmlite type of the field `field` of the struct `name`.
+    fn field_layout(&self, name: &Id, field: &Id) -> Option<(usize, ll::Type)>;
+
+    /// The number of bytes taken by the struct `name`.
+    fn struct_size(&self, name: &Id) -> Option<i64>;
+}
+
+impl StructLayout for TypingContext {
+    fn named_types(&self) -> ll::TypeContext {
+        self.structs()
+            // Generic structs only have a layout once monomorphized
+            .filter(|(name, _)| self.type_params(name).is_empty())
+            .map(|(name, fields)| {
+                let fields = fields.values().cloned().map(lower_type).collect();
+                (*name, ll::Type::Struct(fields))
+            })
+            .collect()
+    }
+
+    fn field_layout(&self, name: &Id, field: &Id) -> Option<(usize, ll::Type)> {
+        let (i, t) = self.get_field(name, field)?;
+        Some((i, lower_type(t.clone())))
+    }
+
+    fn struct_size(&self, name: &Id) -> Option<i64> {
+        Some(self.get_type(name)?.len() as i64 * FIELD_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod layout_tests {
+    use super::*;
+    use oat_parse::parse_program;
+    use oat_symbol::create_session_if_not_set_then;
+
+    fn layout(source: &str) -> TypingContext {
+        let program = parse_program(source).expect("test program should parse");
+        let structs = program
+            .declarations
+            .into_iter()
+            .filter_map(|decl| match decl {
+                oat_ast::Declaration::Type(tdecl) => Some(tdecl),
+                _ => None,
+            })
+            .collect();
+        TypingContext::from_declarations(&structs)
+    }
+
+    #[test]
+    fn named_types() {
+        create_session_if_not_set_then(|_| {
+            let tc = layout(concat!(
+                "struct point { int x; bool y; }\n",
+                "struct line { point start; point? end; int[] marks; }\n",
+                "struct box<T> { T value; }\n",
+            ));
+            let point = ll::Type::Ptr(Box::new(ll::Type::Namedt("point".into())));
+            let marks = ll::Type::Ptr(Box::new(ll::Type::Struct(vec![
+                ll::Type::I64,
+                ll::Type::Array(0, Box::new(ll::Type::I64)),
+            ])));
+            assert_eq!(
+                tc.named_types(),
+                ll::TypeContext::from([
+                    (
+                        "point".into(),
+                        ll::Type::Struct(vec![ll::Type::I64, ll::Type::I1])
+                    ),
+                    (
+                        "line".into(),
+                        ll::Type::Struct(vec![point.clone(), point, marks])
+                    ),
+                ])
+            );
+        })
+    }
+
+    #[test]
+    fn fields() {
+        create_session_if_not_set_then(|_| {
+            let tc = layout("struct point3 { int x; int y; bool z; }");
+            let point3 = Id::from("point3");
+            assert_eq!(
+                tc.field_layout(&point3, &"z".into()),
+                Some((2, ll::Type::I1))
+            );
+            assert_eq!(tc.field_layout(&point3, &"w".into()), None);
+            assert_eq!(tc.struct_size(&point3), Some(24));
+        })
+    }
+}
@@ -1,9 +1,13 @@
 mod compile;
 pub use compile::*;
 
-// mod constant_fold;
+pub mod constant_fold;
 mod context;
 
+pub mod layout;
+
+pub mod monomorphize;
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,294 @@
+//! Monomorphization of generic functions and structs.
+//!
+//! Lowering only deals with concrete types, so every instantiation of a
+//! generic function or struct used by the program is turned into a separate
+//! declaration with a mangled name, e.g. `reverse<int>` becomes the function
+//! `reverse$int$` and `box<int>` becomes the struct `box$int$`. The input
+//! must have been elaborated by the type checker, so that every instantiation
+//! is explicit. Generic declarations that are never instantiated are dropped.
+//!
+//! Arrays of a type parameter `T` created with `new T[n]` are only checked
+//! for a default element once `T` is known, so instantiating such a function
+//! with a type without a default, e.g. `string`, is an error here.
+//!
+//! Polymorphic recursion, where a generic function instantiates itself with
+//! ever larger type arguments, has no finite set of instances and is not
+//! supported.
+
+use std::collections::{HashSet, VecDeque};
+
+use indexmap::IndexMap;
+
+use oat::visit::{substitute, VisitMut};
+use oat_ast as oat;
+use oat_ast::{Declaration, Expression, FunctionDecl, Id, ReferenceType, Type, TypeDeclaration};
+use oat_error::TypeError;
+
+/// The name of the instance of `name` for `type_args`.
+pub fn mangle(name: Id, type_args: &[Type]) -> Id {
+    let mut mangled = format!("{}$", name.name());
+    for t in type_args {
+        mangled.push_str(&mangle_type(t));
+        mangled.push('$');
+    }
+    Id::intern(&mangled)
+}
+
+fn mangle_type(t: &Type) -> String {
+    match t {
+        Type::Bool => "bool".to_string(),
+        Type::Int => "int".to_string(),
+        Type::Param(name) => name.name().to_string(),
+        Type::Ref(rt) => mangle_reftype(rt),
+        Type::NullRef(rt) => format!("{}.opt", mangle_reftype(rt)),
+    }
+}
+
+fn mangle_reftype(rt: &ReferenceType) -> String {
+    match rt {
+        ReferenceType::String => "string".to_string(),
+        ReferenceType::Struct(name) => name.name().to_string(),
+        ReferenceType::GenericStruct(name, type_args) => {
+            mangle(*name, type_args).name().to_string()
+        }
+        ReferenceType::Array(t) => format!("{}.arr", mangle_type(t)),
+        ReferenceType::Function(arg_types, ret) => {
+            let mut mangled = ".fn$".to_string();
+            for t in arg_types {
+                mangled.push_str(&mangle_type(t));
+                mangled.push('$');
+            }
+            match ret.as_ref() {
+                oat::ReturnType::ReturnVoid => mangled.push_str("void"),
+                oat::ReturnType::ReturnValue(t) => mangled.push_str(&mangle_type(t)),
+            }
+            mangled.push('$');
+            mangled
+        }
+    }
+}
+
+enum Instance {
+    Function(Id, Vec<Type>),
+    Struct(Id, Vec<Type>),
+}
+
+#[derive(Default)]
+struct Monomorphizer {
+    functions: IndexMap<Id, FunctionDecl>,
+    structs: IndexMap<Id, TypeDeclaration>,
+    /// Mangled names of the instances generated so far
+    generated: HashSet<Id>,
+    queue: VecDeque<Instance>,
+}
+
+impl Monomorphizer {
+    fn enqueue(&mut self, instance: Instance) -> Id {
+        let (Instance::Function(name, type_args) | Instance::Struct(name, type_args)) = &instance;
+        let mangled = mangle(*name, type_args);
+        if self.generated.insert(mangled) {
+            self.queue.push_back(instance);
+        }
+        mangled
+    }
+
+    /// Replace the uses of generics within `node` by their instances.
+    fn resolve(&mut self, node: &mut impl VisitMut) {
+        node.visit_types_mut(|t| match t {
+            Type::Ref(rt) | Type::NullRef(rt) => {
+                if let ReferenceType::GenericStruct(name, type_args) = rt {
+                    let instance = Instance::Struct(*name, type_args.clone());
+                    *rt = ReferenceType::Struct(self.enqueue(instance));
+                }
+            }
+            _ => {}
+        });
+        node.visit_expressions_mut(|e| match e {
+            Expression::Instantiate(name, type_args) => {
+                let instance = Instance::Function(*name, type_args.clone());
+                *e = Expression::Id(self.enqueue(instance));
+            }
+            Expression::CStruct(name, type_args, _) if !type_args.is_empty() => {
+                let instance = Instance::Struct(*name, std::mem::take(type_args));
+                *name = self.enqueue(instance);
+            }
+            _ => {}
+        });
+    }
+
+    fn instantiate(&mut self, instance: Instance) -> Result<Declaration, TypeError> {
+        match instance {
+            Instance::Function(name, type_args) => {
+                let mut fdecl = self.functions[&name].clone();
+                let type_params = std::mem::take(&mut fdecl.type_params);
+                substitute(&mut fdecl, &type_params, &type_args);
+                // The type checker leaves arrays of type parameters to us
+                let mut non_default = None;
+                fdecl.visit_expressions_mut(|e| match e {
+                    Expression::NewArr(t, _) if !t.has_default_value() => {
+                        non_default.get_or_insert_with(|| t.clone());
+                    }
+                    _ => {}
+                });
+                if let Some(t) = non_default {
+                    return Err(TypeError::NonDefaultArrayElement(t));
+                }
+                fdecl.name = mangle(name, &type_args);
+                self.resolve(&mut fdecl);
+                Ok(Declaration::Function(fdecl))
+            }
+            Instance::Struct(name, type_args) => {
+                let mut tdecl = self.structs[&name].clone();
+                let type_params = std::mem::take(&mut tdecl.type_params);
+                substitute(&mut tdecl, &type_params, &type_args);
+                tdecl.name = mangle(name, &type_args);
+                self.resolve(&mut tdecl);
+                Ok(Declaration::Type(tdecl))
+            }
+        }
+    }
+}
+
+/// Replace the generic functions and structs of an elaborated program by
+/// their instances.
+pub fn monomorphize(program: &oat::Program) -> Result<oat::Program, TypeError> {
+    let mut monomorphizer = Monomorphizer::default();
+    let mut declarations = vec![];
+    for decl in program.declarations.iter() {
+        match decl {
+            Declaration::Function(fdecl) if !fdecl.type_params.is_empty() => {
+                monomorphizer.functions.insert(fdecl.name, fdecl.clone());
+            }
+            Declaration::Type(tdecl) if !tdecl.type_params.is_empty() => {
+                monomorphizer.structs.insert(tdecl.name, tdecl.clone());
+            }
+            decl => declarations.push(decl.clone()),
+        }
+    }
+
+    declarations
+        .iter_mut()
+        .for_each(|decl| monomorphizer.resolve(decl));
+    while let Some(instance) = monomorphizer.queue.pop_front() {
+        declarations.push(monomorphizer.instantiate(instance)?);
+    }
+
+    Ok(oat::Program { declarations })
+}
+
+#[cfg(test)]
+mod monomorphize_tests {
+    use super::*;
+    use oat_parse::parse_program;
+    use oat_symbol::create_session_if_not_set_then;
+    use oat_typecheck::{elaborate, type_check};
+
+    const GENERICS: &str = concat!(
+        "struct box<T> { T value; }\n",
+        "T[] reverse<T>(T[] a) { return a; }\n",
+        "box<T> wrap<T>(T v) { return new box<T>{ value = v }; }\n",
+        "T[] unused<T>(T[] a) { return a; }\n",
+    );
+
+    fn monomorphized(source: &str) -> oat::Program {
+        let program = parse_program(&format!("{}{}", GENERICS, source)).unwrap();
+        monomorphize(&elaborate(&program).unwrap()).unwrap()
+    }
+
+    fn names(program: &oat::Program) -> Vec<&'static str> {
+        program
+            .declarations
+            .iter()
+            .filter_map(|decl| match decl {
+                Declaration::Function(fdecl) => Some(fdecl.name.name()),
+                Declaration::Type(tdecl) => Some(tdecl.name.name()),
+                Declaration::Variable(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn one_function_per_instance() {
+        create_session_if_not_set_then(|_| {
+            let program = monomorphized(concat!(
+                "int[] f(int[] a) { return reverse(a); }\n",
+                "bool[] g(bool[] a) { var b = reverse<bool>(a); return reverse(b); }\n",
+            ));
+            assert_eq!(
+                names(&program),
+                vec!["f", "g", "reverse$int$", "reverse$bool$"]
+            );
+            assert_eq!(type_check(&program), Ok(()));
+        })
+    }
+
+    #[test]
+    fn calls_use_instances() {
+        create_session_if_not_set_then(|_| {
+            let program = monomorphized("int[] f(int[] a) { return reverse(a); }");
+            let f = program.declarations.iter().find_map(|decl| match decl {
+                Declaration::Function(fdecl) if fdecl.name == Id::from("f") => Some(fdecl),
+                _ => None,
+            });
+            assert_eq!(
+                f.unwrap().body,
+                vec![oat::Statement::Return(Some(Expression::Call(
+                    Box::new(Expression::Id("reverse$int$".into())),
+                    vec!["a".into()]
+                )))]
+            );
+        })
+    }
+
+    #[test]
+    fn struct_instances() {
+        create_session_if_not_set_then(|_| {
+            let program = monomorphized("int f() { return wrap(wrap(1)).value.value; }");
+            assert_eq!(
+                names(&program),
+                vec![
+                    "f",
+                    "box$int$",
+                    "wrap$box$int$$",
+                    "wrap$int$",
+                    "box$box$int$$"
+                ]
+            );
+            let box_box_int = program.declarations.iter().find_map(|decl| match decl {
+                Declaration::Type(tdecl) if tdecl.name == Id::from("box$box$int$$") => Some(tdecl),
+                _ => None,
+            });
+            assert_eq!(
+                box_box_int.unwrap().fields[&Id::from("value")],
+                Type::Ref(ReferenceType::Struct("box$int$".into()))
+            );
+            assert_eq!(type_check(&program), Ok(()));
+        })
+    }
+
+    #[test]
+    fn arrays_of_type_parameters() {
+        create_session_if_not_set_then(|_| {
+            let generic = "T[] fill<T>(int n) { return new T[n]; }\n";
+            let program = parse_program(&format!(
+                "{}{}",
+                generic, "int[] f() { return fill<int>(3); }"
+            ))
+            .unwrap();
+            let program = monomorphize(&elaborate(&program).unwrap()).unwrap();
+            assert_eq!(type_check(&program), Ok(()));
+
+            let program = parse_program(&format!(
+                "{}{}",
+                generic, "string[] f() { return fill<string>(3); }"
+            ))
+            .unwrap();
+            assert_eq!(
+                monomorphize(&elaborate(&program).unwrap()),
+                Err(TypeError::NonDefaultArrayElement(Type::Ref(
+                    ReferenceType::String
+                )))
+            );
+        })
+    }
+}
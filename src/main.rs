@@ -1,17 +1,29 @@
 use std::error::Error;
 use std::fs;
+use std::path::Path;
 
-use clap::Parser;
+use clap::{ArgEnum, Parser};
 
+use ir_passes::mem2reg::Mem2Reg;
+use ir_passes::sccp::Sccp;
+use ir_passes::PassManager;
+use oat_lint::{lint, Level, LintLevels};
 use oat_parse::parse_program;
 use oat_symbol::create_session_if_not_set_then;
-use oat_typecheck::type_check;
-
-mod config;
-mod frontend;
+use oat2llvmlite::{compile_program, Options};
+use oat2llvmlite::constant_fold::constant_fold;
+use oat2llvmlite::monomorphize::monomorphize;
+use oat_typecheck::elaborate;
 
 /// Whether or not the current platform is Linux
-const IS_LINUX: bool = cfg!(linux);
+const IS_LINUX: bool = cfg!(target_os = "linux");
+
+/// Intermediate representations which can be emitted instead of an executable
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Emit {
+    /// Textual LLVM IR
+    Llvm,
+}
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -24,9 +36,11 @@ struct Args {
     #[clap(long)]
     test: bool,
 
-    /// Output path. Where to place the executable, or resulting files
-    #[clap(short, long, default_value = "a.out")]
-    output: String,
+    /// Output path. Where to place the executable, or resulting files.
+    /// Defaults to `a.out`, or to the input file with the extension of the
+    /// emitted representation
+    #[clap(short, long)]
+    output: Option<String>,
 
     /// Stop at assembly
     #[clap(short = 'S')]
@@ -40,15 +54,117 @@ struct Args {
     #[clap(short, long)]
     verbose: bool,
 
-    /// Files to compile
+    /// Warn about a lint, or every lint with `warnings`
+    #[clap(short = 'W', value_name = "LINT")]
+    warn: Vec<String>,
+
+    /// Allow a lint, or every lint with `warnings`
+    #[clap(short = 'A', value_name = "LINT")]
+    allow: Vec<String>,
+
+    /// Deny a lint, or every lint with `warnings`
+    #[clap(short = 'D', value_name = "LINT")]
+    deny: Vec<String>,
+
+    /// Do not check array indices against the length of the array
+    #[clap(long)]
+    no_bounds_checks: bool,
+
+    /// Optimization level. Constants are folded and propagated, and locals
+    /// are promoted to registers, from level 1
+    #[clap(short = 'O', default_value = "0", value_name = "LEVEL")]
+    opt_level: u8,
+
+    /// Emit an intermediate representation instead of an executable
+    #[clap(long, arg_enum, value_name = "KIND")]
+    emit: Option<Emit>,
+
+    /// Files to compile. Files ending in `.ll` are read as LLVMLite, and skip
+    /// the Oat front end
     files: Vec<String>,
 }
 
-fn compile(input: &str) -> Result<(), Box<dyn Error>> {
+/// The lint levels given on the command line. Allowing comes first and
+/// denying last, so that e.g. `-A warnings -D unused_locals` only denies
+/// unused locals.
+fn lint_levels(args: &Args) -> Result<LintLevels, String> {
+    let mut levels = LintLevels::new();
+    let flags = [
+        (&args.allow, Level::Allow),
+        (&args.warn, Level::Warn),
+        (&args.deny, Level::Deny),
+    ];
+    for (names, level) in flags {
+        for name in names {
+            if !levels.set_by_name(name, level) {
+                return Err(format!("Unknown lint: {}", name));
+            }
+        }
+    }
+    Ok(levels)
+}
+
+/// Check the output of a pass producing LLVMLite, in debug builds. This must
+/// run in the session of the program, as errors name types.
+fn verified(program: llvmlite::Program) -> Result<llvmlite::Program, String> {
+    if cfg!(debug_assertions) {
+        llvmlite::verify(&program).map_err(|e| format!("Invalid LLVMLite: {}", e))?;
+    }
+    Ok(program)
+}
+
+/// Run the LLVMLite passes of `opt_level` over `program`.
+fn optimized(mut program: llvmlite::Program, opt_level: u8) -> llvmlite::Program {
+    let mut manager = PassManager::new();
+    if opt_level >= 1 {
+        manager.add(Mem2Reg);
+        manager.add(Sccp);
+    }
+    manager.run(&mut program);
+    program
+}
+
+/// Compile an Oat program to textual LLVM IR.
+fn compile(
+    input: &str,
+    levels: &LintLevels,
+    options: &Options,
+    opt_level: u8,
+    verbose: bool,
+) -> Result<String, Box<dyn Error>> {
+    create_session_if_not_set_then(|_| {
+        let result = (|| -> Result<llvmlite::Program, oat_error::Error> {
+            let program = dbg!(parse_program(input)?);
+            // Type checking makes the instantiations of generics explicit
+            let elaborated = elaborate(&program)?;
+            for warning in lint(&program, levels)? {
+                eprintln!("warning: {}", warning);
+            }
+            let mut program = monomorphize(&elaborated)?;
+            if opt_level >= 1 {
+                program = constant_fold(program);
+            }
+            Ok(compile_program(&program, options))
+        })();
+        // Diagnostics name symbols, which can only be printed in the session
+        let llvm = verified(result.map_err(|e| e.to_string())?)?;
+        let llvm = verified(optimized(llvm, opt_level))?;
+        if verbose {
+            dbg!(&llvm);
+        }
+        Ok(llvm.to_string())
+    })
+}
+
+/// Read an LLVMLite program, returning it as printed textual LLVM IR.
+fn compile_llvm(input: &str, opt_level: u8, verbose: bool) -> Result<String, Box<dyn Error>> {
     create_session_if_not_set_then(|_| {
-        let program = dbg!(parse_program(&input)?);
-        type_check(&program)?;
-        Ok(())
+        let llvm = verified(llvmlite::parse_program(input).map_err(|e| e.to_string())?)?;
+        let llvm = verified(optimized(llvm, opt_level))?;
+        if verbose {
+            dbg!(&llvm);
+        }
+        Ok(llvm.to_string())
     })
 }
 
@@ -65,9 +181,27 @@ fn main() -> Result<(), Box<dyn Error>> {
     //
     //
     // String
+    let levels = lint_levels(&args)?;
     let input = fs::read_to_string(&args.files[0])?;
     // let input = content.as_str();
-    compile(&input)?;
+    let options = Options {
+        bounds_checks: !args.no_bounds_checks,
+    };
+    let path = Path::new(&args.files[0]);
+    let llvm = match path.extension() {
+        Some(extension) if extension == "ll" => compile_llvm(&input, args.opt_level, args.verbose)?,
+        _ => compile(&input, &levels, &options, args.opt_level, args.verbose)?,
+    };
+    if let Some(Emit::Llvm) = args.emit {
+        let output = match &args.output {
+            Some(output) => output.clone(),
+            None => path.with_extension("ll").to_string_lossy().into_owned(),
+        };
+        if Path::new(&output) == path {
+            return Err(format!("Emitting LLVM IR would overwrite {}", path.display()).into());
+        }
+        fs::write(output, llvm)?;
+    }
 
     Ok(())
 }